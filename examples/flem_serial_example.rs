@@ -1,4 +1,4 @@
-use std::{io, thread, time::Duration};
+use std::io;
 
 use flem::Status;
 
@@ -83,55 +83,36 @@ fn main() {
         }
     }
 
-    let flem_rx = flem_serial.listen();
+    let mut router = flem_serial_rs::router::PacketRouter::<PACKET_SIZE>::new();
+    router.on(flem::Request::EVENT, |packet| {
+        let mut float_data = Vec::<f32>::new();
+        for slice in packet.get_data().chunks(4) {
+            float_data.push(f32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]));
+        }
+        println!("Real: {}, Imag: {}", float_data[0], float_data[1]);
+    });
+    router.on(flem::Request::ID, |packet| {
+        let id: flem::DataId = flem::DataId::from(packet.get_data()).unwrap();
+        println!(
+            "Flem Device: {:?}, version {}.{}.{}, packet size: {}",
+            id.get_name(),
+            id.get_major(),
+            id.get_minor(),
+            id.get_patch(),
+            id.get_max_packet_size()
+        );
+    });
+    router.on_unknown(|_| println!("Unknown command"));
+
+    // Handlers run on the listener thread until it stops
+    let flem_rx = flem_serial.listen_routed(router).unwrap();
 
     let mut packet = flem::Packet::<PACKET_SIZE>::new();
     packet.set_request(5);
     packet.pack();
-    flem_serial.send(&packet);
-
-    let uart_rx_thread_processor = thread::spawn(move || {
-        let mut timeout = 0;
-        let rx_queue = flem_rx.queue();
-        loop {
-            match rx_queue.recv() {
-                Ok(packet) => {
-                    timeout = 0;
-                    let packet_data = &packet.get_data();
-                    match packet.get_request() {
-                        flem::Request::EVENT => {
-                            let mut float_data = Vec::<f32>::new();
-                            for slice in packet_data.chunks(4) {
-                                float_data.push(f32::from_le_bytes([
-                                    slice[0], slice[1], slice[2], slice[3],
-                                ]));
-                            }
-                            println!("Real: {}, Imag: {}", float_data[0], float_data[1]);
-                        }
-                        flem::Request::ID => {
-                            let id: flem::DataId = flem::DataId::from(packet_data).unwrap();
-                            println!(
-                                "Flem Device: {:?}, version {}.{}.{}, packet size: {}",
-                                id.get_name(),
-                                id.get_major(),
-                                id.get_minor(),
-                                id.get_patch(),
-                                id.get_max_packet_size()
-                            );
-                        }
-                        _ => {
-                            println!("Unknown command");
-                        }
-                    }
-                }
-                Err(_) => {
-                    timeout += 1;
-                    thread::sleep(Duration::from_millis(1));
-                    if timeout > 100 {}
-                }
-            }
-        }
-    });
+    if let Err(error) = flem_serial.send(&packet) {
+        println!("Send failed: {}", error);
+    }
 
-    uart_rx_thread_processor.join().unwrap();
+    flem_rx.join().unwrap();
 }
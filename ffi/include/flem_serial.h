@@ -0,0 +1,50 @@
+/*
+ * C ABI of flem-serial-rs, built by the flem-serial-ffi crate. See
+ * ffi/src/lib.rs for the details of each call.
+ */
+#ifndef FLEM_SERIAL_H
+#define FLEM_SERIAL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Packet size of every link opened through the C ABI. */
+#define FLEM_SERIAL_PACKET_SIZE 512
+
+typedef enum {
+    FLEM_SERIAL_OK = 0,
+    FLEM_SERIAL_NO_PACKET = 1,
+    FLEM_SERIAL_INVALID_ARGUMENT = -1,
+    FLEM_SERIAL_CONNECT_FAILED = -2,
+    FLEM_SERIAL_LISTEN_FAILED = -3,
+    FLEM_SERIAL_SEND_FAILED = -4,
+    FLEM_SERIAL_NOT_CONNECTED = -5,
+    FLEM_SERIAL_BUFFER_TOO_SMALL = -6,
+    FLEM_SERIAL_PANICKED = -7,
+} FlemSerialStatus;
+
+typedef struct FlemSerialHandle FlemSerialHandle;
+
+FlemSerialHandle *flem_serial_new(void);
+void flem_serial_free(FlemSerialHandle *handle);
+
+FlemSerialStatus flem_serial_list_ports(char *buffer, size_t capacity, size_t *length);
+
+FlemSerialStatus flem_serial_connect(FlemSerialHandle *handle, const char *port, uint32_t baud);
+FlemSerialStatus flem_serial_disconnect(FlemSerialHandle *handle);
+
+FlemSerialStatus flem_serial_send(FlemSerialHandle *handle, uint8_t request, const uint8_t *data,
+                                  size_t length);
+FlemSerialStatus flem_serial_poll_receive(FlemSerialHandle *handle, uint8_t *request,
+                                          uint8_t *response, uint8_t *data, size_t capacity,
+                                          size_t *length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FLEM_SERIAL_H */
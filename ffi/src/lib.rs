@@ -0,0 +1,477 @@
+//! C ABI over [FlemSerial], so C, C++ and LabVIEW hosts can reuse the
+//! FLEM framing instead of reimplementing it. `include/flem_serial.h`
+//! declares the functions.
+//!
+//! A link is an opaque [FlemSerialHandle] from [flem_serial_new], freed with
+//! [flem_serial_free]. Connecting starts a listener, whose packets are
+//! collected with [flem_serial_poll_receive] without blocking.
+//!
+//! A panic never unwinds into the caller, the call returns
+//! [FlemSerialStatus::Panicked] instead.
+
+use flem_serial_rs::{FlemRx, FlemSerial, FlemSerialError};
+use std::{
+    ffi::{c_char, CStr},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+    sync::mpsc::TryRecvError,
+};
+
+/// Packet size of every link opened through the C ABI.
+pub const FFI_PACKET_SIZE: usize = 512;
+
+/// Result of a C ABI call. Negative values are errors.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlemSerialStatus {
+    Ok = 0,
+    /// [flem_serial_poll_receive] found no packet waiting.
+    NoPacket = 1,
+    /// A required pointer was null or a string wasn't valid UTF-8.
+    InvalidArgument = -1,
+    /// Listing ports or opening the port failed.
+    ConnectFailed = -2,
+    /// The port opened but its listener couldn't be started.
+    ListenFailed = -3,
+    /// The data doesn't fit in a packet or writing it failed.
+    SendFailed = -4,
+    /// The link isn't connected, or its listener has stopped.
+    NotConnected = -5,
+    /// The caller's buffer is too small. The needed length is written to
+    /// the length argument and nothing is lost, call again with a larger
+    /// buffer.
+    BufferTooSmall = -6,
+    /// The call panicked. The link may be left in any state and should be
+    /// freed.
+    Panicked = -7,
+}
+
+/// Runs the body of a C ABI call, turning a panic into
+/// [FlemSerialStatus::Panicked] since unwinding across `extern "C"` aborts.
+fn guard(body: impl FnOnce() -> FlemSerialStatus) -> FlemSerialStatus {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(FlemSerialStatus::Panicked)
+}
+
+/// An open link and its listener, opaque to C.
+pub struct FlemSerialHandle {
+    serial: FlemSerial<FFI_PACKET_SIZE>,
+    rx: Option<FlemRx<FFI_PACKET_SIZE>>,
+    /// Packet that didn't fit the caller's buffer, returned by the next
+    /// poll.
+    held: Option<flem::Packet<FFI_PACKET_SIZE>>,
+}
+
+impl FlemSerialHandle {
+    fn new(serial: FlemSerial<FFI_PACKET_SIZE>) -> Self {
+        Self {
+            serial,
+            rx: None,
+            held: None,
+        }
+    }
+
+    fn listen(&mut self) -> FlemSerialStatus {
+        match self.serial.listen() {
+            Ok(rx) => {
+                self.rx = Some(rx);
+                FlemSerialStatus::Ok
+            }
+            Err(_) => FlemSerialStatus::ListenFailed,
+        }
+    }
+}
+
+/// Copies `bytes` and a terminating NUL into `buffer`, reporting the length
+/// without the NUL through `length`.
+unsafe fn copy_out(
+    bytes: &[u8],
+    buffer: *mut u8,
+    capacity: usize,
+    length: *mut usize,
+    terminate: bool,
+) -> FlemSerialStatus {
+    *length = bytes.len();
+    let needed = bytes.len() + usize::from(terminate);
+    if needed > capacity {
+        return FlemSerialStatus::BufferTooSmall;
+    }
+    if needed > 0 {
+        if buffer.is_null() {
+            return FlemSerialStatus::InvalidArgument;
+        }
+        ptr::copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+        if terminate {
+            *buffer.add(bytes.len()) = 0;
+        }
+    }
+    FlemSerialStatus::Ok
+}
+
+/// Writes `names` separated by `\n` like [copy_out]. No names is an empty
+/// string, which needs no buffer at all.
+unsafe fn copy_names(
+    names: &[String],
+    buffer: *mut u8,
+    capacity: usize,
+    length: *mut usize,
+) -> FlemSerialStatus {
+    if names.is_empty() && (buffer.is_null() || capacity == 0) {
+        *length = 0;
+        return FlemSerialStatus::Ok;
+    }
+    copy_out(names.join("\n").as_bytes(), buffer, capacity, length, true)
+}
+
+/// Creates an unconnected link. Free it with [flem_serial_free]. Returns
+/// null if creating it panicked.
+#[no_mangle]
+pub extern "C" fn flem_serial_new() -> *mut FlemSerialHandle {
+    panic::catch_unwind(|| Box::into_raw(Box::new(FlemSerialHandle::new(FlemSerial::new()))))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Disconnects and frees a link. Null is ignored.
+///
+/// # Safety
+///
+/// `handle` must be null or come from [flem_serial_new] and not have been
+/// freed already.
+#[no_mangle]
+pub unsafe extern "C" fn flem_serial_free(handle: *mut FlemSerialHandle) {
+    if !handle.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(handle))));
+    }
+}
+
+/// Writes the names of the serial ports, separated by `\n` and NUL
+/// terminated, into `buffer`. `length` receives the length of the names
+/// without the NUL, also when the buffer is too small. With no ports this
+/// returns [FlemSerialStatus::Ok] and a length of 0, even for a null
+/// `buffer`.
+///
+/// # Safety
+///
+/// `buffer` must be valid for `capacity` bytes and `length` must be valid
+/// for a write.
+#[no_mangle]
+pub unsafe extern "C" fn flem_serial_list_ports(
+    buffer: *mut c_char,
+    capacity: usize,
+    length: *mut usize,
+) -> FlemSerialStatus {
+    guard(|| {
+        if length.is_null() {
+            return FlemSerialStatus::InvalidArgument;
+        }
+        let Some(ports) = FlemSerial::<FFI_PACKET_SIZE>::new().list_serial_ports() else {
+            return FlemSerialStatus::ConnectFailed;
+        };
+        copy_names(&ports, buffer.cast(), capacity, length)
+    })
+}
+
+/// Connects to `port` at `baud` and starts listening. Packets are then
+/// collected with [flem_serial_poll_receive].
+///
+/// # Safety
+///
+/// `handle` must come from [flem_serial_new] and `port` must be a NUL
+/// terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn flem_serial_connect(
+    handle: *mut FlemSerialHandle,
+    port: *const c_char,
+    baud: u32,
+) -> FlemSerialStatus {
+    guard(|| {
+        let Some(handle) = handle.as_mut() else {
+            return FlemSerialStatus::InvalidArgument;
+        };
+        if port.is_null() {
+            return FlemSerialStatus::InvalidArgument;
+        }
+        let Ok(port) = CStr::from_ptr(port).to_str() else {
+            return FlemSerialStatus::InvalidArgument;
+        };
+
+        handle.rx = None;
+        handle.held = None;
+        if handle.serial.connect(&port.to_string(), baud).is_err() {
+            return FlemSerialStatus::ConnectFailed;
+        }
+        handle.listen()
+    })
+}
+
+/// Stops the listener and closes the port. Packets not yet received are
+/// dropped.
+///
+/// # Safety
+///
+/// `handle` must come from [flem_serial_new].
+#[no_mangle]
+pub unsafe extern "C" fn flem_serial_disconnect(handle: *mut FlemSerialHandle) -> FlemSerialStatus {
+    guard(|| {
+        let Some(handle) = handle.as_mut() else {
+            return FlemSerialStatus::InvalidArgument;
+        };
+        handle.serial.unlisten();
+        handle.rx = None;
+        handle.held = None;
+        let _ = handle.serial.disconnect();
+        FlemSerialStatus::Ok
+    })
+}
+
+/// Sends a packet with `request` and `length` bytes of `data`.
+///
+/// # Safety
+///
+/// `handle` must come from [flem_serial_new] and `data` must be valid for
+/// `length` bytes. `data` may be null if `length` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn flem_serial_send(
+    handle: *mut FlemSerialHandle,
+    request: u8,
+    data: *const u8,
+    length: usize,
+) -> FlemSerialStatus {
+    guard(|| {
+        let Some(handle) = handle.as_mut() else {
+            return FlemSerialStatus::InvalidArgument;
+        };
+        let data = match (data.is_null(), length) {
+            (_, 0) => &[][..],
+            (true, _) => return FlemSerialStatus::InvalidArgument,
+            (false, _) => slice::from_raw_parts(data, length),
+        };
+
+        let mut packet = flem::Packet::<FFI_PACKET_SIZE>::new();
+        packet.set_request(request);
+        if packet.add_data(data).is_err() {
+            return FlemSerialStatus::SendFailed;
+        }
+        packet.pack();
+        match handle.serial.send(&packet) {
+            Ok(()) => FlemSerialStatus::Ok,
+            Err(FlemSerialError::NotConnected) => FlemSerialStatus::NotConnected,
+            Err(_) => FlemSerialStatus::SendFailed,
+        }
+    })
+}
+
+/// Takes the next received packet without blocking. Returns
+/// [FlemSerialStatus::NoPacket] if none is waiting.
+///
+/// `request` and `response` receive the packet's codes and `data` its
+/// payload, whose size goes to `length`.
+///
+/// # Safety
+///
+/// `handle` must come from [flem_serial_new], `data` must be valid for
+/// `capacity` bytes and the other pointers must be valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn flem_serial_poll_receive(
+    handle: *mut FlemSerialHandle,
+    request: *mut u8,
+    response: *mut u8,
+    data: *mut u8,
+    capacity: usize,
+    length: *mut usize,
+) -> FlemSerialStatus {
+    guard(|| {
+        let Some(handle) = handle.as_mut() else {
+            return FlemSerialStatus::InvalidArgument;
+        };
+        if request.is_null() || response.is_null() || length.is_null() {
+            return FlemSerialStatus::InvalidArgument;
+        }
+
+        let packet = match handle.held.take() {
+            Some(packet) => packet,
+            None => {
+                let Some(rx) = handle.rx.as_ref() else {
+                    return FlemSerialStatus::NotConnected;
+                };
+                match rx.try_recv() {
+                    Ok(packet) => packet,
+                    Err(TryRecvError::Empty) => return FlemSerialStatus::NoPacket,
+                    Err(TryRecvError::Disconnected) => return FlemSerialStatus::NotConnected,
+                }
+            }
+        };
+
+        let status = copy_out(packet.get_data(), data, capacity, length, false);
+        if status != FlemSerialStatus::Ok {
+            handle.held = Some(packet);
+            return status;
+        }
+        *request = packet.get_request();
+        *response = packet.get_response();
+        FlemSerialStatus::Ok
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{self, Cursor},
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Keeps what is written, reads nothing.
+    #[derive(Clone)]
+    struct Wire(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Read for Wire {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sent_packets_are_framed() {
+        let wire = Wire(Arc::new(Mutex::new(Vec::new())));
+        let handle = Box::into_raw(Box::new(FlemSerialHandle::new(FlemSerial::from_transport(
+            wire.clone(),
+        ))));
+
+        unsafe {
+            assert_eq!(
+                flem_serial_send(handle, 0x30, [7, 8].as_ptr(), 2),
+                FlemSerialStatus::Ok
+            );
+            assert_eq!(
+                flem_serial_send(handle, 0x30, ptr::null(), 1),
+                FlemSerialStatus::InvalidArgument
+            );
+            flem_serial_free(handle);
+        }
+
+        let mut expected = flem::Packet::<FFI_PACKET_SIZE>::new();
+        expected.set_request(0x30);
+        expected.add_data(&[7, 8]).unwrap();
+        expected.pack();
+        assert_eq!(*wire.0.lock().unwrap(), expected.bytes());
+    }
+
+    #[test]
+    fn test_poll_receive_keeps_packets_too_large_for_the_buffer() {
+        let mut packet = flem::Packet::<FFI_PACKET_SIZE>::new();
+        packet.set_request(0x31);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        packet.pack();
+        let mut handle = FlemSerialHandle::new(FlemSerial::from_transport(Cursor::new(
+            packet.bytes().to_vec(),
+        )));
+        assert_eq!(handle.listen(), FlemSerialStatus::Ok);
+        let handle = Box::into_raw(Box::new(handle));
+
+        let (mut request, mut response, mut length) = (0, 0, 0);
+        let mut data = [0u8; 3];
+        let mut poll = |capacity| unsafe {
+            flem_serial_poll_receive(
+                handle,
+                &mut request,
+                &mut response,
+                data.as_mut_ptr(),
+                capacity,
+                &mut length,
+            )
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let mut status = poll(2);
+        while status == FlemSerialStatus::NoPacket && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+            status = poll(2);
+        }
+        assert_eq!(status, FlemSerialStatus::BufferTooSmall);
+        assert_eq!(poll(3), FlemSerialStatus::Ok);
+        assert_eq!(poll(3), FlemSerialStatus::NoPacket);
+        assert_eq!((request, length, data), (0x31, 3, [1, 2, 3]));
+
+        unsafe { flem_serial_free(handle) };
+    }
+
+    #[test]
+    fn test_no_ports_is_an_empty_list() {
+        let mut length = usize::MAX;
+        let status = unsafe { copy_names(&[], ptr::null_mut(), 0, &mut length) };
+        assert_eq!((status, length), (FlemSerialStatus::Ok, 0));
+
+        let mut buffer = [0xFFu8; 4];
+        let status = unsafe { copy_names(&[], buffer.as_mut_ptr(), 4, &mut length) };
+        assert_eq!((status, length, buffer[0]), (FlemSerialStatus::Ok, 0, 0));
+
+        let names = ["/dev/ttyUSB0".to_string()];
+        let status = unsafe { copy_names(&names, ptr::null_mut(), 0, &mut length) };
+        assert_eq!((status, length), (FlemSerialStatus::BufferTooSmall, 12));
+    }
+
+    #[test]
+    fn test_a_panic_is_returned_as_a_status() {
+        assert_eq!(guard(|| panic!("bug")), FlemSerialStatus::Panicked);
+        assert_eq!(guard(|| FlemSerialStatus::Ok), FlemSerialStatus::Ok);
+    }
+
+    #[test]
+    fn test_header_matches_the_abi() {
+        let header = include_str!("../include/flem_serial.h");
+        let define = |name: &str| -> i64 {
+            header
+                .lines()
+                .find_map(|line| line.trim().strip_prefix(name))
+                .and_then(|rest| {
+                    rest.trim()
+                        .trim_end_matches(',')
+                        .trim_start_matches('=')
+                        .trim()
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or_else(|| panic!("{} missing from the header", name))
+        };
+
+        assert_eq!(
+            define("#define FLEM_SERIAL_PACKET_SIZE "),
+            FFI_PACKET_SIZE as i64
+        );
+        for (name, status) in [
+            ("FLEM_SERIAL_OK ", FlemSerialStatus::Ok),
+            ("FLEM_SERIAL_NO_PACKET ", FlemSerialStatus::NoPacket),
+            (
+                "FLEM_SERIAL_INVALID_ARGUMENT ",
+                FlemSerialStatus::InvalidArgument,
+            ),
+            (
+                "FLEM_SERIAL_CONNECT_FAILED ",
+                FlemSerialStatus::ConnectFailed,
+            ),
+            ("FLEM_SERIAL_LISTEN_FAILED ", FlemSerialStatus::ListenFailed),
+            ("FLEM_SERIAL_SEND_FAILED ", FlemSerialStatus::SendFailed),
+            ("FLEM_SERIAL_NOT_CONNECTED ", FlemSerialStatus::NotConnected),
+            (
+                "FLEM_SERIAL_BUFFER_TOO_SMALL ",
+                FlemSerialStatus::BufferTooSmall,
+            ),
+            ("FLEM_SERIAL_PANICKED ", FlemSerialStatus::Panicked),
+        ] {
+            assert_eq!(define(name), status as i64, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::stats::{LinkStats, PortBuffers};
+use std::time::{Duration, Instant};
+
+/// Queue depth above which an unbounded receive queue is worth bounding.
+const QUEUE_DEPTH_CONCERN: usize = 1000;
+/// Packet rate above which batched delivery saves noticeable wakeups.
+const BATCHING_RATE: f64 = 1000.0;
+
+/// A concrete setting to change, see [TuningAdvisor].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suggestion {
+    /// The consumer falls behind: bound the receive queue with
+    /// [crate::FlemSerial::set_backpressure].
+    BoundQueue { high_water: usize, low_water: usize },
+    /// Use [crate::FlemSerial::listen_batched] to cut per-packet wakeups.
+    BatchDelivery {
+        max_packets: usize,
+        max_delay: Duration,
+    },
+    /// The OS input buffer backs up faster than the listener drains it. The
+    /// listener reads up to `T` bytes at a time, so a larger `T` reads more
+    /// per call.
+    LargerReadChunk { peak_bytes_waiting: u32 },
+    /// Nearly every payload is much smaller than `T`; a smaller packet size
+    /// saves memory and copying.
+    SmallerPacketSize { suggested: usize },
+    /// The UART lost bytes in hardware. Lower the baud rate or use an
+    /// adapter with flow control.
+    LowerBaudRate { overruns: u64 },
+}
+
+/// A suggestion with the observation that triggered it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recommendation {
+    pub suggestion: Suggestion,
+    pub reason: String,
+}
+
+struct Sample {
+    at: Instant,
+    stats: LinkStats,
+    buffers: Option<PortBuffers>,
+    queue_depth: usize,
+}
+
+/// Collects link statistics over a sampling window and turns them into
+/// tuning recommendations.
+///
+/// Call `sample` periodically, for example every 100 ms, with
+/// [crate::FlemRx::stats], [crate::FlemSerial::port_buffers] and
+/// [crate::FlemRx::queue_depth], then `recommend`.
+pub struct TuningAdvisor {
+    packet_size: usize,
+    samples: Vec<Sample>,
+}
+
+impl TuningAdvisor {
+    /// `packet_size` is the link's `T`.
+    pub fn new(packet_size: usize) -> Self {
+        Self {
+            packet_size,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sample(
+        &mut self,
+        stats: LinkStats,
+        buffers: Option<PortBuffers>,
+        queue_depth: usize,
+        now: Instant,
+    ) {
+        self.samples.push(Sample {
+            at: now,
+            stats,
+            buffers,
+            queue_depth,
+        });
+    }
+
+    /// Recommendations for the window sampled so far, most severe first.
+    /// Needs at least two samples.
+    pub fn recommend(&self) -> Vec<Recommendation> {
+        let (first, last) = match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) if self.samples.len() >= 2 => (first, last),
+            _ => return Vec::new(),
+        };
+        let window = last.at.saturating_duration_since(first.at);
+        let mut recommendations = Vec::new();
+
+        let overruns = match (first.stats.uart_errors, last.stats.uart_errors) {
+            (Some(first), Some(last)) => (last.overrun + last.buffer_overrun)
+                .saturating_sub(first.overrun + first.buffer_overrun),
+            _ => 0,
+        };
+        if overruns > 0 {
+            recommendations.push(Recommendation {
+                suggestion: Suggestion::LowerBaudRate { overruns },
+                reason: format!("{} UART overruns in {:?}", overruns, window),
+            });
+        }
+
+        let peak_bytes_waiting = self
+            .samples
+            .iter()
+            .filter_map(|sample| sample.buffers)
+            .map(|buffers| buffers.bytes_to_read)
+            .max()
+            .unwrap_or(0);
+        if peak_bytes_waiting as usize > 4 * self.packet_size {
+            recommendations.push(Recommendation {
+                suggestion: Suggestion::LargerReadChunk { peak_bytes_waiting },
+                reason: format!(
+                    "up to {} bytes waited in the OS buffer, reads take at most {}",
+                    peak_bytes_waiting, self.packet_size
+                ),
+            });
+        }
+
+        let peak_depth = self
+            .samples
+            .iter()
+            .map(|sample| sample.queue_depth)
+            .max()
+            .unwrap_or(0);
+        if peak_depth > QUEUE_DEPTH_CONCERN && last.queue_depth > first.queue_depth {
+            let high_water = (peak_depth / 2).max(64);
+            recommendations.push(Recommendation {
+                suggestion: Suggestion::BoundQueue {
+                    high_water,
+                    low_water: high_water / 4,
+                },
+                reason: format!(
+                    "receive queue grew from {} to {} packets",
+                    first.queue_depth, last.queue_depth
+                ),
+            });
+        }
+
+        let received = last
+            .stats
+            .session_rx_packets
+            .saturating_sub(first.stats.session_rx_packets);
+        let rate = received as f64 / window.as_secs_f64().max(f64::EPSILON);
+        if rate > BATCHING_RATE {
+            // About 10 ms worth of packets per batch
+            let max_packets = ((rate / 100.0) as usize).clamp(8, 1024);
+            recommendations.push(Recommendation {
+                suggestion: Suggestion::BatchDelivery {
+                    max_packets,
+                    max_delay: Duration::from_millis(10),
+                },
+                reason: format!("{:.0} packets per second delivered one by one", rate),
+            });
+        }
+
+        let sizes = &last.stats.payload_sizes;
+        let total = sizes.total();
+        if total > 0 && self.packet_size > 64 {
+            let mut counted = 0;
+            let covering = sizes.counts.iter().position(|count| {
+                counted += count;
+                counted * 100 >= total * 99
+            });
+            if let Some(bucket) = covering {
+                let suggested = sizes.bucket_range(bucket).end.next_power_of_two();
+                if suggested * 4 <= self.packet_size {
+                    recommendations.push(Recommendation {
+                        suggestion: Suggestion::SmallerPacketSize { suggested },
+                        reason: format!(
+                            "99% of payloads are under {} bytes, packets hold {}",
+                            sizes.bucket_range(bucket).end,
+                            self.packet_size
+                        ),
+                    });
+                }
+            }
+        }
+
+        recommendations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Suggestion, TuningAdvisor};
+    use crate::stats::{LinkStats, PayloadHistogram};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_busy_link_with_small_payloads() {
+        let start = Instant::now();
+        let mut advisor = TuningAdvisor::new(1024);
+
+        advisor.sample(LinkStats::default(), None, 10, start);
+        let stats = LinkStats {
+            session_rx_packets: 5000,
+            payload_sizes: PayloadHistogram {
+                bucket_width: 65,
+                counts: [vec![5000], vec![0; 15]].concat(),
+                full: 0,
+            },
+            ..Default::default()
+        };
+        advisor.sample(stats, None, 2000, start + Duration::from_secs(1));
+
+        let suggestions: Vec<Suggestion> = advisor
+            .recommend()
+            .into_iter()
+            .map(|recommendation| recommendation.suggestion)
+            .collect();
+        assert_eq!(
+            suggestions,
+            [
+                Suggestion::BoundQueue {
+                    high_water: 1000,
+                    low_water: 250
+                },
+                Suggestion::BatchDelivery {
+                    max_packets: 50,
+                    max_delay: Duration::from_millis(10)
+                },
+                Suggestion::SmallerPacketSize { suggested: 128 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    framing::Framing,
+    options::{ConnectOptions, SoftFlowControl},
+    port::OpenPort,
+    scope::{self, LinkScope, ScopeHandle},
+    FlemSerialError, FlemSerialPort,
+};
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        mpsc::{self, Receiver, RecvError, RecvTimeoutError, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Longest line kept, longer lines are split. NMEA sentences are at most 82
+/// characters and AT responses are far shorter.
+pub const MAX_LINE_LENGTH: usize = 1024;
+
+/// A line based ASCII device such as a GPS (NMEA) or an AT command modem,
+/// managed alongside FLEM links by [crate::manager::FlemDeviceManager].
+pub struct AsciiLink {
+    tx_port: Arc<Mutex<FlemSerialPort>>,
+    port_name: String,
+    continue_listening: Arc<Mutex<bool>>,
+    line_ending: String,
+    pub(crate) scope: Option<ScopeHandle>,
+}
+
+/// Lines received from an [AsciiLink], without their line ending.
+pub struct AsciiRx {
+    rx_listener_handle: JoinHandle<()>,
+    rx_line_queue: Receiver<String>,
+}
+
+impl AsciiRx {
+    pub fn queue(&self) -> &Receiver<String> {
+        &self.rx_line_queue
+    }
+
+    pub fn recv(&self) -> Result<String, RecvError> {
+        self.rx_line_queue.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<String, TryRecvError> {
+        self.rx_line_queue.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<String, RecvTimeoutError> {
+        self.rx_line_queue.recv_timeout(timeout)
+    }
+
+    /// Waits for the listener thread to exit. Call [AsciiLink::unlisten]
+    /// first or this will block forever.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+impl AsciiLink {
+    /// Opens `port_name` at `baud`, 8N1 without flow control. Lines are sent
+    /// with a "\r\n" ending.
+    pub fn connect(port_name: &str, baud: u32) -> Result<Self, FlemSerialError> {
+        Self::connect_with_options(port_name, baud, &ConnectOptions::default())
+    }
+
+    /// Like `connect`, with the line settings and flow control of
+    /// `options`, for instruments that need 7E1 or RTS/CTS. Framing and
+    /// device verification don't apply to text lines and are ignored.
+    pub fn connect_with_options(
+        port_name: &str,
+        baud: u32,
+        options: &ConnectOptions,
+    ) -> Result<Self, FlemSerialError> {
+        if options.hardware_flow_control && options.soft_flow_control != SoftFlowControl::Off {
+            return Err(FlemSerialError::ConflictingFlowControl);
+        }
+        let options = ConnectOptions {
+            framing: Framing::None,
+            ..options.clone()
+        };
+
+        let listed = serialport::available_ports()
+            .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))?
+            .iter()
+            .filter(|port| port.port_name == port_name)
+            .count();
+
+        match listed {
+            0 => Err(FlemSerialError::NoDeviceFoundByThatName),
+            1 => OpenPort::open(port_name, baud, &options)
+                .map(|port| Self::from_open_port(port_name, port)),
+            _ => Err(FlemSerialError::MultipleDevicesFoundByThatName),
+        }
+    }
+
+    /// Wraps an already opened port with a short read timeout.
+    pub fn from_open_port(port_name: &str, port: OpenPort) -> Self {
+        Self {
+            tx_port: Arc::new(Mutex::new(port.port)),
+            port_name: port_name.to_string(),
+            continue_listening: Arc::new(Mutex::new(false)),
+            line_ending: "\r\n".to_string(),
+            scope: None,
+        }
+    }
+
+    /// Tracks the listener threads spawned from now on in `scope`, see
+    /// [LinkScope].
+    pub fn set_scope(&mut self, scope: &LinkScope) {
+        self.scope = Some(scope.handle());
+    }
+
+    pub fn clear_scope(&mut self) {
+        self.scope = None;
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Ending appended by [AsciiLink::send_line], "\r\n" by default.
+    pub fn set_line_ending(&mut self, line_ending: &str) {
+        self.line_ending = line_ending.to_string();
+    }
+
+    /// Writes `line` followed by the line ending.
+    pub fn send_line(&mut self, line: &str) -> Result<(), FlemSerialError> {
+        let mut port = self
+            .tx_port
+            .lock()
+            .map_err(|_| FlemSerialError::WriteFailed(io::Error::other("port lock poisoned")))?;
+        port.write_all(line.as_bytes())
+            .and_then(|_| port.write_all(self.line_ending.as_bytes()))
+            .and_then(|_| port.flush())
+            .map_err(FlemSerialError::WriteFailed)
+    }
+
+    /// Spawns a thread that splits received bytes into lines on '\n',
+    /// dropping a trailing '\r'. Invalid UTF-8 is replaced.
+    pub fn listen(&mut self) -> AsciiRx {
+        let (line_queue, rx) = mpsc::channel();
+        *self.continue_listening.lock().unwrap() = true;
+
+        let mut rx_port = self
+            .tx_port
+            .lock()
+            .unwrap()
+            .try_clone()
+            .expect("Couldn't clone serial port for rx_port");
+        let continue_listening = self.continue_listening.clone();
+        let stop_listening = self.continue_listening.clone();
+        let stop = move || *stop_listening.lock().unwrap() = false;
+
+        let handle = scope::spawn_scoped(
+            "ascii",
+            Some(self.port_name.clone()),
+            None,
+            self.scope.as_ref(),
+            stop,
+            move || {
+                let mut splitter = LineSplitter::default();
+                let mut rx_buffer = [0u8; 64];
+                while *continue_listening.lock().unwrap() {
+                    let bytes_read = match rx_port.read(&mut rx_buffer) {
+                        Ok(0) | Err(_) => {
+                            thread::sleep(Duration::from_millis(10));
+                            continue;
+                        }
+                        Ok(bytes_read) => bytes_read,
+                    };
+                    for line in splitter.push(&rx_buffer[..bytes_read]) {
+                        if line_queue.send(line).is_err() {
+                            return;
+                        }
+                    }
+                }
+            },
+        );
+
+        AsciiRx {
+            rx_listener_handle: handle,
+            rx_line_queue: rx,
+        }
+    }
+
+    pub fn unlisten(&mut self) {
+        *self.continue_listening.lock().unwrap() = false;
+    }
+}
+
+/// Splits a byte stream into lines.
+#[derive(Default)]
+struct LineSplitter {
+    line: Vec<u8>,
+}
+
+impl LineSplitter {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for byte in bytes.iter() {
+            if *byte == b'\n' {
+                if self.line.last() == Some(&b'\r') {
+                    self.line.pop();
+                }
+                lines.push(String::from_utf8_lossy(&self.line).into_owned());
+                self.line.clear();
+            } else {
+                self.line.push(*byte);
+                if self.line.len() >= MAX_LINE_LENGTH {
+                    lines.push(String::from_utf8_lossy(&self.line).into_owned());
+                    self.line.clear();
+                }
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineSplitter;
+
+    #[test]
+    fn test_lines_split_across_reads() {
+        let mut splitter = LineSplitter::default();
+        assert!(splitter.push(b"$GPGGA,1").is_empty());
+        assert_eq!(splitter.push(b"23\r\nOK\r\n\n"), ["$GPGGA,123", "OK", ""]);
+    }
+}
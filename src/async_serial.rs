@@ -0,0 +1,193 @@
+use crate::FlemSerialError;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, WriteHalf},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Received packets buffered between the reader task and `recv`. The reader
+/// stops reading while the buffer is full.
+pub const ASYNC_QUEUE_LENGTH: usize = 1024;
+
+/// A FLEM link driven by the Tokio runtime instead of a listener thread.
+///
+/// `connect` spawns a reader task on the current runtime that parses
+/// packets as bytes arrive; `recv` awaits them. Sends go straight to the
+/// port. The task stops when the port fails, on `disconnect`, or when the
+/// link is dropped.
+pub struct AsyncFlemSerial<const T: usize> {
+    writer: Option<WriteHalf<SerialStream>>,
+    packets: Option<mpsc::Receiver<flem::Packet<T>>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl<const T: usize> Default for AsyncFlemSerial<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const T: usize> AsyncFlemSerial<T> {
+    pub fn new() -> Self {
+        Self {
+            writer: None,
+            packets: None,
+            reader: None,
+        }
+    }
+
+    /// Opens `port_name` at `baud` with the FLEM line settings and starts
+    /// the reader task. Must be called from within a Tokio runtime.
+    pub async fn connect(&mut self, port_name: &str, baud: u32) -> Result<(), FlemSerialError> {
+        let listed = tokio_serial::available_ports()
+            .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))?
+            .iter()
+            .filter(|port| port.port_name == port_name)
+            .count();
+        match listed {
+            0 if crate::unlisted_port_exists(port_name) => {}
+            0 => return Err(FlemSerialError::NoDeviceFoundByThatName),
+            1 => {}
+            _ => return Err(FlemSerialError::MultipleDevicesFoundByThatName),
+        }
+
+        let stream = tokio_serial::new(port_name, baud)
+            .flow_control(tokio_serial::FlowControl::None)
+            .parity(tokio_serial::Parity::None)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .stop_bits(tokio_serial::StopBits::One)
+            .open_native_async()
+            .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))?;
+
+        self.disconnect().await;
+
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (packet_tx, packets) = mpsc::channel(ASYNC_QUEUE_LENGTH);
+
+        self.reader = Some(tokio::spawn(async move {
+            let mut rx_packet = flem::Packet::<T>::new();
+            let mut rx_buffer = [0u8; T];
+            loop {
+                let length = match reader.read(&mut rx_buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(length) => length,
+                };
+                for byte in &rx_buffer[..length] {
+                    match rx_packet.add_byte(*byte) {
+                        flem::Status::PacketReceived => {
+                            if packet_tx.send(rx_packet.clone()).await.is_err() {
+                                // The link was dropped
+                                return;
+                            }
+                            rx_packet.reset_lazy();
+                        }
+                        flem::Status::PacketBuilding => {}
+                        _ => rx_packet.reset_lazy(),
+                    }
+                }
+            }
+        }));
+        self.writer = Some(writer);
+        self.packets = Some(packets);
+
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub async fn send(&mut self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        let writer = self.writer.as_mut().ok_or(FlemSerialError::NotConnected)?;
+        writer
+            .write_all(packet.bytes())
+            .await
+            .map_err(FlemSerialError::WriteFailed)?;
+        writer.flush().await.map_err(FlemSerialError::WriteFailed)
+    }
+
+    /// Waits for the next packet. Returns None once the reader task has
+    /// stopped and all received packets were taken, or if not connected.
+    pub async fn recv(&mut self) -> Option<flem::Packet<T>> {
+        self.packets.as_mut()?.recv().await
+    }
+
+    /// Stops the reader task and closes the port.
+    pub async fn disconnect(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+        self.packets = None;
+        if let Some(mut writer) = self.writer.take() {
+            let _ = writer.flush().await;
+        }
+    }
+}
+
+impl<const T: usize> Drop for AsyncFlemSerial<T> {
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncFlemSerial;
+    use crate::FlemSerialError;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_a_link_that_never_connected_sends_and_receives_nothing() {
+        block_on(async {
+            let mut serial = AsyncFlemSerial::<64>::new();
+            assert!(matches!(
+                serial.connect("/dev/flem-no-such-port", 115200).await,
+                Err(FlemSerialError::NoDeviceFoundByThatName)
+            ));
+
+            assert!(!serial.is_connected());
+            assert!(matches!(
+                serial.send(&flem::Packet::<64>::new()).await,
+                Err(FlemSerialError::NotConnected)
+            ));
+            assert!(serial.recv().await.is_none());
+        });
+    }
+
+    #[cfg(all(unix, feature = "link"))]
+    #[test]
+    fn test_packets_round_trip_until_disconnect() {
+        use crate::virtual_port::VirtualDevice;
+
+        let device = VirtualDevice::<64>::echo().unwrap();
+        block_on(async {
+            let mut serial = AsyncFlemSerial::<64>::new();
+            serial.connect(device.port_name(), 115200).await.unwrap();
+            assert!(serial.is_connected());
+
+            let mut packet = flem::Packet::<64>::new();
+            packet.set_request(0x30);
+            packet.add_data(&[1, 2, 3]).unwrap();
+            packet.pack();
+            serial.send(&packet).await.unwrap();
+
+            let echoed = serial.recv().await.unwrap();
+            assert_eq!(echoed.get_request(), 0x30);
+            assert_eq!(echoed.get_data(), [1, 2, 3]);
+
+            serial.disconnect().await;
+            assert!(!serial.is_connected());
+            assert!(serial.recv().await.is_none());
+        });
+    }
+}
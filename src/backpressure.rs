@@ -0,0 +1,88 @@
+/// Watermarks and device requests used to throttle a device when the host
+/// side consumer falls behind.
+///
+/// When more than `high_water` packets are waiting in the receive queue the
+/// `pause` packet is sent to the device. Once the queue drains to
+/// `low_water` or fewer packets the `resume` packet is sent.
+#[derive(Clone)]
+pub struct Backpressure<const T: usize> {
+    pub high_water: usize,
+    pub low_water: usize,
+    pub pause: flem::Packet<T>,
+    pub resume: flem::Packet<T>,
+}
+
+/// Request the listener should send to the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackpressureAction {
+    Pause,
+    Resume,
+}
+
+impl<const T: usize> Backpressure<T> {
+    pub fn new(
+        high_water: usize,
+        low_water: usize,
+        pause: flem::Packet<T>,
+        resume: flem::Packet<T>,
+    ) -> Self {
+        Self {
+            high_water,
+            low_water,
+            pause,
+            resume,
+        }
+    }
+
+    pub fn packet(&self, action: BackpressureAction) -> &flem::Packet<T> {
+        match action {
+            BackpressureAction::Pause => &self.pause,
+            BackpressureAction::Resume => &self.resume,
+        }
+    }
+}
+
+/// Hysteresis between the high and low watermarks.
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct BackpressureState {
+    paused: bool,
+}
+
+#[cfg(feature = "link")]
+impl BackpressureState {
+    /// Returns the action to take for the current queue depth, if any.
+    pub(crate) fn update(
+        &mut self,
+        depth: usize,
+        high_water: usize,
+        low_water: usize,
+    ) -> Option<BackpressureAction> {
+        if !self.paused && depth > high_water {
+            self.paused = true;
+            Some(BackpressureAction::Pause)
+        } else if self.paused && depth <= low_water {
+            self.paused = false;
+            Some(BackpressureAction::Resume)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{BackpressureAction, BackpressureState};
+
+    #[test]
+    fn test_backpressure_hysteresis() {
+        let mut state = BackpressureState::default();
+
+        assert_eq!(state.update(10, 10, 2), None);
+        assert_eq!(state.update(11, 10, 2), Some(BackpressureAction::Pause));
+        assert_eq!(state.update(20, 10, 2), None);
+        assert_eq!(state.update(5, 10, 2), None);
+        assert_eq!(state.update(2, 10, 2), Some(BackpressureAction::Resume));
+        assert_eq!(state.update(0, 10, 2), None);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::clock::Clock;
+use crate::events::LinkEvent;
+use crate::listener::{ListenerShared, ListenerThread};
+use crate::received::ReceivedPacket;
+use crate::stats::{LinkRates, LinkStats};
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Collects packets on the listener thread and sends them as one message
+/// once `max_packets` have been collected or the oldest packet has waited
+/// `max_delay`. Each packet is stamped when it is parsed, not when its
+/// batch is sent.
+pub(crate) struct Batcher<const T: usize> {
+    sender: Sender<Vec<ReceivedPacket<T>>>,
+    batch: Vec<ReceivedPacket<T>>,
+    max_packets: usize,
+    max_delay: Duration,
+    started: Option<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<const T: usize> Batcher<T> {
+    pub(crate) fn new(
+        sender: Sender<Vec<ReceivedPacket<T>>>,
+        max_packets: usize,
+        max_delay: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let max_packets = max_packets.max(1);
+        Self {
+            sender,
+            batch: Vec::with_capacity(max_packets),
+            max_packets,
+            max_delay,
+            started: None,
+            clock,
+        }
+    }
+
+    pub(crate) fn push(&mut self, packet: flem::Packet<T>) -> Result<(), ()> {
+        if self.batch.is_empty() {
+            self.started = Some(self.clock.now());
+        }
+        self.batch
+            .push(ReceivedPacket::stamp(packet, self.clock.as_ref()));
+
+        if self.batch.len() >= self.max_packets {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn tick(&mut self) -> Result<(), ()> {
+        match self.started {
+            Some(started) if self.clock.now() - started >= self.max_delay => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        self.started = None;
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.max_packets));
+        self.sender.send(batch).map_err(|_| ())
+    }
+}
+
+/// Receive handle returned by [crate::FlemSerial::listen_batched]. Each
+/// message holds one or more packets in the order they were received, each
+/// stamped like those of [crate::FlemSerial::listen_timestamped]. Dropping
+/// it stops its listener.
+pub struct FlemBatchRx<const T: usize> {
+    pub(crate) rx_listener_handle: ListenerThread,
+    pub(crate) rx_batch_queue: Receiver<Vec<ReceivedPacket<T>>>,
+    pub(crate) events: Receiver<LinkEvent>,
+    pub(crate) shared: ListenerShared,
+}
+
+impl<const T: usize> FlemBatchRx<T> {
+    /// Raw access to the batch queue. Batches taken directly from the queue
+    /// are not counted by [FlemBatchRx::queue_depth].
+    pub fn queue(&self) -> &Receiver<Vec<ReceivedPacket<T>>> {
+        &self.rx_batch_queue
+    }
+
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    /// Blocks until a batch is received.
+    pub fn recv(&self) -> Result<Vec<ReceivedPacket<T>>, RecvError> {
+        let batch = self.rx_batch_queue.recv()?;
+        self.shared
+            .queue_depth
+            .fetch_sub(batch.len(), Ordering::AcqRel);
+        Ok(batch)
+    }
+
+    /// Returns a batch if one is waiting.
+    pub fn try_recv(&self) -> Result<Vec<ReceivedPacket<T>>, TryRecvError> {
+        let batch = self.rx_batch_queue.try_recv()?;
+        self.shared
+            .queue_depth
+            .fetch_sub(batch.len(), Ordering::AcqRel);
+        Ok(batch)
+    }
+
+    /// Blocks until a batch is received or `timeout` elapses.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Vec<ReceivedPacket<T>>, RecvTimeoutError> {
+        let batch = self.rx_batch_queue.recv_timeout(timeout)?;
+        self.shared
+            .queue_depth
+            .fetch_sub(batch.len(), Ordering::AcqRel);
+        Ok(batch)
+    }
+
+    /// Number of packets delivered by the listener and not yet received,
+    /// including packets still being collected into a batch.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        self.rx_listener_handle.handle()
+    }
+
+    /// Waits for the listener thread to exit.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Batcher;
+    use crate::clock::{Clock, MockClock};
+    use std::{
+        sync::{mpsc, Arc},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_batcher_flushes_on_count_and_delay() {
+        let clock = Arc::new(MockClock::new());
+        let (tx, rx) = mpsc::channel();
+        let mut batcher = Batcher::<8>::new(tx, 2, Duration::from_millis(5), clock.clone());
+
+        batcher.push(flem::Packet::new()).unwrap();
+        assert!(rx.try_recv().is_err());
+        batcher.push(flem::Packet::new()).unwrap();
+        assert_eq!(rx.try_recv().unwrap().len(), 2);
+
+        let parsed_at = clock.now();
+        batcher.push(flem::Packet::new()).unwrap();
+        batcher.tick().unwrap();
+        assert!(rx.try_recv().is_err());
+        clock.advance(Duration::from_millis(5));
+        batcher.tick().unwrap();
+        let batch = rx.try_recv().unwrap();
+        assert_eq!(batch.len(), 1);
+        // Stamped when pushed, not when the batch was sent
+        assert_eq!(batch[0].received_at, parsed_at);
+    }
+}
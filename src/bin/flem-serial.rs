@@ -0,0 +1,241 @@
+//! Talks to a FLEM device from the command line, for debugging in the field
+//! without writing code.
+//!
+//! ```text
+//! flem-serial list
+//! flem-serial send <port> <baud> <request> [hex payload]
+//! flem-serial session <port> <baud>
+//! ```
+//!
+//! Requests are decimal or `0x` prefixed hex. Payloads are hex bytes,
+//! optionally separated by spaces. A session reads one `<request> [hex
+//! payload]` per line from stdin, printing every packet received, until
+//! `quit` or the end of input.
+
+use flem_serial_rs::{request::RequestError, FlemSerial};
+use std::{
+    env,
+    fmt::Write as _,
+    io::{self, BufRead},
+    process::ExitCode,
+    time::Duration,
+};
+
+const PACKET_SIZE: usize = 512;
+
+/// How long `send` waits for the response.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const USAGE: &str = "usage:
+  flem-serial list
+  flem-serial send <port> <baud> <request> [hex payload]
+  flem-serial session <port> <baud>";
+
+fn parse_request(text: &str) -> Result<u8, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid request '{}', expected 0-255 or 0x00-0xff", text))
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.split_whitespace().collect();
+    if !digits.is_ascii() {
+        return Err(format!("invalid hex payload '{}'", text));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in '{}'", text));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex byte '{}'", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+fn response_name(response: u8) -> String {
+    match response {
+        flem::Response::SUCCESS => "SUCCESS".to_string(),
+        flem::Response::BUSY => "BUSY".to_string(),
+        flem::Response::ERROR => "ERROR".to_string(),
+        other => format!("0x{:02x}", other),
+    }
+}
+
+/// A header line, then the payload as a hex dump of 16 bytes per line with
+/// printable ASCII alongside.
+fn format_packet<const T: usize>(packet: &flem::Packet<T>) -> String {
+    let data = packet.get_data();
+    let mut text = format!(
+        "request 0x{:02x}  response {}  {} bytes",
+        packet.get_request(),
+        response_name(packet.get_response()),
+        data.len()
+    );
+    for (line, bytes) in data.chunks(16).enumerate() {
+        let _ = write!(text, "\n  {:04x} ", line * 16);
+        for byte in bytes {
+            let _ = write!(text, " {:02x}", byte);
+        }
+        text.push_str(&"   ".repeat(16 - bytes.len()));
+        text.push_str("  ");
+        text.extend(bytes.iter().map(|byte| match byte {
+            0x20..=0x7e => *byte as char,
+            _ => '.',
+        }));
+    }
+    text
+}
+
+/// Parses `<request> [hex payload]` into a packed packet.
+fn build_packet(request: &str, payload: &str) -> Result<flem::Packet<PACKET_SIZE>, String> {
+    let mut packet = flem::Packet::new();
+    packet.set_request(parse_request(request)?);
+    packet
+        .add_data(&parse_hex(payload)?)
+        .map_err(|_| format!("payload longer than {} bytes", PACKET_SIZE))?;
+    packet.pack();
+    Ok(packet)
+}
+
+fn connect(port: &str, baud: &str) -> Result<FlemSerial<PACKET_SIZE>, String> {
+    let baud: u32 = baud
+        .parse()
+        .map_err(|_| format!("invalid baud rate '{}'", baud))?;
+    let mut serial = FlemSerial::new();
+    serial
+        .connect(&port.to_string(), baud)
+        .map_err(|error| format!("couldn't connect to {}: {}", port, error))?;
+    Ok(serial)
+}
+
+fn list() -> Result<(), String> {
+    let ports = FlemSerial::<PACKET_SIZE>::new()
+        .list_ports()
+        .map_err(|error| format!("couldn't list ports: {}", error))?;
+    if ports.is_empty() {
+        println!("no serial ports found");
+    }
+    for port in ports {
+        match port.usb {
+            Some(usb) => println!(
+                "{}  {:04x}:{:04x}  {}",
+                port.name,
+                usb.vid,
+                usb.pid,
+                usb.product.unwrap_or_default()
+            ),
+            None => println!("{}", port.name),
+        }
+    }
+    Ok(())
+}
+
+fn send(port: &str, baud: &str, request: &str, payload: &str) -> Result<(), String> {
+    let packet = build_packet(request, payload)?;
+    let mut serial = connect(port, baud)?;
+    let _rx = serial
+        .listen()
+        .map_err(|error| format!("couldn't listen on {}: {}", port, error))?;
+
+    match serial.request(&packet, RESPONSE_TIMEOUT) {
+        Ok(response) => {
+            println!("{}", format_packet(&response));
+            Ok(())
+        }
+        Err(RequestError::Timeout) => Err(format!(
+            "no response within {} ms",
+            RESPONSE_TIMEOUT.as_millis()
+        )),
+        Err(error) => Err(format!("request failed: {:?}", error)),
+    }
+}
+
+fn session(port: &str, baud: &str) -> Result<(), String> {
+    let mut serial = connect(port, baud)?;
+    let _rx = serial
+        .listen_with_handler(|packet| println!("{}", format_packet(&packet)))
+        .map_err(|error| format!("couldn't listen on {}: {}", port, error))?;
+    println!(
+        "connected to {}, enter <request> [hex payload] or quit",
+        port
+    );
+
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|error| format!("couldn't read stdin: {}", error))?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "quit" {
+            break;
+        }
+
+        let (request, payload) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match build_packet(request, payload) {
+            Ok(packet) => {
+                if let Err(error) = serial.send(&packet) {
+                    eprintln!("send failed: {}", error);
+                }
+            }
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["list"] => list(),
+        ["send", port, baud, request, payload @ ..] => {
+            send(port, baud, request, &payload.join(" "))
+        }
+        ["session", port, baud] => session(port, baud),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_packet, format_packet, parse_hex, parse_request};
+
+    #[test]
+    fn test_requests_and_payloads_parse() {
+        assert_eq!(parse_request("0x1f"), Ok(0x1f));
+        assert_eq!(parse_request("31"), Ok(31));
+        assert!(parse_request("256").is_err());
+
+        assert_eq!(parse_hex("01 ff 7a"), Ok(vec![0x01, 0xff, 0x7a]));
+        assert_eq!(parse_hex("01ff7a"), Ok(vec![0x01, 0xff, 0x7a]));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+        assert!(parse_hex("1ff").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_packets_print_as_a_hex_dump() {
+        let mut packet = build_packet("0x05", "48 69 00").unwrap();
+        packet.set_response(flem::Response::ERROR);
+        assert_eq!(
+            format_packet(&packet),
+            format!(
+                "request 0x05  response ERROR  3 bytes\n  0000  48 69 00{}  Hi.",
+                "   ".repeat(13)
+            )
+        );
+    }
+}
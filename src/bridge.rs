@@ -0,0 +1,230 @@
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+use crate::{hooks, FlemRx, FlemSerial};
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::VecDeque,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// One end of a [Bridge].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    fn other(&self) -> Side {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeError {
+    /// Packets forwarded out of `side` kept arriving back on `side`, so the
+    /// topology contains a loop. Forwarding was stopped.
+    LoopDetected { side: Side, echoes: u32 },
+}
+
+/// Detects forwarding loops by remembering recently forwarded packets and
+/// counting those that come straight back in on the side they were sent
+/// out of.
+pub struct LoopGuard {
+    window: Duration,
+    max_echoes: u32,
+    recent: VecDeque<(Instant, Side, u64)>,
+    echoes: u32,
+}
+
+impl LoopGuard {
+    /// Declares a loop once more than `max_echoes` packets return within
+    /// `window` of being forwarded.
+    pub fn new(window: Duration, max_echoes: u32) -> Self {
+        Self {
+            window,
+            max_echoes,
+            recent: VecDeque::new(),
+            echoes: 0,
+        }
+    }
+
+    /// Checks a packet that arrived on `from` before forwarding it to the
+    /// other side.
+    pub fn check(&mut self, from: Side, bytes: &[u8], now: Instant) -> Result<(), BridgeError> {
+        while let Some((sent, _, _)) = self.recent.front() {
+            if now.duration_since(*sent) > self.window {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.recent.is_empty() {
+            self.echoes = 0;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        let fingerprint = hasher.finish();
+
+        if self
+            .recent
+            .iter()
+            .any(|(_, to, hash)| *to == from && *hash == fingerprint)
+        {
+            self.echoes += 1;
+            if self.echoes > self.max_echoes {
+                return Err(BridgeError::LoopDetected {
+                    side: from,
+                    echoes: self.echoes,
+                });
+            }
+        }
+
+        self.recent.push_back((now, from.other(), fingerprint));
+        Ok(())
+    }
+}
+
+type BridgeHalf<const T: usize> = JoinHandle<(FlemRx<T>, FlemSerial<T>)>;
+
+/// Forwards every packet received on one link to the other, in both
+/// directions, stopping with [BridgeError::LoopDetected] if the packets
+/// start echoing back.
+pub struct Bridge<const T: usize> {
+    running: Arc<AtomicBool>,
+    error: Arc<Mutex<Option<BridgeError>>>,
+    a_to_b: BridgeHalf<T>,
+    b_to_a: BridgeHalf<T>,
+}
+
+impl<const T: usize> Bridge<T> {
+    /// Starts forwarding between two connected, listening links.
+    pub fn start(
+        a: (FlemSerial<T>, FlemRx<T>),
+        b: (FlemSerial<T>, FlemRx<T>),
+        guard: LoopGuard,
+    ) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let error = Arc::new(Mutex::new(None));
+        let guard = Arc::new(Mutex::new(guard));
+
+        let (serial_a, rx_a) = a;
+        let (serial_b, rx_b) = b;
+
+        let a_to_b = Self::spawn_half(Side::A, rx_a, serial_b, &running, &error, &guard);
+        let b_to_a = Self::spawn_half(Side::B, rx_b, serial_a, &running, &error, &guard);
+
+        Self {
+            running,
+            error,
+            a_to_b,
+            b_to_a,
+        }
+    }
+
+    fn spawn_half(
+        from: Side,
+        rx: FlemRx<T>,
+        mut to: FlemSerial<T>,
+        running: &Arc<AtomicBool>,
+        error: &Arc<Mutex<Option<BridgeError>>>,
+        guard: &Arc<Mutex<LoopGuard>>,
+    ) -> BridgeHalf<T> {
+        let running = running.clone();
+        let error = error.clone();
+        let guard = guard.clone();
+
+        hooks::spawn_supervised("bridge", None, None, move || {
+            while running.load(Ordering::Acquire) {
+                match rx.recv_timeout(Duration::from_millis(50)) {
+                    Ok(packet) => {
+                        let checked =
+                            guard
+                                .lock()
+                                .unwrap()
+                                .check(from, packet.bytes(), Instant::now());
+                        match checked {
+                            Ok(()) => {
+                                let _ = to.send(&packet);
+                            }
+                            Err(loop_error) => {
+                                *error.lock().unwrap() = Some(loop_error);
+                                running.store(false, Ordering::Release);
+                            }
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            (rx, to)
+        })
+    }
+
+    /// True until the bridge is stopped or a loop is detected.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    /// Stops forwarding and hands both links back as `(a, b)`, along with
+    /// the error that stopped the bridge, if any.
+    #[allow(clippy::type_complexity)]
+    pub fn stop(
+        self,
+    ) -> (
+        (FlemSerial<T>, FlemRx<T>),
+        (FlemSerial<T>, FlemRx<T>),
+        Option<BridgeError>,
+    ) {
+        self.running.store(false, Ordering::Release);
+
+        let (rx_a, serial_b) = self.a_to_b.join().unwrap();
+        let (rx_b, serial_a) = self.b_to_a.join().unwrap();
+        let error = self.error.lock().unwrap().take();
+
+        ((serial_a, rx_a), (serial_b, rx_b), error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BridgeError, LoopGuard, Side};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_loop_guard_detects_echoes() {
+        let mut guard = LoopGuard::new(Duration::from_millis(100), 1);
+        let now = Instant::now();
+
+        // Forwarded A -> B, then the same bytes come back in on B
+        guard.check(Side::A, &[1, 2, 3], now).unwrap();
+        guard.check(Side::B, &[1, 2, 3], now).unwrap();
+        assert_eq!(
+            guard.check(Side::A, &[1, 2, 3], now),
+            Err(BridgeError::LoopDetected {
+                side: Side::A,
+                echoes: 2
+            })
+        );
+
+        // Unrelated traffic after the window has passed is fine
+        let later = now + Duration::from_millis(200);
+        guard.check(Side::A, &[4], later).unwrap();
+        guard.check(Side::A, &[4], later).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{hooks, wire::WirePacket, FlemRx, FlemSerial};
+use rumqttc::{Client, Connection, Event, MqttOptions, Packet, QoS};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long the bridge's threads wait for traffic before checking whether
+/// they should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Requests from the bridge to the MQTT event loop that can be waiting.
+const REQUEST_CAPACITY: usize = 64;
+
+/// Where [MqttBridge] publishes and what it subscribes to.
+///
+/// Packets are published as JSON [WirePacket]s. Messages on the command
+/// topic must be JSON [WirePacket]s too, their checksum is computed by the
+/// bridge. Other messages are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttTopics {
+    /// Topic for received packets, with `{request}` replaced by the
+    /// packet's request code in decimal.
+    pub telemetry: String,
+    /// Topics for particular request codes, used instead of `telemetry`.
+    pub by_request: HashMap<u8, String>,
+    /// Topic whose messages are transmitted to the device, without
+    /// wildcards.
+    pub command: String,
+    pub qos: QoS,
+}
+
+impl Default for MqttTopics {
+    fn default() -> Self {
+        Self {
+            telemetry: "flem/telemetry/{request}".to_string(),
+            by_request: HashMap::new(),
+            command: "flem/command".to_string(),
+            qos: QoS::AtLeastOnce,
+        }
+    }
+}
+
+impl MqttTopics {
+    /// Topic packets with `request` are published to.
+    pub fn topic_for(&self, request: u8) -> String {
+        match self.by_request.get(&request) {
+            Some(topic) => topic.clone(),
+            None => self.telemetry.replace("{request}", &request.to_string()),
+        }
+    }
+}
+
+/// Parses a message from the command topic.
+pub(crate) fn decode_command<const T: usize>(payload: &[u8]) -> Option<flem::Packet<T>> {
+    serde_json::from_slice::<WirePacket>(payload)
+        .ok()?
+        .to_packet()
+}
+
+/// Publishes every packet received on a listening link to MQTT and
+/// transmits the messages of a command topic, see [MqttTopics]. Reconnects
+/// to the broker by itself, resubscribing each time; packets received while
+/// the broker is unreachable are dropped once the client's request queue
+/// is full.
+pub struct MqttBridge<const T: usize> {
+    running: Arc<AtomicBool>,
+    client: Client,
+    serial: Arc<Mutex<FlemSerial<T>>>,
+    forward: JoinHandle<FlemRx<T>>,
+    commands: JoinHandle<()>,
+}
+
+impl<const T: usize> MqttBridge<T> {
+    /// Connects to the broker described by `options` and starts forwarding
+    /// between it and a connected, listening link.
+    pub fn start(
+        options: MqttOptions,
+        link: (FlemSerial<T>, FlemRx<T>),
+        topics: MqttTopics,
+    ) -> Self {
+        let (client, connection) = Client::new(options, REQUEST_CAPACITY);
+        let running = Arc::new(AtomicBool::new(true));
+        let (serial, rx) = link;
+        let serial = Arc::new(Mutex::new(serial));
+
+        let forward = {
+            let running = running.clone();
+            let client = client.clone();
+            let topics = topics.clone();
+            hooks::spawn_supervised("mqtt", None, None, move || {
+                while running.load(Ordering::Acquire) {
+                    match rx.recv_timeout(POLL_INTERVAL) {
+                        Ok(packet) => {
+                            let Ok(json) = serde_json::to_vec(&WirePacket::from(&packet)) else {
+                                continue;
+                            };
+                            let topic = topics.topic_for(packet.get_request());
+                            let _ = client.try_publish(topic, topics.qos, false, json);
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                rx
+            })
+        };
+
+        let commands = {
+            let running = running.clone();
+            let client = client.clone();
+            let serial = serial.clone();
+            hooks::spawn_supervised("mqtt", None, None, move || {
+                Self::run_connection(connection, &client, &topics, &serial, &running)
+            })
+        };
+
+        Self {
+            running,
+            client,
+            serial,
+            forward,
+            commands,
+        }
+    }
+
+    /// Drives the MQTT event loop, transmitting command messages.
+    fn run_connection(
+        mut connection: Connection,
+        client: &Client,
+        topics: &MqttTopics,
+        serial: &Mutex<FlemSerial<T>>,
+        running: &AtomicBool,
+    ) {
+        while running.load(Ordering::Acquire) {
+            match connection.recv_timeout(POLL_INTERVAL) {
+                // Subscriptions don't outlive the broker connection
+                Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                    let _ = client.try_subscribe(topics.command.as_str(), topics.qos);
+                }
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    if publish.topic != topics.command {
+                        continue;
+                    }
+                    if let Some(packet) = decode_command::<T>(&publish.payload) {
+                        let _ = serial.lock().unwrap().send(&packet);
+                    }
+                }
+                Ok(Ok(_)) | Err(_) => {}
+                // The next poll reconnects, don't spin while the broker is
+                // unreachable
+                Ok(Err(_)) => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Disconnects from the broker and hands the link back.
+    pub fn stop(self) -> (FlemSerial<T>, FlemRx<T>) {
+        self.running.store(false, Ordering::Release);
+        let _ = self.client.try_disconnect();
+
+        let rx = self.forward.join().unwrap();
+        self.commands.join().unwrap();
+        let serial = match Arc::try_unwrap(self.serial) {
+            Ok(serial) => serial.into_inner().unwrap(),
+            Err(_) => unreachable!("the command thread has been joined"),
+        };
+        (serial, rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_command, MqttTopics};
+
+    #[test]
+    fn test_topics_by_request_override_the_template() {
+        let mut topics = MqttTopics::default();
+        topics.by_request.insert(7, "lab/temperature".to_string());
+
+        assert_eq!(topics.topic_for(7), "lab/temperature");
+        assert_eq!(topics.topic_for(12), "flem/telemetry/12");
+    }
+
+    #[test]
+    fn test_commands_are_json_wire_packets() {
+        let packet = decode_command::<16>(br#"{"request":5,"response":0,"data":[1,2]}"#).unwrap();
+        assert_eq!(packet.get_request(), 5);
+        assert_eq!(packet.get_data(), [1, 2]);
+
+        assert!(decode_command::<16>(b"\x55\x55").is_none());
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{hooks, wire::WirePacket, FlemRx, FlemSerial};
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tungstenite::{Message, WebSocket};
+
+/// How long the bridge's threads wait for traffic before checking whether
+/// they should stop. Also bounds how long a received packet waits for a
+/// client busy reading.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How packets received from the device are sent to WebSocket clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WsFormat {
+    /// A text message holding the packet as a JSON [WirePacket].
+    #[default]
+    Json,
+    /// A binary message holding the packet's FLEM bytes.
+    Binary,
+}
+
+/// Encodes a packet received from the device for the clients.
+pub(crate) fn encode<const T: usize>(packet: &flem::Packet<T>, format: WsFormat) -> Message {
+    match format {
+        WsFormat::Json => {
+            // A WirePacket is only numbers and can't fail to serialize
+            let json = serde_json::to_string(&WirePacket::from(packet)).unwrap_or_default();
+            Message::Text(json)
+        }
+        WsFormat::Binary => Message::Binary(packet.bytes().to_vec()),
+    }
+}
+
+/// Decodes a packet sent by a client, either format regardless of the one
+/// used towards clients. None if the message isn't a valid packet.
+pub(crate) fn decode<const T: usize>(message: &Message) -> Option<flem::Packet<T>> {
+    match message {
+        Message::Text(json) => serde_json::from_str::<WirePacket>(json).ok()?.to_packet(),
+        Message::Binary(bytes) => {
+            let mut packet = flem::Packet::<T>::new();
+            let (last, rest) = bytes.split_last()?;
+            for byte in rest {
+                if !matches!(packet.add_byte(*byte), flem::Status::PacketBuilding) {
+                    return None;
+                }
+            }
+            match packet.add_byte(*last) {
+                flem::Status::PacketReceived => Some(packet),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+type Clients = Arc<Mutex<Vec<Sender<Message>>>>;
+
+/// Serves a listening link to WebSocket clients, such as browser
+/// dashboards: every packet received from the device goes to every client
+/// in the chosen [WsFormat], and packets sent by clients are transmitted.
+///
+/// Clients send a text message with a JSON [WirePacket] or a binary
+/// message with one whole FLEM packet. Messages that are neither are
+/// ignored. The checksum of JSON packets is computed by the bridge.
+pub struct WebSocketBridge<const T: usize> {
+    running: Arc<AtomicBool>,
+    clients: Clients,
+    local_addr: SocketAddr,
+    serial: Arc<Mutex<FlemSerial<T>>>,
+    forward: JoinHandle<FlemRx<T>>,
+    accept: JoinHandle<()>,
+}
+
+impl<const T: usize> WebSocketBridge<T> {
+    /// Accepts WebSocket clients on `address` and starts forwarding between
+    /// them and a connected, listening link.
+    pub fn start(
+        address: &str,
+        link: (FlemSerial<T>, FlemRx<T>),
+        format: WsFormat,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+        let (serial, rx) = link;
+        let serial = Arc::new(Mutex::new(serial));
+
+        let forward = {
+            let running = running.clone();
+            let clients = clients.clone();
+            hooks::spawn_supervised("websocket", None, None, move || {
+                while running.load(Ordering::Acquire) {
+                    match rx.recv_timeout(POLL_INTERVAL) {
+                        Ok(packet) => {
+                            let message = encode(&packet, format);
+                            clients
+                                .lock()
+                                .unwrap()
+                                .retain(|client| client.send(message.clone()).is_ok());
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                rx
+            })
+        };
+
+        let accept = {
+            let running = running.clone();
+            let clients = clients.clone();
+            let serial = serial.clone();
+            hooks::spawn_supervised("websocket", None, None, move || {
+                let mut sessions = Vec::new();
+                while running.load(Ordering::Acquire) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Some(session) =
+                                Self::spawn_client(stream, &running, &clients, &serial)
+                            {
+                                sessions.push(session);
+                            }
+                        }
+                        Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(POLL_INTERVAL);
+                        }
+                        Err(_) => break,
+                    }
+                    sessions.retain(|session: &JoinHandle<()>| !session.is_finished());
+                }
+                for session in sessions {
+                    let _ = session.join();
+                }
+            })
+        };
+
+        Ok(Self {
+            running,
+            clients,
+            local_addr,
+            serial,
+            forward,
+            accept,
+        })
+    }
+
+    /// Completes the handshake and runs the client on its own thread. None
+    /// if the client went away first.
+    fn spawn_client(
+        stream: TcpStream,
+        running: &Arc<AtomicBool>,
+        clients: &Clients,
+        serial: &Arc<Mutex<FlemSerial<T>>>,
+    ) -> Option<JoinHandle<()>> {
+        stream.set_nonblocking(false).ok()?;
+        stream.set_nodelay(true).ok()?;
+        stream.set_read_timeout(Some(POLL_INTERVAL)).ok()?;
+        let mut socket = tungstenite::accept(stream).ok()?;
+
+        let (outbox, inbox) = mpsc::channel();
+        clients.lock().unwrap().push(outbox);
+
+        let running = running.clone();
+        let serial = serial.clone();
+        Some(hooks::spawn_supervised(
+            "websocket",
+            None,
+            None,
+            move || {
+                while running.load(Ordering::Acquire) {
+                    if !Self::serve_client(&mut socket, &inbox, &serial) {
+                        return;
+                    }
+                }
+                let _ = socket.close(None);
+                let _ = socket.flush();
+            },
+        ))
+    }
+
+    /// Sends the packets waiting for the client, then waits briefly for one
+    /// from it. False once the client has gone.
+    fn serve_client(
+        socket: &mut WebSocket<TcpStream>,
+        inbox: &mpsc::Receiver<Message>,
+        serial: &Mutex<FlemSerial<T>>,
+    ) -> bool {
+        loop {
+            match inbox.try_recv() {
+                Ok(message) => {
+                    if socket.send(message).is_err() {
+                        return false;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+
+        match socket.read() {
+            Ok(message) => {
+                if let Some(packet) = decode::<T>(&message) {
+                    let _ = serial.lock().unwrap().send(&packet);
+                }
+                true
+            }
+            Err(tungstenite::Error::Io(error))
+                if matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Address clients connect to, useful after binding port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Number of connected clients. A client that went away is counted
+    /// until the next packet from the device.
+    pub fn client_count(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Disconnects every client and hands the link back.
+    pub fn stop(self) -> (FlemSerial<T>, FlemRx<T>) {
+        self.running.store(false, Ordering::Release);
+
+        let rx = self.forward.join().unwrap();
+        self.accept.join().unwrap();
+        let serial = match Arc::try_unwrap(self.serial) {
+            Ok(serial) => serial.into_inner().unwrap(),
+            Err(_) => unreachable!("every client thread has been joined"),
+        };
+        (serial, rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, WebSocketBridge, WsFormat};
+    use crate::FlemSerial;
+    use std::{
+        io::{self, Cursor},
+        net::TcpStream,
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+    use tungstenite::Message;
+
+    fn packet(request: u8, data: &[u8]) -> flem::Packet<64> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(request);
+        packet.add_data(data).unwrap();
+        packet.pack();
+        packet
+    }
+
+    #[test]
+    fn test_messages_round_trip_in_both_formats() {
+        let sent = packet(0x12, &[1, 2, 3]);
+        for format in [WsFormat::Json, WsFormat::Binary] {
+            let decoded = decode::<64>(&encode(&sent, format)).unwrap();
+            assert_eq!(decoded.bytes(), sent.bytes());
+        }
+
+        assert!(decode::<64>(&Message::Text("{}".into())).is_none());
+        let mut truncated = sent.bytes().to_vec();
+        truncated.pop();
+        assert!(decode::<64>(&Message::Binary(truncated)).is_none());
+    }
+
+    /// Reads `incoming` and keeps what is written.
+    #[derive(Clone, Default)]
+    struct Device {
+        incoming: Arc<Mutex<Cursor<Vec<u8>>>>,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl io::Read for Device {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            io::Read::read(&mut *self.incoming.lock().unwrap(), buf)
+        }
+    }
+
+    impl io::Write for Device {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_clients_receive_and_transmit_packets() {
+        let device = Device::default();
+        let mut serial = FlemSerial::<64>::from_transport(device.clone());
+        let rx = serial.listen().unwrap();
+        let bridge = WebSocketBridge::start("127.0.0.1:0", (serial, rx), WsFormat::Json).unwrap();
+
+        let stream = TcpStream::connect(bridge.local_addr()).unwrap();
+        let url = format!("ws://{}/", bridge.local_addr());
+        let (mut client, _) = tungstenite::client(url.as_str(), stream).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while bridge.client_count() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // From the device to the client
+        let from_device = packet(0x21, &[0xaa]);
+        *device.incoming.lock().unwrap() = Cursor::new(from_device.bytes().to_vec());
+        let Message::Text(json) = client.read().unwrap() else {
+            panic!("expected a text message");
+        };
+        assert_eq!(json, r#"{"request":33,"response":0,"data":[170]}"#);
+
+        // From the client to the device
+        let to_device = packet(0x22, &[0xbb]);
+        client
+            .send(Message::Binary(to_device.bytes().to_vec()))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while device.written.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(*device.written.lock().unwrap(), to_device.bytes());
+
+        let (_serial, _rx) = bridge.stop();
+    }
+}
@@ -0,0 +1,292 @@
+use crate::clock::Clock;
+use crate::events::LinkEvent;
+use crate::listener::{ListenerShared, ListenerThread};
+use crate::stats::{LinkRates, LinkStats};
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Request codes a device sends around a burst of EVENT packets.
+///
+/// If the start marker's payload holds at least 4 bytes, the first 4 are
+/// read as the little endian number of EVENT packets in the burst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BurstMarkers {
+    pub start: u8,
+    pub end: u8,
+    /// A burst whose end marker has not arrived this long after its start
+    /// is delivered incomplete. None waits forever.
+    pub timeout: Option<Duration>,
+}
+
+/// EVENT packets received between a start and an end marker.
+#[derive(Clone)]
+pub struct Burst<const T: usize> {
+    pub start: flem::Packet<T>,
+    pub events: Vec<flem::Packet<T>>,
+    /// None if the burst timed out or a new burst started first.
+    pub end: Option<flem::Packet<T>>,
+    /// Number of events announced by the start marker, if any.
+    pub expected: Option<u32>,
+}
+
+impl<const T: usize> Burst<T> {
+    fn new(start: flem::Packet<T>) -> Self {
+        let expected = start
+            .get_data()
+            .get(..4)
+            .map(|count| u32::from_le_bytes([count[0], count[1], count[2], count[3]]));
+        Self {
+            start,
+            events: Vec::new(),
+            end: None,
+            expected,
+        }
+    }
+
+    pub fn received(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Announced events that never arrived, if a count was announced.
+    pub fn missing(&self) -> Option<u32> {
+        self.expected
+            .map(|expected| expected.saturating_sub(self.events.len() as u32))
+    }
+
+    /// True if the end marker arrived and, when a count was announced,
+    /// exactly that many events were received.
+    pub fn is_complete(&self) -> bool {
+        self.end.is_some()
+            && self
+                .expected
+                .is_none_or(|expected| expected as usize == self.events.len())
+    }
+
+    fn packet_count(&self) -> usize {
+        1 + self.events.len() + self.end.is_some() as usize
+    }
+}
+
+/// What [FlemBurstRx] delivers.
+#[derive(Clone)]
+pub enum BurstItem<const T: usize> {
+    Burst(Burst<T>),
+    /// A packet outside a burst, or a non-EVENT packet during one.
+    Packet(flem::Packet<T>),
+}
+
+impl<const T: usize> BurstItem<T> {
+    fn packet_count(&self) -> usize {
+        match self {
+            BurstItem::Burst(burst) => burst.packet_count(),
+            BurstItem::Packet(_) => 1,
+        }
+    }
+}
+
+/// Groups packets into bursts on the listener thread.
+pub(crate) struct BurstCollector<const T: usize> {
+    sender: Sender<BurstItem<T>>,
+    markers: BurstMarkers,
+    current: Option<(Burst<T>, Instant)>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<const T: usize> BurstCollector<T> {
+    pub(crate) fn new(
+        sender: Sender<BurstItem<T>>,
+        markers: BurstMarkers,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            sender,
+            markers,
+            current: None,
+            clock,
+        }
+    }
+
+    pub(crate) fn push(&mut self, packet: flem::Packet<T>) -> Result<(), ()> {
+        let request = packet.get_request();
+
+        if request == self.markers.start {
+            self.flush()?;
+            self.current = Some((Burst::new(packet), self.clock.now()));
+            return Ok(());
+        }
+
+        match self.current.as_mut() {
+            Some((burst, _)) if request == self.markers.end => {
+                burst.end = Some(packet);
+                self.flush()
+            }
+            Some((burst, _)) if request == flem::Request::EVENT => {
+                burst.events.push(packet);
+                Ok(())
+            }
+            _ => self.send(BurstItem::Packet(packet)),
+        }
+    }
+
+    pub(crate) fn tick(&mut self) -> Result<(), ()> {
+        match (self.current.as_ref(), self.markers.timeout) {
+            (Some((_, started)), Some(timeout)) if self.clock.now() - *started >= timeout => {
+                self.flush()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        match self.current.take() {
+            Some((burst, _)) => self.send(BurstItem::Burst(burst)),
+            None => Ok(()),
+        }
+    }
+
+    fn send(&self, item: BurstItem<T>) -> Result<(), ()> {
+        self.sender.send(item).map_err(|_| ())
+    }
+}
+
+/// Receive handle returned by [crate::FlemSerial::listen_bursts]. Dropping
+/// it stops its listener.
+pub struct FlemBurstRx<const T: usize> {
+    pub(crate) rx_listener_handle: ListenerThread,
+    pub(crate) rx_burst_queue: Receiver<BurstItem<T>>,
+    pub(crate) events: Receiver<LinkEvent>,
+    pub(crate) shared: ListenerShared,
+}
+
+impl<const T: usize> FlemBurstRx<T> {
+    /// Raw access to the queue. Items taken directly from the queue are not
+    /// counted by [FlemBurstRx::queue_depth].
+    pub fn queue(&self) -> &Receiver<BurstItem<T>> {
+        &self.rx_burst_queue
+    }
+
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    fn received(&self, item: BurstItem<T>) -> BurstItem<T> {
+        self.shared
+            .queue_depth
+            .fetch_sub(item.packet_count(), Ordering::AcqRel);
+        item
+    }
+
+    /// Blocks until a burst or packet is received.
+    pub fn recv(&self) -> Result<BurstItem<T>, RecvError> {
+        self.rx_burst_queue.recv().map(|item| self.received(item))
+    }
+
+    /// Returns a burst or packet if one is waiting.
+    pub fn try_recv(&self) -> Result<BurstItem<T>, TryRecvError> {
+        self.rx_burst_queue
+            .try_recv()
+            .map(|item| self.received(item))
+    }
+
+    /// Blocks until a burst or packet is received or `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<BurstItem<T>, RecvTimeoutError> {
+        self.rx_burst_queue
+            .recv_timeout(timeout)
+            .map(|item| self.received(item))
+    }
+
+    /// Number of packets delivered by the listener and not yet received,
+    /// including packets of a burst still in progress.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        self.rx_listener_handle.handle()
+    }
+
+    /// Waits for the listener thread to exit.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BurstCollector, BurstItem, BurstMarkers};
+    use crate::clock::MockClock;
+    use std::{
+        sync::{mpsc, Arc},
+        time::Duration,
+    };
+
+    const START: u8 = 0x20;
+    const END: u8 = 0x21;
+
+    fn packet(request: u8, data: &[u8]) -> flem::Packet<16> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(request);
+        packet.add_data(data).unwrap();
+        packet.pack();
+        packet
+    }
+
+    #[test]
+    fn test_bursts_are_grouped_with_completeness() {
+        let clock = Arc::new(MockClock::new());
+        let (tx, rx) = mpsc::channel();
+        let markers = BurstMarkers {
+            start: START,
+            end: END,
+            timeout: Some(Duration::from_millis(100)),
+        };
+        let mut collector = BurstCollector::new(tx, markers, clock.clone());
+
+        collector.push(packet(START, &2u32.to_le_bytes())).unwrap();
+        collector.push(packet(flem::Request::EVENT, &[1])).unwrap();
+        collector.push(packet(flem::Request::ID, &[])).unwrap();
+        collector.push(packet(flem::Request::EVENT, &[2])).unwrap();
+        assert!(matches!(rx.try_recv(), Ok(BurstItem::Packet(_))));
+        collector.push(packet(END, &[])).unwrap();
+
+        let Ok(BurstItem::Burst(burst)) = rx.try_recv() else {
+            panic!("expected a burst");
+        };
+        assert_eq!(burst.received(), 2);
+        assert_eq!(burst.missing(), Some(0));
+        assert!(burst.is_complete());
+
+        // Only one of three events arrives and the end marker is lost
+        collector.push(packet(START, &3u32.to_le_bytes())).unwrap();
+        collector.push(packet(flem::Request::EVENT, &[1])).unwrap();
+        collector.tick().unwrap();
+        assert!(rx.try_recv().is_err());
+        clock.advance(Duration::from_millis(100));
+        collector.tick().unwrap();
+
+        let Ok(BurstItem::Burst(burst)) = rx.try_recv() else {
+            panic!("expected a burst");
+        };
+        assert!(burst.end.is_none());
+        assert_eq!(burst.missing(), Some(2));
+        assert!(!burst.is_complete());
+    }
+}
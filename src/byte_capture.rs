@@ -0,0 +1,217 @@
+//! Captures of the raw bytes a listener reads, including noise and broken
+//! packets that never make it into a packet capture, so a corrupt stream
+//! reported from the field can be replayed locally.
+//!
+//! # File format
+//!
+//! All integers are little endian. The file starts with a 16 byte header:
+//!
+//! | bytes | contents                                         |
+//! |-------|--------------------------------------------------|
+//! | 0..8  | magic, `FLEMBYT1`                                |
+//! | 8..16 | u64, Unix time in microseconds the capture began |
+//!
+//! followed by one record per read from the port:
+//!
+//! | bytes      | contents                                      |
+//! |------------|-----------------------------------------------|
+//! | 0..8       | u64, microseconds since the capture began     |
+//! | 8..12      | u32, `length` of the read                     |
+//! | 12..12+len | the bytes read, exactly as they came in       |
+//!
+//! A capture cut short, e.g. by a crash, ends with a partial record.
+//! [read_byte_capture] drops it.
+
+use crate::capture_file::CaptureFileError;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// First bytes of every byte capture.
+pub const BYTE_CAPTURE_MAGIC: [u8; 8] = *b"FLEMBYT1";
+
+const FILE_HEADER_SIZE: usize = 16;
+const RECORD_HEADER_SIZE: usize = 12;
+
+/// Writes every byte a listener reads into a file, see the
+/// [module docs](self) for the format. Set on a link with
+/// [crate::FlemSerial::set_byte_capture]; several links should not share
+/// one, as records carry no device.
+pub struct ByteCapture {
+    writer: Mutex<Box<dyn Write + Send>>,
+    start: Instant,
+}
+
+impl ByteCapture {
+    /// Creates (or truncates) the capture file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Arc<Self>> {
+        let file = File::create(path)?;
+        Self::from_writer(BufWriter::new(file))
+    }
+
+    /// Captures into any writer, useful for in-memory captures. Fails if
+    /// the file header can't be written.
+    pub fn from_writer<W: Write + Send + 'static>(mut writer: W) -> io::Result<Arc<Self>> {
+        let wall_micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_micros() as u64)
+            .unwrap_or(0);
+        writer.write_all(&BYTE_CAPTURE_MAGIC)?;
+        writer.write_all(&wall_micros.to_le_bytes())?;
+
+        Ok(Arc::new(Self {
+            writer: Mutex::new(Box::new(writer)),
+            start: Instant::now(),
+        }))
+    }
+
+    /// Records one read from the port.
+    pub fn record(&self, bytes: &[u8]) -> io::Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| io::Error::other("capture lock poisoned"))?;
+
+        let elapsed = self.start.elapsed().as_micros() as u64;
+        writer.write_all(&elapsed.to_le_bytes())?;
+        writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(bytes)
+    }
+
+    /// Flushes buffered records to the underlying file.
+    pub fn flush(&self) -> io::Result<()> {
+        match self.writer.lock() {
+            Ok(mut writer) => writer.flush(),
+            Err(_) => Err(io::Error::other("capture lock poisoned")),
+        }
+    }
+}
+
+impl Drop for ByteCapture {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// One read from the port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteChunk {
+    /// Microseconds since the capture began.
+    pub elapsed_micros: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// The contents of a byte capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteCaptureFile {
+    /// Unix time in microseconds the capture began.
+    pub start_wall_micros: u64,
+    pub chunks: Vec<ByteChunk>,
+}
+
+impl ByteCaptureFile {
+    /// Every captured byte back to back. Feeding this to
+    /// [crate::FlemSerial::from_transport] replays the stream.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.bytes.iter().copied())
+            .collect()
+    }
+}
+
+/// Reads a capture written by [ByteCapture].
+pub fn read_byte_capture<R: Read>(mut reader: R) -> Result<ByteCaptureFile, CaptureFileError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    if data.len() < FILE_HEADER_SIZE || data[..8] != BYTE_CAPTURE_MAGIC {
+        return Err(CaptureFileError::Syntax {
+            line: 0,
+            message: "not a byte capture".to_string(),
+        });
+    }
+    let start_wall_micros = u64::from_le_bytes(data[8..16].try_into().unwrap());
+
+    let mut chunks = Vec::new();
+    let mut offset = FILE_HEADER_SIZE;
+    while let Some(header) = data.get(offset..offset + RECORD_HEADER_SIZE) {
+        let elapsed_micros = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let length = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        let start = offset + RECORD_HEADER_SIZE;
+        let Some(bytes) = data.get(start..start + length) else {
+            break;
+        };
+
+        chunks.push(ByteChunk {
+            elapsed_micros,
+            bytes: bytes.to_vec(),
+        });
+        offset = start + length;
+    }
+
+    Ok(ByteCaptureFile {
+        start_wall_micros,
+        chunks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_byte_capture, ByteCapture};
+    use crate::test_support::SharedBuffer;
+
+    #[test]
+    fn test_reads_come_back_in_order_and_partial_records_are_dropped() {
+        let buffer = SharedBuffer::default();
+        let capture = ByteCapture::from_writer(buffer.clone()).unwrap();
+        capture.record(&[0x00, 0x55]).unwrap();
+        capture.record(&[]).unwrap();
+        capture.record(&[0x55, 0x01]).unwrap();
+
+        let mut data = buffer.contents();
+        // A crash in the middle of the next record
+        data.extend_from_slice(&[0x01, 0x02, 0x03]);
+
+        let file = read_byte_capture(data.as_slice()).unwrap();
+        assert!(file.start_wall_micros > 0);
+        let chunks: Vec<&[u8]> = file.chunks.iter().map(|c| c.bytes.as_slice()).collect();
+        assert_eq!(chunks, [&[0x00, 0x55][..], &[], &[0x55, 0x01]]);
+        assert!(file.chunks[0].elapsed_micros <= file.chunks[2].elapsed_micros);
+        assert_eq!(file.bytes(), [0x00, 0x55, 0x55, 0x01]);
+
+        assert!(read_byte_capture(&b"FLEMRAW"[..]).is_err());
+    }
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_captured_stream_replays_into_a_listener() {
+        use crate::FlemSerial;
+        use std::{io::Cursor, time::Duration};
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.pack();
+
+        let buffer = SharedBuffer::default();
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        serial.set_byte_capture(ByteCapture::from_writer(buffer.clone()).unwrap());
+        let mut stepped = serial.listen_stepped().unwrap();
+        stepped.step(&[0xff, 0xfe]);
+        stepped.step(packet.bytes());
+        assert_eq!(stepped.drain().len(), 1);
+
+        let file = read_byte_capture(buffer.contents().as_slice()).unwrap();
+        assert_eq!(file.chunks.len(), 2);
+        assert_eq!(file.chunks[0].bytes, [0xff, 0xfe]);
+
+        let mut replay = FlemSerial::<64>::from_transport(Cursor::new(file.bytes()));
+        let rx = replay.listen().unwrap();
+        let replayed = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(replayed.bytes(), packet.bytes());
+    }
+}
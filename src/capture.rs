@@ -0,0 +1,312 @@
+use crate::{
+    capture_file::{self, CaptureRecord},
+    session::SessionStamp,
+    timestamp::{Timestamp, TimestampMode},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Direction of a captured packet relative to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+impl Direction {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Rx => "rx",
+            Direction::Tx => "tx",
+        }
+    }
+
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        match text {
+            "rx" => Some(Direction::Rx),
+            "tx" => Some(Direction::Tx),
+            _ => None,
+        }
+    }
+}
+
+/// A capture file shared by several links. Every packet is written as one
+/// tab separated line:
+///
+/// `<us since capture start>\t<unix time us>\t<device>\t<session>\t<index>\t<rx|tx>\t<hex bytes>`
+///
+/// `session` and `index` come from the link's [crate::session::Session] and
+/// are written as `-` when not known. Timestamp columns not selected by the
+/// capture's [TimestampMode] are also written as `-`.
+///
+/// Captures made with [MultiLinkCapture::create_pcapng] are written as
+/// pcapng instead, the same as [crate::capture_file::CaptureFormat::Pcapng],
+/// so they open in Wireshark as they are recorded.
+///
+/// Timestamps are taken while holding the file lock, so lines are always in
+/// time order even when many listener threads write concurrently.
+pub struct MultiLinkCapture {
+    state: Mutex<CaptureState>,
+    start: Instant,
+}
+
+struct CaptureState {
+    writer: Box<dyn Write + Send>,
+    mode: TimestampMode,
+    pcapng: Option<PcapngState>,
+}
+
+struct PcapngState {
+    link_type: u16,
+    /// Devices in the order their interfaces were described.
+    devices: Vec<String>,
+}
+
+impl MultiLinkCapture {
+    /// Creates (or truncates) the capture file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Arc<Self>> {
+        let file = File::create(path)?;
+        Ok(Self::from_writer(BufWriter::new(file)))
+    }
+
+    /// Captures into any writer, useful for in-memory captures.
+    pub fn from_writer<W: Write + Send + 'static>(writer: W) -> Arc<Self> {
+        Self::with_state(writer, None)
+    }
+
+    /// Creates (or truncates) a pcapng capture file at `path`. Every
+    /// device's interface uses `link_type`, usually
+    /// [capture_file::DEFAULT_PCAPNG_LINK_TYPE] or the user DLT a Wireshark
+    /// dissector was registered for.
+    pub fn create_pcapng<P: AsRef<Path>>(path: P, link_type: u16) -> io::Result<Arc<Self>> {
+        let file = File::create(path)?;
+        Self::pcapng_from_writer(BufWriter::new(file), link_type)
+    }
+
+    /// Captures pcapng into any writer. Fails if the section header can't
+    /// be written.
+    pub fn pcapng_from_writer<W: Write + Send + 'static>(
+        mut writer: W,
+        link_type: u16,
+    ) -> io::Result<Arc<Self>> {
+        writer.write_all(&capture_file::pcapng_section())?;
+        Ok(Self::with_state(
+            writer,
+            Some(PcapngState {
+                link_type,
+                devices: Vec::new(),
+            }),
+        ))
+    }
+
+    fn with_state<W: Write + Send + 'static>(writer: W, pcapng: Option<PcapngState>) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(CaptureState {
+                writer: Box::new(writer),
+                mode: TimestampMode::default(),
+                pcapng,
+            }),
+            start: Instant::now(),
+        })
+    }
+
+    /// Selects which timestamp columns are filled in. Defaults to both.
+    pub fn set_timestamp_mode(&self, mode: TimestampMode) {
+        if let Ok(mut state) = self.state.lock() {
+            state.mode = mode;
+        }
+    }
+
+    /// Records the raw bytes of a packet seen on `device`.
+    pub fn record<const T: usize>(
+        &self,
+        device: &str,
+        stamp: Option<SessionStamp>,
+        direction: Direction,
+        packet: &flem::Packet<T>,
+    ) -> io::Result<()> {
+        self.record_bytes(device, stamp, direction, packet.bytes())
+    }
+
+    /// Records an arbitrary byte slice seen on `device`.
+    pub fn record_bytes(
+        &self,
+        device: &str,
+        stamp: Option<SessionStamp>,
+        direction: Direction,
+        bytes: &[u8],
+    ) -> io::Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("capture lock poisoned"))?;
+
+        let now = Timestamp::now(state.mode);
+        let CaptureState { writer, pcapng, .. } = &mut *state;
+
+        if let Some(pcapng) = pcapng {
+            let interface = match pcapng.devices.iter().position(|known| known == device) {
+                Some(interface) => interface,
+                None => {
+                    writer.write_all(&capture_file::pcapng_interface(device, pcapng.link_type))?;
+                    pcapng.devices.push(device.to_string());
+                    pcapng.devices.len() - 1
+                }
+            };
+            let record = CaptureRecord {
+                elapsed_micros: now
+                    .monotonic
+                    .map(|monotonic| monotonic.duration_since(self.start).as_micros() as u64),
+                wall_micros: now.wall_micros().map(|wall| wall as u64),
+                device: device.to_string(),
+                stamp,
+                direction,
+                bytes: bytes.to_vec(),
+            };
+            return writer.write_all(&capture_file::pcapng_packet(interface, &record));
+        }
+
+        match now.monotonic {
+            Some(monotonic) => write!(
+                writer,
+                "{}\t",
+                monotonic.duration_since(self.start).as_micros()
+            )?,
+            None => write!(writer, "-\t")?,
+        }
+        match now.wall_micros() {
+            Some(wall) => write!(writer, "{}\t", wall)?,
+            None => write!(writer, "-\t")?,
+        }
+        write!(writer, "{}\t", device)?;
+        match stamp {
+            Some(stamp) => write!(writer, "{:016x}\t{}\t", stamp.session, stamp.index)?,
+            None => write!(writer, "-\t-\t")?,
+        }
+        write!(writer, "{}\t", direction.as_str())?;
+        for byte in bytes {
+            write!(writer, "{:02x}", byte)?;
+        }
+        writeln!(writer)
+    }
+
+    /// Flushes buffered lines to the underlying file.
+    pub fn flush(&self) -> io::Result<()> {
+        match self.state.lock() {
+            Ok(mut state) => state.writer.flush(),
+            Err(_) => Err(io::Error::other("capture lock poisoned")),
+        }
+    }
+}
+
+impl Drop for MultiLinkCapture {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, MultiLinkCapture};
+    use crate::{
+        capture_file::{read_capture, CaptureFormat},
+        session::SessionStamp,
+        test_support::SharedBuffer,
+        timestamp::TimestampMode,
+    };
+
+    #[test]
+    fn test_capture_lines_are_tagged_and_ordered() {
+        let buffer = SharedBuffer::default();
+        let capture = MultiLinkCapture::from_writer(buffer.clone());
+
+        let stamp = SessionStamp {
+            session: 0xab,
+            index: 3,
+        };
+        capture
+            .record_bytes("a", Some(stamp), Direction::Rx, &[0x55, 0x55])
+            .unwrap();
+        capture
+            .record_bytes("b", None, Direction::Tx, &[0x01])
+            .unwrap();
+
+        let text = String::from_utf8(buffer.contents()).unwrap();
+        let lines: Vec<Vec<&str>> = text.lines().map(|l| l.split('\t').collect()).collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][2..], ["a", "00000000000000ab", "3", "rx", "5555"]);
+        assert_eq!(lines[1][2..], ["b", "-", "-", "tx", "01"]);
+        assert!(lines[0][0].parse::<u128>().unwrap() <= lines[1][0].parse::<u128>().unwrap());
+    }
+
+    #[test]
+    fn test_timestamp_mode_selects_the_time_columns() {
+        let buffer = SharedBuffer::default();
+        let capture = MultiLinkCapture::from_writer(buffer.clone());
+
+        for mode in [
+            TimestampMode::Monotonic,
+            TimestampMode::WallClock,
+            TimestampMode::Both,
+        ] {
+            capture.set_timestamp_mode(mode);
+            capture
+                .record_bytes("a", None, Direction::Rx, &[0x55])
+                .unwrap();
+        }
+
+        let text = String::from_utf8(buffer.contents()).unwrap();
+        let times: Vec<(bool, bool)> = text
+            .lines()
+            .map(|line| {
+                let columns: Vec<&str> = line.split('\t').collect();
+                (
+                    columns[0].parse::<u128>().is_ok(),
+                    columns[1].parse::<u128>().is_ok(),
+                )
+            })
+            .collect();
+        assert_eq!(times, [(true, false), (false, true), (true, true)]);
+    }
+
+    #[test]
+    fn test_pcapng_capture_uses_the_link_type() {
+        let buffer = SharedBuffer::default();
+        let capture = MultiLinkCapture::pcapng_from_writer(buffer.clone(), 150).unwrap();
+
+        capture
+            .record_bytes("a", None, Direction::Tx, &[0x55, 0x55])
+            .unwrap();
+        capture
+            .record_bytes("b", None, Direction::Rx, &[0x01])
+            .unwrap();
+        capture
+            .record_bytes("a", None, Direction::Rx, &[0x02])
+            .unwrap();
+
+        let file = buffer.contents();
+        // Link type of the first interface, after the 28 byte section header
+        assert_eq!(file[36..38], 150u16.to_le_bytes());
+
+        let records = read_capture(file.as_slice(), CaptureFormat::Pcapng).unwrap();
+        let seen: Vec<(&str, Direction, &[u8])> = records
+            .iter()
+            .map(|r| (r.device.as_str(), r.direction, r.bytes.as_slice()))
+            .collect();
+        assert_eq!(
+            seen,
+            [
+                ("a", Direction::Tx, &[0x55, 0x55][..]),
+                ("b", Direction::Rx, &[0x01]),
+                ("a", Direction::Rx, &[0x02]),
+            ]
+        );
+        assert!(records[0].wall_micros.is_some());
+    }
+}
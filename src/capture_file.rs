@@ -0,0 +1,693 @@
+//! Reading, converting and slicing the files written by
+//! [crate::capture::MultiLinkCapture], so analysis tools can be built on
+//! recorded sessions without going through a CLI.
+
+use crate::{
+    capture::Direction,
+    config::{self, Value},
+    inventory::push_json_string,
+    session::SessionStamp,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    ops::Range,
+    time::Duration,
+};
+
+const HEADER: [u8; 2] = [0x55, 0x55];
+const HEADER_SIZE: usize = 8;
+const REQUEST_OFFSET: usize = 4;
+const LENGTH_OFFSET: usize = 6;
+
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// LINKTYPE_USER0, as FLEM has no assigned link type. Wireshark decodes
+/// the user link types with a dissector set up under DLT_USER.
+pub const DEFAULT_PCAPNG_LINK_TYPE: u16 = 147;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_COMMENT: u16 = 1;
+const PCAPNG_IF_NAME: u16 = 2;
+const PCAPNG_EPB_FLAGS: u16 = 2;
+const PCAPNG_INBOUND: u32 = 1;
+const PCAPNG_OUTBOUND: u32 = 2;
+
+/// One packet of a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    /// Microseconds since the capture started.
+    pub elapsed_micros: Option<u64>,
+    /// Microseconds since the Unix epoch.
+    pub wall_micros: Option<u64>,
+    pub device: String,
+    pub stamp: Option<SessionStamp>,
+    pub direction: Direction,
+    /// The packet as sent on the wire, header included.
+    pub bytes: Vec<u8>,
+}
+
+impl CaptureRecord {
+    /// Request code of the packet, None if the bytes are too short to hold
+    /// a FLEM header.
+    pub fn request(&self) -> Option<u8> {
+        (self.bytes.len() >= HEADER_SIZE).then(|| self.bytes[REQUEST_OFFSET])
+    }
+
+    /// Time since the capture started if known, wall-clock time otherwise.
+    fn time_micros(&self) -> Option<u64> {
+        self.elapsed_micros.or(self.wall_micros)
+    }
+}
+
+/// Capture file layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// The tab separated lines written by
+    /// [crate::capture::MultiLinkCapture].
+    Text,
+    /// One JSON object per line. Unknown fields are left out.
+    Jsonl,
+    /// pcapng with one interface per device, readable by Wireshark. The
+    /// capture fields with no pcapng equivalent are kept in each packet's
+    /// comment.
+    Pcapng,
+    /// The packet bytes back to back, as a device would send them. Feeding
+    /// this to [crate::FlemSerial::from_transport] replays the capture.
+    /// Only bytes survive, read packets are received on device "raw".
+    Raw,
+}
+
+#[derive(Debug)]
+pub enum CaptureFileError {
+    Io(io::Error),
+    /// `line` is the line number for text formats and the byte offset of
+    /// the block for pcapng.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    Unsupported(String),
+}
+
+impl From<io::Error> for CaptureFileError {
+    fn from(error: io::Error) -> Self {
+        CaptureFileError::Io(error)
+    }
+}
+
+/// Reads every record of a capture in `format`.
+pub fn read_capture<R: Read>(
+    mut reader: R,
+    format: CaptureFormat,
+) -> Result<Vec<CaptureRecord>, CaptureFileError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    match format {
+        CaptureFormat::Text => parse_text(&text(&data)?),
+        CaptureFormat::Jsonl => parse_jsonl(&text(&data)?),
+        CaptureFormat::Pcapng => parse_pcapng(&data),
+        CaptureFormat::Raw => Ok(parse_raw(&data)),
+    }
+}
+
+/// Writes `records` in `format`.
+pub fn write_capture<W: Write>(
+    mut writer: W,
+    format: CaptureFormat,
+    records: &[CaptureRecord],
+) -> io::Result<()> {
+    match format {
+        CaptureFormat::Text => {
+            for record in records {
+                writeln!(writer, "{}", to_text(record))?;
+            }
+        }
+        CaptureFormat::Jsonl => {
+            for record in records {
+                writeln!(writer, "{}", to_jsonl(record))?;
+            }
+        }
+        CaptureFormat::Pcapng => writer.write_all(&to_pcapng(records, DEFAULT_PCAPNG_LINK_TYPE))?,
+        CaptureFormat::Raw => {
+            for record in records {
+                writer.write_all(&record.bytes)?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Writes `records` as pcapng with every interface using `link_type`, to
+/// match the DLT a Wireshark dissector was registered for.
+/// [CaptureFormat::Pcapng] uses [DEFAULT_PCAPNG_LINK_TYPE].
+pub fn write_pcapng<W: Write>(
+    mut writer: W,
+    records: &[CaptureRecord],
+    link_type: u16,
+) -> io::Result<()> {
+    writer.write_all(&to_pcapng(records, link_type))?;
+    writer.flush()
+}
+
+/// Reads a capture in one format and writes it in another. Returns the
+/// number of records converted.
+pub fn convert<R: Read, W: Write>(
+    reader: R,
+    from: CaptureFormat,
+    writer: W,
+    to: CaptureFormat,
+) -> Result<usize, CaptureFileError> {
+    let records = read_capture(reader, from)?;
+    write_capture(writer, to, &records)?;
+    Ok(records.len())
+}
+
+/// Records whose time since the first record falls in `range`. Records
+/// without any timestamp are dropped.
+pub fn slice_by_time(records: &[CaptureRecord], range: Range<Duration>) -> Vec<CaptureRecord> {
+    let Some(start) = records.iter().find_map(CaptureRecord::time_micros) else {
+        return Vec::new();
+    };
+
+    records
+        .iter()
+        .filter(|record| {
+            record
+                .time_micros()
+                .map(|time| Duration::from_micros(time.saturating_sub(start)))
+                .is_some_and(|offset| range.contains(&offset))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Records whose request code is one of `requests`.
+pub fn filter_by_request(records: &[CaptureRecord], requests: &[u8]) -> Vec<CaptureRecord> {
+    records
+        .iter()
+        .filter(|record| {
+            record
+                .request()
+                .is_some_and(|request| requests.contains(&request))
+        })
+        .cloned()
+        .collect()
+}
+
+fn text(data: &[u8]) -> Result<String, CaptureFileError> {
+    String::from_utf8(data.to_vec()).map_err(|error| CaptureFileError::Syntax {
+        line: 1 + data[..error.utf8_error().valid_up_to()]
+            .iter()
+            .filter(|byte| **byte == b'\n')
+            .count(),
+        message: "not UTF-8".to_string(),
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn optional<N: ToString>(value: Option<N>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+fn to_text(record: &CaptureRecord) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        optional(record.elapsed_micros),
+        optional(record.wall_micros),
+        record.device,
+        optional(record.stamp.map(|stamp| format!("{:016x}", stamp.session))),
+        optional(record.stamp.map(|stamp| stamp.index)),
+        record.direction.as_str(),
+        to_hex(&record.bytes)
+    )
+}
+
+fn parse_text(text: &str) -> Result<Vec<CaptureRecord>, CaptureFileError> {
+    let mut records = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let syntax = |message: &str| CaptureFileError::Syntax {
+            line: index + 1,
+            message: message.to_string(),
+        };
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [elapsed, wall, device, session, packet_index, direction, hex] = fields[..] else {
+            return Err(syntax("expected 7 tab separated fields"));
+        };
+        let number = |text: &str, radix: u32| match text {
+            "-" => Ok(None),
+            _ => u64::from_str_radix(text, radix)
+                .map(Some)
+                .map_err(|_| syntax("expected a number or -")),
+        };
+
+        records.push(CaptureRecord {
+            elapsed_micros: number(elapsed, 10)?,
+            wall_micros: number(wall, 10)?,
+            device: device.to_string(),
+            stamp: stamp(number(session, 16)?, number(packet_index, 10)?),
+            direction: Direction::parse(direction).ok_or_else(|| syntax("expected rx or tx"))?,
+            bytes: from_hex(hex).ok_or_else(|| syntax("expected hex bytes"))?,
+        });
+    }
+    Ok(records)
+}
+
+fn stamp(session: Option<u64>, index: Option<u64>) -> Option<SessionStamp> {
+    Some(SessionStamp {
+        session: session?,
+        index: index?,
+    })
+}
+
+fn to_jsonl(record: &CaptureRecord) -> String {
+    let mut json = String::from("{");
+    if let Some(elapsed) = record.elapsed_micros {
+        let _ = write!(json, "\"elapsed_us\":{},", elapsed);
+    }
+    if let Some(wall) = record.wall_micros {
+        let _ = write!(json, "\"wall_us\":{},", wall);
+    }
+    json.push_str("\"device\":");
+    push_json_string(&mut json, &record.device);
+    if let Some(stamp) = record.stamp {
+        let _ = write!(
+            json,
+            ",\"session\":\"{:016x}\",\"index\":{}",
+            stamp.session, stamp.index
+        );
+    }
+    let _ = write!(
+        json,
+        ",\"direction\":\"{}\",\"bytes\":\"{}\"}}",
+        record.direction.as_str(),
+        to_hex(&record.bytes)
+    );
+    json
+}
+
+fn parse_jsonl(text: &str) -> Result<Vec<CaptureRecord>, CaptureFileError> {
+    let mut records = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let syntax = |message: String| CaptureFileError::Syntax {
+            line: index + 1,
+            message,
+        };
+
+        let fields = config::parse_json(line).map_err(|error| syntax(format!("{:?}", error)))?;
+        let (mut elapsed, mut wall, mut session, mut packet_index) = (None, None, None, None);
+        let (mut device, mut direction, mut bytes) = (None, None, None);
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("elapsed_us", Value::Int(value)) => elapsed = Some(value),
+                ("wall_us", Value::Int(value)) => wall = Some(value),
+                ("device", Value::Str(value)) => device = Some(value),
+                ("session", Value::Str(value)) => {
+                    session = Some(
+                        u64::from_str_radix(&value, 16)
+                            .map_err(|_| syntax("session is not hex".to_string()))?,
+                    )
+                }
+                ("index", Value::Int(value)) => packet_index = Some(value),
+                ("direction", Value::Str(value)) => direction = Direction::parse(&value),
+                ("bytes", Value::Str(value)) => bytes = from_hex(&value),
+                (key, value) => return Err(syntax(format!("unexpected {}: {:?}", key, value))),
+            }
+        }
+
+        records.push(CaptureRecord {
+            elapsed_micros: elapsed,
+            wall_micros: wall,
+            device: device.ok_or_else(|| syntax("missing device".to_string()))?,
+            stamp: stamp(session, packet_index),
+            direction: direction.ok_or_else(|| syntax("missing direction".to_string()))?,
+            bytes: bytes.ok_or_else(|| syntax("missing bytes".to_string()))?,
+        });
+    }
+    Ok(records)
+}
+
+fn push_block(file: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+    let length = (12 + body.len()) as u32;
+    file.extend_from_slice(&block_type.to_le_bytes());
+    file.extend_from_slice(&length.to_le_bytes());
+    file.extend_from_slice(body);
+    file.extend_from_slice(&length.to_le_bytes());
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+/// The section header block every pcapng file starts with.
+pub(crate) fn pcapng_section() -> Vec<u8> {
+    let mut file = Vec::new();
+    let mut section = Vec::new();
+    section.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+    section.extend_from_slice(&1u16.to_le_bytes());
+    section.extend_from_slice(&0u16.to_le_bytes());
+    section.extend_from_slice(&(-1i64).to_le_bytes());
+    push_block(&mut file, PCAPNG_SECTION_HEADER, &section);
+    file
+}
+
+/// An interface description block naming `device`.
+pub(crate) fn pcapng_interface(device: &str, link_type: u16) -> Vec<u8> {
+    let mut block = Vec::new();
+    let mut description = Vec::new();
+    description.extend_from_slice(&link_type.to_le_bytes());
+    description.extend_from_slice(&0u16.to_le_bytes());
+    description.extend_from_slice(&0u32.to_le_bytes());
+    push_option(&mut description, PCAPNG_IF_NAME, device.as_bytes());
+    push_option(&mut description, PCAPNG_OPT_END, &[]);
+    push_block(&mut block, PCAPNG_INTERFACE_DESCRIPTION, &description);
+    block
+}
+
+/// An enhanced packet block holding `record`, seen on the `interface`th
+/// interface described.
+pub(crate) fn pcapng_packet(interface: usize, record: &CaptureRecord) -> Vec<u8> {
+    // Microseconds, the default resolution
+    let timestamp = record.wall_micros.or(record.elapsed_micros).unwrap_or(0);
+    let comment = format!(
+        "elapsed_us={} wall_us={} session={} index={}",
+        optional(record.elapsed_micros),
+        optional(record.wall_micros),
+        optional(record.stamp.map(|stamp| format!("{:016x}", stamp.session))),
+        optional(record.stamp.map(|stamp| stamp.index)),
+    );
+    let flags = match record.direction {
+        Direction::Rx => PCAPNG_INBOUND,
+        Direction::Tx => PCAPNG_OUTBOUND,
+    };
+
+    let mut block = Vec::new();
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&(interface as u32).to_le_bytes());
+    packet.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+    packet.extend_from_slice(&(timestamp as u32).to_le_bytes());
+    packet.extend_from_slice(&(record.bytes.len() as u32).to_le_bytes());
+    packet.extend_from_slice(&(record.bytes.len() as u32).to_le_bytes());
+    packet.extend_from_slice(&record.bytes);
+    packet.resize(packet.len().next_multiple_of(4), 0);
+    push_option(&mut packet, PCAPNG_EPB_FLAGS, &flags.to_le_bytes());
+    push_option(&mut packet, PCAPNG_OPT_COMMENT, comment.as_bytes());
+    push_option(&mut packet, PCAPNG_OPT_END, &[]);
+    push_block(&mut block, PCAPNG_ENHANCED_PACKET, &packet);
+    block
+}
+
+fn to_pcapng(records: &[CaptureRecord], link_type: u16) -> Vec<u8> {
+    let mut file = pcapng_section();
+
+    let mut devices: Vec<&str> = Vec::new();
+    for record in records {
+        let interface = match devices.iter().position(|device| *device == record.device) {
+            Some(interface) => interface,
+            None => {
+                file.extend(pcapng_interface(&record.device, link_type));
+                devices.push(&record.device);
+                devices.len() - 1
+            }
+        };
+        file.extend(pcapng_packet(interface, record));
+    }
+
+    file
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Splits a pcapng options area into (code, value) pairs.
+fn parse_options(mut data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut options = Vec::new();
+    while let (Some(code), Some(length)) = (u16_at(data, 0), u16_at(data, 2)) {
+        let length = length as usize;
+        let Some(value) = data.get(4..4 + length) else {
+            break;
+        };
+        if code == PCAPNG_OPT_END {
+            break;
+        }
+        options.push((code, value));
+        data = data.get((4 + length).next_multiple_of(4)..).unwrap_or(&[]);
+    }
+    options
+}
+
+fn parse_pcapng(data: &[u8]) -> Result<Vec<CaptureRecord>, CaptureFileError> {
+    let mut records = Vec::new();
+    let mut devices = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let syntax = |message: &str| CaptureFileError::Syntax {
+            line: offset,
+            message: message.to_string(),
+        };
+        let block_type = u32_at(data, offset).ok_or_else(|| syntax("truncated block"))?;
+        if block_type == PCAPNG_SECTION_HEADER
+            && u32_at(data, offset + 8) != Some(PCAPNG_BYTE_ORDER_MAGIC)
+        {
+            return Err(CaptureFileError::Unsupported(
+                "big-endian pcapng".to_string(),
+            ));
+        }
+        let length = u32_at(data, offset + 4).ok_or_else(|| syntax("truncated block"))? as usize;
+        let body = data
+            .get(offset + 8..(offset + length).saturating_sub(4))
+            .filter(|_| length >= 12)
+            .ok_or_else(|| syntax("truncated block"))?;
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let name = parse_options(body.get(8..).unwrap_or(&[]))
+                    .into_iter()
+                    .find(|(code, _)| *code == PCAPNG_IF_NAME)
+                    .map(|(_, name)| String::from_utf8_lossy(name).into_owned())
+                    .unwrap_or_else(|| format!("if{}", devices.len()));
+                devices.push(name);
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let field = |at: usize| u32_at(body, at).ok_or_else(|| syntax("truncated packet"));
+                let interface = field(0)? as usize;
+                let timestamp = ((field(4)? as u64) << 32) | field(8)? as u64;
+                let captured = field(12)? as usize;
+                let bytes = body
+                    .get(20..20 + captured)
+                    .ok_or_else(|| syntax("truncated packet"))?
+                    .to_vec();
+                let options = parse_options(
+                    body.get((20 + captured).next_multiple_of(4)..)
+                        .unwrap_or(&[]),
+                );
+
+                let flags = options
+                    .iter()
+                    .find(|(code, _)| *code == PCAPNG_EPB_FLAGS)
+                    .and_then(|(_, flags)| u32_at(flags, 0))
+                    .unwrap_or(PCAPNG_INBOUND);
+                let comment = options
+                    .iter()
+                    .find(|(code, _)| *code == PCAPNG_OPT_COMMENT)
+                    .map(|(_, comment)| String::from_utf8_lossy(comment).into_owned());
+                let field_of = |name: &str, radix: u32| {
+                    comment.as_deref()?.split(' ').find_map(|pair| {
+                        let (key, value) = pair.split_once('=')?;
+                        (key == name).then(|| u64::from_str_radix(value, radix).ok())?
+                    })
+                };
+
+                records.push(CaptureRecord {
+                    elapsed_micros: field_of("elapsed_us", 10),
+                    // Packets from other tools only have the block timestamp
+                    wall_micros: match comment {
+                        Some(_) => field_of("wall_us", 10),
+                        None => Some(timestamp),
+                    },
+                    device: devices
+                        .get(interface)
+                        .cloned()
+                        .ok_or_else(|| syntax("packet on undeclared interface"))?,
+                    stamp: stamp(field_of("session", 16), field_of("index", 10)),
+                    direction: match flags & 0b11 {
+                        PCAPNG_OUTBOUND => Direction::Tx,
+                        _ => Direction::Rx,
+                    },
+                    bytes,
+                });
+            }
+            _ => {}
+        }
+
+        offset += length;
+    }
+
+    Ok(records)
+}
+
+fn parse_raw(data: &[u8]) -> Vec<CaptureRecord> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while offset + HEADER_SIZE <= data.len() {
+        if data[offset..offset + 2] != HEADER {
+            offset += 1;
+            continue;
+        }
+        let length = u16_at(data, offset + LENGTH_OFFSET).unwrap_or(0) as usize;
+        let Some(packet) = data.get(offset..offset + HEADER_SIZE + length) else {
+            break;
+        };
+
+        records.push(CaptureRecord {
+            elapsed_micros: None,
+            wall_micros: None,
+            device: "raw".to_string(),
+            stamp: None,
+            direction: Direction::Rx,
+            bytes: packet.to_vec(),
+        });
+        offset += packet.len();
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        convert, filter_by_request, read_capture, slice_by_time, write_capture, CaptureFormat,
+        CaptureRecord,
+    };
+    use crate::{capture::Direction, session::SessionStamp};
+    use std::time::Duration;
+
+    fn packet(request: u8, data: &[u8]) -> Vec<u8> {
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(request);
+        packet.add_data(data).unwrap();
+        packet.pack();
+        packet.bytes().to_vec()
+    }
+
+    fn records() -> Vec<CaptureRecord> {
+        vec![
+            CaptureRecord {
+                elapsed_micros: Some(0),
+                wall_micros: Some(1_700_000_000_000_000),
+                device: "left \"arm\"".to_string(),
+                stamp: Some(SessionStamp {
+                    session: 0xab,
+                    index: 0,
+                }),
+                direction: Direction::Tx,
+                bytes: packet(flem::Request::ID, &[]),
+            },
+            CaptureRecord {
+                elapsed_micros: Some(1_500),
+                wall_micros: None,
+                device: "right".to_string(),
+                stamp: None,
+                direction: Direction::Rx,
+                bytes: packet(flem::Request::EVENT, &[1, 2, 3]),
+            },
+            CaptureRecord {
+                elapsed_micros: Some(3_000),
+                wall_micros: Some(1_700_000_000_003_000),
+                device: "left \"arm\"".to_string(),
+                stamp: Some(SessionStamp {
+                    session: 0xab,
+                    index: 1,
+                }),
+                direction: Direction::Rx,
+                bytes: packet(flem::Request::ID, &[9]),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        for format in [
+            CaptureFormat::Text,
+            CaptureFormat::Jsonl,
+            CaptureFormat::Pcapng,
+        ] {
+            let mut file = Vec::new();
+            write_capture(&mut file, format, &records()).unwrap();
+            let read = read_capture(file.as_slice(), format).unwrap();
+            assert_eq!(read, records(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_convert_to_raw_keeps_packets() {
+        let mut text = Vec::new();
+        write_capture(&mut text, CaptureFormat::Text, &records()).unwrap();
+
+        let mut raw = Vec::new();
+        let count = convert(
+            text.as_slice(),
+            CaptureFormat::Text,
+            &mut raw,
+            CaptureFormat::Raw,
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+
+        let read = read_capture(raw.as_slice(), CaptureFormat::Raw).unwrap();
+        let bytes: Vec<Vec<u8>> = read.into_iter().map(|record| record.bytes).collect();
+        let expected: Vec<Vec<u8>> = records().into_iter().map(|record| record.bytes).collect();
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn test_slicing() {
+        let records = records();
+
+        let slice = slice_by_time(&records, Duration::from_millis(1)..Duration::from_millis(2));
+        assert_eq!(slice, records[1..2]);
+
+        let ids = filter_by_request(&records, &[flem::Request::ID]);
+        assert_eq!(ids, [records[0].clone(), records[2].clone()]);
+    }
+}
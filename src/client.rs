@@ -0,0 +1,292 @@
+use crate::{
+    compat::FirmwareVersion,
+    matching::{EchoRequest, ResponseMatcher, TransactionIds},
+    FlemRx, FlemSerial, FlemSerialError,
+};
+use std::time::{Duration, Instant};
+
+/// Why a [RequestClient::call] failed, by the stage that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallError {
+    /// The payload did not fit in a packet or the write failed.
+    SendFailed,
+    /// No matching response arrived in time.
+    Timeout,
+    /// The device answered with a response status other than success.
+    DeviceNack(u8),
+    /// The response could not be decoded into the requested type.
+    DecodeFailed,
+    /// The connected firmware doesn't support the request code. Nothing
+    /// was sent.
+    UnsupportedByFirmware(FirmwareVersion),
+}
+
+/// Conversion from a response packet into a typed value.
+pub trait Decode<const T: usize>: Sized {
+    fn decode(packet: &flem::Packet<T>) -> Option<Self>;
+}
+
+impl<const T: usize> Decode<T> for flem::Packet<T> {
+    fn decode(packet: &flem::Packet<T>) -> Option<Self> {
+        Some(packet.clone())
+    }
+}
+
+impl<const T: usize> Decode<T> for Vec<u8> {
+    fn decode(packet: &flem::Packet<T>) -> Option<Self> {
+        Some(packet.get_data().to_vec())
+    }
+}
+
+impl<const T: usize> Decode<T> for String {
+    fn decode(packet: &flem::Packet<T>) -> Option<Self> {
+        String::from_utf8(packet.get_data().to_vec()).ok()
+    }
+}
+
+impl<const T: usize> Decode<T> for flem::DataId {
+    fn decode(packet: &flem::Packet<T>) -> Option<Self> {
+        flem::DataId::from(packet.get_data()).ok()
+    }
+}
+
+impl<const T: usize> Decode<T> for () {
+    fn decode(_packet: &flem::Packet<T>) -> Option<Self> {
+        Some(())
+    }
+}
+
+/// Synchronous request / response calls over a listening link.
+///
+/// Responses are matched to calls by the same request code unless another
+/// [ResponseMatcher] is set. Packets that arrive while waiting but don't
+/// answer the call are kept and can be collected with
+/// [RequestClient::take_unsolicited].
+pub struct RequestClient<'a, const T: usize> {
+    serial: &'a mut FlemSerial<T>,
+    rx: &'a FlemRx<T>,
+    timeout: Duration,
+    matcher: Box<dyn ResponseMatcher<T>>,
+    transaction_ids: Option<TransactionIds>,
+    unsolicited: Vec<flem::Packet<T>>,
+}
+
+impl<'a, const T: usize> RequestClient<'a, T> {
+    pub fn new(serial: &'a mut FlemSerial<T>, rx: &'a FlemRx<T>, timeout: Duration) -> Self {
+        Self {
+            serial,
+            rx,
+            timeout,
+            matcher: Box::new(EchoRequest),
+            transaction_ids: None,
+            unsolicited: Vec::new(),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Replaces how responses are correlated with calls, see
+    /// [crate::matching].
+    pub fn set_matcher<M: ResponseMatcher<T> + 'static>(&mut self, matcher: M) {
+        self.matcher = Box::new(matcher);
+    }
+
+    /// Inserts a fresh transaction ID into every call's payload and matches
+    /// responses by it, so a late response to an earlier identical call is
+    /// never mistaken for the current one. The ID stays in the response
+    /// payload, see [TransactionIds::extract].
+    pub fn set_transaction_ids(&mut self, transaction_ids: TransactionIds) {
+        self.matcher = Box::new(transaction_ids.matcher());
+        self.transaction_ids = Some(transaction_ids);
+    }
+
+    /// Sends `request` with `payload` and waits for the matching response,
+    /// decoding it as `R`.
+    pub fn call<R: Decode<T>>(&mut self, request: u8, payload: &[u8]) -> Result<R, CallError> {
+        let payload = match self.transaction_ids.as_mut() {
+            Some(transaction_ids) => transaction_ids.inject(payload).0,
+            None => payload.to_vec(),
+        };
+
+        let mut packet = flem::Packet::<T>::new();
+        packet.set_request(request);
+        packet
+            .add_data(&payload)
+            .map_err(|_| CallError::SendFailed)?;
+        packet.pack();
+
+        self.serial.send(&packet).map_err(|error| match error {
+            FlemSerialError::UnsupportedByFirmware { firmware, .. } => {
+                CallError::UnsupportedByFirmware(firmware)
+            }
+            _ => CallError::SendFailed,
+        })?;
+
+        let deadline = Instant::now() + self.timeout;
+        let response = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.rx.recv_timeout(remaining) {
+                Ok(response) if self.matcher.matches(&packet, &response) => break response,
+                Ok(other) => self.unsolicited.push(other),
+                Err(_) => return Err(CallError::Timeout),
+            }
+        };
+
+        if response.get_response() != flem::Response::SUCCESS {
+            return Err(CallError::DeviceNack(response.get_response()));
+        }
+
+        R::decode(&response).ok_or(CallError::DecodeFailed)
+    }
+
+    /// Packets received during calls that were not responses to them.
+    pub fn take_unsolicited(&mut self) -> Vec<flem::Packet<T>> {
+        std::mem::take(&mut self.unsolicited)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CallError, RequestClient};
+    use crate::{
+        compat::{AtLeast, FirmwareVersion},
+        inventory::DeviceIdentity,
+        test_support::Unplugged,
+        FlemSerial,
+    };
+    use std::{
+        collections::VecDeque,
+        io::{self, Read, Write},
+        time::Duration,
+    };
+
+    const ECHO: u8 = 0x20;
+    const NACK: u8 = 0x21;
+    const SILENT: u8 = 0x22;
+    const NOTIFY: u8 = 0x23;
+
+    /// Echoes ECHO requests, refuses NACK, ignores SILENT and announces
+    /// NOTIFY with an event before answering it.
+    struct Device {
+        incoming: flem::Packet<64>,
+        outgoing: VecDeque<u8>,
+    }
+
+    impl Device {
+        fn new() -> Self {
+            Self {
+                incoming: flem::Packet::new(),
+                outgoing: VecDeque::new(),
+            }
+        }
+
+        fn answer(&mut self, request: u8, response: u8, data: &[u8]) {
+            let mut packet = flem::Packet::<64>::new();
+            packet.set_request(request);
+            packet.set_response(response);
+            packet.add_data(data).unwrap();
+            packet.pack();
+            self.outgoing.extend(packet.bytes());
+        }
+    }
+
+    impl Read for Device {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.outgoing.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.outgoing.len());
+            for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for Device {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                if let flem::Status::PacketReceived = self.incoming.add_byte(*byte) {
+                    let request = self.incoming.get_request();
+                    let data = self.incoming.get_data().to_vec();
+                    self.incoming.reset_lazy();
+                    match request {
+                        ECHO => self.answer(ECHO, flem::Response::SUCCESS, &data),
+                        NACK => self.answer(NACK, flem::Response::BUSY, &[]),
+                        NOTIFY => {
+                            self.answer(flem::Request::EVENT, flem::Response::SUCCESS, &[9]);
+                            self.answer(NOTIFY, flem::Response::SUCCESS, &[]);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_call_decodes_the_response() {
+        let mut serial = FlemSerial::<64>::from_transport(Device::new());
+        let rx = serial.listen().unwrap();
+        let mut client = RequestClient::new(&mut serial, &rx, Duration::from_secs(1));
+
+        assert_eq!(client.call::<Vec<u8>>(ECHO, &[1, 2, 3]), Ok(vec![1, 2, 3]));
+        assert_eq!(client.call::<String>(ECHO, b"ok"), Ok("ok".to_string()));
+        assert_eq!(client.call::<()>(NOTIFY, &[]), Ok(()));
+
+        let unsolicited = client.take_unsolicited();
+        assert_eq!(unsolicited.len(), 1);
+        assert_eq!(unsolicited[0].get_request(), flem::Request::EVENT);
+        assert!(client.take_unsolicited().is_empty());
+    }
+
+    #[test]
+    fn test_each_failing_stage_has_its_own_error() {
+        let mut serial = FlemSerial::<64>::from_transport(Device::new());
+        let rx = serial.listen().unwrap();
+        serial.session.set_identity(DeviceIdentity {
+            name: "Sensor".into(),
+            major: 1,
+            minor: 2,
+            patch: 0,
+            max_packet_size: 64,
+        });
+        serial.require_firmware(ECHO + 0x10, AtLeast(FirmwareVersion::new(2, 0, 0)));
+        let mut client = RequestClient::new(&mut serial, &rx, Duration::from_millis(50));
+
+        assert_eq!(
+            client.call::<()>(ECHO, &[0; 65]),
+            Err(CallError::SendFailed)
+        );
+        assert_eq!(client.call::<()>(SILENT, &[]), Err(CallError::Timeout));
+        assert_eq!(
+            client.call::<()>(NACK, &[]),
+            Err(CallError::DeviceNack(flem::Response::BUSY))
+        );
+        assert_eq!(
+            client.call::<String>(ECHO, &[0xff, 0xfe]),
+            Err(CallError::DecodeFailed)
+        );
+        assert_eq!(
+            client.call::<()>(ECHO + 0x10, &[]),
+            Err(CallError::UnsupportedByFirmware(FirmwareVersion::new(
+                1, 2, 0
+            )))
+        );
+    }
+
+    #[test]
+    fn test_a_failed_write_is_a_send_failure() {
+        let mut serial = FlemSerial::<64>::from_transport(Unplugged);
+        let rx = serial.listen().unwrap();
+        let mut client = RequestClient::new(&mut serial, &rx, Duration::from_millis(50));
+        assert_eq!(client.call::<()>(ECHO, &[]), Err(CallError::SendFailed));
+    }
+}
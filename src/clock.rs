@@ -0,0 +1,100 @@
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Source of time for timeouts, retries and other time based behaviour.
+///
+/// Production code uses [SystemClock]. Tests can substitute a [MockClock]
+/// to step time forward explicitly instead of sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Waits for `duration` to pass on this clock.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The real monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// A clock that only moves when told to. `sleep` advances the clock
+/// instead of blocking.
+#[derive(Debug)]
+pub struct MockClock {
+    base: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            base: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Time advanced since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, MockClock, SystemClock};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_mock_clock_sleeps_by_moving_forward() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let started = Instant::now();
+
+        clock.sleep(Duration::from_secs(3600));
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(clock.now() - start, Duration::from_millis(3_600_005));
+        assert_eq!(clock.elapsed(), Duration::from_millis(3_600_005));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_system_clock_sleeps_in_real_time() {
+        let clock = SystemClock;
+        let start = clock.now();
+        clock.sleep(Duration::from_millis(5));
+        assert!(clock.now() - start >= Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::inventory::DeviceIdentity;
+#[cfg(feature = "link")]
+use std::collections::HashMap;
+use std::fmt;
+
+/// Firmware version as reported in a device's ID response, ordered by
+/// major, minor, then patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl FirmwareVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn of(identity: &DeviceIdentity) -> Self {
+        Self::new(identity.major, identity.minor, identity.patch)
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Decides whether the connected firmware supports a command, see
+/// [crate::FlemSerial::require_firmware].
+pub trait FirmwarePredicate: Send {
+    fn supports(&self, identity: &DeviceIdentity) -> bool;
+}
+
+impl<F> FirmwarePredicate for F
+where
+    F: Fn(&DeviceIdentity) -> bool + Send,
+{
+    fn supports(&self, identity: &DeviceIdentity) -> bool {
+        self(identity)
+    }
+}
+
+/// Firmware `version` or newer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtLeast(pub FirmwareVersion);
+
+impl FirmwarePredicate for AtLeast {
+    fn supports(&self, identity: &DeviceIdentity) -> bool {
+        FirmwareVersion::of(identity) >= self.0
+    }
+}
+
+/// Firmware older than `version`, for commands that were removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Before(pub FirmwareVersion);
+
+impl FirmwarePredicate for Before {
+    fn supports(&self, identity: &DeviceIdentity) -> bool {
+        FirmwareVersion::of(identity) < self.0
+    }
+}
+
+/// Predicates by request code. A command is supported if every predicate
+/// registered for its request code holds.
+#[cfg(feature = "link")]
+#[derive(Default)]
+pub(crate) struct FirmwareGate {
+    predicates: HashMap<u8, Vec<Box<dyn FirmwarePredicate>>>,
+}
+
+#[cfg(feature = "link")]
+impl FirmwareGate {
+    pub(crate) fn require(&mut self, request: u8, predicate: Box<dyn FirmwarePredicate>) {
+        self.predicates.entry(request).or_default().push(predicate);
+    }
+
+    pub(crate) fn clear(&mut self, request: u8) {
+        self.predicates.remove(&request);
+    }
+
+    /// Returns the firmware version if it doesn't support `request`.
+    /// Without an identity nothing can be ruled out.
+    pub(crate) fn check(
+        &self,
+        request: u8,
+        identity: Option<&DeviceIdentity>,
+    ) -> Result<(), FirmwareVersion> {
+        let (Some(predicates), Some(identity)) = (self.predicates.get(&request), identity) else {
+            return Ok(());
+        };
+        match predicates
+            .iter()
+            .all(|predicate| predicate.supports(identity))
+        {
+            true => Ok(()),
+            false => Err(FirmwareVersion::of(identity)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{AtLeast, Before, FirmwareGate, FirmwareVersion};
+    use crate::inventory::DeviceIdentity;
+
+    fn identity(major: u16, minor: u16) -> DeviceIdentity {
+        DeviceIdentity {
+            name: "Sensor".into(),
+            major,
+            minor,
+            patch: 0,
+            max_packet_size: 64,
+        }
+    }
+
+    #[test]
+    fn test_commands_are_gated_by_version() {
+        let mut gate = FirmwareGate::default();
+        gate.require(0x20, Box::new(AtLeast(FirmwareVersion::new(1, 4, 0))));
+        gate.require(0x21, Box::new(Before(FirmwareVersion::new(2, 0, 0))));
+        gate.require(
+            0x21,
+            Box::new(|identity: &DeviceIdentity| identity.name == "Sensor"),
+        );
+
+        assert_eq!(gate.check(0x20, Some(&identity(1, 4))), Ok(()));
+        assert_eq!(
+            gate.check(0x20, Some(&identity(1, 3))),
+            Err(FirmwareVersion::new(1, 3, 0))
+        );
+        assert_eq!(gate.check(0x21, Some(&identity(1, 9))), Ok(()));
+        assert!(gate.check(0x21, Some(&identity(2, 0))).is_err());
+        // Unknown firmware and ungated commands pass
+        assert_eq!(gate.check(0x20, None), Ok(()));
+        assert_eq!(gate.check(0x22, Some(&identity(0, 1))), Ok(()));
+        assert_eq!(FirmwareVersion::new(1, 3, 0).to_string(), "1.3.0");
+    }
+}
@@ -0,0 +1,568 @@
+use crate::{
+    degrade::DegradePolicy,
+    events::EventRateLimit,
+    framing::Framing,
+    inventory::push_json_string,
+    options::{ConnectOptions, LineSettings, SoftFlowControl},
+    reconnect::ReconnectPolicy,
+};
+#[cfg(feature = "link")]
+use crate::{inventory, FlemSerial, FlemSerialError};
+use std::{fmt::Write, fs, path::Path, time::Duration};
+
+/// Which port a [LinkConfig] connects to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortMatch {
+    Name(String),
+    /// The USB adapter with these IDs, and serial number if given.
+    Usb {
+        vid: u16,
+        pid: u16,
+        serial_number: Option<String>,
+    },
+}
+
+/// A link's settings as stored in a config file.
+///
+/// Files with a `.json` extension are a flat JSON object, anything else a
+/// flat TOML table of `key = value` lines. Durations are written in
+/// milliseconds with a `_ms` suffix, for example:
+///
+/// ```toml
+/// port = "/dev/ttyACM0"
+/// baud = 921600
+/// line = "8N1"
+/// framing = "cobs"
+/// reconnect = true
+/// reconnect_max_attempts = 10
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkConfig {
+    pub port: Option<PortMatch>,
+    pub baud: u32,
+    pub options: ConnectOptions,
+    pub startup_grace: Duration,
+    pub capture_banner: bool,
+    pub event_rate_limit: EventRateLimit,
+    pub reconnect: Option<ReconnectPolicy>,
+    pub degrade: Option<DegradePolicy>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            port: None,
+            baud: 115200,
+            options: ConnectOptions::default(),
+            startup_grace: Duration::ZERO,
+            capture_banner: false,
+            event_rate_limit: EventRateLimit::default(),
+            reconnect: None,
+            degrade: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    Io(String),
+    /// A line (TOML) or token (JSON) could not be parsed.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    Str(String),
+    Int(u64),
+    Bool(bool),
+}
+
+impl Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::Str(text) => text.clone(),
+            Value::Int(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+        }
+    }
+}
+
+/// Reads an integer value that must fit in `N`.
+fn int_as<N: TryFrom<u64>>(key: &str, value: &Value) -> Result<N, ConfigError> {
+    match value {
+        Value::Int(number) => N::try_from(*number).ok(),
+        _ => None,
+    }
+    .ok_or_else(|| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.describe(),
+    })
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+fn ms(duration: Duration) -> Value {
+    Value::Int(duration.as_millis() as u64)
+}
+
+impl LinkConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Io(error.to_string()))?;
+        if is_json(path) {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let text = if is_json(path) {
+            self.to_json()
+        } else {
+            self.to_toml()
+        };
+        fs::write(path, text).map_err(|error| ConfigError::Io(error.to_string()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Self::from_entries(parse_toml(text)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+        Self::from_entries(parse_json(text)?)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        for (key, value) in self.entries() {
+            let _ = write!(toml, "{} = ", key);
+            match value {
+                Value::Str(text) => push_json_string(&mut toml, &text),
+                other => toml.push_str(&other.describe()),
+            }
+            toml.push('\n');
+        }
+        toml
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        let entries = self.entries();
+        for (index, (key, value)) in entries.iter().enumerate() {
+            json.push_str("  ");
+            push_json_string(&mut json, key);
+            json.push_str(": ");
+            match value {
+                Value::Str(text) => push_json_string(&mut json, text),
+                other => json.push_str(&other.describe()),
+            }
+            if index + 1 < entries.len() {
+                json.push(',');
+            }
+            json.push('\n');
+        }
+        json.push_str("}\n");
+        json
+    }
+
+    fn entries(&self) -> Vec<(&'static str, Value)> {
+        let mut entries = Vec::new();
+        match self.port.as_ref() {
+            Some(PortMatch::Name(name)) => entries.push(("port", Value::Str(name.clone()))),
+            Some(PortMatch::Usb {
+                vid,
+                pid,
+                serial_number,
+            }) => {
+                entries.push(("usb_vid", Value::Int(u64::from(*vid))));
+                entries.push(("usb_pid", Value::Int(u64::from(*pid))));
+                if let Some(serial_number) = serial_number {
+                    entries.push(("usb_serial_number", Value::Str(serial_number.clone())));
+                }
+            }
+            None => {}
+        }
+        entries.push(("baud", Value::Int(u64::from(self.baud))));
+
+        let options = &self.options;
+        entries.push(("verify_device", Value::Bool(options.verify_device)));
+        entries.push(("verify_timeout_ms", ms(options.verify_timeout)));
+        let soft_flow_control = match options.soft_flow_control {
+            SoftFlowControl::Off => "off",
+            SoftFlowControl::Raw => "raw",
+            SoftFlowControl::Stuffed => "stuffed",
+        };
+        entries.push(("soft_flow_control", Value::Str(soft_flow_control.into())));
+        let framing = match options.framing {
+            Framing::None => "none",
+            Framing::Cobs => "cobs",
+            Framing::Slip => "slip",
+        };
+        entries.push(("framing", Value::Str(framing.into())));
+        entries.push(("line", Value::Str(options.line.to_string())));
+        entries.push((
+            "hardware_flow_control",
+            Value::Bool(options.hardware_flow_control),
+        ));
+        entries.push(("read_timeout_ms", ms(options.read_timeout)));
+        entries.push(("probe_adapter", Value::Bool(options.probe_adapter)));
+
+        entries.push(("startup_grace_ms", ms(self.startup_grace)));
+        entries.push(("capture_banner", Value::Bool(self.capture_banner)));
+        entries.push((
+            "event_max_events",
+            Value::Int(u64::from(self.event_rate_limit.max_events)),
+        ));
+        entries.push(("event_window_ms", ms(self.event_rate_limit.window)));
+
+        entries.push(("reconnect", Value::Bool(self.reconnect.is_some())));
+        if let Some(reconnect) = self.reconnect {
+            entries.push(("reconnect_initial_delay_ms", ms(reconnect.initial_delay)));
+            entries.push(("reconnect_max_delay_ms", ms(reconnect.max_delay)));
+            entries.push((
+                "reconnect_multiplier",
+                Value::Int(u64::from(reconnect.multiplier)),
+            ));
+            if let Some(max_attempts) = reconnect.max_attempts {
+                entries.push((
+                    "reconnect_max_attempts",
+                    Value::Int(u64::from(max_attempts)),
+                ));
+            }
+        }
+
+        entries.push(("degrade", Value::Bool(self.degrade.is_some())));
+        if let Some(degrade) = self.degrade {
+            entries.push(("degrade_max_errors", Value::Int(degrade.max_errors)));
+            entries.push(("degrade_window_ms", ms(degrade.window)));
+            entries.push(("degrade_recovery_window_ms", ms(degrade.recovery_window)));
+            entries.push((
+                "degrade_raw_capture_bytes",
+                Value::Int(degrade.raw_capture_bytes as u64),
+            ));
+        }
+        entries
+    }
+
+    fn from_entries(entries: Vec<(String, Value)>) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut usb: (Option<u16>, Option<u16>, Option<String>) = (None, None, None);
+        let mut reconnect = ReconnectPolicy::default();
+        let mut degrade = DegradePolicy::default();
+        let mut reconnect_enabled = false;
+        let mut degrade_enabled = false;
+
+        for (key, value) in entries {
+            let invalid = || ConfigError::InvalidValue {
+                key: key.clone(),
+                value: value.describe(),
+            };
+            let text = || match &value {
+                Value::Str(text) => Ok(text.clone()),
+                _ => Err(invalid()),
+            };
+            let int = || match &value {
+                Value::Int(number) => Ok(*number),
+                _ => Err(invalid()),
+            };
+            let flag = || match &value {
+                Value::Bool(flag) => Ok(*flag),
+                _ => Err(invalid()),
+            };
+            let millis = || int().map(Duration::from_millis);
+
+            match key.as_str() {
+                "port" => config.port = Some(PortMatch::Name(text()?)),
+                "usb_vid" => usb.0 = Some(int_as(&key, &value)?),
+                "usb_pid" => usb.1 = Some(int_as(&key, &value)?),
+                "usb_serial_number" => usb.2 = Some(text()?),
+                "baud" => config.baud = int_as(&key, &value)?,
+                "verify_device" => config.options.verify_device = flag()?,
+                "verify_timeout_ms" => config.options.verify_timeout = millis()?,
+                "soft_flow_control" => {
+                    config.options.soft_flow_control = match text()?.as_str() {
+                        "off" => SoftFlowControl::Off,
+                        "raw" => SoftFlowControl::Raw,
+                        "stuffed" => SoftFlowControl::Stuffed,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "framing" => {
+                    config.options.framing = match text()?.as_str() {
+                        "none" => Framing::None,
+                        "cobs" => Framing::Cobs,
+                        "slip" => Framing::Slip,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "line" => {
+                    config.options.line = text()?.parse::<LineSettings>().map_err(|_| invalid())?
+                }
+                "hardware_flow_control" => config.options.hardware_flow_control = flag()?,
+                "read_timeout_ms" => config.options.read_timeout = millis()?,
+                "probe_adapter" => config.options.probe_adapter = flag()?,
+                "startup_grace_ms" => config.startup_grace = millis()?,
+                "capture_banner" => config.capture_banner = flag()?,
+                "event_max_events" => config.event_rate_limit.max_events = int_as(&key, &value)?,
+                "event_window_ms" => config.event_rate_limit.window = millis()?,
+                "reconnect" => reconnect_enabled = flag()?,
+                "reconnect_initial_delay_ms" => reconnect.initial_delay = millis()?,
+                "reconnect_max_delay_ms" => reconnect.max_delay = millis()?,
+                "reconnect_multiplier" => reconnect.multiplier = int_as(&key, &value)?,
+                "reconnect_max_attempts" => reconnect.max_attempts = Some(int_as(&key, &value)?),
+                "degrade" => degrade_enabled = flag()?,
+                "degrade_max_errors" => degrade.max_errors = int()?,
+                "degrade_window_ms" => degrade.window = millis()?,
+                "degrade_recovery_window_ms" => degrade.recovery_window = millis()?,
+                "degrade_raw_capture_bytes" => degrade.raw_capture_bytes = int_as(&key, &value)?,
+                _ => return Err(ConfigError::UnknownKey(key)),
+            }
+        }
+
+        match usb {
+            (Some(vid), Some(pid), serial_number) => {
+                config.port = Some(PortMatch::Usb {
+                    vid,
+                    pid,
+                    serial_number,
+                })
+            }
+            (None, None, None) => {}
+            _ => return Err(ConfigError::UnknownKey("usb_vid and usb_pid".into())),
+        }
+        config.reconnect = reconnect_enabled.then_some(reconnect);
+        config.degrade = degrade_enabled.then_some(degrade);
+        Ok(config)
+    }
+}
+
+impl ConnectOptions {
+    /// Reads the connect options from a [LinkConfig] file, ignoring the
+    /// link settings.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        LinkConfig::from_file(path).map(|config| config.options)
+    }
+
+    /// Writes the options as a [LinkConfig] file with default link
+    /// settings.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        LinkConfig {
+            options: self.clone(),
+            ..LinkConfig::default()
+        }
+        .to_file(path)
+    }
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> FlemSerial<T> {
+    /// Applies the link settings of `config` and connects to its port.
+    pub fn connect_with_config(&mut self, config: &LinkConfig) -> Result<(), FlemSerialError> {
+        let port_name = match config.port.as_ref() {
+            Some(PortMatch::Name(name)) => name.clone(),
+            Some(PortMatch::Usb {
+                vid,
+                pid,
+                serial_number,
+            }) => {
+                inventory::find_usb_port(&self.list_ports()?, *vid, *pid, serial_number.as_deref())?
+            }
+            None => return Err(FlemSerialError::NoDeviceFoundByThatName),
+        };
+
+        self.set_startup_grace(config.startup_grace, config.capture_banner);
+        self.set_event_rate_limit(config.event_rate_limit);
+        match config.reconnect {
+            Some(policy) => self.set_reconnect_policy(policy),
+            None => self.clear_reconnect_policy(),
+        }
+        match config.degrade {
+            Some(policy) => self.set_degrade_policy(policy),
+            None => self.clear_degrade_policy(),
+        }
+
+        self.connect_with_options(&port_name, config.baud, &config.options)
+    }
+}
+
+fn parse_value(text: &str, line: usize) -> Result<Value, ConfigError> {
+    let syntax = |message: &str| ConfigError::Syntax {
+        line,
+        message: message.to_string(),
+    };
+
+    match text {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ if text.starts_with('"') => {
+            let mut chars = text[1..].chars();
+            let mut value = String::new();
+            loop {
+                match chars.next().ok_or_else(|| syntax("unterminated string"))? {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('t') => value.push('\t'),
+                        Some('"') => value.push('"'),
+                        Some('\\') => value.push('\\'),
+                        _ => return Err(syntax("unsupported escape")),
+                    },
+                    c => value.push(c),
+                }
+            }
+            if chars.as_str().trim().is_empty() {
+                Ok(Value::Str(value))
+            } else {
+                Err(syntax("text after string"))
+            }
+        }
+        _ => text
+            .replace('_', "")
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| syntax("expected a string, integer or boolean")),
+    }
+}
+
+fn parse_toml(text: &str) -> Result<Vec<(String, Value)>, ConfigError> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        // Comments after values are not supported, keep `#` in strings
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or(ConfigError::Syntax {
+            line: index + 1,
+            message: "expected key = value".to_string(),
+        })?;
+        entries.push((
+            key.trim().to_string(),
+            parse_value(value.trim(), index + 1)?,
+        ));
+    }
+    Ok(entries)
+}
+
+pub(crate) fn parse_json(text: &str) -> Result<Vec<(String, Value)>, ConfigError> {
+    let body = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|body| body.strip_suffix('}'))
+        .ok_or(ConfigError::Syntax {
+            line: 1,
+            message: "expected a flat JSON object".to_string(),
+        })?;
+
+    // Split on commas and colons outside strings
+    let mut entries = Vec::new();
+    let mut fields = vec![String::new()];
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in body.chars() {
+        if in_string {
+            escaped = !escaped && c == '\\';
+            in_string = escaped || c != '"';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            fields.push(String::new());
+            continue;
+        }
+        fields.last_mut().unwrap().push(c);
+    }
+
+    for (index, field) in fields.iter().enumerate() {
+        if field.trim().is_empty() {
+            continue;
+        }
+        let syntax = ConfigError::Syntax {
+            line: index + 1,
+            message: "expected \"key\": value".to_string(),
+        };
+        let key = match parse_value(
+            field.trim().split(':').next().unwrap_or("").trim(),
+            index + 1,
+        ) {
+            Ok(Value::Str(key)) => key,
+            _ => return Err(syntax),
+        };
+        let value = field
+            .trim()
+            .split_once("\":")
+            .map(|(_, value)| value.trim())
+            .ok_or(syntax)?;
+        entries.push((key, parse_value(value, index + 1)?));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigError, LinkConfig, PortMatch};
+    use crate::{framing::Framing, reconnect::ReconnectPolicy};
+    use std::time::Duration;
+
+    fn bench() -> LinkConfig {
+        let mut config = LinkConfig {
+            port: Some(PortMatch::Usb {
+                vid: 0x0483,
+                pid: 0x5740,
+                serial_number: Some("A1, \"left\"".to_string()),
+            }),
+            baud: 921600,
+            startup_grace: Duration::from_millis(250),
+            capture_banner: true,
+            reconnect: Some(ReconnectPolicy {
+                max_attempts: Some(10),
+                ..ReconnectPolicy::default()
+            }),
+            ..LinkConfig::default()
+        };
+        config.options.framing = Framing::Cobs;
+        config.options.line = "7E1".parse().unwrap();
+        config.options.hardware_flow_control = true;
+        config
+    }
+
+    #[test]
+    fn test_config_round_trips_through_toml_and_json() {
+        let config = bench();
+        assert_eq!(LinkConfig::from_toml(&config.to_toml()), Ok(config.clone()));
+        assert_eq!(LinkConfig::from_json(&config.to_json()), Ok(config));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert_eq!(
+            LinkConfig::from_toml("# bench\nbaud = 9600\nbuad = 9600\n"),
+            Err(ConfigError::UnknownKey("buad".to_string()))
+        );
+        assert_eq!(
+            LinkConfig::from_toml("framing = \"hdlc\""),
+            Err(ConfigError::InvalidValue {
+                key: "framing".to_string(),
+                value: "hdlc".to_string()
+            })
+        );
+        assert!(matches!(
+            LinkConfig::from_toml("baud 9600"),
+            Err(ConfigError::Syntax { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+#[cfg(feature = "link")]
+use std::time::Instant;
+
+/// When to stop delivering packets because the device floods malformed
+/// data, and when to resume.
+///
+/// While degraded, valid packets are dropped instead of delivered, the
+/// latest raw bytes are kept for [crate::FlemRx::degraded_bytes] and the
+/// listener reads less often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DegradePolicy {
+    /// Resync and checksum errors allowed per `window` before degrading.
+    pub max_errors: u64,
+    pub window: Duration,
+    /// How long the stream must be free of errors to recover.
+    pub recovery_window: Duration,
+    /// Raw bytes kept while degraded, oldest dropped first.
+    pub raw_capture_bytes: usize,
+}
+
+impl Default for DegradePolicy {
+    fn default() -> Self {
+        Self {
+            max_errors: 1000,
+            window: Duration::from_secs(1),
+            recovery_window: Duration::from_secs(2),
+            raw_capture_bytes: 4096,
+        }
+    }
+}
+
+/// Change of mode reported by [DegradeMonitor].
+#[cfg(feature = "link")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DegradeTransition {
+    Degraded { errors: u64 },
+    Recovered,
+}
+
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct DegradeMonitor {
+    window_start: Option<Instant>,
+    errors: u64,
+    last_error: Option<Instant>,
+    degraded: bool,
+}
+
+#[cfg(feature = "link")]
+impl DegradeMonitor {
+    pub(crate) fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    pub(crate) fn on_error(
+        &mut self,
+        policy: &DegradePolicy,
+        now: Instant,
+    ) -> Option<DegradeTransition> {
+        self.last_error = Some(now);
+
+        let window_start = *self.window_start.get_or_insert(now);
+        if now.saturating_duration_since(window_start) >= policy.window {
+            self.window_start = Some(now);
+            self.errors = 0;
+        }
+        self.errors += 1;
+
+        if !self.degraded && self.errors > policy.max_errors {
+            self.degraded = true;
+            return Some(DegradeTransition::Degraded {
+                errors: self.errors,
+            });
+        }
+        None
+    }
+
+    /// Recovers once no error was seen for the recovery window.
+    pub(crate) fn check(
+        &mut self,
+        policy: &DegradePolicy,
+        now: Instant,
+    ) -> Option<DegradeTransition> {
+        let clean_since = self.last_error?;
+        if self.degraded && now.saturating_duration_since(clean_since) >= policy.recovery_window {
+            self.degraded = false;
+            self.window_start = None;
+            self.errors = 0;
+            return Some(DegradeTransition::Recovered);
+        }
+        None
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{DegradeMonitor, DegradePolicy, DegradeTransition};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_degrades_on_error_burst_and_recovers_when_clean() {
+        let start = Instant::now();
+        let policy = DegradePolicy {
+            max_errors: 3,
+            window: Duration::from_secs(1),
+            recovery_window: Duration::from_secs(2),
+            raw_capture_bytes: 16,
+        };
+        let mut monitor = DegradeMonitor::default();
+
+        // Spread out errors never degrade
+        for second in 0..5 {
+            let now = start + Duration::from_secs(second);
+            assert_eq!(monitor.on_error(&policy, now), None);
+        }
+
+        let burst = start + Duration::from_secs(10);
+        for _ in 0..3 {
+            assert_eq!(monitor.on_error(&policy, burst), None);
+        }
+        assert_eq!(
+            monitor.on_error(&policy, burst),
+            Some(DegradeTransition::Degraded { errors: 4 })
+        );
+        assert!(monitor.is_degraded());
+
+        assert_eq!(monitor.check(&policy, burst + Duration::from_secs(1)), None);
+        assert_eq!(
+            monitor.check(&policy, burst + Duration::from_secs(2)),
+            Some(DegradeTransition::Recovered)
+        );
+        assert!(!monitor.is_degraded());
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+/// What the listener does once a link is declared desynchronized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DesyncRecovery {
+    /// Only raise [crate::events::LinkEvent::LinkDesynchronized].
+    Report,
+    /// Discard everything in the OS input and output buffers.
+    PurgeBuffers,
+    /// Drop DTR for this long, resetting boards with an auto-reset circuit.
+    ToggleDtr(Duration),
+    /// Close and reopen the port.
+    Reconnect,
+}
+
+/// Declares the link desynchronized after `max_garbage_bytes` consecutive
+/// bytes fail to start a packet. A wrong baud rate looks exactly like this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DesyncPolicy {
+    pub max_garbage_bytes: usize,
+    pub recovery: DesyncRecovery,
+}
+
+/// Counts consecutive garbage bytes outside the startup grace window.
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct DesyncDetector {
+    garbage_bytes: usize,
+}
+
+#[cfg(feature = "link")]
+impl DesyncDetector {
+    /// Records a byte that did not start a packet. Returns the run length
+    /// once it reaches `max_garbage_bytes`, and starts counting again.
+    pub(crate) fn on_garbage(&mut self, max_garbage_bytes: usize) -> Option<usize> {
+        self.garbage_bytes += 1;
+        if self.garbage_bytes >= max_garbage_bytes.max(1) {
+            let run = self.garbage_bytes;
+            self.garbage_bytes = 0;
+            Some(run)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn on_packet(&mut self) {
+        self.garbage_bytes = 0;
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::DesyncDetector;
+
+    #[test]
+    fn test_only_consecutive_garbage_counts() {
+        let mut detector = DesyncDetector::default();
+        assert_eq!(detector.on_garbage(3), None);
+        assert_eq!(detector.on_garbage(3), None);
+        detector.on_packet();
+        assert_eq!(detector.on_garbage(3), None);
+        assert_eq!(detector.on_garbage(3), None);
+        assert_eq!(detector.on_garbage(3), Some(3));
+        assert_eq!(detector.on_garbage(3), None);
+    }
+}
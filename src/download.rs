@@ -0,0 +1,253 @@
+use crate::{
+    telemetry::{DecodeError, Record, SchemaRegistry},
+    FlemRx, FlemSerial,
+};
+use std::time::{Duration, Instant};
+
+/// A region of device memory read with a FLEM request.
+///
+/// Each chunk is requested by sending `request` with an 8 byte payload of
+/// the little endian `u32` offset and `u32` length. The device answers with
+/// the same request code and the bytes read, and may answer with fewer
+/// bytes than asked for to signal the end of the data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub request: u8,
+    pub start: u32,
+    pub length: u32,
+    /// Bytes asked for per request, capped at the packet size.
+    pub chunk: u32,
+    /// How long to wait for each chunk.
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadError {
+    SendFailed {
+        offset: u32,
+    },
+    Timeout {
+        offset: u32,
+    },
+    /// The device answered the chunk request with a non-success response.
+    DeviceError {
+        offset: u32,
+        response: u8,
+    },
+    Decode(DecodeError),
+}
+
+/// Reads `region` from the device one chunk at a time. Packets with other
+/// request codes received meanwhile are dropped.
+pub fn download<const T: usize>(
+    serial: &mut FlemSerial<T>,
+    rx: &FlemRx<T>,
+    region: &Region,
+) -> Result<Vec<u8>, DownloadError> {
+    let chunk = region.chunk.clamp(1, T as u32);
+    let mut data = Vec::with_capacity(region.length as usize);
+
+    while (data.len() as u32) < region.length {
+        let offset = region.start + data.len() as u32;
+        let wanted = chunk.min(region.length - data.len() as u32);
+
+        let mut packet = flem::Packet::<T>::new();
+        packet.set_request(region.request);
+        let mut payload = [0u8; 8];
+        payload[..4].copy_from_slice(&offset.to_le_bytes());
+        payload[4..].copy_from_slice(&wanted.to_le_bytes());
+        packet
+            .add_data(&payload)
+            .map_err(|_| DownloadError::SendFailed { offset })?;
+        packet.pack();
+
+        serial
+            .send(&packet)
+            .map_err(|_| DownloadError::SendFailed { offset })?;
+
+        let deadline = Instant::now() + region.timeout;
+        let response = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(response) if response.get_request() == region.request => break response,
+                Ok(_) => continue,
+                Err(_) => return Err(DownloadError::Timeout { offset }),
+            }
+        };
+
+        if response.get_response() != flem::Response::SUCCESS {
+            return Err(DownloadError::DeviceError {
+                offset,
+                response: response.get_response(),
+            });
+        }
+
+        let bytes = response.get_data();
+        data.extend_from_slice(&bytes[..bytes.len().min(wanted as usize)]);
+        if (bytes.len() as u32) < wanted {
+            break;
+        }
+    }
+
+    Ok(data)
+}
+
+/// Downloads a stored log region and decodes the back to back entries in it
+/// with `registry`, passing each record to `sink` in order.
+pub fn download_log<const T: usize, F: FnMut(Record)>(
+    serial: &mut FlemSerial<T>,
+    rx: &FlemRx<T>,
+    region: &Region,
+    registry: &SchemaRegistry,
+    sink: F,
+) -> Result<(), DownloadError> {
+    let data = download(serial, rx, region)?;
+    registry
+        .decode_all(&data, sink)
+        .map_err(DownloadError::Decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{download, download_log, DownloadError, Region};
+    use crate::{
+        telemetry::{FieldKind, Schema, SchemaRegistry, Value},
+        FlemSerial,
+    };
+    use std::{
+        collections::VecDeque,
+        io::{self, Read, Write},
+        time::Duration,
+    };
+
+    const READ: u8 = 0x60;
+    const BASE: u32 = 0x100;
+
+    /// Serves reads of `memory`, mapped at [BASE], and answers reads that
+    /// start outside it with an error.
+    struct Memory {
+        memory: Vec<u8>,
+        incoming: flem::Packet<64>,
+        outgoing: VecDeque<u8>,
+    }
+
+    impl Read for Memory {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.outgoing.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.outgoing.len());
+            for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for Memory {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                if let flem::Status::PacketReceived = self.incoming.add_byte(*byte) {
+                    let data = self.incoming.get_data().to_vec();
+                    let request = self.incoming.get_request();
+                    self.incoming.reset_lazy();
+                    if request != READ {
+                        continue;
+                    }
+
+                    let offset = u32::from_le_bytes(data[..4].try_into().unwrap());
+                    let length = u32::from_le_bytes(data[4..8].try_into().unwrap());
+                    let mut reply = flem::Packet::<64>::new();
+                    reply.set_request(READ);
+                    match offset.checked_sub(BASE) {
+                        Some(start) if start as usize <= self.memory.len() => {
+                            let start = start as usize;
+                            let end = (start + length as usize).min(self.memory.len());
+                            reply.add_data(&self.memory[start..end]).unwrap();
+                        }
+                        _ => reply.set_response(flem::Response::ERROR),
+                    }
+                    reply.pack();
+                    self.outgoing.extend(reply.bytes());
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn device(memory: &[u8]) -> FlemSerial<64> {
+        FlemSerial::from_transport(Memory {
+            memory: memory.to_vec(),
+            incoming: flem::Packet::new(),
+            outgoing: VecDeque::new(),
+        })
+    }
+
+    fn region(start: u32, length: u32) -> Region {
+        Region {
+            request: READ,
+            start,
+            length,
+            chunk: 3,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_log_is_downloaded_in_chunks_and_decoded() {
+        let log = [1, 2, 0xfe, 0xff, 1, 3, 10, 0];
+        let mut serial = device(&log);
+        let rx = serial.listen().unwrap();
+
+        assert_eq!(
+            download(&mut serial, &rx, &region(BASE + 1, 5)).unwrap(),
+            log[1..6]
+        );
+        // Asking for more than is stored ends at the first short chunk
+        assert_eq!(download(&mut serial, &rx, &region(BASE, 64)).unwrap(), log);
+
+        let mut registry = SchemaRegistry::new();
+        registry.register(
+            1,
+            Schema::new("temperature")
+                .field("sensor", FieldKind::U8)
+                .field("celsius", FieldKind::I16),
+        );
+        let mut records = Vec::new();
+        download_log(&mut serial, &rx, &region(BASE, 64), &registry, |record| {
+            records.push(record)
+        })
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].fields[1].1, Value::Signed(-2));
+        assert_eq!(records[1].fields[0].1, Value::Unsigned(3));
+    }
+
+    #[test]
+    fn test_failed_chunks_report_their_offset() {
+        let mut serial = device(&[0; 4]);
+        let rx = serial.listen().unwrap();
+
+        assert_eq!(
+            download(&mut serial, &rx, &region(0, 8)),
+            Err(DownloadError::DeviceError {
+                offset: 0,
+                response: flem::Response::ERROR
+            })
+        );
+
+        let ignored = Region {
+            request: 0x61,
+            timeout: Duration::from_millis(20),
+            ..region(BASE, 8)
+        };
+        assert_eq!(
+            download(&mut serial, &rx, &ignored),
+            Err(DownloadError::Timeout { offset: BASE })
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::time::Duration;
+#[cfg(feature = "link")]
+use std::{collections::VecDeque, time::Instant};
+
+/// Window [LinkUsage] covers while no [DutyCycleLimit] is set.
+pub const DEFAULT_USAGE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Caps the time a link may spend transmitting within a sliding window,
+/// for radio modems with regulatory duty-cycle limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DutyCycleLimit {
+    pub window: Duration,
+    /// Transmit time allowed per `window`.
+    pub max_airtime: Duration,
+    /// Data rate on air, which is usually lower than the serial baud.
+    pub air_bits_per_second: u32,
+    /// Fixed cost of every packet on air, such as the radio's preamble.
+    pub per_packet_overhead: Duration,
+}
+
+impl DutyCycleLimit {
+    /// A limit of `percent` of `window`, e.g. 1% per hour.
+    pub fn percent(percent: f64, window: Duration, air_bits_per_second: u32) -> Self {
+        Self {
+            window,
+            max_airtime: window.mul_f64(percent / 100.0),
+            air_bits_per_second,
+            per_packet_overhead: Duration::ZERO,
+        }
+    }
+
+    /// Time on air for a packet of `bytes`.
+    pub fn airtime(&self, bytes: usize) -> Duration {
+        let bits = bytes as u64 * 8;
+        let rate = u64::from(self.air_bits_per_second.max(1));
+        self.per_packet_overhead + Duration::from_micros(bits * 1_000_000 / rate)
+    }
+}
+
+/// Why a send was rejected by the [DutyCycleLimit].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DutyCycleError {
+    /// The packet needs more airtime than the whole window allows.
+    PacketTooLong { airtime: Duration },
+    /// Sending now would exceed the window. Enough airtime is free again
+    /// after `retry_after`.
+    WouldExceed {
+        airtime: Duration,
+        available: Duration,
+        retry_after: Duration,
+    },
+    /// The packet was allowed but writing it failed.
+    SendFailed,
+}
+
+/// Bytes and airtime sent by a link, see [crate::FlemSerial::usage].
+/// Airtime is only counted while a [DutyCycleLimit] is set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkUsage {
+    pub total_packets: u64,
+    pub total_bytes: u64,
+    pub total_airtime: Duration,
+    /// Length of the sliding window the `window_` fields cover.
+    pub window: Duration,
+    pub window_bytes: u64,
+    pub window_airtime: Duration,
+}
+
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct UsageMeter {
+    limit: Option<DutyCycleLimit>,
+    sent: VecDeque<(Instant, u64, Duration)>,
+    total_packets: u64,
+    total_bytes: u64,
+    total_airtime: Duration,
+}
+
+#[cfg(feature = "link")]
+impl UsageMeter {
+    pub(crate) fn set_limit(&mut self, limit: Option<DutyCycleLimit>) {
+        self.limit = limit;
+    }
+
+    fn window(&self) -> Duration {
+        self.limit
+            .map(|limit| limit.window)
+            .unwrap_or(DEFAULT_USAGE_WINDOW)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let window = self.window();
+        while let Some((sent_at, _, _)) = self.sent.front() {
+            if now.saturating_duration_since(*sent_at) < window {
+                break;
+            }
+            self.sent.pop_front();
+        }
+    }
+
+    fn window_airtime(&self) -> Duration {
+        self.sent.iter().map(|(_, _, airtime)| *airtime).sum()
+    }
+
+    /// Checks whether a packet of `bytes` may be sent at `now`.
+    pub(crate) fn check(&mut self, bytes: usize, now: Instant) -> Result<(), DutyCycleError> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+        self.expire(now);
+
+        let airtime = limit.airtime(bytes);
+        if airtime > limit.max_airtime {
+            return Err(DutyCycleError::PacketTooLong { airtime });
+        }
+
+        let used = self.window_airtime();
+        if used + airtime <= limit.max_airtime {
+            return Ok(());
+        }
+
+        // Wait until enough of the oldest sends have left the window
+        let mut freed = Duration::ZERO;
+        let mut retry_after = limit.window;
+        for (sent_at, _, sent_airtime) in self.sent.iter() {
+            freed += *sent_airtime;
+            if used - freed + airtime <= limit.max_airtime {
+                retry_after = (*sent_at + limit.window).saturating_duration_since(now);
+                break;
+            }
+        }
+
+        Err(DutyCycleError::WouldExceed {
+            airtime,
+            available: limit.max_airtime.saturating_sub(used),
+            retry_after,
+        })
+    }
+
+    /// Accounts a packet of `bytes` that was sent at `now`.
+    pub(crate) fn record(&mut self, bytes: usize, now: Instant) {
+        let airtime = self
+            .limit
+            .map(|limit| limit.airtime(bytes))
+            .unwrap_or_default();
+
+        self.expire(now);
+        self.sent.push_back((now, bytes as u64, airtime));
+        self.total_packets += 1;
+        self.total_bytes += bytes as u64;
+        self.total_airtime += airtime;
+    }
+
+    pub(crate) fn snapshot(&mut self, now: Instant) -> LinkUsage {
+        self.expire(now);
+        LinkUsage {
+            total_packets: self.total_packets,
+            total_bytes: self.total_bytes,
+            total_airtime: self.total_airtime,
+            window: self.window(),
+            window_bytes: self.sent.iter().map(|(_, bytes, _)| bytes).sum(),
+            window_airtime: self.window_airtime(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{DutyCycleError, DutyCycleLimit, UsageMeter};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sends_over_the_duty_cycle_are_rejected() {
+        let start = Instant::now();
+        let mut meter = UsageMeter::default();
+        // 1% of 100 s is 1 s, which is 100 bytes at 800 bit/s
+        meter.set_limit(Some(DutyCycleLimit::percent(
+            1.0,
+            Duration::from_secs(100),
+            800,
+        )));
+
+        assert_eq!(
+            meter.check(200, start),
+            Err(DutyCycleError::PacketTooLong {
+                airtime: Duration::from_secs(2)
+            })
+        );
+
+        meter.check(60, start).unwrap();
+        meter.record(60, start);
+        let later = start + Duration::from_secs(10);
+        meter.check(40, later).unwrap();
+        meter.record(40, later);
+
+        assert_eq!(
+            meter.check(10, start + Duration::from_secs(20)),
+            Err(DutyCycleError::WouldExceed {
+                airtime: Duration::from_millis(100),
+                available: Duration::ZERO,
+                retry_after: Duration::from_secs(80),
+            })
+        );
+
+        let usage = meter.snapshot(start + Duration::from_secs(20));
+        assert_eq!(usage.total_bytes, 100);
+        assert_eq!(usage.window_airtime, Duration::from_secs(1));
+
+        assert!(meter.check(10, start + Duration::from_secs(100)).is_ok());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::options::ConnectOptions;
+
+/// Port to connect to, required.
+pub const PORT_VAR: &str = "FLEM_SERIAL_PORT";
+/// Baud rate, [DEFAULT_BAUD] if unset.
+pub const BAUD_VAR: &str = "FLEM_SERIAL_BAUD";
+/// Name of a [ConnectOptions::profile], "default" if unset.
+pub const PROFILE_VAR: &str = "FLEM_SERIAL_PROFILE";
+
+pub const DEFAULT_BAUD: u32 = 115200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvConfigError {
+    /// [PORT_VAR] is not set.
+    MissingPort,
+    /// [BAUD_VAR] is not a number.
+    InvalidBaud(String),
+    /// [PROFILE_VAR] names no known profile.
+    UnknownProfile(String),
+}
+
+/// Link settings read from `FLEM_SERIAL_*` environment variables, so test
+/// jobs can pick a port without changes to the binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvConfig {
+    pub port: String,
+    pub baud: u32,
+    pub options: ConnectOptions,
+}
+
+impl EnvConfig {
+    /// Reads the process environment.
+    pub fn from_env() -> Result<Self, EnvConfigError> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Reads the variables through `lookup` instead of the process
+    /// environment.
+    pub fn from_lookup<F>(lookup: F) -> Result<Self, EnvConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let port = lookup(PORT_VAR)
+            .filter(|port| !port.trim().is_empty())
+            .ok_or(EnvConfigError::MissingPort)?;
+
+        let baud = match lookup(BAUD_VAR) {
+            Some(baud) => baud
+                .trim()
+                .parse()
+                .map_err(|_| EnvConfigError::InvalidBaud(baud))?,
+            None => DEFAULT_BAUD,
+        };
+
+        let profile = lookup(PROFILE_VAR).unwrap_or_else(|| "default".to_string());
+        let options = ConnectOptions::profile(profile.trim())
+            .ok_or(EnvConfigError::UnknownProfile(profile))?;
+
+        Ok(Self {
+            port: port.trim().to_string(),
+            baud,
+            options,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnvConfig, EnvConfigError, DEFAULT_BAUD};
+    use crate::{framing::Framing, options::ConnectOptions};
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<EnvConfig, EnvConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        EnvConfig::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_config_from_variables() {
+        assert_eq!(config(&[]), Err(EnvConfigError::MissingPort));
+
+        let defaults = config(&[("FLEM_SERIAL_PORT", "/dev/ttyACM0")]).unwrap();
+        assert_eq!(defaults.port, "/dev/ttyACM0");
+        assert_eq!(defaults.baud, DEFAULT_BAUD);
+        assert_eq!(defaults.options, ConnectOptions::default());
+
+        let cobs = config(&[
+            ("FLEM_SERIAL_PORT", "COM4"),
+            ("FLEM_SERIAL_BAUD", "921600"),
+            ("FLEM_SERIAL_PROFILE", "cobs"),
+        ])
+        .unwrap();
+        assert_eq!(cobs.baud, 921600);
+        assert_eq!(cobs.options.framing, Framing::Cobs);
+
+        assert_eq!(
+            config(&[("FLEM_SERIAL_PORT", "COM4"), ("FLEM_SERIAL_BAUD", "fast")]),
+            Err(EnvConfigError::InvalidBaud("fast".to_string()))
+        );
+        assert_eq!(
+            config(&[("FLEM_SERIAL_PORT", "COM4"), ("FLEM_SERIAL_PROFILE", "x")]),
+            Err(EnvConfigError::UnknownProfile("x".to_string()))
+        );
+    }
+}
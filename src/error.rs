@@ -0,0 +1,220 @@
+use crate::{compat::FirmwareVersion, duty_cycle::DutyCycleError};
+use std::{error::Error, fmt, io};
+
+/// What a failed send means for the link, see
+/// [FlemSerialError::write_failure].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteFailure {
+    /// The port is closed or the device was unplugged. Sends keep failing
+    /// until the link reconnects.
+    PortGone,
+    /// The port couldn't take the bytes for now, such as with a full OS
+    /// buffer, and [crate::retry::TxRetry] ran out of retries. Sending
+    /// again later may succeed.
+    Busy,
+    /// Any other write error.
+    Other,
+}
+
+impl WriteFailure {
+    /// Classifies an error returned by writing to a port.
+    pub fn of(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::BrokenPipe
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotFound
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::WriteZero => WriteFailure::PortGone,
+            io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::TimedOut => {
+                WriteFailure::Busy
+            }
+            _ if error.raw_os_error().is_some_and(is_device_gone) => WriteFailure::PortGone,
+            _ => WriteFailure::Other,
+        }
+    }
+}
+
+/// OS errors a serial port reports once its device has been removed: EIO,
+/// ENXIO and ENODEV on unix, ERROR_GEN_FAILURE and
+/// ERROR_DEVICE_NOT_CONNECTED on Windows.
+fn is_device_gone(code: i32) -> bool {
+    if cfg!(windows) {
+        matches!(code, 31 | 1167)
+    } else {
+        matches!(code, 5 | 6 | 19)
+    }
+}
+
+/// Why connecting to, listening on or sending over a link failed.
+#[derive(Debug)]
+pub enum FlemSerialError {
+    NoDeviceFoundByThatName,
+    MultipleDevicesFoundByThatName,
+    /// Listing or opening the port failed.
+    ErrorConnectingToDevice(io::Error),
+    /// The port opened but did not answer a FLEM ID request in time.
+    NotAFlemDevice,
+    /// XON/XOFF was requested without byte stuffing, which would corrupt
+    /// binary FLEM packets. See [crate::options::SoftFlowControl].
+    SoftFlowControlCorruptsPackets,
+    /// Hardware and software flow control were both requested.
+    ConflictingFlowControl,
+    /// The link has no open port.
+    NotConnected,
+    /// The port could not be cloned for the listener thread.
+    ListenFailed(io::Error),
+    /// A [crate::interceptor::TxInterceptor] dropped the packet.
+    Vetoed,
+    /// The packet would exceed the link's
+    /// [crate::duty_cycle::DutyCycleLimit].
+    DutyCycle(DutyCycleError),
+    /// Writing to the port failed.
+    WriteFailed(io::Error),
+    /// The connected firmware doesn't support the request code, see
+    /// [crate::FlemSerial::require_firmware].
+    UnsupportedByFirmware {
+        request: u8,
+        firmware: FirmwareVersion,
+    },
+    /// Setting RTS/DTR or reading the modem status lines failed, for
+    /// example because the link isn't a serial port.
+    ControlLines(io::Error),
+    /// A message for [crate::FlemSerial::send_large] needs more fragments
+    /// than a fragment header can count.
+    MessageTooLarge {
+        length: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for FlemSerialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlemSerialError::NoDeviceFoundByThatName => write!(f, "no device found by that name"),
+            FlemSerialError::MultipleDevicesFoundByThatName => {
+                write!(f, "multiple devices found by that name")
+            }
+            FlemSerialError::ErrorConnectingToDevice(error) => {
+                write!(f, "error connecting to device: {}", error)
+            }
+            FlemSerialError::NotAFlemDevice => write!(f, "device did not answer a FLEM ID request"),
+            FlemSerialError::SoftFlowControlCorruptsPackets => {
+                write!(
+                    f,
+                    "XON/XOFF flow control without byte stuffing corrupts packets"
+                )
+            }
+            FlemSerialError::ConflictingFlowControl => {
+                write!(f, "hardware and software flow control can't be combined")
+            }
+            FlemSerialError::NotConnected => write!(f, "not connected"),
+            FlemSerialError::ListenFailed(error) => {
+                write!(f, "couldn't start listening: {}", error)
+            }
+            FlemSerialError::Vetoed => write!(f, "packet dropped by a TX interceptor"),
+            FlemSerialError::DutyCycle(error) => write!(f, "duty cycle limit: {:?}", error),
+            FlemSerialError::WriteFailed(error) => write!(f, "write failed: {}", error),
+            FlemSerialError::UnsupportedByFirmware { request, firmware } => write!(
+                f,
+                "request {:#04x} is not supported by firmware {}",
+                request, firmware
+            ),
+            FlemSerialError::ControlLines(error) => {
+                write!(f, "control line access failed: {}", error)
+            }
+            FlemSerialError::MessageTooLarge { length, max } => write!(
+                f,
+                "message of {} bytes exceeds the {} byte limit",
+                length, max
+            ),
+        }
+    }
+}
+
+impl FlemSerialError {
+    /// For errors returned by sending, whether the port is gone or only
+    /// busy. None for errors that aren't write failures.
+    pub fn write_failure(&self) -> Option<WriteFailure> {
+        match self {
+            FlemSerialError::NotConnected => Some(WriteFailure::PortGone),
+            FlemSerialError::WriteFailed(error) => Some(WriteFailure::of(error)),
+            _ => None,
+        }
+    }
+}
+
+impl Error for FlemSerialError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FlemSerialError::ErrorConnectingToDevice(error)
+            | FlemSerialError::ListenFailed(error)
+            | FlemSerialError::WriteFailed(error)
+            | FlemSerialError::ControlLines(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<DutyCycleError> for FlemSerialError {
+    fn from(error: DutyCycleError) -> Self {
+        FlemSerialError::DutyCycle(error)
+    }
+}
+
+#[cfg(feature = "link")]
+impl From<serialport::Error> for FlemSerialError {
+    fn from(error: serialport::Error) -> Self {
+        FlemSerialError::ErrorConnectingToDevice(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlemSerialError, WriteFailure};
+    use std::{error::Error, io};
+
+    #[test]
+    fn test_cause_is_kept() {
+        let error =
+            FlemSerialError::WriteFailed(io::Error::new(io::ErrorKind::TimedOut, "stalled"));
+
+        assert_eq!(error.to_string(), "write failed: stalled");
+        assert_eq!(
+            error
+                .source()
+                .and_then(|source| source.downcast_ref::<io::Error>())
+                .map(io::Error::kind),
+            Some(io::ErrorKind::TimedOut)
+        );
+    }
+
+    #[test]
+    fn test_write_failures_tell_gone_from_busy() {
+        let failure = |error: io::Error| FlemSerialError::WriteFailed(error).write_failure();
+
+        assert_eq!(
+            failure(io::ErrorKind::BrokenPipe.into()),
+            Some(WriteFailure::PortGone)
+        );
+        assert_eq!(
+            failure(io::ErrorKind::WouldBlock.into()),
+            Some(WriteFailure::Busy)
+        );
+        assert_eq!(
+            failure(io::ErrorKind::InvalidInput.into()),
+            Some(WriteFailure::Other)
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            failure(io::Error::from_raw_os_error(19)),
+            Some(WriteFailure::PortGone)
+        );
+        assert_eq!(
+            FlemSerialError::NotConnected.write_failure(),
+            Some(WriteFailure::PortGone)
+        );
+        assert_eq!(FlemSerialError::Vetoed.write_failure(), None);
+    }
+}
@@ -0,0 +1,327 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{io, time::Duration};
+#[cfg(feature = "link")]
+use std::{sync::mpsc::Sender, time::Instant};
+
+/// Changes in the state of a link, delivered on [crate::FlemRx::events].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LinkEvent {
+    /// Reads kept failing but the port path was still present and reopened
+    /// straight away. This is what macOS sleep/wake looks like: the old
+    /// handle is dead but the device never went anywhere.
+    ResumedAfterSleep,
+    /// `garbage_bytes` consecutive bytes failed to start a packet, see
+    /// [crate::desync::DesyncPolicy]. Usually a wrong baud rate.
+    LinkDesynchronized { garbage_bytes: usize },
+    /// Parse errors exceeded the [crate::degrade::DegradePolicy] rate with
+    /// `errors` in the current window. Delivery is paused.
+    Degraded { errors: u64 },
+    /// The stream has been clean for the recovery window, delivery resumed.
+    Recovered,
+    /// A received packet with request code `request` was rejected by the
+    /// link's [crate::validation::RxValidator] and not delivered.
+    ValidationFailed { request: u8, reason: String },
+    /// The port went away and could not be reopened straight away. The
+    /// listener keeps retrying according to the
+    /// [crate::reconnect::ReconnectPolicy].
+    Disconnected,
+    /// The port was reopened after `attempts` attempts and delivery
+    /// resumed.
+    Reconnected { attempts: u32 },
+    /// `packets` queued sends that failed while the link was down were
+    /// written again after it came back, `failed` of them failed again.
+    /// See [crate::FlemSerial::set_replay_on_reconnect].
+    Replayed { packets: usize, failed: usize },
+    /// `missed` keepalives in a row went unanswered, see
+    /// [crate::keepalive::KeepalivePolicy]. The port is still open but the
+    /// device has stopped responding.
+    LinkDown { missed: u32 },
+    /// A keepalive was answered again after [LinkEvent::LinkDown].
+    LinkUp,
+    /// Reading from the port failed with something other than a timeout.
+    ReadError {
+        #[cfg_attr(feature = "serde", serde(with = "error_kind"))]
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// `event` occurred `count` more times after it was last delivered.
+    Repeated { event: Box<LinkEvent>, count: u64 },
+    /// `count` events were dropped by the [EventRateLimit].
+    Suppressed { count: u64 },
+}
+
+impl LinkEvent {
+    /// Whether the event changes the state of the link. These are always
+    /// delivered, a consumer that missed one would be left with the wrong
+    /// state.
+    pub fn is_state_change(&self) -> bool {
+        matches!(
+            self,
+            LinkEvent::ResumedAfterSleep
+                | LinkEvent::Degraded { .. }
+                | LinkEvent::Recovered
+                | LinkEvent::Disconnected
+                | LinkEvent::Reconnected { .. }
+                | LinkEvent::LinkDown { .. }
+                | LinkEvent::LinkUp
+        )
+    }
+}
+
+/// [io::ErrorKind] as its name, e.g. "TimedOut". Kinds this version
+/// doesn't know come back as [io::ErrorKind::Other].
+#[cfg(feature = "serde")]
+mod error_kind {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::io::ErrorKind;
+
+    const KINDS: [ErrorKind; 20] = [
+        ErrorKind::NotFound,
+        ErrorKind::PermissionDenied,
+        ErrorKind::ConnectionRefused,
+        ErrorKind::ConnectionReset,
+        ErrorKind::ConnectionAborted,
+        ErrorKind::NotConnected,
+        ErrorKind::AddrInUse,
+        ErrorKind::AddrNotAvailable,
+        ErrorKind::BrokenPipe,
+        ErrorKind::AlreadyExists,
+        ErrorKind::WouldBlock,
+        ErrorKind::InvalidInput,
+        ErrorKind::InvalidData,
+        ErrorKind::TimedOut,
+        ErrorKind::WriteZero,
+        ErrorKind::Interrupted,
+        ErrorKind::Unsupported,
+        ErrorKind::UnexpectedEof,
+        ErrorKind::OutOfMemory,
+        ErrorKind::Other,
+    ];
+
+    pub(super) fn serialize<S: Serializer>(
+        kind: &ErrorKind,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", kind))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ErrorKind, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(KINDS
+            .into_iter()
+            .find(|kind| format!("{:?}", kind) == name)
+            .unwrap_or(ErrorKind::Other))
+    }
+}
+
+/// Caps how many events a link delivers, so an unplugged cable can't flood
+/// consumers and logs. Identical consecutive events are always folded into
+/// a single [LinkEvent::Repeated]. State changes, see
+/// [LinkEvent::is_state_change], are neither limited nor folded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventRateLimit {
+    pub max_events: u32,
+    pub window: Duration,
+}
+
+impl Default for EventRateLimit {
+    fn default() -> Self {
+        Self {
+            max_events: 20,
+            window: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Delivers events subject to an [EventRateLimit].
+#[cfg(feature = "link")]
+pub(crate) struct EventSender {
+    sender: Sender<LinkEvent>,
+    limit: EventRateLimit,
+    last: Option<LinkEvent>,
+    repeats: u64,
+    window_start: Option<Instant>,
+    sent_in_window: u32,
+    suppressed: u64,
+}
+
+#[cfg(feature = "link")]
+impl EventSender {
+    pub(crate) fn new(sender: Sender<LinkEvent>, limit: EventRateLimit) -> Self {
+        Self {
+            sender,
+            limit,
+            last: None,
+            repeats: 0,
+            window_start: None,
+            sent_in_window: 0,
+            suppressed: 0,
+        }
+    }
+
+    fn deliver(&mut self, event: LinkEvent, now: Instant) {
+        let window_start = *self.window_start.get_or_insert(now);
+        if now.saturating_duration_since(window_start) >= self.limit.window {
+            self.window_start = Some(now);
+            self.sent_in_window = 0;
+        }
+
+        if self.sent_in_window < self.limit.max_events {
+            self.sent_in_window += 1;
+            let _ = self.sender.send(event);
+        } else {
+            self.suppressed += 1;
+        }
+    }
+
+    /// Applies to the current window onwards.
+    pub(crate) fn set_limit(&mut self, limit: EventRateLimit) {
+        self.limit = limit;
+    }
+
+    pub(crate) fn send(&mut self, event: LinkEvent, now: Instant) {
+        if event.is_state_change() {
+            self.flush_repeats(now);
+            self.last = Some(event.clone());
+            let _ = self.sender.send(event);
+            return;
+        }
+        if self.last.as_ref() == Some(&event) {
+            self.repeats += 1;
+            return;
+        }
+
+        self.flush_repeats(now);
+        self.last = Some(event.clone());
+        self.deliver(event, now);
+    }
+
+    fn flush_repeats(&mut self, now: Instant) {
+        if self.repeats > 0 {
+            if let Some(last) = self.last.clone() {
+                let count = self.repeats;
+                self.repeats = 0;
+                self.deliver(
+                    LinkEvent::Repeated {
+                        event: Box::new(last),
+                        count,
+                    },
+                    now,
+                );
+            }
+        }
+    }
+
+    /// Reports pending repeats and suppressed events once per window. Called
+    /// on every pass of the listener loop.
+    pub(crate) fn tick(&mut self, now: Instant) {
+        let window_over = self
+            .window_start
+            .map(|start| now.saturating_duration_since(start) >= self.limit.window)
+            .unwrap_or(false);
+        if !window_over {
+            return;
+        }
+
+        self.flush_repeats(now);
+        if self.suppressed > 0 {
+            let count = self.suppressed;
+            self.suppressed = 0;
+            // Always delivered, even over the limit
+            let _ = self.sender.send(LinkEvent::Suppressed { count });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{EventRateLimit, EventSender, LinkEvent};
+    use std::{
+        io,
+        sync::mpsc,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn test_repeats_are_folded_and_floods_suppressed() {
+        let start = Instant::now();
+        let (sender, events) = mpsc::channel();
+        let mut limited = EventSender::new(
+            sender,
+            EventRateLimit {
+                max_events: 2,
+                window: Duration::from_secs(1),
+            },
+        );
+
+        let error = LinkEvent::ReadError {
+            kind: io::ErrorKind::BrokenPipe,
+            message: "unplugged".into(),
+        };
+        let desync = |garbage_bytes| LinkEvent::LinkDesynchronized { garbage_bytes };
+        for _ in 0..1000 {
+            limited.send(error.clone(), start);
+        }
+        limited.send(desync(10), start);
+        limited.send(desync(20), start);
+        limited.tick(start + Duration::from_millis(500));
+        limited.tick(start + Duration::from_secs(1));
+
+        let received: Vec<LinkEvent> = events.try_iter().collect();
+        assert_eq!(
+            received,
+            [
+                error.clone(),
+                LinkEvent::Repeated {
+                    event: Box::new(error),
+                    count: 999
+                },
+                LinkEvent::Suppressed { count: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_state_changes_are_never_limited() {
+        let start = Instant::now();
+        let (sender, events) = mpsc::channel();
+        let mut limited = EventSender::new(
+            sender,
+            EventRateLimit {
+                max_events: 1,
+                window: Duration::from_secs(1),
+            },
+        );
+
+        let error = LinkEvent::ReadError {
+            kind: io::ErrorKind::BrokenPipe,
+            message: "unplugged".into(),
+        };
+        limited.send(error.clone(), start);
+        limited.send(error.clone(), start);
+        for _ in 0..2 {
+            limited.send(LinkEvent::Disconnected, start);
+            limited.send(LinkEvent::Reconnected { attempts: 1 }, start);
+        }
+        limited.send(LinkEvent::LinkDown { missed: 3 }, start);
+        limited.send(LinkEvent::LinkUp, start);
+
+        let received: Vec<LinkEvent> = events.try_iter().collect();
+        assert_eq!(
+            received,
+            [
+                error,
+                LinkEvent::Disconnected,
+                LinkEvent::Reconnected { attempts: 1 },
+                LinkEvent::Disconnected,
+                LinkEvent::Reconnected { attempts: 1 },
+                LinkEvent::LinkDown { missed: 3 },
+                LinkEvent::LinkUp,
+            ]
+        );
+    }
+}
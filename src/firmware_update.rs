@@ -0,0 +1,310 @@
+use crate::{FlemRx, FlemSerial};
+use std::time::{Duration, Instant};
+
+/// Bytes in front of the image data in every chunk.
+const CHUNK_HEADER_SIZE: usize = 8;
+
+/// How a firmware image is sent.
+///
+/// Each chunk is sent with `request` and a payload of the little endian
+/// `u32` sequence number, starting at 0, the little endian `u32` offset of
+/// the chunk in the image, then the image bytes. The device acknowledges
+/// with the same request code, a success response and the sequence number
+/// as the first 4 payload bytes. Any other response status rejects the
+/// chunk. A final chunk with no image bytes, at the offset of the image's
+/// end, marks the end of the image and is acknowledged the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateOptions {
+    pub request: u8,
+    /// Image bytes per chunk, capped to what fits in a packet after the
+    /// chunk header.
+    pub chunk: usize,
+    /// How long to wait for each acknowledgement.
+    pub timeout: Duration,
+    /// Times a chunk is sent again after a timeout or rejection before the
+    /// update fails.
+    pub max_retries: u32,
+}
+
+/// How far an update has got, passed to the progress callback after every
+/// acknowledged chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateProgress {
+    pub sent_bytes: usize,
+    pub total_bytes: usize,
+    /// Chunks acknowledged so far, end of image marker included.
+    pub chunks_done: u32,
+    pub chunks_total: u32,
+    /// Chunks sent again so far.
+    pub retries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateError {
+    /// The image has more chunks than sequence numbers.
+    ImageTooLarge,
+    /// Writing chunk `sequence` failed.
+    SendFailed { sequence: u32 },
+    /// Chunk `sequence` was not acknowledged after every retry.
+    Timeout { sequence: u32 },
+    /// The device rejected chunk `sequence` on every retry, the last time
+    /// with `response`.
+    Rejected { sequence: u32, response: u8 },
+}
+
+/// Sends firmware images to a listening device in acknowledged chunks, see
+/// [UpdateOptions] for the protocol.
+///
+/// Packets received during the update that don't acknowledge the current
+/// chunk are dropped, including late acknowledgements of earlier attempts.
+pub struct FirmwareUpdater<'a, const T: usize> {
+    serial: &'a mut FlemSerial<T>,
+    rx: &'a FlemRx<T>,
+    options: UpdateOptions,
+    on_progress: Option<Box<dyn FnMut(UpdateProgress) + 'a>>,
+}
+
+impl<'a, const T: usize> FirmwareUpdater<'a, T> {
+    pub fn new(serial: &'a mut FlemSerial<T>, rx: &'a FlemRx<T>, options: UpdateOptions) -> Self {
+        Self {
+            serial,
+            rx,
+            options,
+            on_progress: None,
+        }
+    }
+
+    /// Calls `callback` after every acknowledged chunk.
+    pub fn on_progress<F: FnMut(UpdateProgress) + 'a>(&mut self, callback: F) {
+        self.on_progress = Some(Box::new(callback));
+    }
+
+    /// Sends `image` and waits for every chunk to be acknowledged. Returns
+    /// the final progress.
+    pub fn update(&mut self, image: &[u8]) -> Result<UpdateProgress, UpdateError> {
+        let chunk = self
+            .options
+            .chunk
+            .clamp(1, T.saturating_sub(CHUNK_HEADER_SIZE).max(1));
+        // The end of image marker is a chunk too
+        let chunks_total = u32::try_from(image.len().div_ceil(chunk) + 1)
+            .map_err(|_| UpdateError::ImageTooLarge)?;
+
+        let mut progress = UpdateProgress {
+            sent_bytes: 0,
+            total_bytes: image.len(),
+            chunks_done: 0,
+            chunks_total,
+            retries: 0,
+        };
+
+        for sequence in 0..chunks_total {
+            let offset = (sequence as usize * chunk).min(image.len());
+            let data = &image[offset..(offset + chunk).min(image.len())];
+
+            self.send_chunk(sequence, offset as u32, data, &mut progress.retries)?;
+
+            progress.sent_bytes += data.len();
+            progress.chunks_done += 1;
+            if let Some(callback) = self.on_progress.as_mut() {
+                callback(progress);
+            }
+        }
+
+        Ok(progress)
+    }
+
+    /// Sends one chunk until it is acknowledged or the retries run out.
+    fn send_chunk(
+        &mut self,
+        sequence: u32,
+        offset: u32,
+        data: &[u8],
+        retries: &mut u32,
+    ) -> Result<(), UpdateError> {
+        let mut packet = flem::Packet::<T>::new();
+        packet.set_request(self.options.request);
+        let mut payload = Vec::with_capacity(CHUNK_HEADER_SIZE + data.len());
+        payload.extend_from_slice(&sequence.to_le_bytes());
+        payload.extend_from_slice(&offset.to_le_bytes());
+        payload.extend_from_slice(data);
+        packet
+            .add_data(&payload)
+            .map_err(|_| UpdateError::SendFailed { sequence })?;
+        packet.pack();
+
+        let mut attempt = 0;
+        loop {
+            let failure = match self.attempt(&packet, sequence) {
+                Ok(()) => return Ok(()),
+                Err(error @ UpdateError::SendFailed { .. }) => return Err(error),
+                Err(failure) => failure,
+            };
+
+            if attempt == self.options.max_retries {
+                return Err(failure);
+            }
+            attempt += 1;
+            *retries += 1;
+        }
+    }
+
+    fn attempt(&mut self, packet: &flem::Packet<T>, sequence: u32) -> Result<(), UpdateError> {
+        self.serial
+            .send(packet)
+            .map_err(|_| UpdateError::SendFailed { sequence })?;
+
+        let deadline = Instant::now() + self.options.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let response = self
+                .rx
+                .recv_timeout(remaining)
+                .map_err(|_| UpdateError::Timeout { sequence })?;
+
+            let acked = response
+                .get_data()
+                .get(..4)
+                .map(|acked| u32::from_le_bytes(acked.try_into().unwrap()));
+            if response.get_request() != self.options.request || acked != Some(sequence) {
+                continue;
+            }
+            if response.get_response() != flem::Response::SUCCESS {
+                return Err(UpdateError::Rejected {
+                    sequence,
+                    response: response.get_response(),
+                });
+            }
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FirmwareUpdater, UpdateError, UpdateOptions};
+    use crate::FlemSerial;
+    use std::{
+        collections::VecDeque,
+        io::{self, Read, Write},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    const UPDATE: u8 = 0x40;
+
+    /// Writes acknowledged chunks into `image`. Ignores the first attempt
+    /// at chunk 1 and rejects every chunk at or past `reject_from`.
+    struct Bootloader {
+        incoming: flem::Packet<64>,
+        outgoing: VecDeque<u8>,
+        image: Arc<Mutex<Vec<u8>>>,
+        dropped_once: bool,
+        reject_from: u32,
+    }
+
+    impl Read for Bootloader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.outgoing.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.outgoing.len());
+            for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for Bootloader {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                if let flem::Status::PacketReceived = self.incoming.add_byte(*byte) {
+                    let data = self.incoming.get_data().to_vec();
+                    self.incoming.reset_lazy();
+                    let sequence = u32::from_le_bytes(data[..4].try_into().unwrap());
+                    if sequence == 1 && !self.dropped_once {
+                        self.dropped_once = true;
+                        continue;
+                    }
+
+                    let mut ack = flem::Packet::<64>::new();
+                    ack.set_request(UPDATE);
+                    if sequence >= self.reject_from {
+                        ack.set_response(flem::Response::ERROR);
+                    } else {
+                        let offset = u32::from_le_bytes(data[4..8].try_into().unwrap());
+                        let mut image = self.image.lock().unwrap();
+                        assert_eq!(offset as usize, image.len());
+                        image.extend_from_slice(&data[8..]);
+                    }
+                    ack.add_data(&sequence.to_le_bytes()).unwrap();
+                    ack.pack();
+                    self.outgoing.extend(ack.bytes());
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn bootloader(reject_from: u32) -> (Bootloader, Arc<Mutex<Vec<u8>>>) {
+        let image = Arc::new(Mutex::new(Vec::new()));
+        let device = Bootloader {
+            incoming: flem::Packet::new(),
+            outgoing: VecDeque::new(),
+            image: image.clone(),
+            dropped_once: false,
+            reject_from,
+        };
+        (device, image)
+    }
+
+    fn options() -> UpdateOptions {
+        UpdateOptions {
+            request: UPDATE,
+            chunk: 100,
+            timeout: Duration::from_millis(50),
+            max_retries: 2,
+        }
+    }
+
+    #[test]
+    fn test_image_arrives_in_order_despite_a_lost_chunk() {
+        let (device, written) = bootloader(u32::MAX);
+        let mut serial = FlemSerial::<64>::from_transport(device);
+        let rx = serial.listen().unwrap();
+        let image: Vec<u8> = (0..150).map(|i| i as u8).collect();
+
+        let mut reports = Vec::new();
+        let mut updater = FirmwareUpdater::new(&mut serial, &rx, options());
+        updater.on_progress(|progress| reports.push(progress.sent_bytes));
+        let progress = updater.update(&image).unwrap();
+        drop(updater);
+
+        // 56 bytes of image fit in a 64 byte packet
+        assert_eq!(reports, [56, 112, 150, 150]);
+        assert_eq!(progress.chunks_total, 4);
+        assert_eq!(progress.retries, 1);
+        assert_eq!(*written.lock().unwrap(), image);
+    }
+
+    #[test]
+    fn test_rejected_chunk_fails_after_retries() {
+        let (device, _) = bootloader(0);
+        let mut serial = FlemSerial::<64>::from_transport(device);
+        let rx = serial.listen().unwrap();
+
+        let mut updater = FirmwareUpdater::new(&mut serial, &rx, options());
+        assert_eq!(
+            updater.update(&[1, 2, 3]),
+            Err(UpdateError::Rejected {
+                sequence: 0,
+                response: flem::Response::ERROR,
+            })
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use crate::clock::Clock;
+use crate::events::LinkEvent;
+use crate::listener::{Delivery, ListenerShared, ListenerThread};
+use crate::stats::{LinkRates, LinkStats};
+use crate::{FlemSerial, FlemSerialError};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Bytes in front of the message data in every fragment: the little endian
+/// `u16` message ID, `u16` fragment index and `u16` fragment count.
+pub const FRAGMENT_HEADER_SIZE: usize = 6;
+
+/// Largest fragment count a message can be split into.
+const MAX_FRAGMENTS: usize = u16::MAX as usize;
+
+/// Largest message [FlemSerial::send_large] can send in packets with
+/// `packet_size` byte payloads.
+pub const fn max_message_size(packet_size: usize) -> usize {
+    packet_size.saturating_sub(FRAGMENT_HEADER_SIZE) * MAX_FRAGMENTS
+}
+
+/// Splits `data` into packets with `request`, each carrying a fragment
+/// header. Empty messages are sent as one empty fragment.
+pub(crate) fn fragment<const T: usize>(
+    request: u8,
+    message_id: u16,
+    data: &[u8],
+) -> Result<Vec<flem::Packet<T>>, FlemSerialError> {
+    let max = max_message_size(T);
+    if data.len() > max || T <= FRAGMENT_HEADER_SIZE {
+        return Err(FlemSerialError::MessageTooLarge {
+            length: data.len(),
+            max,
+        });
+    }
+
+    let chunk = T - FRAGMENT_HEADER_SIZE;
+    let count = data.len().div_ceil(chunk).max(1);
+    (0..count)
+        .map(|index| {
+            let part =
+                &data[(index * chunk).min(data.len())..((index + 1) * chunk).min(data.len())];
+            let mut payload = Vec::with_capacity(FRAGMENT_HEADER_SIZE + part.len());
+            payload.extend_from_slice(&message_id.to_le_bytes());
+            payload.extend_from_slice(&(index as u16).to_le_bytes());
+            payload.extend_from_slice(&(count as u16).to_le_bytes());
+            payload.extend_from_slice(part);
+
+            let mut packet = flem::Packet::<T>::new();
+            packet.set_request(request);
+            packet
+                .add_data(&payload)
+                .map_err(|_| FlemSerialError::MessageTooLarge {
+                    length: data.len(),
+                    max,
+                })?;
+            packet.pack();
+            Ok(packet)
+        })
+        .collect()
+}
+
+/// Where a fragment belongs, read from its header.
+struct FragmentHeader {
+    message_id: u16,
+    index: usize,
+    count: usize,
+}
+
+impl FragmentHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < FRAGMENT_HEADER_SIZE {
+            return None;
+        }
+        let field = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]) as usize;
+        let header = Self {
+            message_id: field(0) as u16,
+            index: field(2),
+            count: field(4),
+        };
+        (header.index < header.count).then_some(header)
+    }
+}
+
+/// What [FlemMessageRx] delivers.
+#[derive(Clone)]
+pub enum MessageItem<const T: usize> {
+    /// Every fragment of a message arrived, joined in order.
+    Message(Vec<u8>),
+    /// A message was abandoned because its fragments stopped coming or the
+    /// next message started first.
+    Incomplete {
+        message_id: u16,
+        received: usize,
+        expected: usize,
+    },
+    /// A packet that isn't a fragment.
+    Packet(flem::Packet<T>),
+}
+
+impl<const T: usize> fmt::Debug for MessageItem<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageItem::Message(data) => f.debug_tuple("Message").field(data).finish(),
+            MessageItem::Incomplete {
+                message_id,
+                received,
+                expected,
+            } => f
+                .debug_struct("Incomplete")
+                .field("message_id", message_id)
+                .field("received", received)
+                .field("expected", expected)
+                .finish(),
+            MessageItem::Packet(packet) => f
+                .debug_struct("Packet")
+                .field("request", &packet.get_request())
+                .field("response", &packet.get_response())
+                .field("data", &packet.get_data())
+                .finish(),
+        }
+    }
+}
+
+struct Partial {
+    message_id: u16,
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// Fragment packets taken from the listener, duplicates included.
+    packets: usize,
+    started: Instant,
+}
+
+/// Reassembles fragments on the listener thread. Senders finish one
+/// message before starting the next, so only one is assembled at a time.
+pub(crate) struct Reassembler<const T: usize> {
+    sender: Sender<MessageItem<T>>,
+    request: u8,
+    timeout: Option<Duration>,
+    current: Option<Partial>,
+    clock: Arc<dyn Clock>,
+    /// The listener counts every packet it delivers, the reassembler
+    /// counts the fragments of a message back off down to the one item
+    /// that is queued for them.
+    queue_depth: Option<Arc<AtomicUsize>>,
+}
+
+impl<const T: usize> Reassembler<T> {
+    pub(crate) fn new(
+        sender: Sender<MessageItem<T>>,
+        request: u8,
+        timeout: Option<Duration>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            sender,
+            request,
+            timeout,
+            current: None,
+            clock,
+            queue_depth: None,
+        }
+    }
+
+    pub(crate) fn count_depth_in(&mut self, queue_depth: Arc<AtomicUsize>) {
+        self.queue_depth = Some(queue_depth);
+    }
+
+    pub(crate) fn push(&mut self, packet: flem::Packet<T>) -> Result<(), ()> {
+        let header = match packet.get_request() == self.request {
+            true => FragmentHeader::parse(packet.get_data()),
+            false => None,
+        };
+        let Some(header) = header else {
+            return self.send(MessageItem::Packet(packet), 1);
+        };
+
+        let belongs = self.current.as_ref().is_some_and(|partial| {
+            partial.message_id == header.message_id && partial.fragments.len() == header.count
+        });
+        if !belongs {
+            self.abandon()?;
+            self.current = Some(Partial {
+                message_id: header.message_id,
+                fragments: vec![None; header.count],
+                received: 0,
+                packets: 0,
+                started: self.clock.now(),
+            });
+        }
+
+        let partial = self.current.as_mut().unwrap();
+        partial.packets += 1;
+        let slot = &mut partial.fragments[header.index];
+        if slot.is_none() {
+            *slot = Some(packet.get_data()[FRAGMENT_HEADER_SIZE..].to_vec());
+            partial.received += 1;
+        }
+
+        if partial.received == partial.fragments.len() {
+            let partial = self.current.take().unwrap();
+            let packets = partial.packets;
+            let data = partial.fragments.into_iter().flatten().flatten().collect();
+            return self.send(MessageItem::Message(data), packets);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn tick(&mut self) -> Result<(), ()> {
+        match (self.current.as_ref(), self.timeout) {
+            (Some(partial), Some(timeout)) if self.clock.now() - partial.started >= timeout => {
+                self.abandon()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn abandon(&mut self) -> Result<(), ()> {
+        match self.current.take() {
+            Some(partial) => self.send(
+                MessageItem::Incomplete {
+                    message_id: partial.message_id,
+                    received: partial.received,
+                    expected: partial.fragments.len(),
+                },
+                partial.packets,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Queues an item made of `packets` delivered packets.
+    fn send(&self, item: MessageItem<T>, packets: usize) -> Result<(), ()> {
+        if let Some(queue_depth) = self.queue_depth.as_ref() {
+            queue_depth.fetch_sub(packets.saturating_sub(1), Ordering::AcqRel);
+        }
+        self.sender.send(item).map_err(|_| ())
+    }
+}
+
+impl<const T: usize> FlemSerial<T> {
+    /// Sends `data` as one message, split across as many packets with
+    /// `request` as it needs. Each packet carries a
+    /// [FRAGMENT_HEADER_SIZE] byte header, see
+    /// [FlemSerial::listen_reassembled] for the receiving side. Fragments go
+    /// through `send`, so interceptors and limits apply to each one.
+    pub fn send_large(&mut self, request: u8, data: &[u8]) -> Result<(), FlemSerialError> {
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        for packet in fragment::<T>(request, message_id, data)? {
+            self.send(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Like [FlemSerial::listen], but packets with `request` are taken as
+    /// fragments from [FlemSerial::send_large] and delivered once the whole
+    /// message has arrived. A message whose fragments stop arriving for
+    /// `timeout` is reported as [MessageItem::Incomplete]. None waits until
+    /// the next message starts.
+    pub fn listen_reassembled(
+        &mut self,
+        request: u8,
+        timeout: Option<Duration>,
+    ) -> Result<FlemMessageRx<T>, FlemSerialError> {
+        let (message_queue, rx) = mpsc::channel::<MessageItem<T>>();
+
+        let (rx_thread_handle, events, shared) = self.spawn_listener(Delivery::Reassembled(
+            Reassembler::new(message_queue, request, timeout, self.clock.clone()),
+        ))?;
+
+        Ok(FlemMessageRx {
+            rx_listener_handle: rx_thread_handle,
+            rx_message_queue: rx,
+            events,
+            shared,
+        })
+    }
+}
+
+/// Receive handle returned by [crate::FlemSerial::listen_reassembled].
+/// Dropping it stops its listener.
+pub struct FlemMessageRx<const T: usize> {
+    pub(crate) rx_listener_handle: ListenerThread,
+    pub(crate) rx_message_queue: Receiver<MessageItem<T>>,
+    pub(crate) events: Receiver<LinkEvent>,
+    pub(crate) shared: ListenerShared,
+}
+
+impl<const T: usize> FlemMessageRx<T> {
+    /// Raw access to the queue. Items taken directly from the queue are not
+    /// counted by [FlemMessageRx::queue_depth].
+    pub fn queue(&self) -> &Receiver<MessageItem<T>> {
+        &self.rx_message_queue
+    }
+
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    fn received(&self, item: MessageItem<T>) -> MessageItem<T> {
+        self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        item
+    }
+
+    /// Blocks until a message or packet is received.
+    pub fn recv(&self) -> Result<MessageItem<T>, RecvError> {
+        self.rx_message_queue.recv().map(|item| self.received(item))
+    }
+
+    /// Returns a message or packet if one is waiting.
+    pub fn try_recv(&self) -> Result<MessageItem<T>, TryRecvError> {
+        self.rx_message_queue
+            .try_recv()
+            .map(|item| self.received(item))
+    }
+
+    /// Blocks until a message or packet is received or `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<MessageItem<T>, RecvTimeoutError> {
+        self.rx_message_queue
+            .recv_timeout(timeout)
+            .map(|item| self.received(item))
+    }
+
+    /// Number of items waiting to be received plus the fragments of a
+    /// message still being assembled.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        self.rx_listener_handle.handle()
+    }
+
+    /// Waits for the listener thread to exit. The listener must be stopped
+    /// first or this will block forever.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fragment, MessageItem, Reassembler};
+    use crate::{clock::MockClock, FlemSerial};
+    use std::{
+        io::{self, Cursor},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    const LARGE: u8 = 0x30;
+
+    #[test]
+    fn test_fragments_are_joined_in_order() {
+        let clock = Arc::new(MockClock::new());
+        let (tx, rx) = mpsc::channel();
+        let mut reassembler =
+            Reassembler::<16>::new(tx, LARGE, Some(Duration::from_millis(100)), clock.clone());
+
+        let data: Vec<u8> = (0..25).collect();
+        let mut fragments = fragment::<16>(LARGE, 7, &data).unwrap();
+        assert_eq!(fragments.len(), 3);
+        // Arrive out of order, with an ordinary packet in between
+        let last = fragments.pop().unwrap();
+        reassembler.push(last).unwrap();
+        let mut event = flem::Packet::<16>::new();
+        event.set_request(flem::Request::EVENT);
+        event.pack();
+        reassembler.push(event).unwrap();
+        for packet in fragments {
+            reassembler.push(packet).unwrap();
+        }
+
+        assert!(matches!(rx.try_recv(), Ok(MessageItem::Packet(_))));
+        let Ok(MessageItem::Message(joined)) = rx.try_recv() else {
+            panic!("expected a message");
+        };
+        assert_eq!(joined, data);
+
+        // The rest of this message never arrives
+        let first = fragment::<16>(LARGE, 8, &data).unwrap().remove(0);
+        reassembler.push(first).unwrap();
+        clock.advance(Duration::from_millis(100));
+        reassembler.tick().unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(MessageItem::Incomplete {
+                message_id: 8,
+                received: 1,
+                expected: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_a_message_counts_once_however_many_fragments_arrived() {
+        let (tx, rx) = mpsc::channel();
+        let mut reassembler = Reassembler::<16>::new(tx, LARGE, None, Arc::new(MockClock::new()));
+        let queue_depth = Arc::new(AtomicUsize::new(0));
+        reassembler.count_depth_in(queue_depth.clone());
+
+        let data: Vec<u8> = (0..25).collect();
+        let fragments = fragment::<16>(LARGE, 3, &data).unwrap();
+        // The listener counts every packet it delivers, the first fragment
+        // arrives twice
+        for packet in [&fragments[0], &fragments[0], &fragments[1], &fragments[2]] {
+            queue_depth.fetch_add(1, Ordering::AcqRel);
+            reassembler.push(packet.clone()).unwrap();
+        }
+
+        assert!(matches!(rx.try_recv(), Ok(MessageItem::Message(_))));
+        assert_eq!(queue_depth.load(Ordering::Acquire), 1);
+    }
+
+    /// Keeps what is written, reads nothing.
+    #[derive(Clone)]
+    struct Wire(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Read for Wire {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send_large_round_trips_through_a_listener() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let wire = Wire(Arc::new(Mutex::new(Vec::new())));
+        let mut sender = FlemSerial::<64>::from_transport(wire.clone());
+        sender.send_large(LARGE, &data).unwrap();
+        sender.send_large(LARGE, &[]).unwrap();
+
+        let bytes = wire.0.lock().unwrap().clone();
+        let mut receiver = FlemSerial::<64>::from_transport(Cursor::new(bytes));
+        let rx = receiver.listen_reassembled(LARGE, None).unwrap();
+        let timeout = Duration::from_secs(1);
+        let Ok(MessageItem::Message(joined)) = rx.recv_timeout(timeout) else {
+            panic!("expected a message");
+        };
+        assert_eq!(joined, data);
+        assert!(
+            matches!(rx.recv_timeout(timeout), Ok(MessageItem::Message(empty)) if empty.is_empty())
+        );
+    }
+}
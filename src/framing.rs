@@ -0,0 +1,412 @@
+#[cfg(feature = "link")]
+use crate::listener::MAX_READ_BUFFER;
+#[cfg(feature = "link")]
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+#[cfg(feature = "link")]
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+/// Byte-stuffed framing applied around each write, for links such as
+/// radio modems that need delimited frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    #[default]
+    None,
+    /// Consistent Overhead Byte Stuffing, frames end with 0x00.
+    Cobs,
+    /// RFC 1055 SLIP, frames start and end with 0xC0.
+    Slip,
+}
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+/// COBS encodes `bytes` and appends the 0x00 delimiter.
+pub fn cobs_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len() + bytes.len() / 254 + 2);
+    let mut code_index = 0;
+    encoded.push(0);
+    let mut code = 1u8;
+
+    for byte in bytes.iter() {
+        if *byte == 0 {
+            encoded[code_index] = code;
+            code_index = encoded.len();
+            encoded.push(0);
+            code = 1;
+        } else {
+            encoded.push(*byte);
+            code += 1;
+            if code == 0xFF {
+                encoded[code_index] = code;
+                code_index = encoded.len();
+                encoded.push(0);
+                code = 1;
+            }
+        }
+    }
+
+    encoded[code_index] = code;
+    encoded.push(0);
+    encoded
+}
+
+/// Decodes one COBS frame without its delimiter. Returns None if the frame
+/// is malformed.
+pub fn cobs_decode(frame: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(frame.len());
+    let mut i = 0;
+
+    while i < frame.len() {
+        let code = frame[i] as usize;
+        if code == 0 || i + code > frame.len() {
+            return None;
+        }
+        decoded.extend_from_slice(&frame[i + 1..i + code]);
+        i += code;
+        if code < 0xFF && i < frame.len() {
+            decoded.push(0);
+        }
+    }
+
+    Some(decoded)
+}
+
+/// SLIP encodes `bytes` between two END bytes. The leading END flushes any
+/// line noise the receiver has buffered.
+pub fn slip_encode(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(bytes.len() + 2);
+    encoded.push(SLIP_END);
+    for byte in bytes.iter() {
+        match *byte {
+            SLIP_END => encoded.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => encoded.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            byte => encoded.push(byte),
+        }
+    }
+    encoded.push(SLIP_END);
+    encoded
+}
+
+/// Decodes one SLIP frame without its END bytes. Returns None if the frame
+/// contains an invalid escape.
+pub fn slip_decode(frame: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(frame.len());
+    let mut bytes = frame.iter();
+
+    while let Some(byte) = bytes.next() {
+        match *byte {
+            SLIP_ESC => match bytes.next() {
+                Some(&SLIP_ESC_END) => decoded.push(SLIP_END),
+                Some(&SLIP_ESC_ESC) => decoded.push(SLIP_ESC),
+                _ => return None,
+            },
+            byte => decoded.push(byte),
+        }
+    }
+
+    Some(decoded)
+}
+
+/// Encodes every write as one frame and hands back the decoded contents of
+/// each frame read. Malformed frames are dropped, as are frames longer than
+/// [MAX_READ_BUFFER], up to the next delimiter.
+#[cfg(feature = "link")]
+pub(crate) struct FramedPort {
+    inner: Box<dyn SerialPort>,
+    framing: Framing,
+    rx_frame: Vec<u8>,
+    /// Set once `rx_frame` overflowed, until the next delimiter.
+    rx_discarding: bool,
+    rx_decoded: VecDeque<u8>,
+}
+
+#[cfg(feature = "link")]
+impl FramedPort {
+    pub fn new(inner: Box<dyn SerialPort>, framing: Framing) -> Self {
+        Self {
+            inner,
+            framing,
+            rx_frame: Vec::new(),
+            rx_discarding: false,
+            rx_decoded: VecDeque::new(),
+        }
+    }
+
+    fn delimiter(&self) -> Option<u8> {
+        match self.framing {
+            Framing::None => None,
+            Framing::Cobs => Some(0),
+            Framing::Slip => Some(SLIP_END),
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if self.rx_frame.is_empty() {
+            return;
+        }
+        let decoded = match self.framing {
+            Framing::None => Some(self.rx_frame.clone()),
+            Framing::Cobs => cobs_decode(&self.rx_frame),
+            Framing::Slip => slip_decode(&self.rx_frame),
+        };
+        if let Some(decoded) = decoded {
+            self.rx_decoded.extend(decoded);
+        }
+        self.rx_frame.clear();
+    }
+}
+
+#[cfg(feature = "link")]
+impl Read for FramedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let delimiter = match self.delimiter() {
+            Some(delimiter) => delimiter,
+            None => return self.inner.read(buf),
+        };
+
+        while self.rx_decoded.is_empty() {
+            let mut raw = [0u8; 64];
+            let bytes_read = self.inner.read(&mut raw)?;
+            if bytes_read == 0 {
+                return Ok(0);
+            }
+            for byte in raw[..bytes_read].iter() {
+                if *byte == delimiter {
+                    match self.rx_discarding {
+                        true => self.rx_discarding = false,
+                        false => self.end_frame(),
+                    }
+                } else if !self.rx_discarding {
+                    if self.rx_frame.len() < MAX_READ_BUFFER {
+                        self.rx_frame.push(*byte);
+                    } else {
+                        // Noise, or a peer that never delimits its frames
+                        self.rx_frame = Vec::new();
+                        self.rx_discarding = true;
+                    }
+                }
+            }
+        }
+
+        let length = buf.len().min(self.rx_decoded.len());
+        for (slot, byte) in buf.iter_mut().zip(self.rx_decoded.drain(..length)) {
+            *slot = byte;
+        }
+        Ok(length)
+    }
+}
+
+#[cfg(feature = "link")]
+impl Write for FramedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let frame = match self.framing {
+            Framing::None => return self.inner.write(buf),
+            Framing::Cobs => cobs_encode(buf),
+            Framing::Slip => slip_encode(buf),
+        };
+        self.inner.write_all(&frame)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "link")]
+impl SerialPort for FramedPort {
+    fn name(&self) -> Option<String> {
+        self.inner.name()
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        self.inner.baud_rate()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        self.inner.data_bits()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        self.inner.flow_control()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        self.inner.parity()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        self.inner.stop_bits()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.inner.timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.inner.set_baud_rate(baud_rate)
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.inner.set_data_bits(data_bits)
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.inner.set_flow_control(flow_control)
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.inner.set_parity(parity)
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.inner.set_stop_bits(stop_bits)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_request_to_send(level)
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_data_terminal_ready(level)
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.inner.read_clear_to_send()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.inner.read_data_set_ready()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.inner.read_ring_indicator()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.inner.read_carrier_detect()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_read()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_write()
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        self.inner.clear(buffer_to_clear)
+    }
+
+    // The clone starts with its own frame buffer, so only one clone should
+    // read.
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(FramedPort::new(
+            self.inner.try_clone()?,
+            self.framing,
+        )))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.inner.set_break()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.inner.clear_break()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cobs_decode, cobs_encode, slip_decode, slip_encode, SLIP_END};
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_frames_past_the_read_limit_are_dropped() {
+        use super::{FramedPort, Framing, MAX_READ_BUFFER};
+        use crate::port::OpenPort;
+        use std::io::{Cursor, Read};
+
+        let mut stream = vec![0x55; MAX_READ_BUFFER + 100];
+        stream.push(0);
+        stream.extend(cobs_encode(&[0x11, 0x00, 0x22]));
+        let inner = OpenPort::from_stream(Cursor::new(stream)).port;
+        let mut port = FramedPort::new(inner, Framing::Cobs);
+
+        let mut buf = [0u8; 16];
+        let length = port.read(&mut buf).unwrap();
+        assert_eq!(buf[..length], [0x11, 0x00, 0x22]);
+        assert!(port.rx_frame.capacity() <= MAX_READ_BUFFER);
+        assert!(!port.rx_discarding);
+    }
+
+    #[test]
+    fn test_cobs_round_trip() {
+        let mut long = vec![0x01; 300];
+        long[100] = 0;
+        for data in [vec![], vec![0], vec![0x11, 0x00, 0x22, 0x00], long] {
+            let encoded = cobs_encode(&data);
+            let (delimiter, frame) = encoded.split_last().unwrap();
+            assert_eq!(*delimiter, 0);
+            assert!(!frame.contains(&0));
+            assert_eq!(cobs_decode(frame).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_slip_round_trip() {
+        let data = [0x01, SLIP_END, 0xDB, 0x02];
+        let encoded = slip_encode(&data);
+        let frame = &encoded[1..encoded.len() - 1];
+        assert!(!frame.contains(&SLIP_END));
+        assert_eq!(slip_decode(frame).unwrap(), data);
+    }
+
+    #[test]
+    fn test_packets_survive_the_codecs_without_a_serial_backend() {
+        use crate::{
+            router::PacketRouter,
+            xon_xoff::{stuff, Unstuffer},
+        };
+        use std::sync::{Arc, Mutex};
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[0x00, 0x11, 0x13, 0xC0]).unwrap();
+        packet.pack();
+
+        // Layered the way a link layers them: framing inside, stuffing outside
+        let mut received = stuff(&cobs_encode(packet.bytes()));
+        let length = Unstuffer::default().unstuff(&mut received);
+        let (_, frame) = received[..length].split_last().unwrap();
+        let decoded = cobs_decode(frame).unwrap();
+
+        let mut parsed = flem::Packet::<64>::new();
+        let statuses: Vec<_> = decoded.iter().map(|byte| parsed.add_byte(*byte)).collect();
+        assert!(matches!(
+            statuses.last(),
+            Some(flem::Status::PacketReceived)
+        ));
+
+        // The router needs no serial backend either
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut router = PacketRouter::<64>::new();
+        let routed = events.clone();
+        router.on(flem::Request::EVENT, move |packet| {
+            routed.lock().unwrap().push(packet.get_data().to_vec())
+        });
+        assert!(router.dispatch(parsed));
+        assert_eq!(*events.lock().unwrap(), [vec![0x00, 0x11, 0x13, 0xC0]]);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{events::LinkEvent, scope, FlemRx};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Repaint interval used by [GuiAdapter::egui], about 60 frames a second.
+pub const DEFAULT_REPAINT_INTERVAL: Duration = Duration::from_millis(16);
+
+/// How long the forwarding thread waits for a packet before checking
+/// events and pending repaints.
+const FORWARD_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Something a link delivered, as one message type for a GUI's update
+/// loop.
+#[derive(Clone)]
+pub enum GuiMessage<const T: usize> {
+    Packet(flem::Packet<T>),
+    Event(LinkEvent),
+}
+
+/// Coalesces bursts of incoming data into at most one repaint per
+/// `min_interval`, without ever leaving data unpainted.
+#[derive(Debug, Clone)]
+pub struct RepaintThrottle {
+    min_interval: Duration,
+    last: Option<Instant>,
+    pending: bool,
+}
+
+impl RepaintThrottle {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last: None,
+            pending: false,
+        }
+    }
+
+    /// Data arrived at `now`. Returns true if the UI should repaint now,
+    /// otherwise the repaint is deferred to a later [RepaintThrottle::tick].
+    pub fn on_data(&mut self, now: Instant) -> bool {
+        self.pending = true;
+        self.tick(now)
+    }
+
+    /// Returns true if a deferred repaint is due at `now`.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let due = self
+            .last
+            .map(|last| now.saturating_duration_since(last) >= self.min_interval)
+            .unwrap_or(true);
+        if self.pending && due {
+            self.pending = false;
+            self.last = Some(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Default)]
+struct AdapterShared {
+    messages: AtomicU64,
+    repaints: AtomicU64,
+    #[cfg(feature = "async")]
+    waker: std::sync::Mutex<Option<std::task::Waker>>,
+}
+
+impl AdapterShared {
+    fn wake(&self) {
+        #[cfg(feature = "async")]
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Moves a link's packets and events onto a single message queue for a GUI
+/// thread and wakes the GUI, throttled, when new messages arrive.
+///
+/// With egui, poll [GuiAdapter::drain] from `update` and let the adapter
+/// call `request_repaint`. With iced, enable the `async` feature and use the
+/// adapter as the stream of a subscription; wakeups follow the same
+/// throttle.
+pub struct GuiAdapter<const T: usize> {
+    messages: Receiver<GuiMessage<T>>,
+    shared: Arc<AdapterShared>,
+    handle: JoinHandle<()>,
+}
+
+impl<const T: usize> GuiAdapter<T> {
+    /// Takes over `rx` and calls `repaint` at most once per
+    /// `min_repaint_interval` while messages keep arriving.
+    pub fn spawn<F>(rx: FlemRx<T>, min_repaint_interval: Duration, repaint: F) -> Self
+    where
+        F: Fn() + Send + 'static,
+    {
+        let (sender, messages) = mpsc::channel();
+        let shared = Arc::new(AdapterShared::default());
+        let thread_shared = shared.clone();
+
+        let scope = rx.scope_handle();
+        // Nothing to stop, the thread ends with the listener, which the same
+        // scope stops
+        let handle = scope::spawn_scoped(
+            "gui",
+            None,
+            None,
+            scope.as_ref(),
+            || {},
+            move || {
+                let mut throttle = RepaintThrottle::new(min_repaint_interval);
+                loop {
+                    let mut received = 0;
+                    let connected = match rx.recv_timeout(FORWARD_POLL_INTERVAL) {
+                        Ok(packet) => {
+                            received += 1;
+                            sender.send(GuiMessage::Packet(packet)).is_ok()
+                        }
+                        Err(RecvTimeoutError::Timeout) => true,
+                        Err(RecvTimeoutError::Disconnected) => false,
+                    };
+                    while let Ok(event) = rx.events().try_recv() {
+                        received += 1;
+                        let _ = sender.send(GuiMessage::Event(event));
+                    }
+
+                    if !connected {
+                        break;
+                    }
+
+                    thread_shared
+                        .messages
+                        .fetch_add(received, Ordering::Relaxed);
+                    let now = Instant::now();
+                    let due = if received > 0 {
+                        throttle.on_data(now)
+                    } else {
+                        throttle.tick(now)
+                    };
+                    if due {
+                        thread_shared.repaints.fetch_add(1, Ordering::Relaxed);
+                        repaint();
+                        thread_shared.wake();
+                    }
+                }
+
+                // Let the GUI see the final messages and the end of the link
+                repaint();
+                thread_shared.wake();
+            },
+        );
+
+        Self {
+            messages,
+            shared,
+            handle,
+        }
+    }
+
+    /// Forwards to an egui context with [DEFAULT_REPAINT_INTERVAL].
+    #[cfg(feature = "egui")]
+    pub fn egui(rx: FlemRx<T>, context: egui::Context) -> Self {
+        Self::spawn(rx, DEFAULT_REPAINT_INTERVAL, move || {
+            context.request_repaint()
+        })
+    }
+
+    /// Returns up to `max` waiting messages without blocking. Bounding the
+    /// batch keeps one frame from stalling on a flood of packets.
+    pub fn drain(&self, max: usize) -> Vec<GuiMessage<T>> {
+        self.messages.try_iter().take(max).collect()
+    }
+
+    pub fn try_recv(&self) -> Result<GuiMessage<T>, TryRecvError> {
+        self.messages.try_recv()
+    }
+
+    /// Messages forwarded so far.
+    pub fn message_count(&self) -> u64 {
+        self.shared.messages.load(Ordering::Relaxed)
+    }
+
+    /// Repaints requested so far. Compared with [GuiAdapter::message_count]
+    /// this shows how well bursts are being coalesced.
+    pub fn repaint_count(&self) -> u64 {
+        self.shared.repaints.load(Ordering::Relaxed)
+    }
+
+    /// True once the link stopped listening and its last messages were
+    /// forwarded.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<const T: usize> futures_core::Stream for GuiAdapter<T> {
+    type Item = GuiMessage<T>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+
+        *self.shared.waker.lock().unwrap() = Some(cx.waker().clone());
+        match self.messages.try_recv() {
+            Ok(message) => Poll::Ready(Some(message)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RepaintThrottle;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_repaint_throttle_coalesces_bursts() {
+        let start = Instant::now();
+        let mut throttle = RepaintThrottle::new(Duration::from_millis(16));
+
+        assert!(throttle.on_data(start));
+        assert!(!throttle.on_data(start + Duration::from_millis(1)));
+        assert!(!throttle.on_data(start + Duration::from_millis(5)));
+        assert!(!throttle.tick(start + Duration::from_millis(10)));
+        // The deferred data is painted once the interval has passed
+        assert!(throttle.tick(start + Duration::from_millis(16)));
+        assert!(!throttle.tick(start + Duration::from_millis(40)));
+    }
+}
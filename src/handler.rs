@@ -0,0 +1,89 @@
+use crate::events::LinkEvent;
+use crate::listener::{ListenerShared, ListenerThread};
+use crate::stats::{LinkRates, LinkStats};
+use std::{
+    sync::mpsc::Receiver,
+    thread::{self, JoinHandle},
+};
+
+/// Callback run by the listener for every packet, see
+/// [crate::FlemSerial::listen_with_handler].
+pub(crate) type PacketHandler<const T: usize> = Box<dyn FnMut(flem::Packet<T>) + Send>;
+
+/// Handle returned by [crate::FlemSerial::listen_with_handler]. There is no
+/// queue, packets go straight to the handler. Dropping it stops the
+/// listener and with it the handler.
+pub struct FlemHandlerRx<const T: usize> {
+    pub(crate) rx_listener_handle: ListenerThread,
+    pub(crate) events: Receiver<LinkEvent>,
+    pub(crate) shared: ListenerShared,
+}
+
+impl<const T: usize> FlemHandlerRx<T> {
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        self.rx_listener_handle.handle()
+    }
+
+    /// Waits for the listener thread to exit.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FlemSerial;
+    use std::{
+        io::Cursor,
+        sync::{mpsc, Arc, Mutex},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_handler_runs_on_the_listener_thread() {
+        let mut bytes = Vec::new();
+        for data in 0..3u8 {
+            let mut event = flem::Packet::<64>::new();
+            event.set_request(flem::Request::EVENT);
+            event.add_data(&[data]).unwrap();
+            event.pack();
+            bytes.extend_from_slice(event.bytes());
+        }
+
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(bytes));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (done, finished) = mpsc::channel();
+        let handled = seen.clone();
+        let rx = serial
+            .listen_with_handler(move |packet| {
+                handled.lock().unwrap().push(packet.get_data()[0]);
+                if packet.get_data()[0] == 2 {
+                    done.send(std::thread::current().id()).unwrap();
+                }
+            })
+            .unwrap();
+
+        let thread = finished.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(thread, rx.join_handle().thread().id());
+        assert_eq!(*seen.lock().unwrap(), vec![0, 1, 2]);
+        assert_eq!(rx.stats().rx_packets, 3);
+
+        serial.unlisten();
+        rx.join().unwrap();
+    }
+}
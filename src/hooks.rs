@@ -0,0 +1,129 @@
+#[cfg(feature = "link")]
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    thread::{self, JoinHandle},
+};
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+/// Why an internal thread stopped unexpectedly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AbortReason {
+    /// The thread panicked with this message.
+    Panicked(String),
+    /// The port failed in a way the thread could not recover from.
+    FatalIo {
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+/// Passed to abort hooks when an internal thread dies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbortReport {
+    /// Which thread stopped, e.g. "listener", "bridge" or "pool".
+    pub thread: &'static str,
+    /// Port name of the link the thread served, if known.
+    pub link: Option<String>,
+    pub reason: AbortReason,
+}
+
+pub type AbortHook = Arc<dyn Fn(&AbortReport) + Send + Sync>;
+
+static GLOBAL_HOOK: Mutex<Option<AbortHook>> = Mutex::new(None);
+
+/// Installs a hook called whenever any thread spawned by this crate panics
+/// or stops on a fatal I/O error. Runs after the link's own hook, on the
+/// dying thread.
+pub fn set_global_abort_hook(hook: AbortHook) {
+    *GLOBAL_HOOK.lock().unwrap() = Some(hook);
+}
+
+pub fn clear_global_abort_hook() {
+    *GLOBAL_HOOK.lock().unwrap() = None;
+}
+
+/// Calls the link hook, if any, then the global hook.
+#[cfg(feature = "link")]
+pub(crate) fn report(report: AbortReport, link_hook: Option<&AbortHook>) {
+    if let Some(hook) = link_hook {
+        hook(&report);
+    }
+
+    let global = GLOBAL_HOOK.lock().ok().and_then(|hook| hook.clone());
+    if let Some(hook) = global {
+        hook(&report);
+    }
+}
+
+#[cfg(feature = "link")]
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Spawns a thread that reports a panic to the abort hooks before
+/// unwinding, so `join` still returns the panic.
+#[cfg(feature = "link")]
+pub(crate) fn spawn_supervised<R, F>(
+    thread: &'static str,
+    link: Option<String>,
+    link_hook: Option<AbortHook>,
+    f: F,
+) -> JoinHandle<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+{
+    thread::spawn(move || match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            report(
+                AbortReport {
+                    thread,
+                    link,
+                    reason: AbortReason::Panicked(panic_message(payload.as_ref())),
+                },
+                link_hook.as_ref(),
+            );
+            panic::resume_unwind(payload)
+        }
+    })
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{spawn_supervised, AbortHook, AbortReason, AbortReport};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_panic_is_reported_to_link_hook() {
+        let reports: Arc<Mutex<Vec<AbortReport>>> = Arc::new(Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        let hook: AbortHook = Arc::new(move |report| {
+            reports_clone.lock().unwrap().push(report.clone());
+        });
+
+        let handle = spawn_supervised("listener", Some("COM1".into()), Some(hook), || {
+            panic!("boom");
+        });
+
+        assert!(handle.join().is_err());
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [AbortReport {
+                thread: "listener",
+                link: Some("COM1".into()),
+                reason: AbortReason::Panicked("boom".into()),
+            }]
+        );
+    }
+}
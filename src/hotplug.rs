@@ -0,0 +1,192 @@
+use crate::{
+    inventory::PortInfo,
+    scope::{self, LinkScope, ScopeHandle},
+};
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Interval for [PortWatcher::spawn] that feels live without busy polling.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change in the ports the OS lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortEvent {
+    Added(PortInfo),
+    Removed(PortInfo),
+}
+
+/// Changes from `old` to `new`. Removals come first, so a port that
+/// reappears under the same name with another adapter reads as removed and
+/// added.
+fn diff(old: &[PortInfo], new: &[PortInfo]) -> Vec<PortEvent> {
+    let removed = old
+        .iter()
+        .filter(|port| !new.contains(port))
+        .cloned()
+        .map(PortEvent::Removed);
+    let added = new
+        .iter()
+        .filter(|port| !old.contains(port))
+        .cloned()
+        .map(PortEvent::Added);
+    removed.chain(added).collect()
+}
+
+/// Reports serial ports appearing and disappearing, for device lists that
+/// update without a refresh button.
+///
+/// The watcher lists the ports on a background thread every interval; the
+/// serial library has no portable hotplug notifications. The ports present
+/// when it starts are reported as [PortEvent::Added] first. Stops when
+/// dropped.
+pub struct PortWatcher {
+    events: Receiver<PortEvent>,
+    /// Dropped to stop the thread.
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PortWatcher {
+    /// Starts watching, listing the ports every `interval`.
+    pub fn spawn(interval: Duration) -> Self {
+        Self::spawn_with(interval, None, list_ports)
+    }
+
+    /// Like [PortWatcher::spawn], with the thread tracked by `scope`.
+    pub fn spawn_in(interval: Duration, scope: &LinkScope) -> Self {
+        Self::spawn_with(interval, Some(&scope.handle()), list_ports)
+    }
+
+    /// Watches the ports returned by `list`, which returns None when
+    /// listing failed. Failed listings are skipped.
+    pub(crate) fn spawn_with<L>(
+        interval: Duration,
+        scope: Option<&ScopeHandle>,
+        mut list: L,
+    ) -> Self
+    where
+        L: FnMut() -> Option<Vec<PortInfo>> + Send + 'static,
+    {
+        let (sender, events) = mpsc::channel();
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let scope_stop = stop.clone();
+        let stop_scoped = move || {
+            let _ = scope_stop.send(());
+        };
+        let handle = scope::spawn_scoped("hotplug", None, None, scope, stop_scoped, move || {
+            let mut known = Vec::new();
+            loop {
+                if let Some(ports) = list() {
+                    for event in diff(&known, &ports) {
+                        if sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                    known = ports;
+                }
+
+                match stopped.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+            }
+        });
+
+        Self {
+            events,
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    pub fn events(&self) -> &Receiver<PortEvent> {
+        &self.events
+    }
+}
+
+fn list_ports() -> Option<Vec<PortInfo>> {
+    serialport::available_ports().ok().map(|ports| {
+        ports
+            .into_iter()
+            .map(PortInfo::from_serial_port_info)
+            .collect()
+    })
+}
+
+impl Drop for PortWatcher {
+    fn drop(&mut self) {
+        // The scope may hold another sender, so stop explicitly
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PortEvent, PortWatcher};
+    use crate::{
+        inventory::PortInfo,
+        scope::{LinkScope, ThreadOutcome},
+    };
+    use std::{collections::VecDeque, time::Duration};
+
+    fn port(name: &str) -> PortInfo {
+        PortInfo {
+            name: name.to_string(),
+            usb: None,
+        }
+    }
+
+    #[test]
+    fn test_watcher_reports_changes_between_listings() {
+        let mut listings = VecDeque::from(vec![
+            Some(vec![port("COM1")]),
+            None,
+            Some(vec![port("COM1"), port("COM3")]),
+            Some(vec![port("COM3")]),
+        ]);
+        let watcher = PortWatcher::spawn_with(Duration::from_millis(1), None, move || {
+            listings.pop_front().unwrap_or(Some(vec![port("COM3")]))
+        });
+
+        let timeout = Duration::from_secs(1);
+        let events: Vec<PortEvent> = (0..3)
+            .map(|_| watcher.events().recv_timeout(timeout).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                PortEvent::Added(port("COM1")),
+                PortEvent::Added(port("COM3")),
+                PortEvent::Removed(port("COM1")),
+            ]
+        );
+        assert!(watcher
+            .events()
+            .recv_timeout(Duration::from_millis(20))
+            .is_err());
+    }
+
+    #[test]
+    fn test_watcher_is_stopped_by_its_scope() {
+        let scope = LinkScope::new();
+        let watcher =
+            PortWatcher::spawn_with(Duration::from_secs(60), Some(&scope.handle()), || {
+                Some(Vec::new())
+            });
+        assert_eq!(scope.running(), 1);
+
+        let reports = scope.close(Duration::from_secs(1));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].outcome, ThreadOutcome::Exited);
+        drop(watcher);
+    }
+}
@@ -0,0 +1,118 @@
+#[cfg(feature = "link")]
+use crate::FlemSerial;
+use crate::{
+    interceptor::{Intercept, TxInterceptor},
+    validation::RxValidator,
+};
+
+/// Length of the trailer [PayloadCrc32] appends to the payload.
+pub const CRC32_LEN: usize = 4;
+
+/// CRC-32 (IEEE 802.3, as used by zlib and Ethernet) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// End-to-end integrity check on top of the FLEM checksum, for links where
+/// corruption has slipped past it, such as very long cables.
+///
+/// As a [TxInterceptor] it appends the little endian CRC-32 of the payload
+/// to the payload, as an [RxValidator] it rejects received packets whose
+/// trailer doesn't match. The device must do the same. ID packets are left
+/// alone so device verification keeps working. Use [payload] to get the
+/// data of a received packet without the trailer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PayloadCrc32;
+
+/// Data of a packet checked by [PayloadCrc32], without the CRC trailer.
+pub fn payload<const T: usize>(packet: &flem::Packet<T>) -> &[u8] {
+    let data = packet.get_data();
+    if packet.get_request() == flem::Request::ID {
+        return data;
+    }
+    &data[..data.len().saturating_sub(CRC32_LEN)]
+}
+
+impl<const T: usize> TxInterceptor<T> for PayloadCrc32 {
+    fn intercept(&mut self, packet: &mut flem::Packet<T>) -> Intercept {
+        if packet.get_request() == flem::Request::ID {
+            return Intercept::Continue;
+        }
+        let crc = crc32(packet.get_data());
+        // A payload with no room for the trailer can't be protected
+        if packet.add_data(&crc.to_le_bytes()).is_err() {
+            return Intercept::Veto;
+        }
+        packet.pack();
+        Intercept::Continue
+    }
+}
+
+impl<const T: usize> RxValidator<T> for PayloadCrc32 {
+    fn validate(&mut self, packet: &flem::Packet<T>) -> Result<(), String> {
+        if packet.get_request() == flem::Request::ID {
+            return Ok(());
+        }
+        let data = packet.get_data();
+        let Some(split) = data.len().checked_sub(CRC32_LEN) else {
+            return Err("payload shorter than its CRC-32".to_string());
+        };
+        let (data, trailer) = data.split_at(split);
+        let expected = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let actual = crc32(data);
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(format!("payload CRC-32 {:08x} != {:08x}", actual, expected))
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> FlemSerial<T> {
+    /// Appends a [PayloadCrc32] to every sent payload and checks it on
+    /// every received one. Adds an interceptor after the existing ones and
+    /// replaces any [RxValidator].
+    pub fn enable_payload_crc32(&mut self) {
+        self.add_tx_interceptor(PayloadCrc32);
+        self.set_rx_validator(PayloadCrc32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, payload, PayloadCrc32};
+    use crate::{
+        interceptor::{Intercept, TxInterceptor},
+        validation::RxValidator,
+    };
+
+    #[test]
+    fn test_crc32_round_trip_and_corruption() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let mut packet = flem::Packet::<16>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        assert_eq!(PayloadCrc32.intercept(&mut packet), Intercept::Continue);
+        assert_eq!(packet.get_data().len(), 7);
+        assert_eq!(payload(&packet), [1, 2, 3]);
+        assert_eq!(PayloadCrc32.validate(&packet), Ok(()));
+
+        let mut corrupted = flem::Packet::<16>::new();
+        corrupted.set_request(flem::Request::EVENT);
+        let mut data = packet.get_data().to_vec();
+        data[1] ^= 0x10;
+        corrupted.add_data(&data).unwrap();
+        assert!(PayloadCrc32.validate(&corrupted).is_err());
+
+        let mut full = flem::Packet::<4>::new();
+        full.set_request(flem::Request::EVENT);
+        full.add_data(&[1]).unwrap();
+        assert_eq!(PayloadCrc32.intercept(&mut full), Intercept::Veto);
+    }
+}
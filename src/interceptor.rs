@@ -0,0 +1,100 @@
+/// What an interceptor decided about an outgoing packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intercept {
+    /// Pass the (possibly modified) packet to the next interceptor.
+    Continue,
+    /// Drop the packet, the send fails and nothing reaches the wire.
+    Veto,
+}
+
+/// Runs on every packet sent with [crate::FlemSerial::send] before it is
+/// written. Interceptors may log, modify or veto the packet and run in the
+/// order they were added.
+pub trait TxInterceptor<const T: usize>: Send {
+    fn intercept(&mut self, packet: &mut flem::Packet<T>) -> Intercept;
+}
+
+impl<const T: usize, F> TxInterceptor<T> for F
+where
+    F: FnMut(&mut flem::Packet<T>) -> Intercept + Send,
+{
+    fn intercept(&mut self, packet: &mut flem::Packet<T>) -> Intercept {
+        self(packet)
+    }
+}
+
+/// An ordered chain of interceptors.
+#[cfg(feature = "link")]
+pub(crate) struct InterceptorChain<const T: usize> {
+    interceptors: Vec<Box<dyn TxInterceptor<T>>>,
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> Default for InterceptorChain<T> {
+    fn default() -> Self {
+        Self {
+            interceptors: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> InterceptorChain<T> {
+    pub(crate) fn push(&mut self, interceptor: Box<dyn TxInterceptor<T>>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.interceptors.clear();
+    }
+
+    /// Runs the chain, returning the packet to write or None if vetoed.
+    pub(crate) fn run(&mut self, packet: &flem::Packet<T>) -> Option<flem::Packet<T>> {
+        if self.interceptors.is_empty() {
+            return Some(packet.clone());
+        }
+
+        let mut packet = packet.clone();
+        for interceptor in self.interceptors.iter_mut() {
+            if interceptor.intercept(&mut packet) == Intercept::Veto {
+                return None;
+            }
+        }
+        Some(packet)
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{Intercept, InterceptorChain};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_interceptors_run_in_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut chain = InterceptorChain::<8>::default();
+
+        let first = order.clone();
+        chain.push(Box::new(move |packet: &mut flem::Packet<8>| {
+            first.lock().unwrap().push(1);
+            packet.set_request(9);
+            Intercept::Continue
+        }));
+        let second = order.clone();
+        chain.push(Box::new(move |packet: &mut flem::Packet<8>| {
+            second.lock().unwrap().push(2);
+            if packet.get_request() == 9 {
+                Intercept::Continue
+            } else {
+                Intercept::Veto
+            }
+        }));
+
+        let sent = chain.run(&flem::Packet::new()).unwrap();
+        assert_eq!(sent.get_request(), 9);
+        assert_eq!(*order.lock().unwrap(), [1, 2]);
+
+        chain.push(Box::new(|_: &mut flem::Packet<8>| Intercept::Veto));
+        assert!(chain.run(&flem::Packet::new()).is_none());
+    }
+}
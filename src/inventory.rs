@@ -0,0 +1,569 @@
+#[cfg(feature = "link")]
+use crate::FlemSerialError;
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A device's answer to a FLEM ID request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub name: String,
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    pub max_packet_size: u16,
+}
+
+impl DeviceIdentity {
+    pub fn from_data_id(id: &flem::DataId) -> Self {
+        Self {
+            name: id
+                .get_name()
+                .iter()
+                .filter(|c| **c != '\0')
+                .collect::<String>()
+                .trim()
+                .to_string(),
+            major: id.get_major(),
+            minor: id.get_minor(),
+            patch: id.get_patch(),
+            max_packet_size: id.get_max_packet_size(),
+        }
+    }
+
+    /// Firmware version as "major.minor.patch".
+    pub fn version(&self) -> String {
+        format!("{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// USB descriptor fields of the port's adapter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbIds {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+#[cfg(feature = "link")]
+impl UsbIds {
+    pub(crate) fn from_usb_port_info(usb: &serialport::UsbPortInfo) -> Self {
+        Self {
+            vid: usb.vid,
+            pid: usb.pid,
+            serial_number: usb.serial_number.clone(),
+            manufacturer: usb.manufacturer.clone(),
+            product: usb.product.clone(),
+        }
+    }
+}
+
+/// A port as listed by [crate::FlemSerial::list_ports].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortInfo {
+    pub name: String,
+    /// None for ports that are not USB.
+    pub usb: Option<UsbIds>,
+}
+
+#[cfg(feature = "link")]
+impl PortInfo {
+    pub(crate) fn from_serial_port_info(info: serialport::SerialPortInfo) -> Self {
+        let usb = match &info.port_type {
+            serialport::SerialPortType::UsbPort(usb) => Some(UsbIds::from_usb_port_info(usb)),
+            _ => None,
+        };
+        Self {
+            name: info.port_name,
+            usb,
+        }
+    }
+}
+
+/// Baud rates [AdapterInfo] checks the driver for.
+pub const STANDARD_BAUD_RATES: [u32; 12] = [
+    1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600, 1000000,
+];
+
+/// USB-serial chip family of well known vendor IDs.
+pub fn chipset(vid: u16) -> Option<&'static str> {
+    match vid {
+        0x0403 => Some("FTDI"),
+        0x1a86 => Some("WCH CH34x"),
+        0x10c4 => Some("Silicon Labs CP210x"),
+        0x067b => Some("Prolific PL2303"),
+        0x0483 => Some("STMicroelectronics"),
+        0x2e8a => Some("Raspberry Pi"),
+        _ => None,
+    }
+}
+
+/// What the adapter and its driver reported when the link was opened, for
+/// triaging bugs that only show up with some adapters. Fields are None or
+/// empty where the driver doesn't answer or wasn't asked.
+///
+/// The serial library doesn't expose the driver name or version, the
+/// chipset guessed from the USB vendor ID is the closest it gets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub port: Option<String>,
+    pub usb: Option<UsbIds>,
+    pub chipset: Option<&'static str>,
+    /// Baud rate the port was opened with.
+    pub baud_rate: Option<u32>,
+    /// Which of [STANDARD_BAUD_RATES] the driver accepted and read back.
+    /// Only checked with [crate::ConnectOptions::probe_adapter].
+    pub baud_rates: Vec<u32>,
+    /// Whether the driver accepted RTS/CTS flow control. Only checked with
+    /// [crate::ConnectOptions::probe_adapter].
+    pub hardware_flow_control: Option<bool>,
+    /// Whether the driver accepted XON/XOFF flow control. Only checked
+    /// with [crate::ConnectOptions::probe_adapter].
+    pub software_flow_control: Option<bool>,
+}
+
+/// Puts back the baud rate and flow control a port had when created,
+/// however the probe ends.
+#[cfg(feature = "link")]
+struct RestoreSettings<'a> {
+    port: &'a mut dyn serialport::SerialPort,
+    baud_rate: Option<u32>,
+    flow_control: Option<serialport::FlowControl>,
+}
+
+#[cfg(feature = "link")]
+impl RestoreSettings<'_> {
+    fn restore(&mut self) -> Result<(), serialport::Error> {
+        if let Some(baud_rate) = self.baud_rate {
+            self.port.set_baud_rate(baud_rate)?;
+        }
+        if let Some(flow_control) = self.flow_control {
+            self.port.set_flow_control(flow_control)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "link")]
+impl Drop for RestoreSettings<'_> {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+#[cfg(feature = "link")]
+impl AdapterInfo {
+    /// Reads what `port` reports without changing any of its settings.
+    pub(crate) fn read(port: &dyn serialport::SerialPort, usb: Option<UsbIds>) -> Self {
+        Self {
+            port: port.name(),
+            chipset: usb.as_ref().and_then(|usb| chipset(usb.vid)),
+            usb,
+            baud_rate: port.baud_rate().ok(),
+            baud_rates: Vec::new(),
+            hardware_flow_control: None,
+            software_flow_control: None,
+        }
+    }
+
+    /// Like [AdapterInfo::read], then tries each standard baud rate and
+    /// flow control setting on `port`. The settings it was opened with are
+    /// put back even if the probe fails part way, and an error is returned
+    /// if they can't be.
+    pub(crate) fn probe(
+        port: &mut dyn serialport::SerialPort,
+        usb: Option<UsbIds>,
+    ) -> Result<Self, serialport::Error> {
+        let mut info = Self::read(port, usb);
+        let mut settings = RestoreSettings {
+            baud_rate: info.baud_rate,
+            flow_control: port.flow_control().ok(),
+            port,
+        };
+
+        if settings.baud_rate.is_some() {
+            info.baud_rates = STANDARD_BAUD_RATES
+                .into_iter()
+                .filter(|rate| {
+                    settings.port.set_baud_rate(*rate).is_ok()
+                        && settings.port.baud_rate().is_ok_and(|read| read == *rate)
+                })
+                .collect();
+        }
+        if settings.flow_control.is_some() {
+            let mut accepts = |setting| Some(settings.port.set_flow_control(setting).is_ok());
+            info.hardware_flow_control = accepts(serialport::FlowControl::Hardware);
+            info.software_flow_control = accepts(serialport::FlowControl::Software);
+        }
+
+        settings.restore()?;
+        Ok(info)
+    }
+}
+
+/// Name of the one port in `ports` whose USB adapter has these IDs, and
+/// serial number if given.
+#[cfg(feature = "link")]
+pub(crate) fn find_usb_port(
+    ports: &[PortInfo],
+    vid: u16,
+    pid: u16,
+    serial_number: Option<&str>,
+) -> Result<String, FlemSerialError> {
+    let mut matching = ports.iter().filter(|port| {
+        port.usb.as_ref().is_some_and(|usb| {
+            usb.vid == vid
+                && usb.pid == pid
+                && (serial_number.is_none() || usb.serial_number.as_deref() == serial_number)
+        })
+    });
+
+    match (matching.next(), matching.next()) {
+        (None, _) => Err(FlemSerialError::NoDeviceFoundByThatName),
+        (Some(port), None) => Ok(port.name.clone()),
+        (Some(_), Some(_)) => Err(FlemSerialError::MultipleDevicesFoundByThatName),
+    }
+}
+
+/// One managed link as reported by
+/// [crate::manager::FlemDeviceManager::inventory].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceRecord {
+    pub device: String,
+    pub port: Option<String>,
+    /// None for ports that are not USB or are no longer listed.
+    pub usb: Option<UsbIds>,
+    /// Last ID response seen, from a verified connect or while listening.
+    pub identity: Option<DeviceIdentity>,
+    /// When the last packet was received.
+    pub last_seen: Option<SystemTime>,
+}
+
+pub(crate) fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn push_json_optional_string(json: &mut String, value: Option<&str>) {
+    match value {
+        Some(value) => push_json_string(json, value),
+        None => json.push_str("null"),
+    }
+}
+
+impl DeviceRecord {
+    /// Serializes the record as a JSON object. `last_seen` is in
+    /// microseconds since the Unix epoch.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"device\":");
+        push_json_string(&mut json, &self.device);
+        json.push_str(",\"port\":");
+        push_json_optional_string(&mut json, self.port.as_deref());
+
+        json.push_str(",\"usb\":");
+        match self.usb.as_ref() {
+            Some(usb) => {
+                let _ = write!(
+                    json,
+                    "{{\"vid\":{},\"pid\":{},\"serial_number\":",
+                    usb.vid, usb.pid
+                );
+                push_json_optional_string(&mut json, usb.serial_number.as_deref());
+                json.push_str(",\"manufacturer\":");
+                push_json_optional_string(&mut json, usb.manufacturer.as_deref());
+                json.push_str(",\"product\":");
+                push_json_optional_string(&mut json, usb.product.as_deref());
+                json.push('}');
+            }
+            None => json.push_str("null"),
+        }
+
+        json.push_str(",\"identity\":");
+        match self.identity.as_ref() {
+            Some(identity) => {
+                json.push_str("{\"name\":");
+                push_json_string(&mut json, &identity.name);
+                json.push_str(",\"version\":");
+                push_json_string(&mut json, &identity.version());
+                let _ = write!(json, ",\"max_packet_size\":{}}}", identity.max_packet_size);
+            }
+            None => json.push_str("null"),
+        }
+
+        json.push_str(",\"last_seen\":");
+        match self
+            .last_seen
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        {
+            Some(since_epoch) => {
+                let _ = write!(json, "{}", since_epoch.as_micros());
+            }
+            None => json.push_str("null"),
+        }
+
+        json.push('}');
+        json
+    }
+}
+
+/// Serializes records as a JSON array.
+pub fn to_json(records: &[DeviceRecord]) -> String {
+    let objects: Vec<String> = records.iter().map(|record| record.to_json()).collect();
+    format!("[{}]", objects.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_json, DeviceIdentity, DeviceRecord, UsbIds};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_json_export() {
+        let records = [DeviceRecord {
+            device: "bench \"A\"".into(),
+            port: Some("/dev/ttyACM0".into()),
+            usb: Some(UsbIds {
+                vid: 0x0483,
+                pid: 0x5740,
+                serial_number: Some("SN1".into()),
+                manufacturer: None,
+                product: None,
+            }),
+            identity: Some(DeviceIdentity {
+                name: "Sensor".into(),
+                major: 1,
+                minor: 2,
+                patch: 3,
+                max_packet_size: 512,
+            }),
+            last_seen: Some(UNIX_EPOCH + Duration::from_micros(42)),
+        }];
+
+        assert_eq!(
+            to_json(&records),
+            "[{\"device\":\"bench \\\"A\\\"\",\"port\":\"/dev/ttyACM0\",\
+             \"usb\":{\"vid\":1155,\"pid\":22336,\"serial_number\":\"SN1\",\
+             \"manufacturer\":null,\"product\":null},\
+             \"identity\":{\"name\":\"Sensor\",\"version\":\"1.2.3\",\"max_packet_size\":512},\
+             \"last_seen\":42}]"
+        );
+    }
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_find_usb_port() {
+        use super::{find_usb_port, PortInfo};
+        use crate::FlemSerialError;
+
+        let usb = |serial_number: &str| {
+            Some(UsbIds {
+                vid: 0x0483,
+                pid: 0x5740,
+                serial_number: Some(serial_number.into()),
+                manufacturer: None,
+                product: None,
+            })
+        };
+        let ports = [
+            PortInfo {
+                name: "COM1".into(),
+                usb: None,
+            },
+            PortInfo {
+                name: "COM3".into(),
+                usb: usb("SN1"),
+            },
+            PortInfo {
+                name: "COM7".into(),
+                usb: usb("SN2"),
+            },
+        ];
+
+        assert!(matches!(
+            find_usb_port(&ports, 0x0483, 0x5740, Some("SN2")),
+            Ok(name) if name == "COM7"
+        ));
+        assert!(matches!(
+            find_usb_port(&ports, 0x0483, 0x5740, None),
+            Err(FlemSerialError::MultipleDevicesFoundByThatName)
+        ));
+        assert!(matches!(
+            find_usb_port(&ports, 0x0483, 0x5741, None),
+            Err(FlemSerialError::NoDeviceFoundByThatName)
+        ));
+    }
+
+    /// A driver that accepts baud rates up to 921600 and both kinds of flow
+    /// control, counting the settings written to it.
+    #[cfg(feature = "link")]
+    struct Driver {
+        baud_rate: u32,
+        flow_control: serialport::FlowControl,
+        writes: usize,
+    }
+
+    #[cfg(feature = "link")]
+    mod driver {
+        use super::Driver;
+        use crate::transport::not_serial;
+        use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+        use std::{io, time::Duration};
+
+        impl io::Read for Driver {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Ok(0)
+            }
+        }
+
+        impl io::Write for Driver {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        impl SerialPort for Driver {
+            fn name(&self) -> Option<String> {
+                Some("/dev/ttyUSB0".into())
+            }
+            fn baud_rate(&self) -> serialport::Result<u32> {
+                Ok(self.baud_rate)
+            }
+            fn data_bits(&self) -> serialport::Result<DataBits> {
+                Ok(DataBits::Eight)
+            }
+            fn flow_control(&self) -> serialport::Result<FlowControl> {
+                Ok(self.flow_control)
+            }
+            fn parity(&self) -> serialport::Result<Parity> {
+                Ok(Parity::None)
+            }
+            fn stop_bits(&self) -> serialport::Result<StopBits> {
+                Ok(StopBits::One)
+            }
+            fn timeout(&self) -> Duration {
+                Duration::ZERO
+            }
+            fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+                self.writes += 1;
+                if baud_rate > 921600 {
+                    return not_serial();
+                }
+                self.baud_rate = baud_rate;
+                Ok(())
+            }
+            fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+                self.writes += 1;
+                self.flow_control = flow_control;
+                Ok(())
+            }
+            fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn set_timeout(&mut self, _timeout: Duration) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+                not_serial()
+            }
+            fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+                not_serial()
+            }
+            fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+                not_serial()
+            }
+            fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+                not_serial()
+            }
+            fn bytes_to_read(&self) -> serialport::Result<u32> {
+                not_serial()
+            }
+            fn bytes_to_write(&self) -> serialport::Result<u32> {
+                not_serial()
+            }
+            fn clear(&self, _buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+                not_serial()
+            }
+            fn set_break(&self) -> serialport::Result<()> {
+                not_serial()
+            }
+            fn clear_break(&self) -> serialport::Result<()> {
+                not_serial()
+            }
+        }
+    }
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_adapter_info_is_read_only_unless_probed() {
+        use super::{AdapterInfo, STANDARD_BAUD_RATES};
+        use serialport::FlowControl;
+
+        let mut driver = Driver {
+            baud_rate: 9600,
+            flow_control: FlowControl::None,
+            writes: 0,
+        };
+        let read = AdapterInfo::read(&driver, None);
+        assert_eq!(read.baud_rate, Some(9600));
+        assert!(read.baud_rates.is_empty());
+        assert_eq!(read.hardware_flow_control, None);
+        assert_eq!(driver.writes, 0);
+
+        let probed = AdapterInfo::probe(&mut driver, None).unwrap();
+        assert_eq!(probed.baud_rates, STANDARD_BAUD_RATES[..11]);
+        assert_eq!(probed.hardware_flow_control, Some(true));
+        assert_eq!(probed.software_flow_control, Some(true));
+        // Left as it was opened
+        assert_eq!(driver.baud_rate, 9600);
+        assert_eq!(driver.flow_control, FlowControl::None);
+    }
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_adapter_info_of_a_stream_is_empty() {
+        use crate::FlemSerial;
+        use std::io::Cursor;
+
+        let serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        let info = serial.adapter_info().unwrap();
+        assert_eq!(info.port, None);
+        assert!(info.baud_rates.is_empty());
+        assert_eq!(info.hardware_flow_control, None);
+        assert_eq!(super::chipset(0x1a86), Some("WCH CH34x"));
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(feature = "link")]
+use crate::events::LinkEvent;
+use std::time::Duration;
+#[cfg(feature = "link")]
+use std::{collections::VecDeque, sync::Mutex, time::Instant};
+
+/// Sends `request` every `interval` and declares the link down once
+/// `missed_limit` requests in a row went unanswered. A wedged device
+/// otherwise looks just like an idle one.
+///
+/// Responses are paired with requests of the same code in the order they
+/// were sent, so the consumer's own requests with the keepalive's code still
+/// get their responses. Responses to keepalives are not delivered to the
+/// consumer. The interval can be changed while listening, see
+/// [crate::tunables::Tunables::keepalive_interval].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepalivePolicy {
+    pub request: u8,
+    pub interval: Duration,
+    pub missed_limit: u32,
+}
+
+impl Default for KeepalivePolicy {
+    fn default() -> Self {
+        Self {
+            request: flem::Request::ID,
+            interval: Duration::from_secs(1),
+            missed_limit: 3,
+        }
+    }
+}
+
+/// Change of state reported by [KeepaliveMonitor].
+#[cfg(feature = "link")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeepaliveTransition {
+    Down { missed: u32 },
+    Up,
+}
+
+#[cfg(feature = "link")]
+impl KeepaliveTransition {
+    pub(crate) fn event(self) -> LinkEvent {
+        match self {
+            KeepaliveTransition::Down { missed } => LinkEvent::LinkDown { missed },
+            KeepaliveTransition::Up => LinkEvent::LinkUp,
+        }
+    }
+}
+
+/// What [KeepaliveMonitor::on_response] decided about a packet.
+#[cfg(feature = "link")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct KeepaliveResponse {
+    /// The packet answered a keepalive and shouldn't be delivered.
+    pub(crate) answered: bool,
+    pub(crate) transition: Option<KeepaliveTransition>,
+}
+
+#[cfg(feature = "link")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Waiting {
+    Keepalive,
+    Consumer { sent: Instant },
+}
+
+/// Requests with the keepalive's code waiting for a response, oldest first.
+/// Devices answer in order, so a response only answers the keepalive if
+/// nothing the consumer sent before it is still waiting.
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct KeepaliveLedger(Mutex<VecDeque<Waiting>>);
+
+#[cfg(feature = "link")]
+impl KeepaliveLedger {
+    /// The consumer is about to write a request with the keepalive's code.
+    /// Requests older than `interval` are taken as never answered.
+    pub(crate) fn on_send(&self, now: Instant, interval: Duration) {
+        let mut waiting = self.0.lock().unwrap();
+        waiting.retain(|waiting| match waiting {
+            Waiting::Keepalive => true,
+            Waiting::Consumer { sent } => now.saturating_duration_since(*sent) < interval,
+        });
+        waiting.push_back(Waiting::Consumer { sent: now });
+    }
+
+    /// A keepalive is about to be written. The previous one and requests
+    /// older than `interval` are taken as never answered.
+    fn on_keepalive(&self, now: Instant, interval: Duration) {
+        let mut waiting = self.0.lock().unwrap();
+        waiting.retain(|waiting| match waiting {
+            Waiting::Keepalive => false,
+            Waiting::Consumer { sent } => now.saturating_duration_since(*sent) < interval,
+        });
+        waiting.push_back(Waiting::Keepalive);
+    }
+
+    /// Pairs a response with the oldest request waiting. True if that was
+    /// the keepalive.
+    fn on_response(&self) -> bool {
+        self.0.lock().unwrap().pop_front() == Some(Waiting::Keepalive)
+    }
+}
+
+/// Tracks outstanding keepalives on the listener thread.
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct KeepaliveMonitor {
+    next_due: Option<Instant>,
+    awaiting: bool,
+    missed: u32,
+    down: bool,
+}
+
+#[cfg(feature = "link")]
+impl KeepaliveMonitor {
+    /// True if a keepalive should be sent at `now`. The first one goes out
+    /// on the first call. Returns the transition to down as well once too
+    /// many have gone unanswered.
+    pub(crate) fn due(
+        &mut self,
+        policy: &KeepalivePolicy,
+        ledger: &KeepaliveLedger,
+        now: Instant,
+    ) -> (bool, Option<KeepaliveTransition>) {
+        let next_due = self.next_due.get_or_insert(now);
+        if now < *next_due {
+            return (false, None);
+        }
+        *next_due = now + policy.interval;
+
+        let mut transition = None;
+        if self.awaiting {
+            self.missed += 1;
+            if self.missed >= policy.missed_limit.max(1) && !self.down {
+                self.down = true;
+                transition = Some(KeepaliveTransition::Down {
+                    missed: self.missed,
+                });
+            }
+        }
+        self.awaiting = true;
+        ledger.on_keepalive(now, policy.interval);
+        (true, transition)
+    }
+
+    /// Checks a received packet against the outstanding keepalive.
+    pub(crate) fn on_response(
+        &mut self,
+        policy: &KeepalivePolicy,
+        ledger: &KeepaliveLedger,
+        request: u8,
+    ) -> KeepaliveResponse {
+        if request != policy.request || !ledger.on_response() || !self.awaiting {
+            return KeepaliveResponse::default();
+        }
+
+        self.awaiting = false;
+        self.missed = 0;
+        let transition = if self.down {
+            self.down = false;
+            Some(KeepaliveTransition::Up)
+        } else {
+            None
+        };
+        KeepaliveResponse {
+            answered: true,
+            transition,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{KeepaliveLedger, KeepaliveMonitor, KeepalivePolicy, KeepaliveTransition};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_down_after_missed_limit_and_up_on_response() {
+        let policy = KeepalivePolicy {
+            request: 0x10,
+            interval: Duration::from_millis(100),
+            missed_limit: 2,
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut monitor = KeepaliveMonitor::default();
+        let ledger = KeepaliveLedger::default();
+
+        assert_eq!(monitor.due(&policy, &ledger, at(0)), (true, None));
+        assert_eq!(monitor.due(&policy, &ledger, at(50)), (false, None));
+        assert!(monitor.on_response(&policy, &ledger, 0x10).answered);
+        // Nothing outstanding, delivered as usual
+        assert!(!monitor.on_response(&policy, &ledger, 0x10).answered);
+
+        assert_eq!(monitor.due(&policy, &ledger, at(100)), (true, None));
+        assert_eq!(monitor.due(&policy, &ledger, at(200)), (true, None));
+        assert_eq!(
+            monitor.due(&policy, &ledger, at(300)),
+            (true, Some(KeepaliveTransition::Down { missed: 2 }))
+        );
+        // Reported once
+        assert_eq!(monitor.due(&policy, &ledger, at(400)), (true, None));
+
+        assert!(!monitor.on_response(&policy, &ledger, 0x11).answered);
+        let response = monitor.on_response(&policy, &ledger, 0x10);
+        assert!(response.answered);
+        assert_eq!(response.transition, Some(KeepaliveTransition::Up));
+    }
+
+    #[test]
+    fn test_consumer_requests_with_the_keepalive_code_keep_their_responses() {
+        let policy = KeepalivePolicy {
+            request: 0x10,
+            interval: Duration::from_millis(100),
+            missed_limit: 2,
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut monitor = KeepaliveMonitor::default();
+        let ledger = KeepaliveLedger::default();
+
+        // Sent after the keepalive, answered after it
+        monitor.due(&policy, &ledger, at(0));
+        ledger.on_send(at(10), policy.interval);
+        assert!(monitor.on_response(&policy, &ledger, 0x10).answered);
+        assert!(!monitor.on_response(&policy, &ledger, 0x10).answered);
+
+        // Sent before the keepalive, answered before it
+        ledger.on_send(at(90), policy.interval);
+        monitor.due(&policy, &ledger, at(100));
+        assert!(!monitor.on_response(&policy, &ledger, 0x10).answered);
+        assert!(monitor.on_response(&policy, &ledger, 0x10).answered);
+
+        // Never answered, given up on by the next keepalive
+        ledger.on_send(at(150), policy.interval);
+        monitor.due(&policy, &ledger, at(300));
+        assert!(monitor.on_response(&policy, &ledger, 0x10).answered);
+    }
+
+    #[test]
+    fn test_silent_device_raises_link_down() {
+        use crate::{events::LinkEvent, FlemSerial};
+        use std::io::Cursor;
+
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        serial.set_keepalive(KeepalivePolicy {
+            interval: Duration::from_millis(5),
+            missed_limit: 2,
+            ..KeepalivePolicy::default()
+        });
+        let rx = serial.listen().unwrap();
+
+        let event = rx.events().recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(event, LinkEvent::LinkDown { missed: 2 });
+    }
+}
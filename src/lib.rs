@@ -1,52 +1,939 @@
-use flem::Status;
-use serialport::SerialPort;
-use std::{
-    sync::{
-        mpsc::{self, Receiver},
-        Arc, Mutex,
+pub mod advisor;
+#[cfg(feature = "link")]
+pub mod ascii;
+#[cfg(feature = "tokio")]
+pub mod async_serial;
+pub mod backpressure;
+#[cfg(feature = "link")]
+pub mod batch;
+#[cfg(feature = "link")]
+pub mod bridge;
+#[cfg(feature = "link")]
+pub mod burst;
+pub mod byte_capture;
+pub mod capture;
+pub mod capture_file;
+#[cfg(feature = "link")]
+pub mod client;
+pub mod clock;
+pub mod compat;
+pub mod config;
+pub mod degrade;
+pub mod desync;
+#[cfg(feature = "link")]
+pub mod download;
+pub mod duty_cycle;
+pub mod env_config;
+pub mod error;
+pub mod events;
+#[cfg(feature = "link")]
+pub mod firmware_update;
+#[cfg(feature = "link")]
+pub mod fragment;
+pub mod framing;
+#[cfg(feature = "link")]
+pub mod gui;
+#[cfg(feature = "link")]
+pub mod handler;
+pub mod hooks;
+#[cfg(feature = "link")]
+pub mod hotplug;
+pub mod integrity;
+pub mod interceptor;
+pub mod inventory;
+pub mod keepalive;
+#[cfg(feature = "link")]
+mod listener;
+#[cfg(all(feature = "link", feature = "async"))]
+pub mod mailbox;
+#[cfg(feature = "link")]
+pub mod manager;
+pub mod matching;
+#[cfg(feature = "async")]
+pub mod merge;
+pub mod options;
+pub mod playback;
+#[cfg(feature = "link")]
+pub mod polling;
+#[cfg(feature = "link")]
+pub mod pool;
+#[cfg(feature = "link")]
+pub mod port;
+/// The types most applications need. Nothing here exposes the serial
+/// library, and breaking changes to it follow semver.
+pub mod prelude;
+#[cfg(feature = "link")]
+pub mod qualify;
+#[cfg(feature = "link")]
+pub mod quickstart;
+#[cfg(feature = "link")]
+pub mod reboot;
+#[cfg(feature = "link")]
+pub mod received;
+pub mod reconnect;
+#[cfg(feature = "link")]
+pub mod replay;
+pub mod request;
+pub mod retry;
+#[cfg(feature = "link")]
+pub mod rfc2217;
+pub mod router;
+pub mod scheduler;
+#[cfg(feature = "link")]
+pub mod scope;
+pub mod session;
+#[cfg(feature = "link")]
+pub mod shell;
+#[cfg(feature = "link")]
+pub mod shutdown;
+pub mod stats;
+#[cfg(feature = "link")]
+pub mod stepped;
+#[cfg(feature = "link")]
+pub mod supervisor;
+#[cfg(feature = "link")]
+pub mod tcp;
+pub mod telemetry;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod throttle;
+pub mod timestamp;
+#[cfg(feature = "link")]
+mod transport;
+pub mod tunables;
+#[cfg(feature = "link")]
+mod tx_queue;
+pub mod uart_errors;
+pub mod validation;
+#[cfg(all(feature = "link", any(target_os = "linux", target_os = "macos")))]
+pub mod virtual_port;
+pub mod virtual_time;
+pub mod warmup;
+pub mod wire;
+pub mod xon_xoff;
+
+#[cfg(feature = "link")]
+use {
+    backpressure::Backpressure,
+    batch::{Batcher, FlemBatchRx},
+    burst::{BurstCollector, BurstItem, BurstMarkers, FlemBurstRx},
+    byte_capture::ByteCapture,
+    capture::MultiLinkCapture,
+    clock::{Clock, SystemClock},
+    compat::{FirmwareGate, FirmwarePredicate},
+    degrade::DegradePolicy,
+    desync::DesyncPolicy,
+    duty_cycle::{DutyCycleError, DutyCycleLimit, LinkUsage, UsageMeter},
+    env_config::{EnvConfig, EnvConfigError},
+    events::{EventRateLimit, EventSender, LinkEvent},
+    framing::{FramedPort, Framing},
+    handler::FlemHandlerRx,
+    hooks::AbortHook,
+    interceptor::{InterceptorChain, TxInterceptor},
+    inventory::{AdapterInfo, PortInfo},
+    keepalive::{KeepaliveLedger, KeepalivePolicy},
+    listener::{
+        Delivery, Listener, ListenerExit, ListenerShared, ListenerThread, RxState,
+        LISTENER_SHUTDOWN_TIMEOUT,
+    },
+    options::{ConnectOptions, DataBits, ListenOptions, Parity, SoftFlowControl, StopBits},
+    polling::{FlemPollRx, PollDelivery, PollSchedule, PolledPacket},
+    received::{FlemTimestampedRx, ReceivedPacket, TimestampedDelivery},
+    reconnect::ReconnectPolicy,
+    replay::{ReplayBuffer, ReplayGuard, SharedReplay},
+    request::{PendingRequests, RequestError},
+    retry::{BusyRetry, BusyRetryState, TxRetry},
+    scope::{LinkScope, ScopeHandle},
+    serialport::SerialPort,
+    session::Session,
+    stats::{LinkRates, LinkStats, ModemLines, PortBuffers, DEFAULT_RATE_WINDOW},
+    std::{
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc::{self, Receiver, RecvError, RecvTimeoutError, TryRecvError},
+            Arc, Mutex,
+        },
+        thread,
+        thread::JoinHandle,
+        time::{Duration, Instant},
     },
-    thread,
-    thread::JoinHandle,
-    time::Duration,
+    throttle::{PacedPort, SharedPacer, TxThrottle},
+    tunables::{Tunables, TunablesCell},
+    tx_queue::{TxQueue, TxWriter},
+    uart_errors::{ErrorCounters, UartErrorCounts, UartErrorPoller},
+    validation::{RxValidator, SharedValidator},
+    warmup::{WarmupStats, WarmupTracker},
 };
 
+#[cfg(feature = "link")]
 type FlemSerialPort = Box<dyn SerialPort>;
+#[cfg(feature = "link")]
 type FlemSerialTx = Option<Arc<Mutex<FlemSerialPort>>>;
+#[cfg(feature = "link")]
+type FlemCapture = Option<(String, Arc<MultiLinkCapture>)>;
+
+/// True for ports the OS doesn't list that can still be opened by path,
+/// such as pseudo-terminals. Only character devices qualify, so a stray
+/// file or directory of that name isn't taken for a port.
+#[cfg(any(feature = "link", feature = "tokio"))]
+pub(crate) fn unlisted_port_exists(port_name: &str) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        std::fs::metadata(port_name)
+            .map(|metadata| metadata.file_type().is_char_device())
+            .unwrap_or(false)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = port_name;
+        false
+    }
+}
+
+/// Opens a port with the FLEM line settings, along with the driver's line
+/// error counters where it has them.
+#[cfg(feature = "link")]
+pub(crate) fn open_port(
+    port_name: &str,
+    baud: u32,
+    options: &ConnectOptions,
+    pacer: &SharedPacer,
+) -> serialport::Result<(FlemSerialPort, Option<ErrorCounters>)> {
+    let flow_control = match (options.soft_flow_control, options.hardware_flow_control) {
+        (SoftFlowControl::Off, false) => serialport::FlowControl::None,
+        (SoftFlowControl::Off, true) => serialport::FlowControl::Hardware,
+        (SoftFlowControl::Raw | SoftFlowControl::Stuffed, _) => serialport::FlowControl::Software,
+    };
+    let data_bits = match options.line.data_bits {
+        DataBits::Five => serialport::DataBits::Five,
+        DataBits::Six => serialport::DataBits::Six,
+        DataBits::Seven => serialport::DataBits::Seven,
+        DataBits::Eight => serialport::DataBits::Eight,
+    };
+    let parity = match options.line.parity {
+        Parity::None => serialport::Parity::None,
+        Parity::Odd => serialport::Parity::Odd,
+        Parity::Even => serialport::Parity::Even,
+    };
+    let stop_bits = match options.line.stop_bits {
+        StopBits::One => serialport::StopBits::One,
+        StopBits::Two => serialport::StopBits::Two,
+    };
+
+    let mut counters = None;
+    let port: FlemSerialPort = match rfc2217::address(port_name) {
+        Some(address) => {
+            let line = rfc2217::RemoteLine {
+                baud,
+                data_bits,
+                parity,
+                stop_bits,
+                flow_control,
+            };
+            Box::new(rfc2217::Rfc2217Port::connect(
+                address,
+                line,
+                options.read_timeout,
+                rfc2217::RFC2217_NEGOTIATION_TIMEOUT,
+            )?)
+        }
+        None => {
+            let builder = serialport::new(port_name, baud)
+                .flow_control(flow_control)
+                .parity(parity)
+                .data_bits(data_bits)
+                .stop_bits(stop_bits)
+                .timeout(options.read_timeout);
+            #[cfg(unix)]
+            let port = {
+                let port = builder.open_native()?;
+                counters = ErrorCounters::of(&port);
+                Box::new(port)
+            };
+            #[cfg(not(unix))]
+            let port = builder.open()?;
+            port
+        }
+    };
+
+    let port: FlemSerialPort = Box::new(PacedPort::new(port, pacer.clone()));
+
+    // Framing is applied to the packet bytes first, then XON/XOFF stuffing
+    let port: FlemSerialPort = match options.soft_flow_control {
+        SoftFlowControl::Stuffed => Box::new(xon_xoff::StuffedPort::new(port)),
+        _ => port,
+    };
+
+    let port: FlemSerialPort = match options.framing {
+        Framing::None => port,
+        framing => Box::new(FramedPort::new(port, framing)),
+    };
+    Ok((port, counters))
+}
+
+pub use error::FlemSerialError;
+#[cfg(feature = "link")]
+pub use tx_queue::TxPriority;
+
+#[deprecated(note = "renamed to FlemSerialError")]
+pub type HostSerialPortErrors = FlemSerialError;
 
-pub enum HostSerialPortErrors {
-    NoDeviceFoundByThatName,
-    MultipleDevicesFoundByThatName,
-    ErrorConnectingToDevice,
+/// Why [FlemSerial::from_env] failed.
+#[cfg(feature = "link")]
+pub enum EnvConnectError {
+    Config(EnvConfigError),
+    Connect(FlemSerialError),
 }
 
+#[cfg(feature = "link")]
 pub struct FlemSerial<const T: usize> {
     tx_port: FlemSerialTx,
     continue_listening: Arc<Mutex<bool>>,
+    live_listeners: Arc<AtomicUsize>,
+    capture: FlemCapture,
+    byte_capture: Option<Arc<ByteCapture>>,
+    backpressure: Option<Backpressure<T>>,
+    connected_at: Option<Instant>,
+    startup_grace: Duration,
+    capture_banner: bool,
+    busy_retry: Arc<Mutex<BusyRetryState<T>>>,
+    session: Arc<Session>,
+    tx_retry: TxRetry,
+    clock: Arc<dyn Clock>,
+    port_settings: Option<(String, u32)>,
+    uart_counters: Option<ErrorCounters>,
+    connect_options: ConnectOptions,
+    desync: Option<DesyncPolicy>,
+    degrade: Option<DegradePolicy>,
+    keepalive: Option<KeepalivePolicy>,
+    keepalive_ledger: Arc<KeepaliveLedger>,
+    next_message_id: u16,
+    reconnect: Option<ReconnectPolicy>,
+    replay: Option<SharedReplay<T>>,
+    event_rate_limit: EventRateLimit,
+    rate_window: Duration,
+    tx_interceptors: InterceptorChain<T>,
+    rx_validator: Option<SharedValidator<T>>,
+    pending_requests: Arc<Mutex<PendingRequests<T>>>,
+    abort_hook: Option<AbortHook>,
+    warmup: Arc<WarmupTracker>,
+    usage: Mutex<UsageMeter>,
+    tx_queue: Option<TxQueue<T>>,
+    listen_options: ListenOptions,
+    pacer: SharedPacer,
+    tunables: Arc<TunablesCell>,
+    listener_shared: Option<ListenerShared>,
+    listener_exit: Option<ListenerExit>,
+    scope: Option<ScopeHandle>,
+    firmware_gate: FirmwareGate,
 }
 
+#[cfg(feature = "link")]
 pub struct FlemRx<const T: usize> {
-    rx_listener_handle: JoinHandle<()>,
+    rx_listener_handle: ListenerThread,
     rx_packet_queue: Receiver<flem::Packet<T>>,
+    events: Receiver<LinkEvent>,
+    shared: ListenerShared,
 }
 
+#[cfg(feature = "link")]
 impl<const T: usize> FlemRx<T> {
+    /// Raw access to the packet queue. Packets taken directly from the queue
+    /// are not counted by [FlemRx::queue_depth], use [FlemRx::recv] and
+    /// friends when backpressure is enabled.
     pub fn queue(&self) -> &Receiver<flem::Packet<T>> {
         &self.rx_packet_queue
     }
 
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    /// Blocks until a packet is received.
+    pub fn recv(&self) -> Result<flem::Packet<T>, RecvError> {
+        let packet = self.rx_packet_queue.recv()?;
+        self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        Ok(packet)
+    }
+
+    /// Returns a packet if one is waiting.
+    pub fn try_recv(&self) -> Result<flem::Packet<T>, TryRecvError> {
+        let packet = self.rx_packet_queue.try_recv()?;
+        self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        Ok(packet)
+    }
+
+    /// Blocks until a packet is received or `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<flem::Packet<T>, RecvTimeoutError> {
+        let packet = self.rx_packet_queue.recv_timeout(timeout)?;
+        self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        Ok(packet)
+    }
+
+    /// Number of packets delivered by the listener and not yet received.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Label of the link, see [FlemSerial::set_label]. Useful to tell
+    /// events apart once several links feed one handler.
+    pub fn label(&self) -> Option<String> {
+        self.shared.session.label()
+    }
+
+    /// Smoothed receive rates, see [FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    /// Non-FLEM text received during the startup grace window, if banner
+    /// capture is enabled. See [FlemSerial::set_startup_grace].
+    pub fn startup_banner(&self) -> String {
+        String::from_utf8_lossy(&self.shared.banner.lock().unwrap()).into_owned()
+    }
+
+    /// Latest raw bytes received while the link is or was last degraded,
+    /// see [FlemSerial::set_degrade_policy].
+    pub fn degraded_bytes(&self) -> Vec<u8> {
+        self.shared
+            .degraded_bytes
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect()
+    }
+
     pub fn join_handle(&self) -> &JoinHandle<()> {
-        &self.rx_listener_handle
+        self.rx_listener_handle.handle()
+    }
+
+    /// Scope of the link, for threads that consume this handle.
+    pub(crate) fn scope_handle(&self) -> Option<ScopeHandle> {
+        self.shared.scope.clone()
+    }
+
+    /// Waits for the listener thread to exit. Call [FlemSerial::unlisten]
+    /// first or this will block forever.
+    ///
+    /// Dropping the handle instead stops its listener, which would
+    /// otherwise keep reading the port until [FlemSerial::unlisten], and
+    /// waits a bounded time for it to exit. Other listeners of the link
+    /// keep running.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+/// Received packets as a stream, for use with stream combinators instead of
+/// a `recv` loop. The stream ends once the listener stops.
+#[cfg(all(feature = "link", feature = "async"))]
+impl<const T: usize> futures_core::Stream for FlemRx<T> {
+    type Item = flem::Packet<T>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+
+        // Registered before checking the queue so a packet queued in
+        // between still wakes the task
+        *self.shared.waker.lock().unwrap() = Some(cx.waker().clone());
+        match self.try_recv() {
+            Ok(packet) => Poll::Ready(Some(packet)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+}
+
+/// Stops the link's listeners and waits a bounded time for the latest one
+/// to exit, so the port is closed once the link and the listener are gone.
+#[cfg(feature = "link")]
+impl<const T: usize> Drop for FlemSerial<T> {
+    fn drop(&mut self) {
+        self.unlisten();
+        if let Some(exit) = self.listener_exit.take() {
+            exit.wait(LISTENER_SHUTDOWN_TIMEOUT);
+        }
     }
 }
 
+#[cfg(feature = "link")]
 impl<const T: usize> FlemSerial<T> {
     pub fn new() -> Self {
         Self {
             tx_port: None,
             continue_listening: Arc::new(Mutex::new(false)),
+            live_listeners: Arc::new(AtomicUsize::new(0)),
+            capture: None,
+            byte_capture: None,
+            backpressure: None,
+            connected_at: None,
+            startup_grace: Duration::ZERO,
+            capture_banner: false,
+            busy_retry: Arc::new(Mutex::new(BusyRetryState::default())),
+            session: Arc::new(Session::new()),
+            tx_retry: TxRetry::default(),
+            clock: Arc::new(SystemClock),
+            port_settings: None,
+            uart_counters: None,
+            connect_options: ConnectOptions::default(),
+            desync: None,
+            degrade: None,
+            keepalive: None,
+            keepalive_ledger: Arc::new(KeepaliveLedger::default()),
+            next_message_id: 0,
+            reconnect: None,
+            replay: None,
+            event_rate_limit: EventRateLimit::default(),
+            rate_window: DEFAULT_RATE_WINDOW,
+            tx_interceptors: InterceptorChain::default(),
+            rx_validator: None,
+            pending_requests: Arc::new(Mutex::new(PendingRequests::default())),
+            abort_hook: None,
+            warmup: Arc::new(WarmupTracker::default()),
+            usage: Mutex::new(UsageMeter::default()),
+            tx_queue: None,
+            listen_options: ListenOptions::default(),
+            pacer: SharedPacer::default(),
+            tunables: Arc::new(TunablesCell::default()),
+            listener_shared: None,
+            listener_exit: None,
+            scope: None,
+            firmware_gate: FirmwareGate::default(),
+        }
+    }
+
+    /// Wraps a port that was already opened and configured, see
+    /// [port::OpenPort].
+    pub fn from_open_port(port: port::OpenPort) -> Self {
+        let mut serial = Self::new();
+        serial.attach(port);
+        serial
+    }
+
+    /// Makes `port` the port of the link, as connecting does.
+    pub(crate) fn attach(&mut self, port: port::OpenPort) {
+        let port::OpenPort {
+            port,
+            pacer,
+            uart_counters,
+        } = port;
+        self.pacer = pacer;
+        self.uart_counters = uart_counters;
+        self.session
+            .set_adapter(AdapterInfo::read(port.as_ref(), None));
+        self.port_settings = match (port.name(), port.baud_rate()) {
+            (Some(name), Ok(baud)) => Some((name, baud)),
+            _ => None,
+        };
+        self.tx_port = Some(Arc::new(Mutex::new(port)));
+        self.connected_at = Some(self.clock.now());
+        self.warmup.on_connect(self.clock.now());
+    }
+
+    /// Connects to the port named by the `FLEM_SERIAL_*` environment
+    /// variables, see [env_config]. Only links constructed this way read
+    /// the environment.
+    pub fn from_env() -> Result<Self, EnvConnectError> {
+        let config = EnvConfig::from_env().map_err(EnvConnectError::Config)?;
+        let mut serial = Self::new();
+        serial
+            .connect_with_options(&config.port, config.baud, &config.options)
+            .map_err(EnvConnectError::Connect)?;
+        Ok(serial)
+    }
+
+    /// Runs the FLEM engine over any byte stream, such as a socket or a
+    /// test double. See [port::OpenPort::from_stream] for the requirements
+    /// on the stream.
+    pub fn from_transport<S>(stream: S) -> Self
+    where
+        S: std::io::Read + std::io::Write + Send + 'static,
+    {
+        Self::from_open_port(port::OpenPort::from_stream(stream))
+    }
+
+    /// Runs FLEM over a TCP connection to `address`, for devices behind a
+    /// serial-to-Ethernet converter. Listening and sending work as on a
+    /// serial port, but reconnect policies don't apply.
+    pub fn from_tcp(address: &str) -> Result<Self, FlemSerialError> {
+        port::OpenPort::connect_tcp(address, tcp::TCP_CONNECT_TIMEOUT).map(Self::from_open_port)
+    }
+
+    /// Adds an interceptor that sees, and may modify or veto, every packet
+    /// passed to `send` before it is written. Interceptors run in the order
+    /// they were added. Busy retries resend the intercepted packet as is,
+    /// and backpressure requests are not intercepted.
+    pub fn add_tx_interceptor<I: TxInterceptor<T> + 'static>(&mut self, interceptor: I) {
+        self.tx_interceptors.push(Box::new(interceptor));
+    }
+
+    pub fn clear_tx_interceptors(&mut self) {
+        self.tx_interceptors.clear();
+    }
+
+    /// Runs the interceptors, returning the packet to write or None if one
+    /// vetoed it.
+    pub(crate) fn intercept(&mut self, packet: &flem::Packet<T>) -> Option<flem::Packet<T>> {
+        self.tx_interceptors.run(packet)
+    }
+
+    /// Installs a validator that runs on every received packet before it is
+    /// delivered, replacing any previous one. Takes effect on the next call
+    /// to `listen`.
+    pub fn set_rx_validator<V: RxValidator<T> + 'static>(&mut self, validator: V) {
+        self.rx_validator = Some(Arc::new(Mutex::new(Box::new(validator))));
+    }
+
+    pub fn clear_rx_validator(&mut self) {
+        self.rx_validator = None;
+    }
+
+    /// Installs a hook called if this link's listener thread panics or
+    /// stops because the port failed for good. Takes effect on the next
+    /// call to `listen`. See also [hooks::set_global_abort_hook].
+    pub fn set_abort_hook(&mut self, hook: AbortHook) {
+        self.abort_hook = Some(hook);
+    }
+
+    /// Tracks the threads this link spawns from now on, its listeners and
+    /// the TX thread, in `scope`. Closing the scope stops them. See
+    /// [LinkScope].
+    pub fn set_scope(&mut self, scope: &LinkScope) {
+        self.set_scope_handle(Some(scope.handle()));
+    }
+
+    pub fn clear_scope(&mut self) {
+        self.set_scope_handle(None);
+    }
+
+    pub(crate) fn set_scope_handle(&mut self, scope: Option<ScopeHandle>) {
+        self.scope = scope;
+    }
+
+    /// Replaces the clock used for the startup grace window, busy retries
+    /// and batching. Intended for tests, see [clock::MockClock].
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.pacer.lock().unwrap().set_clock(clock.clone());
+        self.clock = clock;
+    }
+
+    /// Sets how often and how patiently `send` retries transient write
+    /// errors before giving up. Defaults to [TxRetry::default]. Use
+    /// [FlemSerialError::write_failure] on the error to tell a busy port
+    /// from one that is gone.
+    pub fn set_tx_retry(&mut self, policy: TxRetry) {
+        self.tx_retry = policy;
+    }
+
+    /// Name of the connected port, if known.
+    pub fn port_name(&self) -> Option<&str> {
+        self.port_settings.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Names the link, e.g. "left-arm-controller". The label is reported in
+    /// [LinkStats] and by [FlemRx::label], and tags the link's capture
+    /// lines.
+    ///
+    /// Stats and `FlemRx` see it immediately, captures on the next call to
+    /// `listen`.
+    pub fn set_label(&mut self, label: &str) {
+        self.session.set_label(label);
+        if let Some((device, _)) = self.capture.as_mut() {
+            *device = label.to_string();
         }
     }
 
+    pub fn label(&self) -> Option<String> {
+        self.session.label()
+    }
+
+    /// The logical session of this link, which outlives reconnects.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Counters of the link as of now: those of the most recent listener,
+    /// which count from its `listen`, and those of the session, which count
+    /// from the link's creation.
+    pub fn stats(&self) -> LinkStats {
+        match self.listener_shared.as_ref() {
+            Some(shared) => shared.snapshot(),
+            None => ListenerShared::new::<T>(
+                self.session.clone(),
+                self.warmup.clone(),
+                self.clock.clone(),
+                self.rate_window,
+            )
+            .snapshot(),
+        }
+    }
+
+    /// Time to the first packet and to the first ID response since the
+    /// last connect. Also included in [FlemRx::stats].
+    pub fn warmup_stats(&self) -> WarmupStats {
+        self.warmup.snapshot()
+    }
+
+    /// Paces every byte written to the port with `throttle`, sleeping as
+    /// needed. Applies to `send`, the queued sends and packets written by
+    /// the listener, such as keepalives, and to the bytes on the wire,
+    /// after framing. Takes effect immediately, including for packets
+    /// already queued.
+    pub fn set_tx_throttle(&mut self, throttle: TxThrottle) {
+        self.pacer.lock().unwrap().set_throttle(Some(throttle));
+    }
+
+    pub fn clear_tx_throttle(&mut self) {
+        self.pacer.lock().unwrap().set_throttle(None);
+    }
+
+    /// Rejects sends that would exceed `limit`. Such packets are dropped
+    /// and `send` returns [FlemSerialError::DutyCycle], whose
+    /// [DutyCycleError] tells why and when to retry.
+    pub fn set_duty_cycle_limit(&mut self, limit: DutyCycleLimit) {
+        self.usage.lock().unwrap().set_limit(Some(limit));
+    }
+
+    pub fn clear_duty_cycle_limit(&mut self) {
+        self.usage.lock().unwrap().set_limit(None);
+    }
+
+    /// Bytes and airtime sent so far and within the current window.
+    pub fn usage(&self) -> LinkUsage {
+        self.usage.lock().unwrap().snapshot(self.clock.now())
+    }
+
+    /// Makes sends with request code `request` fail with
+    /// [FlemSerialError::UnsupportedByFirmware] unless `predicate` holds
+    /// for the connected device, instead of timing out on firmware that
+    /// ignores the request. Several predicates for one request code must
+    /// all hold. Until the device's ID was read, for example by connecting
+    /// with [ConnectOptions::verify_device], nothing is rejected.
+    pub fn require_firmware<P: FirmwarePredicate + 'static>(&mut self, request: u8, predicate: P) {
+        self.firmware_gate.require(request, Box::new(predicate));
+    }
+
+    pub fn clear_firmware_requirements(&mut self, request: u8) {
+        self.firmware_gate.clear(request);
+    }
+
+    pub(crate) fn check_firmware(&self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        let request = packet.get_request();
+        self.firmware_gate
+            .check(request, self.session.identity().as_ref())
+            .map_err(|firmware| FlemSerialError::UnsupportedByFirmware { request, firmware })
+    }
+
+    pub(crate) fn check_duty_cycle(&self, packet: &flem::Packet<T>) -> Result<(), DutyCycleError> {
+        self.usage
+            .lock()
+            .unwrap()
+            .check(packet.bytes().len(), self.clock.now())
+    }
+
+    /// When the device answers `request` with `flem::Response::BUSY`, resend
+    /// the request after `policy.delay` instead of delivering the reply, up
+    /// to `policy.max_retries` times.
+    pub fn set_busy_retry(&mut self, request: u8, policy: BusyRetry) {
+        self.busy_retry.lock().unwrap().set_policy(request, policy);
+    }
+
+    /// Delivers busy replies to `request` to the consumer again.
+    pub fn clear_busy_retry(&mut self, request: u8) {
+        self.busy_retry.lock().unwrap().clear_policy(request);
+    }
+
+    /// Many devices print a boot banner before FLEM framing starts. For
+    /// `window` after `connect` resync errors are not counted, and when
+    /// `capture_banner` is set the discarded bytes are kept and made
+    /// available through [FlemRx::startup_banner].
+    pub fn set_startup_grace(&mut self, window: Duration, capture_banner: bool) {
+        self.startup_grace = window;
+        self.capture_banner = capture_banner;
+    }
+
+    /// Raises [LinkEvent::LinkDesynchronized], and applies the policy's
+    /// recovery action, whenever too many consecutive bytes fail to start a
+    /// packet. Bytes in the startup grace window don't count. Takes effect
+    /// on the next call to `listen`.
+    pub fn set_desync_policy(&mut self, policy: DesyncPolicy) {
+        self.desync = Some(policy);
+    }
+
+    pub fn clear_desync_policy(&mut self) {
+        self.desync = None;
+    }
+
+    /// Time constant of the moving average behind [FlemRx::rates]. Longer
+    /// windows give steadier numbers that react more slowly. Takes effect
+    /// on the next call to `listen`.
+    pub fn set_rate_window(&mut self, window: Duration) {
+        self.rate_window = window;
+    }
+
+    /// Limits how many events the listener delivers per window. Identical
+    /// consecutive events are folded into [LinkEvent::Repeated] regardless.
+    /// Takes effect on the next call to `listen`.
+    pub fn set_event_rate_limit(&mut self, limit: EventRateLimit) {
+        self.event_rate_limit = limit;
+    }
+
+    /// Pauses delivery and captures raw bytes instead when parse errors
+    /// exceed the policy's rate, raising [LinkEvent::Degraded], and resumes
+    /// with [LinkEvent::Recovered] once the stream is clean again. Takes
+    /// effect on the next call to `listen`.
+    pub fn set_degrade_policy(&mut self, policy: DegradePolicy) {
+        self.degrade = Some(policy);
+    }
+
+    pub fn clear_degrade_policy(&mut self) {
+        self.degrade = None;
+    }
+
+    /// Sends the policy's request periodically from the listener and raises
+    /// [LinkEvent::LinkDown] once too many go unanswered, then
+    /// [LinkEvent::LinkUp] when the device answers again. Takes effect on
+    /// the next call to `listen`.
+    pub fn set_keepalive(&mut self, policy: KeepalivePolicy) {
+        self.keepalive = Some(policy);
+    }
+
+    pub fn clear_keepalive(&mut self) {
+        self.keepalive = None;
+    }
+
+    /// Keeps the listener running when the port disappears, for example
+    /// when the USB cable is pulled, and reopens it following `policy`.
+    /// Raises [LinkEvent::Disconnected] and [LinkEvent::Reconnected]. Without
+    /// a policy the listener stops. Takes effect on the next call to
+    /// `listen`.
+    ///
+    /// A dead handle on a port that is still present, as after macOS
+    /// sleep/wake, is reopened straight away with or without a policy and
+    /// raises [LinkEvent::ResumedAfterSleep] instead.
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect = Some(policy);
+    }
+
+    pub fn clear_reconnect_policy(&mut self) {
+        self.reconnect = None;
+    }
+
+    /// Holds up to `max_packets` packets from [FlemSerial::send_queued]
+    /// whose write failed, and writes them again once the listener reopens
+    /// the port, raising [LinkEvent::Replayed]. Packets `guard` doesn't
+    /// consider idempotent are reported by [FlemSerial::flush_tx] as
+    /// before, since the device may have acted on them before the link
+    /// dropped. Packets sent while the held ones are being written may
+    /// overtake them. Takes effect on the next call to `listen`.
+    pub fn set_replay_on_reconnect<G: ReplayGuard<T> + 'static>(
+        &mut self,
+        max_packets: usize,
+        guard: G,
+    ) {
+        self.replay = Some(Arc::new(Mutex::new(ReplayBuffer::new(max_packets, guard))));
+    }
+
+    /// Stops holding failed sends. Takes effect on the next call to
+    /// `listen`.
+    pub fn clear_replay_on_reconnect(&mut self) {
+        self.replay = None;
+    }
+
+    /// Sends `backpressure.pause` to the device when the receive queue grows
+    /// past the high watermark and `backpressure.resume` once it drains
+    /// back to the low watermark. Takes effect on the next call to `listen`.
+    pub fn set_backpressure(&mut self, backpressure: Backpressure<T>) {
+        self.backpressure = Some(backpressure);
+    }
+
+    /// Disables automatic backpressure.
+    pub fn clear_backpressure(&mut self) {
+        self.backpressure = None;
+    }
+
+    /// Sets how the listener polls a port that has no data. Takes effect on
+    /// the next call to `listen`, except for
+    /// [ListenOptions::max_idle_interval], which is a [Tunables] setting
+    /// and also applies to a running listener.
+    pub fn set_listen_options(&mut self, options: ListenOptions) {
+        self.listen_options = options;
+        self.reconfigure(Tunables {
+            idle_poll_interval: options.max_idle_interval,
+            ..self.tunables()
+        });
+    }
+
+    /// Current [Tunables] of the link.
+    pub fn tunables(&self) -> Tunables {
+        Tunables {
+            idle_poll_interval: self.tunables.get().idle_poll_interval,
+            event_rate_limit: self.event_rate_limit,
+            queue_watermarks: self
+                .backpressure
+                .as_ref()
+                .map(|bp| (bp.high_water, bp.low_water)),
+            keepalive_interval: self.keepalive.map(|policy| policy.interval),
+        }
+    }
+
+    /// Replaces the [Tunables] of the link. Unlike the setters above, a
+    /// running listener applies them too, all at once on its next pass, so
+    /// long running links can be tuned without being restarted.
+    pub fn reconfigure(&mut self, tunables: Tunables) {
+        self.event_rate_limit = tunables.event_rate_limit;
+        if let (Some(bp), Some((high_water, low_water))) =
+            (self.backpressure.as_mut(), tunables.queue_watermarks)
+        {
+            bp.high_water = high_water;
+            bp.low_water = low_water;
+        }
+        if let (Some(policy), Some(interval)) =
+            (self.keepalive.as_mut(), tunables.keepalive_interval)
+        {
+            policy.interval = interval;
+        }
+        self.tunables.publish(tunables);
+    }
+
+    /// Records every packet sent and received on this link into `capture`,
+    /// tagged with the link's label, or `device` if it has none. Several
+    /// links can share one capture to produce a single time-ordered file.
+    /// Takes effect on the next call to `listen`.
+    pub fn set_capture(&mut self, device: &str, capture: Arc<MultiLinkCapture>) {
+        let device = self.label().unwrap_or_else(|| device.to_string());
+        self.capture = Some((device, capture));
+    }
+
+    /// Stops recording packets for this link.
+    pub fn clear_capture(&mut self) {
+        self.capture = None;
+    }
+
+    /// Records every byte the listener reads into `capture`, before any
+    /// parsing, so corrupt streams can be replayed exactly. Takes effect on
+    /// the next call to `listen`.
+    pub fn set_byte_capture(&mut self, capture: Arc<ByteCapture>) {
+        self.byte_capture = Some(capture);
+    }
+
+    /// Stops recording the bytes read on this link.
+    pub fn clear_byte_capture(&mut self) {
+        self.byte_capture = None;
+    }
+
     /// Lists the ports detected by the SerialPort library. Returns None if
     /// no serial ports are detected.
     pub fn list_serial_ports(&self) -> Option<Vec<String>> {
@@ -67,153 +954,683 @@ impl<const T: usize> FlemSerial<T> {
         }
     }
 
-    /// Attempts to connect to a serial port with a set baud.
-    pub fn connect(&mut self, port_name: &String, baud: u32) -> Result<(), HostSerialPortErrors> {
-        let ports = serialport::available_ports().unwrap();
+    /// Lists the ports detected by the SerialPort library along with the
+    /// USB IDs of their adapters.
+    pub fn list_ports(&self) -> Result<Vec<PortInfo>, FlemSerialError> {
+        let ports = serialport::available_ports()
+            .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))?;
+        Ok(ports
+            .into_iter()
+            .map(PortInfo::from_serial_port_info)
+            .collect())
+    }
+
+    /// Connects to the one port whose USB adapter has this VID and PID, and
+    /// `serial_number` if given. Unlike port names, these stay the same
+    /// across reboots and replugs.
+    pub fn connect_by_usb(
+        &mut self,
+        vid: u16,
+        pid: u16,
+        serial_number: Option<&str>,
+        baud: u32,
+    ) -> Result<(), FlemSerialError> {
+        let port_name = inventory::find_usb_port(&self.list_ports()?, vid, pid, serial_number)?;
+        self.connect(&port_name, baud)
+    }
 
-        let filtered_ports: Vec<_> = ports
-            .iter()
-            .filter(|port| port.port_name == *port_name)
-            .collect();
-
-        match filtered_ports.len() {
-            0 => Err(HostSerialPortErrors::NoDeviceFoundByThatName),
-            1 => {
-                if let Ok(port) = serialport::new(port_name, baud)
-                    .flow_control(serialport::FlowControl::None)
-                    .parity(serialport::Parity::None)
-                    .data_bits(serialport::DataBits::Eight)
-                    .stop_bits(serialport::StopBits::One)
-                    .timeout(Duration::from_millis(10))
-                    .open()
-                {
-                    self.tx_port = Some(Arc::new(Mutex::new(
-                        port.try_clone()
-                            .expect("Couldn't clone serial port for tx_port"),
-                    )));
-
-                    return Ok(());
-                } else {
-                    return Err(HostSerialPortErrors::ErrorConnectingToDevice);
+    /// Attempts to connect to a serial port with a set baud. Names starting
+    /// with [rfc2217::RFC2217_SCHEME] connect to a network serial server,
+    /// which is asked to apply the baud rate and line settings.
+    pub fn connect(&mut self, port_name: &String, baud: u32) -> Result<(), FlemSerialError> {
+        self.connect_with_options(port_name, baud, &ConnectOptions::default())
+    }
+
+    /// Attempts to connect to a serial port with a set baud, applying
+    /// `options`.
+    pub fn connect_with_options(
+        &mut self,
+        port_name: &String,
+        baud: u32,
+        options: &ConnectOptions,
+    ) -> Result<(), FlemSerialError> {
+        if options.soft_flow_control == SoftFlowControl::Raw {
+            return Err(FlemSerialError::SoftFlowControlCorruptsPackets);
+        }
+        if options.hardware_flow_control && options.soft_flow_control != SoftFlowControl::Off {
+            return Err(FlemSerialError::ConflictingFlowControl);
+        }
+
+        // RFC 2217 servers aren't listed by the OS
+        let remote = rfc2217::address(port_name).is_some();
+        let usb = match remote {
+            true => None,
+            false => {
+                let ports = serialport::available_ports()
+                    .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))?;
+
+                let filtered_ports: Vec<_> = ports
+                    .iter()
+                    .filter(|port| port.port_name == *port_name)
+                    .collect();
+
+                match filtered_ports.len() {
+                    0 if unlisted_port_exists(port_name) => None,
+                    0 => return Err(FlemSerialError::NoDeviceFoundByThatName),
+                    1 => match &filtered_ports[0].port_type {
+                        serialport::SerialPortType::UsbPort(usb) => {
+                            Some(inventory::UsbIds::from_usb_port_info(usb))
+                        }
+                        _ => None,
+                    },
+                    _ => return Err(FlemSerialError::MultipleDevicesFoundByThatName),
                 }
             }
-            _ => Err(HostSerialPortErrors::MultipleDevicesFoundByThatName),
+        };
+
+        let (mut port, uart_counters) = open_port(port_name, baud, options, &self.pacer)?;
+        // Each setting tried would be a round trip to the server and a
+        // change on its port
+        let adapter = match options.probe_adapter && !remote {
+            true => AdapterInfo::probe(port.as_mut(), usb)
+                .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))?,
+            false => AdapterInfo::read(port.as_ref(), usb),
+        };
+        self.session.set_adapter(adapter);
+        let opened_at = self.clock.now();
+        self.warmup.on_connect(opened_at);
+        if options.verify_device {
+            self.warmup.on_id_sent(opened_at);
+            match Self::probe_id(&mut port, options.verify_timeout) {
+                Some(id) => self
+                    .session
+                    .set_identity(inventory::DeviceIdentity::from_data_id(&id)),
+                None => return Err(FlemSerialError::NotAFlemDevice),
+            }
+            self.warmup.on_packet(true, self.clock.now());
         }
-    }
 
-    pub fn disconnect(&mut self) -> Option<()> {
-        self.unlisten();
+        self.tx_port = Some(Arc::new(Mutex::new(port.try_clone()?)));
+        self.connected_at = Some(self.clock.now());
+        self.port_settings = Some((port_name.clone(), baud));
+        self.uart_counters = uart_counters;
+        self.connect_options = options.clone();
 
-        Some(())
+        Ok(())
     }
 
-    /// Spawns a new thread and listens for data on. Returns a handle to the
-    /// thread that can be used to join later.
-    ///
-    /// Use [received_packets] to get a mpsc::Receiver of type flem::Packet::<T>
-    pub fn listen(&mut self) -> FlemRx<T> {
-        // Reset the continue_listening flag
-        *self.continue_listening.lock().unwrap() = true;
+    /// Sends an ID request on a freshly opened port and waits up to
+    /// `timeout` for a valid ID response.
+    fn probe_id(port: &mut FlemSerialPort, timeout: Duration) -> Option<flem::DataId> {
+        let mut request = flem::Packet::<T>::new();
+        request.set_request(flem::Request::ID);
+        request.pack();
+        port.write_all(request.bytes()).ok()?;
+        port.flush().ok()?;
 
-        // Clone the continue_listening flag
-        let continue_listening_clone = self.continue_listening.clone();
+        let deadline = Instant::now() + timeout;
+        let mut rx_buffer = [0u8; 64];
+        let mut rx_packet = flem::Packet::<T>::new();
 
-        // Create producer / consumer queues
-        let (successful_packet_queue, rx) = mpsc::channel::<flem::Packet<T>>();
+        while Instant::now() < deadline {
+            let bytes_read = match port.read(&mut rx_buffer) {
+                Ok(bytes_read) => bytes_read,
+                Err(_) => continue,
+            };
 
-        let mut local_rx_port = self
-            .tx_port
-            .as_mut()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .try_clone()
-            .expect("Couldn't clone serial port for rx_port");
-
-        let rx_thread_handle = thread::spawn(move || {
-            let mut rx_buffer = [0 as u8; T];
-            let mut rx_packet = flem::Packet::<T>::new();
-
-            while *continue_listening_clone.lock().unwrap() {
-                match local_rx_port.read(&mut rx_buffer) {
-                    Ok(bytes_to_read) => {
-                        // Check if there are any bytes, if there are no bytes,
-                        // put the thread to sleep
-                        if bytes_to_read == 0 {
-                            thread::sleep(Duration::from_millis(10));
-                        } else {
-                            for i in 0..bytes_to_read {
-                                match rx_packet.add_byte(rx_buffer[i]) {
-                                    Status::PacketReceived => {
-                                        successful_packet_queue.send(rx_packet.clone()).unwrap();
-                                        rx_packet.reset_lazy();
-                                    }
-                                    Status::PacketBuilding => {
-                                        // Normal, building packet
-                                    }
-                                    Status::HeaderBytesNotFound => {
-                                        rx_packet.reset_lazy();
-                                    }
-                                    _ => {
-                                        rx_packet.reset_lazy();
-                                    }
-                                }
+            for byte in rx_buffer[..bytes_read].iter() {
+                match rx_packet.add_byte(*byte) {
+                    flem::Status::PacketReceived => {
+                        if rx_packet.get_request() == flem::Request::ID {
+                            if let Ok(id) = flem::DataId::from(rx_packet.get_data()) {
+                                return Some(id);
                             }
                         }
+                        rx_packet.reset_lazy();
                     }
-                    Err(_error) => {
-                        // Library indicates to retry on errors, so that is
-                        // what we will do.
-                    }
+                    flem::Status::PacketBuilding => {}
+                    _ => rx_packet.reset_lazy(),
                 }
             }
+        }
 
-            *continue_listening_clone.lock().unwrap() = false;
-        });
+        None
+    }
+
+    /// What the adapter reported when the link was opened, see
+    /// [AdapterInfo]. None before the first connect.
+    pub fn adapter_info(&self) -> Option<AdapterInfo> {
+        self.session.adapter()
+    }
+
+    /// Queries the driver for the number of bytes waiting in the OS input
+    /// and output buffers. Returns None if not connected or the driver does
+    /// not support the query.
+    pub fn port_buffers(&self) -> Option<PortBuffers> {
+        let port = self.tx_port.as_ref()?.lock().ok()?;
+
+        Some(PortBuffers {
+            bytes_to_read: port.bytes_to_read().ok()?,
+            bytes_to_write: port.bytes_to_write().ok()?,
+        })
+    }
 
-        FlemRx {
+    /// Drives RTS, which some boards use to select their boot mode. Takes
+    /// effect straight away.
+    pub fn set_rts(&mut self, level: bool) -> Result<(), FlemSerialError> {
+        self.control_lines(|port| port.write_request_to_send(level))
+    }
+
+    /// Drives DTR, which some boards use to hold the MCU in reset. Takes
+    /// effect straight away.
+    pub fn set_dtr(&mut self, level: bool) -> Result<(), FlemSerialError> {
+        self.control_lines(|port| port.write_data_terminal_ready(level))
+    }
+
+    /// Reads the CTS, DSR, CD and RI lines.
+    pub fn read_modem_lines(&self) -> Result<ModemLines, FlemSerialError> {
+        self.control_lines(|port| {
+            Ok(ModemLines {
+                cts: port.read_clear_to_send()?,
+                dsr: port.read_data_set_ready()?,
+                cd: port.read_carrier_detect()?,
+                ri: port.read_ring_indicator()?,
+            })
+        })
+    }
+
+    fn control_lines<R>(
+        &self,
+        f: impl FnOnce(&mut FlemSerialPort) -> serialport::Result<R>,
+    ) -> Result<R, FlemSerialError> {
+        let mut port = self
+            .tx_port
+            .as_ref()
+            .ok_or(FlemSerialError::NotConnected)?
+            .lock()
+            .map_err(|_| FlemSerialError::ControlLines(io::Error::other("port lock poisoned")))?;
+        f(&mut port).map_err(|error| FlemSerialError::ControlLines(error.into()))
+    }
+
+    /// Stops every listener of the link. Fails if the link is not
+    /// connected.
+    pub fn disconnect(&mut self) -> Result<(), FlemSerialError> {
+        self.unlisten();
+
+        match self.tx_port {
+            Some(_) => Ok(()),
+            None => Err(FlemSerialError::NotConnected),
+        }
+    }
+
+    /// Spawns a new thread and listens for data on. Fails if the link is
+    /// not connected or the port can't be cloned for the listener.
+    ///
+    /// Received packets are taken from the returned [FlemRx].
+    pub fn listen(&mut self) -> Result<FlemRx<T>, FlemSerialError> {
+        // Create producer / consumer queues
+        let (successful_packet_queue, rx) = mpsc::channel::<flem::Packet<T>>();
+
+        let (rx_thread_handle, events, shared) =
+            self.spawn_listener(Delivery::Single(successful_packet_queue))?;
+
+        Ok(FlemRx {
             rx_listener_handle: rx_thread_handle,
             rx_packet_queue: rx,
+            events,
+            shared,
+        })
+    }
+
+    /// Like [FlemSerial::listen], but each packet is delivered as a
+    /// [ReceivedPacket] stamped when its final byte was parsed, for latency
+    /// measurements that shouldn't include time spent in the queue.
+    pub fn listen_timestamped(&mut self) -> Result<FlemTimestampedRx<T>, FlemSerialError> {
+        let (packet_queue, rx) = mpsc::channel::<ReceivedPacket<T>>();
+
+        let (rx_thread_handle, events, shared) = self.spawn_listener(Delivery::Timestamped(
+            TimestampedDelivery::new(packet_queue, self.clock.clone()),
+        ))?;
+
+        Ok(FlemTimestampedRx {
+            rx_listener_handle: rx_thread_handle,
+            rx_packet_queue: rx,
+            events,
+            shared,
+        })
+    }
+
+    /// Like [FlemSerial::listen], but `handler` is called with each packet
+    /// instead of queueing it, so there is no consumer thread to run.
+    ///
+    /// The handler runs on the listener thread, so:
+    /// - Nothing is read while it runs. Keep it short, or hand long work
+    ///   off to another thread.
+    /// - It must not block on [FlemSerial::request] or
+    ///   [FlemSerial::request_async] for this link. The response can't be
+    ///   read until the handler returns, so the request times out.
+    /// - Sending from it is fine, the listener doesn't hold the port while
+    ///   the handler runs.
+    /// - A panic in the handler stops the listener and is reported to the
+    ///   abort hook, see [FlemSerial::set_abort_hook].
+    ///
+    /// The handler is dropped when the listener stops.
+    pub fn listen_with_handler<H>(
+        &mut self,
+        handler: H,
+    ) -> Result<FlemHandlerRx<T>, FlemSerialError>
+    where
+        H: FnMut(flem::Packet<T>) + Send + 'static,
+    {
+        let (rx_thread_handle, events, shared) =
+            self.spawn_listener(Delivery::Handler(Box::new(handler)))?;
+
+        Ok(FlemHandlerRx {
+            rx_listener_handle: rx_thread_handle,
+            events,
+            shared,
+        })
+    }
+
+    /// Like [FlemSerial::listen], but packets are delivered in batches of up
+    /// to `max_packets`, sent early if the oldest packet has waited
+    /// `max_delay`. Reduces wakeups for consumers handling very high packet
+    /// rates. Packets are stamped as in [FlemSerial::listen_timestamped].
+    pub fn listen_batched(
+        &mut self,
+        max_packets: usize,
+        max_delay: Duration,
+    ) -> Result<FlemBatchRx<T>, FlemSerialError> {
+        let (batch_queue, rx) = mpsc::channel::<Vec<ReceivedPacket<T>>>();
+
+        let (rx_thread_handle, events, shared) = self.spawn_listener(Delivery::Batched(
+            Batcher::new(batch_queue, max_packets, max_delay, self.clock.clone()),
+        ))?;
+
+        Ok(FlemBatchRx {
+            rx_listener_handle: rx_thread_handle,
+            rx_batch_queue: rx,
+            events,
+            shared,
+        })
+    }
+
+    /// Like [FlemSerial::listen], but EVENT packets between the `markers`
+    /// request codes are delivered together as one [burst::Burst], along
+    /// with whether the burst arrived complete.
+    pub fn listen_bursts(
+        &mut self,
+        markers: BurstMarkers,
+    ) -> Result<FlemBurstRx<T>, FlemSerialError> {
+        let (burst_queue, rx) = mpsc::channel::<BurstItem<T>>();
+
+        let (rx_thread_handle, events, shared) = self.spawn_listener(Delivery::Bursts(
+            BurstCollector::new(burst_queue, markers, self.clock.clone()),
+        ))?;
+
+        Ok(FlemBurstRx {
+            rx_listener_handle: rx_thread_handle,
+            rx_burst_queue: rx,
+            events,
+            shared,
+        })
+    }
+
+    /// Like [FlemSerial::listen], but the listener also sends the requests
+    /// of `schedule` when they are due, and tags their responses with the
+    /// poll group. Polls are written directly, like busy retries, without
+    /// running the TX interceptors or the duty cycle limit.
+    pub fn listen_polled(
+        &mut self,
+        schedule: PollSchedule<T>,
+    ) -> Result<FlemPollRx<T>, FlemSerialError> {
+        let (packet_queue, rx) = mpsc::channel::<PolledPacket<T>>();
+
+        let (rx_thread_handle, events, shared) = self.spawn_listener(Delivery::Polled(
+            PollDelivery::new(packet_queue, schedule, self.clock.clone()),
+        ))?;
+
+        Ok(FlemPollRx {
+            rx_listener_handle: rx_thread_handle,
+            rx_packet_queue: rx,
+            events,
+            shared,
+        })
+    }
+
+    fn spawn_listener(
+        &mut self,
+        mut delivery: Delivery<T>,
+    ) -> Result<(ListenerThread, Receiver<LinkEvent>, ListenerShared), FlemSerialError> {
+        let (listener, events) = self.build_listener()?;
+        let shared = listener.shared.clone();
+        if let Delivery::Reassembled(reassembler) = &mut delivery {
+            reassembler.count_depth_in(shared.queue_depth.clone());
         }
+        self.listener_exit = Some(shared.exit.clone());
+        self.live_listeners.fetch_add(1, Ordering::AcqRel);
+
+        let stop = shared.stop.clone();
+        let rx_thread_handle = scope::spawn_scoped(
+            "listener",
+            self.port_settings.as_ref().map(|(name, _)| name.clone()),
+            self.abort_hook.clone(),
+            self.scope.as_ref(),
+            move || stop.store(true, Ordering::Release),
+            move || listener.run(delivery),
+        );
+
+        Ok((
+            ListenerThread::new(rx_thread_handle, &shared),
+            events,
+            shared,
+        ))
+    }
+
+    pub(crate) fn build_listener(
+        &mut self,
+    ) -> Result<(Listener<T>, Receiver<LinkEvent>), FlemSerialError> {
+        let rx_port = self
+            .tx_port
+            .as_ref()
+            .ok_or(FlemSerialError::NotConnected)?
+            .lock()
+            .map_err(|_| FlemSerialError::ListenFailed(io::Error::other("port lock poisoned")))?
+            .try_clone()
+            .map_err(|error| FlemSerialError::ListenFailed(error.into()))?;
+
+        // Reset the continue_listening flag
+        *self.continue_listening.lock().unwrap() = true;
+
+        let mut shared = ListenerShared::new::<T>(
+            self.session.clone(),
+            self.warmup.clone(),
+            self.clock.clone(),
+            self.rate_window,
+        );
+        shared.scope = self.scope.clone();
+        self.listener_shared = Some(shared.clone());
+        let (events_tx, events) = mpsc::channel();
+
+        let listener = Listener {
+            rx_port,
+            continue_listening: self.continue_listening.clone(),
+            live_listeners: self.live_listeners.clone(),
+            tx: TxWriter {
+                // Listener packets aren't held for replay, and keepalives
+                // are tracked by the listener itself
+                replay: None,
+                keepalive: None,
+                ..self.tx_writer()
+            },
+            byte_capture: self.byte_capture.clone(),
+            backpressure: self.backpressure.clone(),
+            grace_deadline: self
+                .connected_at
+                .unwrap_or_else(|| self.clock.now())
+                .checked_add(self.startup_grace),
+            capture_banner: self.capture_banner,
+            clock: self.clock.clone(),
+            port_settings: self.port_settings.clone(),
+            connect_options: self.connect_options.clone(),
+            pacer: self.pacer.clone(),
+            desync: self.desync,
+            degrade: self.degrade,
+            keepalive: self.keepalive,
+            keepalive_ledger: self.keepalive_ledger.clone(),
+            events: EventSender::new(events_tx, self.event_rate_limit),
+            reconnect: self.reconnect,
+            replay: self.replay.clone(),
+            rx_validator: self.rx_validator.clone(),
+            pending_requests: self.pending_requests.clone(),
+            abort_hook: self.abort_hook.clone(),
+            tunables: self.tunables.watch(),
+            listen_options: self.listen_options,
+            shared,
+            state: RxState::new(
+                self.uart_counters
+                    .as_ref()
+                    .and_then(ErrorCounters::try_clone)
+                    .and_then(|counters| {
+                        UartErrorPoller::open(
+                            counters,
+                            UartErrorCounts::default(),
+                            self.clock.now(),
+                        )
+                    }),
+            ),
+        };
+
+        Ok((listener, events))
+    }
+
+    /// Like `listen`, but nothing reads the port. Received bytes are fed in
+    /// with [stepped::SteppedRx::step] instead, so tests and simulators
+    /// control exactly when receive processing happens. Fails like `listen`.
+    pub fn listen_stepped(&mut self) -> Result<stepped::SteppedRx<T>, FlemSerialError> {
+        let (packet_queue, rx) = mpsc::channel::<flem::Packet<T>>();
+        let (listener, events) = self.build_listener()?;
+        Ok(stepped::SteppedRx::new(
+            listener,
+            Delivery::Single(packet_queue),
+            rx,
+            events,
+        ))
     }
 
     pub fn unlisten(&mut self) {
         *self.continue_listening.lock().unwrap() = false;
     }
 
-    pub fn send(&mut self, packet: &flem::Packet<T>) -> Option<()> {
-        if let Some(mutex_ref) = self.tx_port.as_ref() {
-            if let Ok(mut port) = mutex_ref.lock() {
-                if let Ok(_) = port.as_mut().write_all(&packet.bytes()) {
-                    port.as_mut().flush().unwrap();
-                    return Some(());
-                } else {
-                    return None;
+    /// Counts a packet that was written to the port and adds it to the
+    /// capture, if any.
+    pub(crate) fn record_tx(&self, packet: &flem::Packet<T>) {
+        self.record_usage(packet);
+        self.tx_writer().record(packet);
+    }
+
+    fn record_usage(&self, packet: &flem::Packet<T>) {
+        self.usage
+            .lock()
+            .unwrap()
+            .record(packet.bytes().len(), self.clock.now());
+    }
+
+    fn tx_writer(&self) -> TxWriter<T> {
+        TxWriter {
+            port: self.tx_port.clone(),
+            retry: self.tx_retry,
+            busy_retry: self.busy_retry.clone(),
+            warmup: self.warmup.clone(),
+            session: self.session.clone(),
+            capture: self.capture.clone(),
+            clock: self.clock.clone(),
+            replay: self.replay.clone(),
+            keepalive: self
+                .keepalive
+                .map(|policy| (policy, self.keepalive_ledger.clone())),
+        }
+    }
+
+    pub fn send(&mut self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        self.check_firmware(packet)?;
+        let packet = &self.intercept(packet).ok_or(FlemSerialError::Vetoed)?;
+        self.check_duty_cycle(packet)?;
+        self.write_packet(packet)
+    }
+
+    /// Like `send`, but hands the packet to a writer thread instead of
+    /// writing it inline, so a full OS buffer doesn't block the caller.
+    /// Interceptors and the duty cycle limit are applied before queueing,
+    /// write errors are reported by [FlemSerial::flush_tx]. Queued packets
+    /// are written in order, but packets passed to `send` meanwhile may
+    /// overtake them.
+    pub fn send_queued(&mut self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        self.send_queued_with_priority(packet, TxPriority::Normal)
+    }
+
+    /// Like `send_queued`, but the packet is written before any waiting
+    /// packet of lower priority, so control packets such as an abort don't
+    /// wait behind bulk traffic. A packet already being written is
+    /// finished first.
+    pub fn send_queued_with_priority(
+        &mut self,
+        packet: &flem::Packet<T>,
+        priority: TxPriority,
+    ) -> Result<(), FlemSerialError> {
+        self.check_firmware(packet)?;
+        let packet = self.intercept(packet).ok_or(FlemSerialError::Vetoed)?;
+        self.check_duty_cycle(&packet)?;
+        if self.tx_port.is_none() {
+            return Err(FlemSerialError::NotConnected);
+        }
+        // Counted when queued so packets waiting in the queue can't exceed
+        // the duty cycle limit
+        self.record_usage(&packet);
+
+        let writer = self.tx_writer();
+        let link = self.port_settings.as_ref().map(|(name, _)| name.clone());
+        let abort_hook = self.abort_hook.clone();
+        self.tx_queue
+            .get_or_insert_with(|| TxQueue::spawn(link, abort_hook, self.scope.as_ref()))
+            .push(writer, packet, priority)
+    }
+
+    /// Blocks until every packet passed to [FlemSerial::send_queued] has
+    /// been written, then returns the first write error since the last
+    /// flush, if any.
+    pub fn flush_tx(&self) -> Result<(), FlemSerialError> {
+        match self.tx_queue.as_ref() {
+            Some(queue) => queue.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of packets passed to [FlemSerial::send_queued] and not yet
+    /// written.
+    pub fn tx_queued(&self) -> usize {
+        self.tx_queue.as_ref().map_or(0, TxQueue::len)
+    }
+
+    /// Number of failed sends held for replay, see
+    /// [FlemSerial::set_replay_on_reconnect].
+    pub fn tx_held(&self) -> usize {
+        self.replay
+            .as_ref()
+            .map_or(0, |replay| replay.lock().unwrap().len())
+    }
+
+    /// Sends `packet` and waits up to `timeout` for the response with the
+    /// same request code, which is returned here instead of being delivered
+    /// to the [FlemRx]. Other packets, such as events, are delivered as
+    /// usual meanwhile. Only one request per request code may be waiting.
+    pub fn request(
+        &mut self,
+        packet: &flem::Packet<T>,
+        timeout: Duration,
+    ) -> Result<flem::Packet<T>, RequestError> {
+        if !*self.continue_listening.lock().unwrap() {
+            return Err(RequestError::NotListening);
+        }
+
+        if let Err(FlemSerialError::UnsupportedByFirmware { firmware, .. }) =
+            self.check_firmware(packet)
+        {
+            return Err(RequestError::UnsupportedByFirmware(firmware));
+        }
+
+        let request = packet.get_request();
+        let response = self.pending_requests.lock().unwrap().register(request)?;
+
+        if self.send(packet).is_err() {
+            self.pending_requests.lock().unwrap().cancel(request);
+            return Err(RequestError::SendFailed);
+        }
+
+        response.recv_timeout(timeout).map_err(|_| {
+            self.pending_requests.lock().unwrap().cancel(request);
+            RequestError::Timeout
+        })
+    }
+
+    /// Sends `packet` and returns a future resolved by the listener when the
+    /// response arrives, or with [RequestError::Timeout] once `timeout` has
+    /// passed. Any number of requests may be awaited at once; responses are
+    /// paired with requests by the [FlemSerial::set_response_matcher].
+    /// Requests waiting on the same request code through `request` get
+    /// their response first.
+    #[cfg(feature = "async")]
+    pub fn request_async(
+        &mut self,
+        packet: &flem::Packet<T>,
+        timeout: Duration,
+    ) -> mailbox::ResponseFuture<T> {
+        // Registered before sending so a quick response isn't missed
+        let future = self
+            .pending_requests
+            .lock()
+            .unwrap()
+            .mailbox
+            .register(packet.clone(), self.clock.now() + timeout);
+
+        let failure = if !*self.continue_listening.lock().unwrap() {
+            Some(RequestError::NotListening)
+        } else {
+            match self.send(packet) {
+                Ok(()) => None,
+                Err(FlemSerialError::UnsupportedByFirmware { firmware, .. }) => {
+                    Some(RequestError::UnsupportedByFirmware(firmware))
                 }
-            } else {
-                return None;
+                Err(_) => Some(RequestError::SendFailed),
             }
-        } else {
-            return None;
+        };
+        if let Some(error) = failure {
+            self.pending_requests
+                .lock()
+                .unwrap()
+                .mailbox
+                .cancel(&future, error);
         }
 
-        // if let Ok(()) = self
-        //     .tx_port
-        //     .as_ref()
-        //     .unwrap()
-        //     .lock()
-        //     .unwrap()
-        //     .as_mut()
-        //     .flush()
-        // {
-        //     return Some(());
-        // } else {
-        //     return None;
-        // }
+        future
+    }
+
+    /// Sets how [FlemSerial::request_async] pairs responses with requests.
+    /// Defaults to [matching::EchoRequest]. Takes effect immediately.
+    #[cfg(feature = "async")]
+    pub fn set_response_matcher<M: matching::ResponseMatcher<T> + 'static>(&mut self, matcher: M) {
+        self.pending_requests
+            .lock()
+            .unwrap()
+            .mailbox
+            .set_matcher(Box::new(matcher));
+    }
+
+    /// Like `send`, but reports a packet rejected by the
+    /// [DutyCycleLimit] as such. Vetoed packets, packets unsupported by the
+    /// firmware and write errors are reported as
+    /// [DutyCycleError::SendFailed].
+    #[deprecated(note = "use send and match on FlemSerialError::DutyCycle")]
+    pub fn send_within_duty_cycle(
+        &mut self,
+        packet: &flem::Packet<T>,
+    ) -> Result<(), DutyCycleError> {
+        self.check_firmware(packet)
+            .map_err(|_| DutyCycleError::SendFailed)?;
+        let packet = &self.intercept(packet).ok_or(DutyCycleError::SendFailed)?;
+        self.check_duty_cycle(packet)?;
+        self.write_packet(packet)
+            .map_err(|_| DutyCycleError::SendFailed)
+    }
+
+    fn write_packet(&mut self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        self.tx_writer().write(packet)?;
+        self.record_usage(packet);
+        Ok(())
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "link"))]
 mod tests {
     use crate::FlemSerial;
     use std::{
@@ -231,7 +1648,7 @@ mod tests {
         let result = flem_serial.connect(&ports[4], 115200);
         match result {
             Ok(()) => {
-                let flem_rx = flem_serial.listen();
+                let flem_rx = flem_serial.listen().unwrap();
 
                 // let listener_handle = thread::spawn(move || {
                 //     // Handle the incoming packets
@@ -243,7 +1660,7 @@ mod tests {
                 }
 
                 let mut valid_packets = 0;
-                let rx_packet_queue = flem_rx.rx_packet_queue;
+                let rx_packet_queue = flem_rx.queue();
                 loop {
                     match rx_packet_queue.recv() {
                         Ok(packet) => {
@@ -259,9 +1676,254 @@ mod tests {
 
                 flem_serial.unlisten();
 
-                flem_rx.rx_listener_handle.join().unwrap();
+                flem_rx.join().unwrap();
             }
             Err(error) => {}
         }
     }
+
+    #[test]
+    fn test_control_lines_need_a_serial_port() {
+        let mut serial = FlemSerial::<64>::new();
+        assert!(matches!(
+            serial.set_dtr(false),
+            Err(crate::FlemSerialError::NotConnected)
+        ));
+
+        let mut serial = FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        assert!(matches!(
+            serial.set_rts(true),
+            Err(crate::FlemSerialError::ControlLines(_))
+        ));
+        assert!(serial.read_modem_lines().is_err());
+    }
+
+    #[test]
+    fn test_duty_cycle_rejections_carry_their_cause() {
+        use crate::duty_cycle::{DutyCycleError, DutyCycleLimit};
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.pack();
+        let limit = DutyCycleLimit {
+            window: Duration::from_secs(60),
+            max_airtime: Duration::ZERO,
+            air_bits_per_second: 800,
+            per_packet_overhead: Duration::ZERO,
+        };
+        let airtime = limit.airtime(packet.bytes().len());
+
+        let mut serial = FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        serial.set_duty_cycle_limit(DutyCycleLimit {
+            max_airtime: airtime,
+            ..limit
+        });
+        serial.send(&packet).unwrap();
+        assert!(matches!(
+            serial.send(&packet),
+            Err(crate::FlemSerialError::DutyCycle(
+                DutyCycleError::WouldExceed { .. }
+            ))
+        ));
+
+        serial.set_duty_cycle_limit(limit);
+        assert!(matches!(
+            serial.send(&packet),
+            Err(crate::FlemSerialError::DutyCycle(
+                DutyCycleError::PacketTooLong { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_listening_needs_a_connected_link() {
+        let mut serial = FlemSerial::<64>::new();
+        assert!(matches!(
+            serial.listen_batched(4, Duration::from_millis(10)),
+            Err(crate::FlemSerialError::NotConnected)
+        ));
+        assert!(matches!(
+            serial.listen_stepped(),
+            Err(crate::FlemSerialError::NotConnected)
+        ));
+        assert!(matches!(
+            serial.disconnect(),
+            Err(crate::FlemSerialError::NotConnected)
+        ));
+
+        let mut serial = FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        assert!(serial.disconnect().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_port_buffers_show_bytes_the_host_has_not_read() {
+        use crate::virtual_port::VirtualDevice;
+
+        let mut serial = FlemSerial::<64>::new();
+        assert_eq!(serial.port_buffers(), None);
+        let transport = FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        assert_eq!(transport.port_buffers(), None);
+
+        let device = VirtualDevice::<64>::echo().unwrap();
+        serial
+            .connect(&device.port_name().to_string(), 115200)
+            .unwrap();
+        assert_eq!(serial.port_buffers().unwrap().bytes_to_read, 0);
+
+        // Nothing is listening, so the event stays in the input buffer
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.add_data(&[1, 2, 3]).unwrap();
+        event.pack();
+        device.emit(&event).unwrap();
+
+        let waiting = event.bytes().len() as u32;
+        let deadline = std::time::Instant::now() + Duration::from_secs(1);
+        while serial.port_buffers().unwrap().bytes_to_read < waiting
+            && std::time::Instant::now() < deadline
+        {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(serial.port_buffers().unwrap().bytes_to_read, waiting);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_verified_connect_rejects_a_device_that_does_not_answer_its_id() {
+        use crate::{options::ConnectOptions, virtual_port::VirtualDevice};
+
+        // Reads everything and answers nothing, like a GPS module
+        let device = VirtualDevice::<64>::spawn(|_| Vec::new()).unwrap();
+        let port_name = device.port_name().to_string();
+        let verified = ConnectOptions {
+            verify_device: true,
+            verify_timeout: Duration::from_millis(50),
+            ..ConnectOptions::default()
+        };
+
+        let mut serial = FlemSerial::<64>::new();
+        assert!(matches!(
+            serial.connect_with_options(&port_name, 115200, &verified),
+            Err(crate::FlemSerialError::NotAFlemDevice)
+        ));
+        assert!(serial.listen().is_err());
+        assert_eq!(serial.session().identity(), None);
+
+        // The rejected port was closed again
+        serial.connect(&port_name, 115200).unwrap();
+        assert!(serial.listen().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_only_character_devices_count_as_unlisted_ports() {
+        assert!(crate::unlisted_port_exists("/dev/null"));
+        assert!(!crate::unlisted_port_exists("Cargo.toml"));
+        assert!(!crate::unlisted_port_exists("src"));
+        assert!(!crate::unlisted_port_exists("/dev/no-such-port"));
+    }
+
+    #[test]
+    fn test_dropping_rx_stops_only_its_listener() {
+        let mut serial = FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        let old = serial.listen().unwrap();
+        let old_exit = old.shared.exit.clone();
+        let new = serial.listen().unwrap();
+
+        drop(old);
+        assert!(old_exit.wait(Duration::from_secs(1)));
+        assert!(!new.shared.exit.wait(Duration::from_millis(50)));
+
+        // The link going away stops the rest
+        drop(serial);
+        assert!(new.shared.exit.wait(Duration::ZERO));
+        assert!(new.recv().is_err());
+    }
+
+    #[test]
+    fn test_dropping_any_receive_handle_stops_its_listener() {
+        let mut serial = FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        let mut exits = Vec::new();
+        let started = std::time::Instant::now();
+
+        let rx = serial.listen_batched(4, Duration::from_millis(10)).unwrap();
+        exits.push(rx.shared.exit.clone());
+        drop(rx);
+        let rx = serial
+            .listen_bursts(crate::burst::BurstMarkers {
+                start: 0x40,
+                end: 0x41,
+                timeout: None,
+            })
+            .unwrap();
+        exits.push(rx.shared.exit.clone());
+        drop(rx);
+        let rx = serial
+            .listen_polled(crate::polling::PollSchedule::new())
+            .unwrap();
+        exits.push(rx.shared.exit.clone());
+        drop(rx);
+        let rx = serial.listen_timestamped().unwrap();
+        exits.push(rx.shared.exit.clone());
+        drop(rx);
+        let rx = serial.listen_with_handler(|_| {}).unwrap();
+        exits.push(rx.shared.exit.clone());
+        drop(rx);
+        let rx = serial.listen_reassembled(0x42, None).unwrap();
+        exits.push(rx.shared.exit.clone());
+        drop(rx);
+        assert!(started.elapsed() < crate::LISTENER_SHUTDOWN_TIMEOUT);
+
+        let current = serial.listen().unwrap();
+        for exit in exits {
+            assert!(exit.wait(Duration::from_secs(1)));
+        }
+        assert!(!current.shared.exit.wait(Duration::from_millis(50)));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_rx_is_a_stream() {
+        use futures_core::Stream;
+        use std::{
+            io::Cursor,
+            pin::Pin,
+            sync::mpsc,
+            task::{Context, Poll, Wake, Waker},
+        };
+
+        struct ChannelWaker(Mutex<mpsc::Sender<()>>);
+
+        impl Wake for ChannelWaker {
+            fn wake(self: Arc<Self>) {
+                let _ = self.0.lock().unwrap().send(());
+            }
+        }
+
+        let mut bytes = Vec::new();
+        for data in 0..2u8 {
+            let mut event = flem::Packet::<64>::new();
+            event.set_request(flem::Request::EVENT);
+            event.add_data(&[data]).unwrap();
+            event.pack();
+            bytes.extend_from_slice(event.bytes());
+        }
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(bytes));
+        let mut rx = serial.listen().unwrap();
+
+        let (woken, wakes) = mpsc::channel();
+        let waker = Waker::from(Arc::new(ChannelWaker(Mutex::new(woken))));
+        let mut cx = Context::from_waker(&waker);
+        let mut next = |rx: &mut crate::FlemRx<64>| loop {
+            match Pin::new(&mut *rx).poll_next(&mut cx) {
+                Poll::Ready(item) => return item.map(|packet| packet.get_data()[0]),
+                Poll::Pending => wakes.recv_timeout(Duration::from_secs(1)).unwrap(),
+            }
+        };
+
+        assert_eq!(next(&mut rx), Some(0));
+        assert_eq!(next(&mut rx), Some(1));
+        serial.unlisten();
+        assert_eq!(next(&mut rx), None);
+    }
 }
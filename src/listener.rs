@@ -0,0 +1,997 @@
+use crate::{
+    backpressure::{Backpressure, BackpressureState},
+    batch::Batcher,
+    burst::BurstCollector,
+    byte_capture::ByteCapture,
+    capture::Direction,
+    clock::Clock,
+    degrade::{DegradeMonitor, DegradePolicy, DegradeTransition},
+    desync::{DesyncDetector, DesyncPolicy, DesyncRecovery},
+    events::{EventSender, LinkEvent},
+    fragment::Reassembler,
+    handler::PacketHandler,
+    hooks::{self, AbortHook, AbortReason, AbortReport},
+    inventory::DeviceIdentity,
+    keepalive::{KeepaliveLedger, KeepaliveMonitor, KeepalivePolicy, KeepaliveResponse},
+    options::{ConnectOptions, ListenOptions},
+    polling::PollDelivery,
+    received::TimestampedDelivery,
+    reconnect::{self, ReconnectBackoff, ReconnectPolicy},
+    replay::SharedReplay,
+    request::PendingRequests,
+    scope::ScopeHandle,
+    session::Session,
+    stats::{LinkCounters, LinkRates, LinkStats, RateMeter},
+    throttle::SharedPacer,
+    tunables::TunablesWatch,
+    tx_queue::TxWriter,
+    uart_errors::{UartErrorCounts, UartErrorPoller},
+    validation::SharedValidator,
+    warmup::WarmupTracker,
+    FlemSerialError, FlemSerialPort,
+};
+use flem::Status;
+#[cfg(feature = "async")]
+use std::task::Waker;
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Upper bound on the number of startup banner bytes kept in memory.
+const MAX_BANNER_BYTES: usize = 4096;
+
+/// True while the startup grace window is still open.
+fn in_grace(deadline: Option<Instant>, now: Instant) -> bool {
+    deadline.map(|deadline| now < deadline).unwrap_or(false)
+}
+
+/// How long dropping a link waits for its listener thread to exit.
+pub(crate) const LISTENER_SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Set once a listener thread has exited, so a link can wait for it
+/// without holding its join handle.
+#[derive(Clone, Default)]
+pub(crate) struct ListenerExit(Arc<(Mutex<bool>, Condvar)>);
+
+impl ListenerExit {
+    pub(crate) fn set(&self) {
+        let (exited, changed) = &*self.0;
+        *exited.lock().unwrap() = true;
+        changed.notify_all();
+    }
+
+    /// Waits up to `timeout` for the thread to exit. Returns true if it has.
+    pub(crate) fn wait(&self, timeout: Duration) -> bool {
+        let (exited, changed) = &*self.0;
+        let (exited, _) = changed
+            .wait_timeout_while(exited.lock().unwrap(), timeout, |exited| !*exited)
+            .unwrap();
+        *exited
+    }
+}
+
+/// The listener thread behind a receive handle. Dropping it stops that
+/// listener and waits up to [LISTENER_SHUTDOWN_TIMEOUT] for the thread to
+/// exit and close its port. Other listeners of the link keep running.
+pub(crate) struct ListenerThread {
+    /// Taken by [ListenerThread::join].
+    handle: Option<JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    exit: ListenerExit,
+}
+
+impl ListenerThread {
+    pub(crate) fn new(handle: JoinHandle<()>, shared: &ListenerShared) -> Self {
+        Self {
+            handle: Some(handle),
+            stop: shared.stop.clone(),
+            exit: shared.exit.clone(),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> &JoinHandle<()> {
+        self.handle.as_ref().unwrap()
+    }
+
+    /// Waits for the thread to exit without stopping it.
+    pub(crate) fn join(mut self) -> thread::Result<()> {
+        self.handle.take().unwrap().join()
+    }
+}
+
+impl Drop for ListenerThread {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.stop.store(true, Ordering::Release);
+            // A handle dropped on its own listener thread can't wait for it
+            if handle.thread().id() != thread::current().id() {
+                self.exit.wait(LISTENER_SHUTDOWN_TIMEOUT);
+            }
+        }
+    }
+}
+
+/// Marks the listener exited when dropped, also when it panics.
+struct ExitOnDrop(ListenerExit);
+
+impl Drop for ExitOnDrop {
+    fn drop(&mut self) {
+        self.0.set();
+    }
+}
+
+/// State shared between the listener thread and the receive handle.
+#[derive(Clone)]
+pub(crate) struct ListenerShared {
+    pub(crate) queue_depth: Arc<AtomicUsize>,
+    pub(crate) counters: Arc<LinkCounters>,
+    pub(crate) banner: Arc<Mutex<Vec<u8>>>,
+    pub(crate) session: Arc<Session>,
+    pub(crate) warmup: Arc<WarmupTracker>,
+    pub(crate) uart_errors: Arc<Mutex<Option<UartErrorCounts>>>,
+    pub(crate) degraded_bytes: Arc<Mutex<VecDeque<u8>>>,
+    pub(crate) rates: Arc<Mutex<RateMeter>>,
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Woken when a packet is queued or the listener stops, for receive
+    /// handles polled as a stream.
+    #[cfg(feature = "async")]
+    pub(crate) waker: Arc<Mutex<Option<Waker>>>,
+    /// Stops just this listener, unlike [crate::FlemSerial::unlisten] which
+    /// stops every listener of the link.
+    pub(crate) stop: Arc<AtomicBool>,
+    pub(crate) exit: ListenerExit,
+    /// Why the listener stopped on its own, if it did. Set before `exit`.
+    pub(crate) abort: Arc<Mutex<Option<AbortReason>>>,
+    /// Scope of the link, which threads consuming the receive handle join.
+    pub(crate) scope: Option<ScopeHandle>,
+}
+
+impl ListenerShared {
+    pub(crate) fn new<const T: usize>(
+        session: Arc<Session>,
+        warmup: Arc<WarmupTracker>,
+        clock: Arc<dyn Clock>,
+        rate_window: Duration,
+    ) -> Self {
+        Self {
+            queue_depth: Arc::new(AtomicUsize::new(0)),
+            counters: Arc::new(LinkCounters::new(T)),
+            banner: Arc::new(Mutex::new(Vec::new())),
+            session,
+            warmup,
+            uart_errors: Arc::new(Mutex::new(None)),
+            degraded_bytes: Arc::new(Mutex::new(VecDeque::new())),
+            rates: Arc::new(Mutex::new(RateMeter::new(rate_window))),
+            clock,
+            #[cfg(feature = "async")]
+            waker: Arc::new(Mutex::new(None)),
+            stop: Arc::new(AtomicBool::new(false)),
+            exit: ListenerExit::default(),
+            abort: Arc::new(Mutex::new(None)),
+            scope: None,
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn wake(&self) {
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    pub(crate) fn rates(&self) -> LinkRates {
+        self.rates.lock().unwrap().rates(self.clock.now())
+    }
+
+    pub(crate) fn snapshot(&self) -> LinkStats {
+        let mut stats = self.counters.snapshot();
+        stats.label = self.session.label();
+        stats.session_id = self.session.id();
+        stats.session_rx_packets = self.session.rx_packets();
+        stats.session_tx_packets = self.session.tx_packets();
+        stats.session_tx_bytes = self.session.tx_bytes();
+        stats.warmup = self.warmup.snapshot();
+        stats.uart_errors = *self.uart_errors.lock().unwrap();
+        stats
+    }
+}
+
+/// The parts of the link a listener thread releases when it exits.
+struct LinkListening<const T: usize> {
+    continue_listening: Arc<Mutex<bool>>,
+    live_listeners: Arc<AtomicUsize>,
+    #[cfg(feature = "async")]
+    pending_requests: Arc<Mutex<PendingRequests<T>>>,
+    released: AtomicBool,
+}
+
+impl<const T: usize> LinkListening<T> {
+    /// Counts the listener out. The link stops listening when a listener
+    /// stops on its own, or when the last one stopped through its handle
+    /// exits, so requests fail at once instead of waiting out their timeout.
+    fn release(&self, stopped: bool) {
+        if self.released.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let last = self.live_listeners.fetch_sub(1, Ordering::AcqRel) == 1;
+        if last || !stopped {
+            *self.continue_listening.lock().unwrap() = false;
+            #[cfg(feature = "async")]
+            self.pending_requests.lock().unwrap().mailbox.close();
+        }
+    }
+}
+
+/// How parsed packets are handed to the consumer.
+pub(crate) enum Delivery<const T: usize> {
+    Single(Sender<flem::Packet<T>>),
+    Batched(Batcher<T>),
+    Bursts(BurstCollector<T>),
+    Reassembled(Reassembler<T>),
+    Polled(PollDelivery<T>),
+    Timestamped(TimestampedDelivery<T>),
+    Handler(PacketHandler<T>),
+}
+
+impl<const T: usize> Delivery<T> {
+    /// Hands a packet over. Fails once the consumer has gone away.
+    fn deliver(&mut self, packet: flem::Packet<T>) -> Result<(), ()> {
+        match self {
+            Delivery::Single(sender) => sender.send(packet).map_err(|_| ()),
+            Delivery::Batched(batcher) => batcher.push(packet),
+            Delivery::Bursts(collector) => collector.push(packet),
+            Delivery::Reassembled(reassembler) => reassembler.push(packet),
+            Delivery::Polled(polled) => polled.push(packet),
+            Delivery::Timestamped(stamper) => stamper.push(packet),
+            Delivery::Handler(handler) => {
+                handler(packet);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether delivered packets wait in a queue, and so count towards the
+    /// queue depth.
+    fn is_queued(&self) -> bool {
+        !matches!(self, Delivery::Handler(_))
+    }
+
+    /// Called on every pass of the listener loop.
+    fn tick(&mut self) -> Result<(), ()> {
+        match self {
+            Delivery::Single(_)
+            | Delivery::Polled(_)
+            | Delivery::Timestamped(_)
+            | Delivery::Handler(_) => Ok(()),
+            Delivery::Batched(batcher) => batcher.tick(),
+            Delivery::Bursts(collector) => collector.tick(),
+            Delivery::Reassembled(reassembler) => reassembler.tick(),
+        }
+    }
+}
+
+/// How often a disconnected listener checks whether it was stopped while
+/// waiting for the next reconnect attempt.
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Initial size of the listener's read buffer, and its size for ports
+/// that can't tell how many bytes are waiting.
+pub(crate) const MIN_READ_BUFFER: usize = 256;
+
+/// Largest single read, so a flood of bytes can't grow the read buffer
+/// without bound.
+pub(crate) const MAX_READ_BUFFER: usize = 64 * 1024;
+
+/// Grows `buffer` to hold the `available` bytes the OS has waiting, so one
+/// read drains them. Never shrinks it, a link that saw a burst once will
+/// likely see another.
+pub(crate) fn size_read_buffer(buffer: &mut Vec<u8>, available: Option<u32>) {
+    let wanted = available
+        .map_or(0, |available| available as usize)
+        .clamp(MIN_READ_BUFFER, MAX_READ_BUFFER);
+    if wanted > buffer.len() {
+        buffer.resize(wanted, 0);
+    }
+}
+
+/// Everything the listener thread needs, moved into the thread on spawn.
+pub(crate) struct Listener<const T: usize> {
+    pub(crate) rx_port: FlemSerialPort,
+    pub(crate) continue_listening: Arc<Mutex<bool>>,
+    /// Listener threads of the link still running, counted up when one is
+    /// spawned and down by [Listener::run] when it exits.
+    pub(crate) live_listeners: Arc<AtomicUsize>,
+    /// Writes the packets the listener sends on its own, through the same
+    /// path as [crate::FlemSerial::send].
+    pub(crate) tx: TxWriter<T>,
+    pub(crate) byte_capture: Option<Arc<ByteCapture>>,
+    pub(crate) backpressure: Option<Backpressure<T>>,
+    pub(crate) grace_deadline: Option<Instant>,
+    pub(crate) capture_banner: bool,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) port_settings: Option<(String, u32)>,
+    pub(crate) connect_options: ConnectOptions,
+    pub(crate) pacer: SharedPacer,
+    pub(crate) desync: Option<DesyncPolicy>,
+    pub(crate) degrade: Option<DegradePolicy>,
+    pub(crate) keepalive: Option<KeepalivePolicy>,
+    pub(crate) keepalive_ledger: Arc<KeepaliveLedger>,
+    pub(crate) events: EventSender,
+    pub(crate) reconnect: Option<ReconnectPolicy>,
+    pub(crate) replay: Option<SharedReplay<T>>,
+    pub(crate) rx_validator: Option<SharedValidator<T>>,
+    pub(crate) pending_requests: Arc<Mutex<PendingRequests<T>>>,
+    pub(crate) abort_hook: Option<AbortHook>,
+    pub(crate) tunables: TunablesWatch,
+    pub(crate) listen_options: ListenOptions,
+    pub(crate) shared: ListenerShared,
+    pub(crate) state: RxState<T>,
+}
+
+/// Parsing state carried between reads.
+pub(crate) struct RxState<const T: usize> {
+    packet: flem::Packet<T>,
+    backpressure: BackpressureState,
+    desync: DesyncDetector,
+    degrade: DegradeMonitor,
+    keepalive: KeepaliveMonitor,
+    uart_errors: Option<UartErrorPoller>,
+    /// Sleep after the last read, None if it returned data.
+    idle_interval: Option<Duration>,
+}
+
+impl<const T: usize> RxState<T> {
+    pub(crate) fn new(uart_errors: Option<UartErrorPoller>) -> Self {
+        Self {
+            packet: flem::Packet::<T>::new(),
+            backpressure: BackpressureState::default(),
+            desync: DesyncDetector::default(),
+            degrade: DegradeMonitor::default(),
+            keepalive: KeepaliveMonitor::default(),
+            uart_errors,
+            idle_interval: None,
+        }
+    }
+}
+
+impl<const T: usize> Listener<T> {
+    /// Runs the listener until it is stopped, the port fails or the consumer
+    /// goes away.
+    pub(crate) fn run(self, delivery: Delivery<T>) {
+        let shared = self.shared.clone();
+        let _exit = ExitOnDrop(shared.exit.clone());
+        let link = LinkListening {
+            continue_listening: self.continue_listening.clone(),
+            live_listeners: self.live_listeners.clone(),
+            #[cfg(feature = "async")]
+            pending_requests: self.pending_requests.clone(),
+            released: AtomicBool::new(false),
+        };
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| self.listen(delivery, &link)))
+        {
+            let reason = AbortReason::Panicked(hooks::panic_message(payload.as_ref()));
+            *shared.abort.lock().unwrap() = Some(reason);
+            link.release(false);
+            panic::resume_unwind(payload);
+        }
+    }
+
+    fn listen(mut self, mut delivery: Delivery<T>, link: &LinkListening<T>) {
+        let mut rx_buffer = Vec::new();
+        let mut read_errors = 0;
+
+        while self.listening() {
+            if self.poll(&mut delivery).is_err() {
+                break;
+            }
+
+            size_read_buffer(&mut rx_buffer, self.rx_port.bytes_to_read().ok());
+            match self.rx_port.read(&mut rx_buffer) {
+                Ok(bytes_to_read) => {
+                    read_errors = 0;
+
+                    let max_interval = self.tunables.current().idle_poll_interval;
+                    if self.state.degrade.is_degraded() {
+                        // Degraded links are read less often to save CPU
+                        thread::sleep(max_interval);
+                    } else if bytes_to_read == 0 {
+                        let interval = self
+                            .listen_options
+                            .next_idle_interval(self.state.idle_interval, max_interval);
+                        self.state.idle_interval = Some(interval);
+                        thread::sleep(interval);
+                    } else {
+                        self.state.idle_interval = None;
+                    }
+                    if bytes_to_read > 0
+                        && self
+                            .process(&rx_buffer[..bytes_to_read], &mut delivery)
+                            .is_err()
+                    {
+                        // Consumer is gone, nobody to listen for
+                        break;
+                    }
+                }
+                Err(error) => {
+                    // Library indicates to retry on errors, so that is
+                    // what we will do. A handle that only ever errors is
+                    // dead though, reopen it.
+                    if !reconnect::is_idle_error(error.kind()) {
+                        self.events.send(
+                            LinkEvent::ReadError {
+                                kind: error.kind(),
+                                message: error.to_string(),
+                            },
+                            self.clock.now(),
+                        );
+                        read_errors += 1;
+                        LinkCounters::increment(&self.shared.counters.read_errors);
+                        if read_errors >= reconnect::DEAD_HANDLE_ERRORS {
+                            if self.reopen() {
+                                read_errors = 0;
+                                self.state.packet.reset_lazy();
+                                self.events
+                                    .send(LinkEvent::ResumedAfterSleep, self.clock.now());
+                                self.replay_held();
+                            } else if self.reconnect.is_some() {
+                                self.events.send(LinkEvent::Disconnected, self.clock.now());
+                                match self.wait_for_reconnect(&mut delivery) {
+                                    Some(attempts) => {
+                                        read_errors = 0;
+                                        self.state.packet.reset_lazy();
+                                        self.events.send(
+                                            LinkEvent::Reconnected { attempts },
+                                            self.clock.now(),
+                                        );
+                                        self.replay_held();
+                                    }
+                                    None => {
+                                        if self.listening() {
+                                            self.report_fatal(&error);
+                                        }
+                                        break;
+                                    }
+                                }
+                            } else {
+                                // The port is gone, nothing left to listen to
+                                self.report_fatal(&error);
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        link.release(self.shared.stop.load(Ordering::Acquire));
+        // Streams see the end of the queue once its sender is gone
+        drop(delivery);
+        #[cfg(feature = "async")]
+        self.shared.wake();
+    }
+
+    fn listening(&self) -> bool {
+        !self.shared.stop.load(Ordering::Acquire) && *self.continue_listening.lock().unwrap()
+    }
+
+    /// Time based work done on every pass of the loop: reconfiguration,
+    /// backpressure, busy retries, polls and batch flushing. Fails once the
+    /// consumer has gone away or a write finds the port gone.
+    pub(crate) fn poll(&mut self, delivery: &mut Delivery<T>) -> Result<(), ()> {
+        if let Some(tunables) = self.tunables.changed() {
+            self.events.set_limit(tunables.event_rate_limit);
+            if let (Some(bp), Some((high_water, low_water))) =
+                (self.backpressure.as_mut(), tunables.queue_watermarks)
+            {
+                bp.high_water = high_water;
+                bp.low_water = low_water;
+            }
+            if let (Some(policy), Some(interval)) =
+                (self.keepalive.as_mut(), tunables.keepalive_interval)
+            {
+                policy.interval = interval;
+            }
+        }
+
+        if let Some(bp) = self.backpressure.as_ref() {
+            let depth = self.shared.queue_depth.load(Ordering::Acquire);
+            if let Some(action) = self
+                .state
+                .backpressure
+                .update(depth, bp.high_water, bp.low_water)
+            {
+                let packet = bp.packet(action);
+                self.check_write(self.tx.write(packet))?;
+            }
+        }
+
+        let retries = self.tx.busy_retry.lock().unwrap().due(self.clock.now());
+        for packet in retries.iter() {
+            self.check_write(self.tx.resend(packet))?;
+        }
+
+        if let Delivery::Polled(polled) = delivery {
+            self.write_unqueued(&polled.due())?;
+        }
+
+        if let Some(policy) = self.keepalive {
+            let (send, transition) =
+                self.state
+                    .keepalive
+                    .due(&policy, &self.keepalive_ledger, self.clock.now());
+            if let Some(transition) = transition {
+                self.events.send(transition.event(), self.clock.now());
+            }
+            if send {
+                let mut keepalive = flem::Packet::<T>::new();
+                keepalive.set_request(policy.request);
+                keepalive.pack();
+                self.write_unqueued(&[keepalive])?;
+            }
+        }
+
+        if let Some(policy) = self.degrade {
+            if let Some(transition) = self.state.degrade.check(&policy, self.clock.now()) {
+                self.on_degrade_transition(transition);
+            }
+        }
+
+        if let Some(poller) = self.state.uart_errors.as_mut() {
+            if let Some(counts) = poller.poll(self.clock.now()) {
+                *self.shared.uart_errors.lock().unwrap() = Some(counts);
+            }
+        }
+
+        self.events.tick(self.clock.now());
+        #[cfg(feature = "async")]
+        self.pending_requests
+            .lock()
+            .unwrap()
+            .mailbox
+            .expire(self.clock.now());
+
+        delivery.tick()
+    }
+
+    /// Writes packets the listener sends on its own, such as polls and
+    /// keepalives, and adds them to the capture.
+    fn write_unqueued(&self, packets: &[flem::Packet<T>]) -> Result<(), ()> {
+        packets
+            .iter()
+            .try_for_each(|packet| self.check_write(self.tx.write(packet)))
+    }
+
+    /// A write that failed after its retries means the port is gone, which
+    /// is reported like a dead read and stops the listener. Links without a
+    /// port have nothing to write to.
+    fn check_write(&self, result: Result<(), FlemSerialError>) -> Result<(), ()> {
+        match result {
+            Err(FlemSerialError::WriteFailed(error)) => {
+                self.report_fatal(&error);
+                Err(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parses received bytes and delivers complete packets. Fails once the
+    /// consumer has gone away.
+    pub(crate) fn process(&mut self, bytes: &[u8], delivery: &mut Delivery<T>) -> Result<(), ()> {
+        let counters = self.shared.counters.clone();
+        counters
+            .rx_bytes
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        self.shared
+            .rates
+            .lock()
+            .unwrap()
+            .record_bytes(bytes.len(), self.clock.now());
+        if let Some(capture) = self.byte_capture.as_ref() {
+            let _ = capture.record(bytes);
+        }
+
+        if let (Some(policy), true) = (self.degrade, self.state.degrade.is_degraded()) {
+            let mut degraded_bytes = self.shared.degraded_bytes.lock().unwrap();
+            degraded_bytes.extend(bytes.iter());
+            let excess = degraded_bytes
+                .len()
+                .saturating_sub(policy.raw_capture_bytes);
+            degraded_bytes.drain(..excess);
+        }
+
+        for byte in bytes.iter() {
+            match self.state.packet.add_byte(*byte) {
+                Status::PacketReceived => {
+                    let rx_packet = &mut self.state.packet;
+                    if self
+                        .tx
+                        .busy_retry
+                        .lock()
+                        .unwrap()
+                        .on_response(rx_packet, self.clock.now())
+                    {
+                        // Device was busy, the request will be resent
+                        rx_packet.reset_lazy();
+                        continue;
+                    }
+                    self.state.desync.on_packet();
+                    let is_id = rx_packet.get_request() == flem::Request::ID;
+                    self.shared.warmup.on_packet(is_id, self.clock.now());
+                    if is_id {
+                        if let Ok(id) = flem::DataId::from(rx_packet.get_data()) {
+                            self.shared
+                                .session
+                                .set_identity(DeviceIdentity::from_data_id(&id));
+                        }
+                    }
+                    counters.record_payload(rx_packet.get_data().len());
+                    self.shared
+                        .rates
+                        .lock()
+                        .unwrap()
+                        .record_packet(self.clock.now());
+                    let stamp = self.shared.session.next_rx();
+                    if let Some((device, capture)) = self.tx.capture.as_ref() {
+                        let _ = capture.record(device, Some(stamp), Direction::Rx, rx_packet);
+                    }
+                    if let Some(validator) = self.rx_validator.as_ref() {
+                        if let Err(reason) = validator.lock().unwrap().validate(rx_packet) {
+                            LinkCounters::increment(&counters.validation_failures);
+                            self.events.send(
+                                LinkEvent::ValidationFailed {
+                                    request: rx_packet.get_request(),
+                                    reason,
+                                },
+                                self.clock.now(),
+                            );
+                            rx_packet.reset_lazy();
+                            continue;
+                        }
+                    }
+                    let keepalive = match self.keepalive {
+                        Some(policy) => self.state.keepalive.on_response(
+                            &policy,
+                            &self.keepalive_ledger,
+                            rx_packet.get_request(),
+                        ),
+                        None => KeepaliveResponse::default(),
+                    };
+                    if let Some(transition) = keepalive.transition {
+                        self.events.send(transition.event(), self.clock.now());
+                    }
+                    if self.state.degrade.is_degraded() {
+                        LinkCounters::increment(&counters.dropped_while_degraded);
+                        rx_packet.reset_lazy();
+                        continue;
+                    }
+                    if keepalive.answered {
+                        rx_packet.reset_lazy();
+                        continue;
+                    }
+                    if self.pending_requests.lock().unwrap().route(rx_packet) {
+                        rx_packet.reset_lazy();
+                        continue;
+                    }
+                    // Counted before delivering, the consumer may take the
+                    // packet and count it off before deliver returns
+                    let queued = delivery.is_queued();
+                    if queued {
+                        self.shared.queue_depth.fetch_add(1, Ordering::AcqRel);
+                    }
+                    if delivery.deliver(rx_packet.clone()).is_err() {
+                        if queued {
+                            self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+                        }
+                        LinkCounters::increment(&counters.queue_drops);
+                        *self.continue_listening.lock().unwrap() = false;
+                        return Err(());
+                    }
+                    #[cfg(feature = "async")]
+                    if queued {
+                        self.shared.wake();
+                    }
+                    rx_packet.reset_lazy();
+                }
+                Status::PacketBuilding => {
+                    // Normal, building packet
+                }
+                Status::HeaderBytesNotFound => {
+                    if in_grace(self.grace_deadline, self.clock.now()) {
+                        LinkCounters::increment(&counters.suppressed_resync_errors);
+                        if self.capture_banner {
+                            let mut banner = self.shared.banner.lock().unwrap();
+                            if banner.len() < MAX_BANNER_BYTES {
+                                banner.push(*byte);
+                            }
+                        }
+                    } else {
+                        LinkCounters::increment(&counters.resync_errors);
+                        self.on_parse_error();
+                        if let Some(policy) = self.desync {
+                            if let Some(garbage_bytes) =
+                                self.state.desync.on_garbage(policy.max_garbage_bytes)
+                            {
+                                self.events.send(
+                                    LinkEvent::LinkDesynchronized { garbage_bytes },
+                                    self.clock.now(),
+                                );
+                                self.recover(policy.recovery);
+                            }
+                        }
+                    }
+                    self.state.packet.reset_lazy();
+                }
+                Status::ChecksumError => {
+                    if in_grace(self.grace_deadline, self.clock.now()) {
+                        LinkCounters::increment(&counters.suppressed_checksum_errors);
+                    } else {
+                        LinkCounters::increment(&counters.checksum_errors);
+                        self.on_parse_error();
+                    }
+                    self.state.packet.reset_lazy();
+                }
+                _ => {
+                    self.state.packet.reset_lazy();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_parse_error(&mut self) {
+        if let Some(policy) = self.degrade {
+            if let Some(transition) = self.state.degrade.on_error(&policy, self.clock.now()) {
+                self.on_degrade_transition(transition);
+            }
+        }
+    }
+
+    fn on_degrade_transition(&mut self, transition: DegradeTransition) {
+        let event = match transition {
+            DegradeTransition::Degraded { errors } => {
+                self.shared.degraded_bytes.lock().unwrap().clear();
+                LinkEvent::Degraded { errors }
+            }
+            DegradeTransition::Recovered => LinkEvent::Recovered,
+        };
+        self.events.send(event, self.clock.now());
+    }
+
+    /// Applies a desync recovery action. Failures are ignored, the detector
+    /// fires again if the link stays desynchronized.
+    fn recover(&mut self, recovery: DesyncRecovery) {
+        match recovery {
+            DesyncRecovery::Report => {}
+            DesyncRecovery::PurgeBuffers => {
+                let _ = self.rx_port.clear(serialport::ClearBuffer::All);
+            }
+            DesyncRecovery::ToggleDtr(width) => {
+                let _ = self.rx_port.write_data_terminal_ready(false);
+                thread::sleep(width);
+                let _ = self.rx_port.write_data_terminal_ready(true);
+            }
+            DesyncRecovery::Reconnect => {
+                self.reopen();
+            }
+        }
+    }
+
+    /// Records why the listener is stopping and reports it to the abort hook.
+    fn report_fatal(&self, error: &std::io::Error) {
+        let reason = AbortReason::FatalIo {
+            kind: error.kind(),
+            message: error.to_string(),
+        };
+        *self.shared.abort.lock().unwrap() = Some(reason.clone());
+        hooks::report(
+            AbortReport {
+                thread: "listener",
+                link: self.port_settings.as_ref().map(|(name, _)| name.clone()),
+                reason,
+            },
+            self.abort_hook.as_ref(),
+        );
+    }
+
+    /// Retries `reopen` following the reconnect policy. Returns the number
+    /// of attempts it took, or None if the policy gave up, listening was
+    /// stopped or the consumer went away.
+    fn wait_for_reconnect(&mut self, delivery: &mut Delivery<T>) -> Option<u32> {
+        let mut backoff = ReconnectBackoff::new(self.reconnect?);
+        backoff.on_disconnect(self.clock.now());
+
+        loop {
+            let next_attempt = backoff.next_attempt()?;
+            while self.clock.now() < next_attempt {
+                if !self.listening() || delivery.tick().is_err() {
+                    return None;
+                }
+                thread::sleep(RECONNECT_POLL_INTERVAL);
+            }
+
+            if self.reopen() {
+                let attempts = backoff.attempts() + 1;
+                backoff.on_success();
+                return Some(attempts);
+            }
+            backoff.on_failure(self.clock.now());
+        }
+    }
+
+    /// Writes the packets held while the link was down, see
+    /// [crate::FlemSerial::set_replay_on_reconnect].
+    fn replay_held(&mut self) {
+        let held = match self.replay.as_ref() {
+            Some(replay) => replay.lock().unwrap().take(),
+            None => return,
+        };
+        if held.is_empty() {
+            return;
+        }
+
+        let packets = held.len();
+        let failed = held
+            .into_iter()
+            .filter(|(writer, packet)| writer.write(packet).is_err())
+            .count();
+        self.events
+            .send(LinkEvent::Replayed { packets, failed }, self.clock.now());
+    }
+
+    /// Replaces the dead rx and tx handles with freshly opened ones.
+    fn reopen(&mut self) -> bool {
+        let (port_name, baud) = match self.port_settings.as_ref() {
+            Some(settings) => settings,
+            None => return false,
+        };
+
+        let (port, counters) =
+            match reconnect::reopen(port_name, *baud, &self.connect_options, &self.pacer) {
+                Some(opened) => opened,
+                None => return false,
+            };
+
+        if let Some(tx_port) = self.tx.port.as_ref() {
+            match port.try_clone() {
+                Ok(tx) => *tx_port.lock().unwrap() = tx,
+                Err(_) => return false,
+            }
+        }
+        self.rx_port = port;
+        // The old counters belong to the closed port, keep what they counted
+        let carried = self.shared.uart_errors.lock().unwrap().unwrap_or_default();
+        self.state.uart_errors = counters
+            .and_then(|counters| UartErrorPoller::open(counters, carried, self.clock.now()));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{size_read_buffer, MAX_READ_BUFFER, MIN_READ_BUFFER};
+    #[cfg(unix)]
+    use crate::virtual_port::VirtualDevice;
+    use crate::{
+        events::LinkEvent,
+        hooks::{AbortHook, AbortReason},
+        keepalive::KeepalivePolicy,
+        request::RequestError,
+        test_support::{DeadHandle, Unplugged},
+        FlemSerial,
+    };
+    use std::{
+        io,
+        sync::{mpsc, Arc, Mutex},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_failed_keepalive_write_stops_the_listener() {
+        let (reports_tx, reports) = mpsc::channel();
+        let reports_tx = Mutex::new(reports_tx);
+        let hook: AbortHook = Arc::new(move |report| {
+            let _ = reports_tx.lock().unwrap().send(report.clone());
+        });
+
+        let mut serial = FlemSerial::<64>::from_transport(Unplugged);
+        serial.set_abort_hook(hook);
+        serial.set_keepalive(KeepalivePolicy {
+            interval: Duration::from_millis(5),
+            ..KeepalivePolicy::default()
+        });
+        let rx = serial.listen().unwrap();
+
+        let report = reports.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(matches!(
+            report.reason,
+            AbortReason::FatalIo {
+                kind: io::ErrorKind::BrokenPipe,
+                ..
+            }
+        ));
+        assert!(rx.join().is_ok());
+    }
+
+    #[test]
+    fn test_dropping_the_last_handle_stops_the_link_listening() {
+        let mut serial = FlemSerial::<64>::from_transport(Unplugged);
+        let first = serial.listen().unwrap();
+        let second = serial.listen().unwrap();
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.pack();
+
+        drop(first);
+        // The other listener keeps the link listening, the request is sent
+        // and fails on the unplugged port instead
+        assert!(matches!(
+            serial.request(&packet, Duration::from_millis(10)),
+            Err(RequestError::SendFailed)
+        ));
+
+        drop(second);
+        assert!(matches!(
+            serial.request(&packet, Duration::from_secs(5)),
+            Err(RequestError::NotListening)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_a_dead_handle_on_a_present_port_is_reopened_without_a_policy() {
+        let device = VirtualDevice::<64>::echo().unwrap();
+        let mut serial = FlemSerial::<64>::from_transport(DeadHandle);
+        serial.port_settings = Some((device.port_name().to_string(), 115200));
+        let rx = serial.listen().unwrap();
+
+        let resumed = std::iter::from_fn(|| rx.events().recv_timeout(Duration::from_secs(2)).ok())
+            .find(LinkEvent::is_state_change);
+        assert_eq!(resumed, Some(LinkEvent::ResumedAfterSleep));
+
+        // Both directions now use the reopened port
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(0x30);
+        packet.pack();
+        serial.send(&packet).unwrap();
+        let echoed = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(echoed.get_request(), 0x30);
+    }
+
+    #[test]
+    fn test_a_dead_handle_without_a_port_stops_the_listener() {
+        let mut serial = FlemSerial::<64>::from_transport(DeadHandle);
+        let rx = serial.listen().unwrap();
+        assert!(rx.join().is_ok());
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.pack();
+        assert!(matches!(
+            serial.request(&packet, Duration::from_secs(5)),
+            Err(RequestError::NotListening)
+        ));
+    }
+
+    #[test]
+    fn test_read_buffer_fits_the_waiting_bytes() {
+        let mut buffer = Vec::new();
+        size_read_buffer(&mut buffer, None);
+        assert_eq!(buffer.len(), MIN_READ_BUFFER);
+
+        size_read_buffer(&mut buffer, Some(5000));
+        assert_eq!(buffer.len(), 5000);
+        size_read_buffer(&mut buffer, Some(10));
+        assert_eq!(buffer.len(), 5000);
+
+        size_read_buffer(&mut buffer, Some(u32::MAX));
+        assert_eq!(buffer.len(), MAX_READ_BUFFER);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    matching::{EchoRequest, ResponseMatcher},
+    request::RequestError,
+};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+type ResponseResult<const T: usize> = Result<flem::Packet<T>, RequestError>;
+
+struct Slot<const T: usize> {
+    result: Option<ResponseResult<T>>,
+    waker: Option<Waker>,
+}
+
+impl<const T: usize> Slot<T> {
+    fn resolve(&mut self, result: ResponseResult<T>) {
+        self.result = Some(result);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+type SharedSlot<const T: usize> = Arc<Mutex<Slot<T>>>;
+
+struct Waiting<const T: usize> {
+    request: flem::Packet<T>,
+    deadline: Instant,
+    slot: SharedSlot<T>,
+}
+
+/// Requests awaiting their response asynchronously. Any number may wait at
+/// once, responses go to the oldest request the [ResponseMatcher] pairs them
+/// with. The listener thread resolves and expires them, so no async
+/// runtime timer is needed.
+pub(crate) struct Mailbox<const T: usize> {
+    matcher: Box<dyn ResponseMatcher<T>>,
+    waiting: Vec<Waiting<T>>,
+}
+
+impl<const T: usize> Default for Mailbox<T> {
+    fn default() -> Self {
+        Self {
+            matcher: Box::new(EchoRequest),
+            waiting: Vec::new(),
+        }
+    }
+}
+
+impl<const T: usize> Mailbox<T> {
+    pub(crate) fn set_matcher(&mut self, matcher: Box<dyn ResponseMatcher<T>>) {
+        self.matcher = matcher;
+    }
+
+    pub(crate) fn register(
+        &mut self,
+        request: flem::Packet<T>,
+        deadline: Instant,
+    ) -> ResponseFuture<T> {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+        self.waiting.push(Waiting {
+            request,
+            deadline,
+            slot: slot.clone(),
+        });
+        ResponseFuture { slot }
+    }
+
+    /// Withdraws a request that could not be sent.
+    pub(crate) fn cancel(&mut self, future: &ResponseFuture<T>, error: RequestError) {
+        self.waiting
+            .retain(|waiting| !Arc::ptr_eq(&waiting.slot, &future.slot));
+        future.slot.lock().unwrap().resolve(Err(error));
+    }
+
+    /// Resolves the oldest request `packet` answers. Returns false if none
+    /// was waiting for it.
+    pub(crate) fn route(&mut self, packet: &flem::Packet<T>) -> bool {
+        // Futures dropped before their response no longer care
+        self.waiting
+            .retain(|waiting| Arc::strong_count(&waiting.slot) > 1);
+
+        match self
+            .waiting
+            .iter()
+            .position(|waiting| self.matcher.matches(&waiting.request, packet))
+        {
+            Some(index) => {
+                let waiting = self.waiting.remove(index);
+                waiting.slot.lock().unwrap().resolve(Ok(packet.clone()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Times out requests whose deadline has passed.
+    pub(crate) fn expire(&mut self, now: Instant) {
+        self.waiting.retain(|waiting| {
+            if now < waiting.deadline {
+                return true;
+            }
+            waiting
+                .slot
+                .lock()
+                .unwrap()
+                .resolve(Err(RequestError::Timeout));
+            false
+        });
+    }
+
+    /// Fails every waiting request, once nothing will resolve them.
+    pub(crate) fn close(&mut self) {
+        for waiting in self.waiting.drain(..) {
+            waiting
+                .slot
+                .lock()
+                .unwrap()
+                .resolve(Err(RequestError::NotListening));
+        }
+    }
+}
+
+/// Resolves to the response of a request sent with
+/// [crate::FlemSerial::request_async], or to the reason there is none.
+pub struct ResponseFuture<const T: usize> {
+    slot: SharedSlot<T>,
+}
+
+impl<const T: usize> Future for ResponseFuture<T> {
+    type Output = ResponseResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mailbox;
+    use crate::request::RequestError;
+    use std::{
+        future::Future,
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        time::{Duration, Instant},
+    };
+
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn packet(request: u8, data: &[u8]) -> flem::Packet<8> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(request);
+        packet.add_data(data).unwrap();
+        packet
+    }
+
+    fn poll<F: Future>(future: std::pin::Pin<&mut F>) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        future.poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn test_concurrent_requests_resolve_independently() {
+        let start = Instant::now();
+        let mut mailbox = Mailbox::<8>::default();
+        let mut first = pin!(mailbox.register(packet(5, &[]), start + Duration::from_secs(1)));
+        let mut second = pin!(mailbox.register(packet(6, &[]), start + Duration::from_secs(1)));
+        let mut third = pin!(mailbox.register(packet(7, &[]), start + Duration::from_millis(10)));
+        assert!(poll(first.as_mut()).is_pending());
+
+        assert!(!mailbox.route(&packet(flem::Request::EVENT, &[])));
+        assert!(mailbox.route(&packet(6, &[2])));
+        assert!(mailbox.route(&packet(5, &[1])));
+        mailbox.expire(start + Duration::from_millis(10));
+
+        assert!(
+            matches!(poll(second.as_mut()), Poll::Ready(Ok(response)) if response.get_data() == [2])
+        );
+        assert!(
+            matches!(poll(first.as_mut()), Poll::Ready(Ok(response)) if response.get_data() == [1])
+        );
+        assert!(matches!(
+            poll(third.as_mut()),
+            Poll::Ready(Err(RequestError::Timeout))
+        ));
+    }
+}
@@ -0,0 +1,716 @@
+use crate::{
+    ascii::AsciiLink,
+    compat::FirmwareVersion,
+    inventory::{self, DeviceRecord, UsbIds},
+    scope::{self, LinkScope, ScopeHandle},
+    FlemSerial, FlemSerialError,
+};
+use serialport::SerialPortType;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        mpsc::{self, Receiver, RecvError, RecvTimeoutError, TryRecvError},
+        Barrier,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// A packet received by [FlemDeviceManager::listen_all], tagged with the
+/// device it came from.
+#[derive(Clone)]
+pub struct DevicePacket<const T: usize> {
+    pub device: String,
+    pub packet: flem::Packet<T>,
+}
+
+/// Receive handle returned by [FlemDeviceManager::listen_all]. Packets of
+/// one device arrive in order, packets of different devices in the order
+/// they were received.
+pub struct ManagerRx<const T: usize> {
+    rx_packet_queue: Receiver<DevicePacket<T>>,
+    forwarders: Vec<JoinHandle<()>>,
+}
+
+impl<const T: usize> ManagerRx<T> {
+    pub fn queue(&self) -> &Receiver<DevicePacket<T>> {
+        &self.rx_packet_queue
+    }
+
+    /// Blocks until a packet is received from any device.
+    pub fn recv(&self) -> Result<DevicePacket<T>, RecvError> {
+        self.rx_packet_queue.recv()
+    }
+
+    /// Returns a packet if one is waiting.
+    pub fn try_recv(&self) -> Result<DevicePacket<T>, TryRecvError> {
+        self.rx_packet_queue.try_recv()
+    }
+
+    /// Blocks until a packet is received or `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<DevicePacket<T>, RecvTimeoutError> {
+        self.rx_packet_queue.recv_timeout(timeout)
+    }
+
+    /// Waits for every device's listener to exit. Call
+    /// [FlemDeviceManager::unlisten_all] first or this will block forever.
+    pub fn join(self) -> thread::Result<()> {
+        for forwarder in self.forwarders {
+            forwarder.join()?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of [FlemDeviceManager::execute_synchronized].
+#[derive(Debug, Clone)]
+pub struct SyncReport {
+    /// Time each device's write completed, relative to the earliest one.
+    pub offsets: BTreeMap<String, Duration>,
+    /// Difference between the earliest and latest write completion.
+    pub skew: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyncError {
+    /// A device in the request is not managed or not connected. Nothing was
+    /// sent.
+    UnknownDevice(String),
+    /// A transmit interceptor of the device vetoed its packet. Nothing was
+    /// sent.
+    Vetoed(String),
+    /// The device's firmware doesn't support the packet's request code,
+    /// see [FlemSerial::require_firmware]. Nothing was sent.
+    UnsupportedByFirmware {
+        device: String,
+        request: u8,
+        firmware: FirmwareVersion,
+    },
+    /// Sending to a device would exceed its duty cycle limit. Nothing was
+    /// sent.
+    DutyCycleExceeded(String),
+    /// Writing to a device failed. Other devices may have received their
+    /// packet.
+    SendFailed(String),
+    /// All packets were sent but the achieved skew was larger than allowed.
+    SkewExceeded(SyncReport),
+}
+
+/// Owns several FLEM links keyed by a device name, and optionally line
+/// based ASCII devices in a separate namespace.
+pub struct FlemDeviceManager<const T: usize> {
+    devices: BTreeMap<String, FlemSerial<T>>,
+    ascii_devices: BTreeMap<String, AsciiLink>,
+    groups: HashMap<String, Vec<String>>,
+    scope: Option<ScopeHandle>,
+}
+
+impl<const T: usize> Default for FlemDeviceManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const T: usize> FlemDeviceManager<T> {
+    pub fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+            ascii_devices: BTreeMap::new(),
+            groups: HashMap::new(),
+            scope: None,
+        }
+    }
+
+    /// Tracks the threads of every managed link, and the forwarders of
+    /// [FlemDeviceManager::listen_all], in `scope` from now on. See
+    /// [LinkScope].
+    pub fn set_scope(&mut self, scope: &LinkScope) {
+        self.scope = Some(scope.handle());
+        for serial in self.devices.values_mut() {
+            serial.set_scope(scope);
+        }
+        for link in self.ascii_devices.values_mut() {
+            link.set_scope(scope);
+        }
+    }
+
+    pub fn clear_scope(&mut self) {
+        self.scope = None;
+        for serial in self.devices.values_mut() {
+            serial.clear_scope();
+        }
+        for link in self.ascii_devices.values_mut() {
+            link.clear_scope();
+        }
+    }
+
+    /// Connects to `port_name` and manages the link as `device`.
+    pub fn connect(
+        &mut self,
+        device: &str,
+        port_name: &String,
+        baud: u32,
+    ) -> Result<(), FlemSerialError> {
+        let mut serial = FlemSerial::<T>::new();
+        serial.connect(port_name, baud)?;
+        self.insert(device, serial);
+        Ok(())
+    }
+
+    /// Connects to the USB adapter with this serial number and manages the
+    /// link under the serial number, which unlike the port name stays the
+    /// same when boards are replugged in a different order.
+    pub fn connect_by_serial_number(
+        &mut self,
+        serial_number: &str,
+        baud: u32,
+    ) -> Result<(), FlemSerialError> {
+        let mut serial = FlemSerial::<T>::new();
+        let mut matching = serial.list_ports()?.into_iter().filter(|port| {
+            port.usb
+                .as_ref()
+                .is_some_and(|usb| usb.serial_number.as_deref() == Some(serial_number))
+        });
+
+        let port_name = match (matching.next(), matching.next()) {
+            (None, _) => return Err(FlemSerialError::NoDeviceFoundByThatName),
+            (Some(port), None) => port.name,
+            (Some(_), Some(_)) => return Err(FlemSerialError::MultipleDevicesFoundByThatName),
+        };
+        serial.connect(&port_name, baud)?;
+        self.insert(serial_number, serial);
+        Ok(())
+    }
+
+    /// Manages an already connected link as `device`, replacing any link
+    /// previously registered under that name. Links without a label are
+    /// labelled `device`.
+    pub fn insert(&mut self, device: &str, mut serial: FlemSerial<T>) -> Option<FlemSerial<T>> {
+        if serial.label().is_none() {
+            serial.set_label(device);
+        }
+        if self.scope.is_some() {
+            serial.set_scope_handle(self.scope.clone());
+        }
+        self.devices.insert(device.to_string(), serial)
+    }
+
+    /// Stops managing `device` and hands its link back.
+    pub fn remove(&mut self, device: &str) -> Option<FlemSerial<T>> {
+        self.devices.remove(device)
+    }
+
+    pub fn device(&mut self, device: &str) -> Option<&mut FlemSerial<T>> {
+        self.devices.get_mut(device)
+    }
+
+    /// Names of the managed devices in sorted order.
+    pub fn devices(&self) -> Vec<String> {
+        self.devices.keys().cloned().collect()
+    }
+
+    /// Describes every managed FLEM link: its port, the USB adapter's IDs,
+    /// the last identity the device reported and when it last sent a
+    /// packet. Nothing is sent to the devices.
+    pub fn inventory(&self) -> Vec<DeviceRecord> {
+        let ports = serialport::available_ports().unwrap_or_default();
+
+        self.devices
+            .iter()
+            .map(|(device, serial)| {
+                let port = serial.port_name().map(str::to_string);
+                let usb = ports
+                    .iter()
+                    .find(|info| Some(info.port_name.as_str()) == port.as_deref())
+                    .and_then(|info| match &info.port_type {
+                        SerialPortType::UsbPort(usb) => Some(UsbIds::from_usb_port_info(usb)),
+                        _ => None,
+                    });
+
+                DeviceRecord {
+                    device: device.clone(),
+                    port,
+                    usb,
+                    identity: serial.session().identity(),
+                    last_seen: serial.session().last_rx(),
+                }
+            })
+            .collect()
+    }
+
+    /// [FlemDeviceManager::inventory] as a JSON array.
+    pub fn inventory_json(&self) -> String {
+        inventory::to_json(&self.inventory())
+    }
+
+    /// Connects to a line based ASCII device on `port_name` and manages it
+    /// as `device`. Use [FlemDeviceManager::ascii_device] to listen for its
+    /// lines.
+    pub fn connect_ascii(
+        &mut self,
+        device: &str,
+        port_name: &str,
+        baud: u32,
+    ) -> Result<(), FlemSerialError> {
+        let link = AsciiLink::connect(port_name, baud)?;
+        self.insert_ascii(device, link);
+        Ok(())
+    }
+
+    pub fn insert_ascii(&mut self, device: &str, mut link: AsciiLink) -> Option<AsciiLink> {
+        if self.scope.is_some() {
+            link.scope = self.scope.clone();
+        }
+        self.ascii_devices.insert(device.to_string(), link)
+    }
+
+    pub fn remove_ascii(&mut self, device: &str) -> Option<AsciiLink> {
+        self.ascii_devices.remove(device)
+    }
+
+    pub fn ascii_device(&mut self, device: &str) -> Option<&mut AsciiLink> {
+        self.ascii_devices.get_mut(device)
+    }
+
+    /// Names of the managed ASCII devices in sorted order.
+    pub fn ascii_devices(&self) -> Vec<String> {
+        self.ascii_devices.keys().cloned().collect()
+    }
+
+    /// Sends `line` to an ASCII device.
+    pub fn send_line(&mut self, device: &str, line: &str) -> Result<(), FlemSerialError> {
+        self.ascii_devices
+            .get_mut(device)
+            .ok_or(FlemSerialError::NoDeviceFoundByThatName)?
+            .send_line(line)
+    }
+
+    /// Starts listening on every managed device and merges their packets
+    /// into one queue, tagged with the device they came from. Fails without
+    /// listening if any device can't listen. Link events and stats stay
+    /// available per device through [FlemDeviceManager::device].
+    pub fn listen_all(&mut self) -> Result<ManagerRx<T>, FlemSerialError> {
+        let mut links = Vec::new();
+        for (device, serial) in self.devices.iter_mut() {
+            match serial.listen() {
+                Ok(rx) => links.push((device.clone(), rx)),
+                Err(error) => {
+                    self.unlisten_all();
+                    return Err(error);
+                }
+            }
+        }
+
+        let (sender, rx_packet_queue) = mpsc::channel();
+        let forwarders = links
+            .into_iter()
+            .map(|(device, rx)| {
+                let sender = sender.clone();
+                // Ends once the device's listener stops, so there is
+                // nothing else to stop
+                let link = Some(device.clone());
+                scope::spawn_scoped(
+                    "manager",
+                    link,
+                    None,
+                    self.scope.as_ref(),
+                    || {},
+                    move || {
+                        while let Ok(packet) = rx.recv() {
+                            let tagged = DevicePacket {
+                                device: device.clone(),
+                                packet,
+                            };
+                            if sender.send(tagged).is_err() {
+                                break;
+                            }
+                        }
+                    },
+                )
+            })
+            .collect();
+
+        Ok(ManagerRx {
+            rx_packet_queue,
+            forwarders,
+        })
+    }
+
+    /// Stops listening on every managed device.
+    pub fn unlisten_all(&mut self) {
+        for serial in self.devices.values_mut() {
+            serial.unlisten();
+        }
+    }
+
+    /// Sends `packet` to a single device.
+    pub fn send(&mut self, device: &str, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        self.devices
+            .get_mut(device)
+            .ok_or(FlemSerialError::NoDeviceFoundByThatName)?
+            .send(packet)
+    }
+
+    /// Defines (or redefines) a named group of devices.
+    pub fn set_group(&mut self, group: &str, devices: &[&str]) {
+        self.groups.insert(
+            group.to_string(),
+            devices.iter().map(|device| device.to_string()).collect(),
+        );
+    }
+
+    pub fn remove_group(&mut self, group: &str) -> Option<Vec<String>> {
+        self.groups.remove(group)
+    }
+
+    /// Sends `packet` to each device of `group` in turn. Devices in the
+    /// group that are not managed report
+    /// [FlemSerialError::NoDeviceFoundByThatName]. Returns `None` if the
+    /// group does not exist.
+    pub fn send_to_group(
+        &mut self,
+        group: &str,
+        packet: &flem::Packet<T>,
+    ) -> Option<BTreeMap<String, Result<(), FlemSerialError>>> {
+        let members = self.groups.get(group)?.clone();
+
+        Some(
+            members
+                .into_iter()
+                .map(|device| {
+                    let result = self.send(&device, packet);
+                    (device, result)
+                })
+                .collect(),
+        )
+    }
+
+    /// Sends `packet` to every managed device in turn.
+    pub fn broadcast(
+        &mut self,
+        packet: &flem::Packet<T>,
+    ) -> BTreeMap<String, Result<(), FlemSerialError>> {
+        self.devices
+            .iter_mut()
+            .map(|(device, serial)| (device.clone(), serial.send(packet)))
+            .collect()
+    }
+
+    /// Sends one packet to each device as close to simultaneously as the OS
+    /// allows.
+    ///
+    /// Every port is locked and a writer thread is parked on a barrier
+    /// before any bytes go out, so the only remaining skew is thread wakeup
+    /// and driver latency. The achieved skew is measured between write
+    /// completions and reported as an error if it exceeds `max_skew`.
+    pub fn execute_synchronized(
+        &mut self,
+        packets_by_device: &BTreeMap<String, flem::Packet<T>>,
+        max_skew: Duration,
+    ) -> Result<SyncReport, SyncError> {
+        let mut staged = Vec::new();
+        for (device, packet) in packets_by_device.iter() {
+            let serial = self
+                .devices
+                .get_mut(device)
+                .ok_or_else(|| SyncError::UnknownDevice(device.clone()))?;
+            let port = serial
+                .tx_port
+                .clone()
+                .ok_or_else(|| SyncError::UnknownDevice(device.clone()))?;
+            if let Err(FlemSerialError::UnsupportedByFirmware { request, firmware }) =
+                serial.check_firmware(packet)
+            {
+                return Err(SyncError::UnsupportedByFirmware {
+                    device: device.clone(),
+                    request,
+                    firmware,
+                });
+            }
+            let packet = serial
+                .intercept(packet)
+                .ok_or_else(|| SyncError::Vetoed(device.clone()))?;
+            serial
+                .check_duty_cycle(&packet)
+                .map_err(|_| SyncError::DutyCycleExceeded(device.clone()))?;
+            staged.push((device, packet, port));
+        }
+
+        let barrier = Barrier::new(staged.len());
+        let results: Vec<(String, Option<Instant>)> = thread::scope(|scope| {
+            let handles: Vec<_> = staged
+                .iter()
+                .map(|(device, packet, port)| {
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        let bytes = packet.bytes();
+                        let mut port = port.lock().unwrap();
+                        barrier.wait();
+                        let sent = port.write_all(bytes).and_then(|_| port.flush());
+                        let completed = Instant::now();
+                        ((*device).clone(), sent.ok().map(|_| completed))
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        // Every packet that went out counts against its device's duty
+        // cycle, even when another device's write failed
+        let mut completions = BTreeMap::new();
+        let mut failed = None;
+        for ((device, completed), (_, packet, _)) in results.into_iter().zip(staged.iter()) {
+            match completed {
+                Some(completed) => {
+                    self.devices[&device].record_tx(packet);
+                    completions.insert(device, completed);
+                }
+                None => {
+                    failed.get_or_insert(device);
+                }
+            }
+        }
+        if let Some(device) = failed {
+            return Err(SyncError::SendFailed(device));
+        }
+
+        let first = completions.values().min().copied();
+        let last = completions.values().max().copied();
+        let skew = match (first, last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        };
+
+        let report = SyncReport {
+            offsets: completions
+                .into_iter()
+                .map(|(device, completed)| (device, completed - first.unwrap()))
+                .collect(),
+            skew,
+        };
+
+        if report.skew > max_skew {
+            Err(SyncError::SkewExceeded(report))
+        } else {
+            Ok(report)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlemDeviceManager, SyncError};
+    use crate::{
+        ascii::AsciiLink,
+        port::OpenPort,
+        test_support::{SharedBuffer, Unplugged},
+        FlemSerial, FlemSerialError,
+    };
+    use std::{collections::BTreeMap, io::Cursor, time::Duration};
+
+    fn event(data: u8) -> Vec<u8> {
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[data]).unwrap();
+        packet.pack();
+        packet.bytes().to_vec()
+    }
+
+    /// A manager of three recorded devices, "a", "b" and "c".
+    fn recorded_rack() -> (FlemDeviceManager<64>, [SharedBuffer; 3]) {
+        let recorders = [
+            SharedBuffer::default(),
+            SharedBuffer::default(),
+            SharedBuffer::default(),
+        ];
+        let mut manager = FlemDeviceManager::<64>::new();
+        for (device, recorder) in ["a", "b", "c"].into_iter().zip(recorders.iter()) {
+            manager.insert(device, FlemSerial::from_transport(recorder.clone()));
+        }
+        (manager, recorders)
+    }
+
+    #[test]
+    fn test_listen_all_tags_packets_with_their_device() {
+        let mut manager = FlemDeviceManager::<64>::new();
+        manager.insert(
+            "left",
+            FlemSerial::from_transport(Cursor::new([event(1), event(2)].concat())),
+        );
+        manager.insert("right", FlemSerial::from_transport(Cursor::new(event(3))));
+
+        let rx = manager.listen_all().unwrap();
+        let mut received: Vec<(String, u8)> = (0..3)
+            .map(|_| {
+                let tagged = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+                (tagged.device, tagged.packet.get_data()[0])
+            })
+            .collect();
+        received.sort();
+        assert_eq!(
+            received,
+            [
+                ("left".to_string(), 1),
+                ("left".to_string(), 2),
+                ("right".to_string(), 3)
+            ]
+        );
+
+        manager.unlisten_all();
+        rx.join().unwrap();
+    }
+
+    #[test]
+    fn test_group_sends_reach_only_the_group() {
+        let (mut manager, [a, b, c]) = recorded_rack();
+        manager.set_group("front", &["a", "c", "missing"]);
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[7]).unwrap();
+        packet.pack();
+        let start = packet.bytes().to_vec();
+
+        assert!(manager.send_to_group("rear", &packet).is_none());
+
+        let results = manager.send_to_group("front", &packet).unwrap();
+        assert_eq!(
+            results.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["a", "c", "missing"]
+        );
+        assert!(results["a"].is_ok() && results["c"].is_ok());
+        assert!(matches!(
+            results["missing"],
+            Err(FlemSerialError::NoDeviceFoundByThatName)
+        ));
+        assert_eq!(
+            (a.contents(), b.contents(), c.contents()),
+            (start.clone(), vec![], start.clone())
+        );
+
+        let results = manager.broadcast(&packet);
+        assert_eq!(results.len(), 3);
+        assert!(results.values().all(Result::is_ok));
+        assert_eq!(b.contents(), start);
+        assert_eq!(a.contents(), [start.clone(), start].concat());
+    }
+
+    #[test]
+    fn test_synchronized_commands_reach_every_device_or_none() {
+        let (mut manager, [a, b, c]) = recorded_rack();
+        let packets: BTreeMap<String, flem::Packet<64>> = [("a", 1), ("b", 2)]
+            .into_iter()
+            .map(|(device, data)| {
+                let mut packet = flem::Packet::<64>::new();
+                packet.set_request(flem::Request::EVENT);
+                packet.add_data(&[data]).unwrap();
+                packet.pack();
+                (device.to_string(), packet)
+            })
+            .collect();
+
+        let report = manager
+            .execute_synchronized(&packets, Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(
+            report
+                .offsets
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert!(report.offsets.values().any(Duration::is_zero));
+        assert!(report.offsets.values().all(|offset| *offset <= report.skew));
+        assert_eq!((a.contents(), b.contents()), (event(1), event(2)));
+        assert!(c.contents().is_empty());
+
+        // An unknown device stops the whole command before anything is sent
+        let mut packets = packets;
+        let packet = packets["a"].clone();
+        packets.insert("missing".to_string(), packet);
+        assert!(matches!(
+            manager.execute_synchronized(&packets, Duration::from_secs(1)),
+            Err(SyncError::UnknownDevice(device)) if device == "missing"
+        ));
+        assert_eq!((a.contents(), b.contents()), (event(1), event(2)));
+    }
+
+    #[test]
+    fn test_lines_go_to_ascii_devices_by_name() {
+        let printer = SharedBuffer::default();
+        let mut manager = FlemDeviceManager::<64>::new();
+        manager.insert_ascii(
+            "printer",
+            AsciiLink::from_open_port("printer", OpenPort::from_stream(printer.clone())),
+        );
+
+        manager.send_line("printer", "G28").unwrap();
+        assert_eq!(printer.contents(), b"G28\r\n");
+        assert!(matches!(
+            manager.send_line("scanner", "G28"),
+            Err(FlemSerialError::NoDeviceFoundByThatName)
+        ));
+    }
+
+    #[test]
+    fn test_synchronized_commands_check_the_firmware_before_release() {
+        use crate::{
+            compat::{AtLeast, FirmwareVersion},
+            inventory::DeviceIdentity,
+        };
+
+        let (mut manager, [a, b, _]) = recorded_rack();
+        let old = manager.device("b").unwrap();
+        old.session.set_identity(DeviceIdentity {
+            name: "Drive".into(),
+            major: 1,
+            minor: 4,
+            patch: 0,
+            max_packet_size: 64,
+        });
+        old.require_firmware(flem::Request::EVENT, AtLeast(FirmwareVersion::new(2, 0, 0)));
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.pack();
+        let packets: BTreeMap<String, flem::Packet<64>> = ["a", "b"]
+            .into_iter()
+            .map(|device| (device.to_string(), packet.clone()))
+            .collect();
+
+        assert!(matches!(
+            manager.execute_synchronized(&packets, Duration::from_secs(1)),
+            Err(SyncError::UnsupportedByFirmware { device, request, firmware })
+                if device == "b"
+                    && request == flem::Request::EVENT
+                    && firmware == FirmwareVersion::new(1, 4, 0)
+        ));
+        assert!(a.contents().is_empty() && b.contents().is_empty());
+    }
+
+    #[test]
+    fn test_a_failed_synchronized_write_still_counts_the_others() {
+        let (mut manager, [_, b, _]) = recorded_rack();
+        manager.insert("a", FlemSerial::from_transport(Unplugged));
+        let mut packet = flem::Packet::<64>::new();
+        packet.pack();
+        let packets: BTreeMap<String, flem::Packet<64>> = ["a", "b"]
+            .into_iter()
+            .map(|device| (device.to_string(), packet.clone()))
+            .collect();
+
+        assert!(matches!(
+            manager.execute_synchronized(&packets, Duration::from_secs(1)),
+            Err(SyncError::SendFailed(device)) if device == "a"
+        ));
+        assert_eq!(b.contents(), packet.bytes());
+        let usage = manager.device("b").unwrap().usage();
+        assert_eq!(usage.total_packets, 1);
+        assert_eq!(usage.total_bytes, packet.bytes().len() as u64);
+        assert_eq!(manager.device("a").unwrap().usage().total_packets, 0);
+    }
+}
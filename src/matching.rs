@@ -0,0 +1,163 @@
+/// Decides whether a received packet answers a request that was sent.
+///
+/// Firmwares correlate responses differently, pick the implementation that
+/// matches the device or provide your own.
+pub trait ResponseMatcher<const T: usize>: Send {
+    fn matches(&self, request: &flem::Packet<T>, response: &flem::Packet<T>) -> bool;
+}
+
+/// The response carries the same request code as the request.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EchoRequest;
+
+impl<const T: usize> ResponseMatcher<T> for EchoRequest {
+    fn matches(&self, request: &flem::Packet<T>, response: &flem::Packet<T>) -> bool {
+        response.get_request() == request.get_request()
+    }
+}
+
+/// The response carries the request code with the top bit set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HighBitResponse;
+
+impl<const T: usize> ResponseMatcher<T> for HighBitResponse {
+    fn matches(&self, request: &flem::Packet<T>, response: &flem::Packet<T>) -> bool {
+        response.get_request() == request.get_request() | 0x80
+    }
+}
+
+/// Request and response carry the same transaction ID of `width` bytes at
+/// `offset` in their payloads.
+#[derive(Debug, Clone, Copy)]
+pub struct PayloadTransactionId {
+    pub offset: usize,
+    pub width: usize,
+}
+
+impl PayloadTransactionId {
+    fn id<'p, const T: usize>(&self, packet: &'p flem::Packet<T>) -> Option<&'p [u8]> {
+        packet.get_data().get(self.offset..self.offset + self.width)
+    }
+}
+
+impl<const T: usize> ResponseMatcher<T> for PayloadTransactionId {
+    fn matches(&self, request: &flem::Packet<T>, response: &flem::Packet<T>) -> bool {
+        match (self.id(request), self.id(response)) {
+            (Some(sent), Some(received)) => sent == received,
+            _ => false,
+        }
+    }
+}
+
+/// Allocates transaction IDs for firmwares that echo an ID embedded in the
+/// payload. IDs are `width` bytes (1 to 4, little endian) inserted at
+/// `offset`, counting up and wrapping.
+#[derive(Debug, Clone)]
+pub struct TransactionIds {
+    offset: usize,
+    width: usize,
+    next: u32,
+}
+
+impl TransactionIds {
+    pub fn new(offset: usize, width: usize) -> Self {
+        Self {
+            offset,
+            width: width.clamp(1, 4),
+            next: 0,
+        }
+    }
+
+    fn allocate(&mut self) -> u32 {
+        let id = self.next;
+        let mask = u32::MAX >> (32 - 8 * self.width);
+        self.next = self.next.wrapping_add(1) & mask;
+        id
+    }
+
+    /// Returns `payload` with a fresh ID inserted at the offset, and the ID.
+    /// Payloads shorter than the offset are zero padded.
+    pub fn inject(&mut self, payload: &[u8]) -> (Vec<u8>, u32) {
+        let id = self.allocate();
+        let split = self.offset.min(payload.len());
+        let mut injected = payload[..split].to_vec();
+        injected.resize(self.offset, 0);
+        injected.extend_from_slice(&id.to_le_bytes()[..self.width]);
+        injected.extend_from_slice(&payload[split..]);
+        (injected, id)
+    }
+
+    /// Reads the ID out of a received packet.
+    pub fn extract<const T: usize>(&self, packet: &flem::Packet<T>) -> Option<u32> {
+        let bytes = packet
+            .get_data()
+            .get(self.offset..self.offset + self.width)?;
+        let mut id = [0u8; 4];
+        id[..self.width].copy_from_slice(bytes);
+        Some(u32::from_le_bytes(id))
+    }
+
+    /// Matcher comparing the IDs of request and response.
+    pub fn matcher(&self) -> PayloadTransactionId {
+        PayloadTransactionId {
+            offset: self.offset,
+            width: self.width,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        EchoRequest, HighBitResponse, PayloadTransactionId, ResponseMatcher, TransactionIds,
+    };
+
+    fn packet(request: u8, data: &[u8]) -> flem::Packet<16> {
+        let mut packet = flem::Packet::<16>::new();
+        packet.set_request(request);
+        packet.add_data(data).unwrap();
+        packet.pack();
+        packet
+    }
+
+    #[test]
+    fn test_matchers() {
+        let request = packet(0x05, &[0xAA, 0x01, 0x02]);
+
+        assert!(EchoRequest.matches(&request, &packet(0x05, &[])));
+        assert!(!EchoRequest.matches(&request, &packet(0x85, &[])));
+
+        assert!(HighBitResponse.matches(&request, &packet(0x85, &[])));
+        assert!(!HighBitResponse.matches(&request, &packet(0x05, &[])));
+
+        let by_id = PayloadTransactionId {
+            offset: 1,
+            width: 2,
+        };
+        assert!(by_id.matches(&request, &packet(0x33, &[0x00, 0x01, 0x02, 0x09])));
+        assert!(!by_id.matches(&request, &packet(0x05, &[0xAA, 0x01, 0x03])));
+        assert!(!by_id.matches(&request, &packet(0x05, &[0xAA])));
+    }
+
+    #[test]
+    fn test_transaction_ids_are_unique_and_extracted() {
+        let mut ids = TransactionIds::new(1, 1);
+        let (first, id) = ids.inject(&[0x10, 0x20]);
+        assert_eq!(first, [0x10, 0x00, 0x20]);
+        assert_eq!(id, 0);
+        let (second, id) = ids.inject(&[0x10, 0x20]);
+        assert_eq!(id, 1);
+
+        let request = packet(0x05, &second);
+        assert_eq!(ids.extract(&request), Some(1));
+        assert!(ids
+            .matcher()
+            .matches(&request, &packet(0x05, &[0x00, 0x01])));
+        assert!(!ids.matcher().matches(&request, &packet(0x05, &first)));
+
+        for _ in 2..256 {
+            ids.inject(&[]);
+        }
+        assert_eq!(ids.inject(&[]).1, 0);
+    }
+}
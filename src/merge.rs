@@ -0,0 +1,131 @@
+use futures_core::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Stream combinator returned by [merge_links].
+pub struct MergeLinks<K, S> {
+    links: Vec<(K, S)>,
+    next: usize,
+}
+
+/// Merges the packet streams of several links into one stream of
+/// `(device, item)` pairs.
+///
+/// Links are polled round robin starting after the link that produced the
+/// previous item, so a busy link can't starve a quiet one. Links whose
+/// stream ends are dropped, and the merged stream ends once all have ended.
+pub fn merge_links<K, S, I>(links: I) -> MergeLinks<K, S>
+where
+    I: IntoIterator<Item = (K, S)>,
+{
+    MergeLinks {
+        links: links.into_iter().collect(),
+        next: 0,
+    }
+}
+
+impl<K, S> MergeLinks<K, S> {
+    /// Adds another link to the merged stream.
+    pub fn push(&mut self, device: K, stream: S) {
+        self.links.push((device, stream));
+    }
+
+    /// Number of links that have not ended yet.
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+}
+
+impl<K, S> Stream for MergeLinks<K, S>
+where
+    K: Clone + Unpin,
+    S: Stream + Unpin,
+{
+    type Item = (K, S::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let mut polled = 0;
+        while polled < this.links.len() {
+            let index = this.next % this.links.len();
+            match Pin::new(&mut this.links[index].1).poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.next = index + 1;
+                    return Poll::Ready(Some((this.links[index].0.clone(), item)));
+                }
+                Poll::Ready(None) => {
+                    // Stream ended, the next link slides into this index
+                    this.links.remove(index);
+                    this.next = index;
+                }
+                Poll::Pending => {
+                    this.next = index + 1;
+                    polled += 1;
+                }
+            }
+        }
+
+        if this.links.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_links;
+    use futures_core::Stream;
+    use std::{
+        collections::VecDeque,
+        pin::Pin,
+        ptr,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    struct Items(VecDeque<u8>);
+
+    impl Stream for Items {
+        type Item = u8;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<u8>> {
+            Poll::Ready(self.0.pop_front())
+        }
+    }
+
+    fn noop_waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+    }
+
+    #[test]
+    fn test_merge_links_is_fair() {
+        let mut merged = merge_links(vec![
+            ("a", Items(VecDeque::from(vec![1, 2, 3]))),
+            ("b", Items(VecDeque::from(vec![10]))),
+        ]);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        while let Poll::Ready(Some(item)) = Pin::new(&mut merged).poll_next(&mut cx) {
+            items.push(item);
+        }
+
+        assert_eq!(items, [("a", 1), ("b", 10), ("a", 2), ("a", 3)]);
+        assert!(merged.is_empty());
+    }
+}
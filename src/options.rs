@@ -0,0 +1,270 @@
+use crate::{framing::Framing, tunables::DEFAULT_IDLE_POLL_INTERVAL};
+use std::{fmt, str::FromStr, time::Duration};
+
+/// Software (XON/XOFF) flow control setting.
+///
+/// The driver consumes XON (0x11) and XOFF (0x13) bytes, which appear in
+/// binary FLEM headers, payloads and checksums, so they must be escaped if
+/// XON/XOFF is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SoftFlowControl {
+    #[default]
+    Off,
+    /// XON/XOFF without escaping. Rejected by `connect` with
+    /// [crate::FlemSerialError::SoftFlowControlCorruptsPackets].
+    Raw,
+    /// XON/XOFF with XON, XOFF and the escape byte stuffed, see
+    /// [crate::xon_xoff]. The device must escape the same way.
+    Stuffed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    #[default]
+    Eight,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StopBits {
+    #[default]
+    One,
+    Two,
+}
+
+/// Character format on the wire, written the usual way such as "8N1" or
+/// "7E1".
+///
+/// Binary FLEM packets need eight data bits, fewer only suit
+/// [crate::ascii] links to legacy instruments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineSettings {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl fmt::Display for LineSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Five => '5',
+            DataBits::Six => '6',
+            DataBits::Seven => '7',
+            DataBits::Eight => '8',
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => '1',
+            StopBits::Two => '2',
+        };
+        write!(f, "{}{}{}", data_bits, parity, stop_bits)
+    }
+}
+
+impl FromStr for LineSettings {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, ()> {
+        let &[data_bits, parity, stop_bits] = text.as_bytes() else {
+            return Err(());
+        };
+        Ok(Self {
+            data_bits: match data_bits {
+                b'5' => DataBits::Five,
+                b'6' => DataBits::Six,
+                b'7' => DataBits::Seven,
+                b'8' => DataBits::Eight,
+                _ => return Err(()),
+            },
+            parity: match parity.to_ascii_uppercase() {
+                b'N' => Parity::None,
+                b'O' => Parity::Odd,
+                b'E' => Parity::Even,
+                _ => return Err(()),
+            },
+            stop_bits: match stop_bits {
+                b'1' => StopBits::One,
+                b'2' => StopBits::Two,
+                _ => return Err(()),
+            },
+        })
+    }
+}
+
+/// Options for [crate::FlemSerial::connect_with_options].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectOptions {
+    /// Require the device to answer a FLEM ID request before `connect`
+    /// returns Ok. Guards against opening the wrong port.
+    pub verify_device: bool,
+    /// How long to wait for the ID response when `verify_device` is set.
+    pub verify_timeout: Duration,
+    pub soft_flow_control: SoftFlowControl,
+    /// Framing wrapped around each packet on the wire. The device must use
+    /// the same framing.
+    pub framing: Framing,
+    pub line: LineSettings,
+    /// RTS/CTS flow control. Can't be combined with `soft_flow_control`,
+    /// `connect` rejects that with
+    /// [crate::FlemSerialError::ConflictingFlowControl].
+    pub hardware_flow_control: bool,
+    /// How long a read waits for data. The listener checks for shutdown and
+    /// timers between reads, so keep this short.
+    pub read_timeout: Duration,
+    /// Try every standard baud rate and flow control setting on connect to
+    /// fill in [crate::inventory::AdapterInfo]. Off by default, as some
+    /// drivers glitch the line while settings change; the original settings
+    /// are restored either way. Ignored for RFC 2217 servers.
+    pub probe_adapter: bool,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            verify_device: false,
+            verify_timeout: Duration::from_millis(500),
+            soft_flow_control: SoftFlowControl::Off,
+            framing: Framing::None,
+            line: LineSettings::default(),
+            hardware_flow_control: false,
+            read_timeout: Duration::from_millis(10),
+            probe_adapter: false,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// Named presets, as used by [crate::env_config::PROFILE_VAR]:
+    /// "default", "verified" (checks the device ID on connect), "stuffed"
+    /// (escaped XON/XOFF), "cobs" and "slip".
+    pub fn profile(name: &str) -> Option<Self> {
+        let default = Self::default();
+        match name {
+            "default" => Some(default),
+            "verified" => Some(Self {
+                verify_device: true,
+                ..default
+            }),
+            "stuffed" => Some(Self {
+                soft_flow_control: SoftFlowControl::Stuffed,
+                ..default
+            }),
+            "cobs" => Some(Self {
+                framing: Framing::Cobs,
+                ..default
+            }),
+            "slip" => Some(Self {
+                framing: Framing::Slip,
+                ..default
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// How the listener polls a port that has no data, see
+/// [crate::FlemSerial::set_listen_options]. Reads are repeated straight
+/// away while data keeps arriving; once a read comes back empty the
+/// listener sleeps, longer with every further empty read.
+///
+/// Ports with a read timeout, see [ConnectOptions::read_timeout], wait in
+/// the read instead and only sleep here if the read returns early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenOptions {
+    /// Sleep after the first empty read. Must not be zero for the sleep to
+    /// grow.
+    pub min_idle_interval: Duration,
+    /// Longest sleep between reads of an idle port. The same setting as
+    /// [crate::tunables::Tunables::idle_poll_interval], which changes it
+    /// while listening.
+    pub max_idle_interval: Duration,
+    /// Factor the sleep grows by with every further empty read, 1 keeps
+    /// it at `min_idle_interval`.
+    pub idle_backoff: u32,
+}
+
+impl Default for ListenOptions {
+    fn default() -> Self {
+        Self {
+            min_idle_interval: Duration::from_micros(250),
+            max_idle_interval: DEFAULT_IDLE_POLL_INTERVAL,
+            idle_backoff: 2,
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl ListenOptions {
+    /// Sleep after an empty read, given the sleep after the previous one,
+    /// if that read was empty too, and the current longest sleep.
+    pub(crate) fn next_idle_interval(&self, previous: Option<Duration>, max: Duration) -> Duration {
+        match previous {
+            Some(previous) => previous.saturating_mul(self.idle_backoff.max(1)),
+            None => self.min_idle_interval,
+        }
+        .min(max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataBits, LineSettings, Parity, StopBits};
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_idle_polling_backs_off_to_the_maximum() {
+        use super::ListenOptions;
+        use std::time::Duration;
+
+        let ms = Duration::from_millis;
+        let options = ListenOptions {
+            min_idle_interval: ms(1),
+            max_idle_interval: ms(10),
+            idle_backoff: 2,
+        };
+
+        let mut previous = None;
+        let intervals: Vec<Duration> = (0..6)
+            .map(|_| {
+                let interval = options.next_idle_interval(previous, options.max_idle_interval);
+                previous = Some(interval);
+                interval
+            })
+            .collect();
+        assert_eq!(intervals, [ms(1), ms(2), ms(4), ms(8), ms(10), ms(10)]);
+
+        // Data arriving starts over at the minimum
+        assert_eq!(options.next_idle_interval(None, ms(10)), ms(1));
+    }
+
+    #[test]
+    fn test_line_settings_notation() {
+        let settings: LineSettings = "7e1".parse().unwrap();
+        assert_eq!(
+            settings,
+            LineSettings {
+                data_bits: DataBits::Seven,
+                parity: Parity::Even,
+                stop_bits: StopBits::One,
+            }
+        );
+        assert_eq!(settings.to_string(), "7E1");
+        assert_eq!(LineSettings::default().to_string(), "8N1");
+        assert!("9N1".parse::<LineSettings>().is_err());
+        assert!("8N".parse::<LineSettings>().is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    byte_capture::{read_byte_capture, ByteCaptureFile, ByteChunk},
+    capture_file::CaptureFileError,
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How fast a [ReplayTransport] hands out the captured bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayPace {
+    /// Each read returns as soon as it's asked for.
+    #[default]
+    AsFastAsPossible,
+    /// Reads come at the same offsets from the first read as they did
+    /// from the start of the capture, so timeouts behave as in the field.
+    Original,
+}
+
+/// How far a [ReplayTransport] has got, readable after the transport has
+/// been handed to a link.
+#[derive(Debug, Clone)]
+pub struct ReplayProgress {
+    remaining: Arc<AtomicUsize>,
+}
+
+impl ReplayProgress {
+    /// Captured reads not fully handed out yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::Acquire)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.remaining() == 0
+    }
+}
+
+/// Plays a capture written by [crate::byte_capture::ByteCapture] back as a
+/// byte stream, for [crate::FlemSerial::from_transport]. The bytes go
+/// through the same parser and packet queue as they did when captured, so
+/// field captures can become regression tests.
+///
+/// Each read returns at most one captured read, so packets split across
+/// reads in the field are split the same way. Writes are accepted and
+/// discarded. Once the capture is used up reads return nothing.
+pub struct ReplayTransport {
+    chunks: VecDeque<ByteChunk>,
+    /// Bytes of the front chunk already read.
+    offset: usize,
+    pace: ReplayPace,
+    started: Option<Instant>,
+    remaining: Arc<AtomicUsize>,
+}
+
+impl ReplayTransport {
+    /// Replays the capture file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P, pace: ReplayPace) -> Result<Self, CaptureFileError> {
+        let file = read_byte_capture(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(file, pace))
+    }
+
+    pub fn new(file: ByteCaptureFile, pace: ReplayPace) -> Self {
+        let chunks: VecDeque<ByteChunk> = file.chunks.into();
+        Self {
+            remaining: Arc::new(AtomicUsize::new(chunks.len())),
+            chunks,
+            offset: 0,
+            pace,
+            started: None,
+        }
+    }
+
+    pub fn progress(&self) -> ReplayProgress {
+        ReplayProgress {
+            remaining: self.remaining.clone(),
+        }
+    }
+
+    /// True if the front chunk may be read now.
+    fn due(&mut self, chunk: &ByteChunk) -> bool {
+        match self.pace {
+            ReplayPace::AsFastAsPossible => true,
+            ReplayPace::Original => {
+                let started = *self.started.get_or_insert_with(Instant::now);
+                started.elapsed() >= Duration::from_micros(chunk.elapsed_micros)
+            }
+        }
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(chunk) = self.chunks.front().cloned() else {
+            return Ok(0);
+        };
+        // Returning nothing keeps the listener responsive during long gaps
+        if !self.due(&chunk) {
+            return Ok(0);
+        }
+
+        let unread = &chunk.bytes[self.offset..];
+        let count = unread.len().min(buf.len());
+        buf[..count].copy_from_slice(&unread[..count]);
+        self.offset += count;
+
+        if self.offset == chunk.bytes.len() {
+            self.chunks.pop_front();
+            self.offset = 0;
+            self.remaining.fetch_sub(1, Ordering::AcqRel);
+        }
+        Ok(count)
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplayPace, ReplayTransport};
+    use crate::byte_capture::{ByteCaptureFile, ByteChunk};
+    use std::{
+        io::Read,
+        time::{Duration, Instant},
+    };
+
+    fn capture(chunks: &[(u64, &[u8])]) -> ByteCaptureFile {
+        ByteCaptureFile {
+            start_wall_micros: 0,
+            chunks: chunks
+                .iter()
+                .map(|(elapsed_micros, bytes)| ByteChunk {
+                    elapsed_micros: *elapsed_micros,
+                    bytes: bytes.to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_reads_keep_their_boundaries_and_timing() {
+        let mut replay = ReplayTransport::new(
+            capture(&[(0, &[1, 2, 3]), (30_000, &[4])]),
+            ReplayPace::Original,
+        );
+        let progress = replay.progress();
+        let start = Instant::now();
+        let mut buf = [0; 2];
+
+        assert_eq!(replay.read(&mut buf).unwrap(), 2);
+        assert_eq!(replay.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 3);
+        assert_eq!(progress.remaining(), 1);
+
+        let mut read = 0;
+        while read == 0 {
+            read = replay.read(&mut buf).unwrap();
+        }
+        assert_eq!(buf[0], 4);
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert!(progress.is_finished());
+        assert_eq!(replay.read(&mut buf).unwrap(), 0);
+    }
+
+    #[cfg(feature = "link")]
+    #[test]
+    fn test_replayed_packets_reach_the_queue() {
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.pack();
+        let (head, tail) = packet.bytes().split_at(5);
+
+        let replay = ReplayTransport::new(
+            capture(&[(0, &[0xff]), (10, head), (20, tail)]),
+            ReplayPace::AsFastAsPossible,
+        );
+        let progress = replay.progress();
+        let mut serial = crate::FlemSerial::<64>::from_transport(replay);
+        let rx = serial.listen().unwrap();
+
+        let received = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received.bytes(), packet.bytes());
+        assert!(progress.is_finished());
+        assert_eq!(serial.stats().resync_errors, 1);
+    }
+}
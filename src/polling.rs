@@ -0,0 +1,278 @@
+use crate::clock::Clock;
+use crate::events::LinkEvent;
+use crate::listener::{ListenerShared, ListenerThread};
+use crate::matching::{EchoRequest, ResponseMatcher};
+use crate::stats::{LinkRates, LinkStats};
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Shortest period a poll group may have.
+pub const MIN_POLL_PERIOD: Duration = Duration::from_millis(1);
+
+struct PollGroup<const T: usize> {
+    name: String,
+    period: Duration,
+    request: flem::Packet<T>,
+    next_due: Option<Instant>,
+    /// When the request now waiting for its response was sent.
+    sent_at: Option<Instant>,
+    missed: u64,
+}
+
+/// Requests sent periodically to devices that only answer polls, each
+/// group with its own period.
+///
+/// Polls stay on a fixed grid from the first one, so a late poll doesn't
+/// push the later ones back. Polls that could not be sent in time are
+/// skipped rather than sent in a burst, and counted as missed.
+pub struct PollSchedule<const T: usize> {
+    groups: Vec<PollGroup<T>>,
+}
+
+impl<const T: usize> Default for PollSchedule<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const T: usize> PollSchedule<T> {
+    pub fn new() -> Self {
+        Self { groups: Vec::new() }
+    }
+
+    /// Adds a group polling with `request` every `period`, at least
+    /// [MIN_POLL_PERIOD]. Responses are paired with the group's request by
+    /// request code.
+    pub fn add(&mut self, group: &str, period: Duration, request: &flem::Packet<T>) {
+        let mut request = request.clone();
+        request.pack();
+        self.groups.push(PollGroup {
+            name: group.to_string(),
+            period: period.max(MIN_POLL_PERIOD),
+            request,
+            next_due: None,
+            sent_at: None,
+            missed: 0,
+        });
+    }
+
+    /// Names of the groups in the order they were added.
+    pub fn groups(&self) -> Vec<String> {
+        self.groups.iter().map(|group| group.name.clone()).collect()
+    }
+
+    /// Requests to send at `now`. Groups poll for the first time on the
+    /// first call.
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<flem::Packet<T>> {
+        let mut due = Vec::new();
+        for group in self.groups.iter_mut() {
+            let next_due = group.next_due.get_or_insert(now);
+            if now < *next_due {
+                continue;
+            }
+
+            due.push(group.request.clone());
+            group.sent_at = Some(now);
+            *next_due += group.period;
+            if *next_due <= now {
+                let skipped = (now - *next_due).as_nanos() / group.period.as_nanos() + 1;
+                group.missed += skipped as u64;
+                *next_due += group.period * skipped as u32;
+            }
+        }
+        due
+    }
+
+    /// Tags a received packet with the group whose poll it answers, if any.
+    pub(crate) fn tag(&mut self, packet: flem::Packet<T>, now: Instant) -> PolledPacket<T> {
+        let answered = self
+            .groups
+            .iter_mut()
+            .find(|group| group.sent_at.is_some() && EchoRequest.matches(&group.request, &packet));
+
+        match answered {
+            Some(group) => PolledPacket {
+                group: Some(group.name.clone()),
+                round_trip: group.sent_at.take().map(|sent_at| now - sent_at),
+                missed_polls: group.missed,
+                packet,
+            },
+            None => PolledPacket {
+                group: None,
+                round_trip: None,
+                missed_polls: 0,
+                packet,
+            },
+        }
+    }
+}
+
+/// What [FlemPollRx] delivers.
+#[derive(Clone)]
+pub struct PolledPacket<const T: usize> {
+    /// The poll group the packet answers, None for unsolicited packets
+    /// such as events.
+    pub group: Option<String>,
+    /// Time from sending the poll to receiving its response.
+    pub round_trip: Option<Duration>,
+    /// Polls of the group skipped so far because they were overdue.
+    pub missed_polls: u64,
+    pub packet: flem::Packet<T>,
+}
+
+/// Sends polls and tags responses on the listener thread.
+pub(crate) struct PollDelivery<const T: usize> {
+    sender: Sender<PolledPacket<T>>,
+    schedule: PollSchedule<T>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<const T: usize> PollDelivery<T> {
+    pub(crate) fn new(
+        sender: Sender<PolledPacket<T>>,
+        schedule: PollSchedule<T>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            sender,
+            schedule,
+            clock,
+        }
+    }
+
+    pub(crate) fn due(&mut self) -> Vec<flem::Packet<T>> {
+        self.schedule.due(self.clock.now())
+    }
+
+    pub(crate) fn push(&mut self, packet: flem::Packet<T>) -> Result<(), ()> {
+        let tagged = self.schedule.tag(packet, self.clock.now());
+        self.sender.send(tagged).map_err(|_| ())
+    }
+}
+
+/// Receive handle returned by [crate::FlemSerial::listen_polled]. Dropping
+/// it stops its listener.
+pub struct FlemPollRx<const T: usize> {
+    pub(crate) rx_listener_handle: ListenerThread,
+    pub(crate) rx_packet_queue: Receiver<PolledPacket<T>>,
+    pub(crate) events: Receiver<LinkEvent>,
+    pub(crate) shared: ListenerShared,
+}
+
+impl<const T: usize> FlemPollRx<T> {
+    /// Raw access to the queue. Packets taken directly from the queue are
+    /// not counted by [FlemPollRx::queue_depth].
+    pub fn queue(&self) -> &Receiver<PolledPacket<T>> {
+        &self.rx_packet_queue
+    }
+
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    fn received(&self, packet: PolledPacket<T>) -> PolledPacket<T> {
+        self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        packet
+    }
+
+    /// Blocks until a packet is received.
+    pub fn recv(&self) -> Result<PolledPacket<T>, RecvError> {
+        self.rx_packet_queue
+            .recv()
+            .map(|packet| self.received(packet))
+    }
+
+    /// Returns a packet if one is waiting.
+    pub fn try_recv(&self) -> Result<PolledPacket<T>, TryRecvError> {
+        self.rx_packet_queue
+            .try_recv()
+            .map(|packet| self.received(packet))
+    }
+
+    /// Blocks until a packet is received or `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<PolledPacket<T>, RecvTimeoutError> {
+        self.rx_packet_queue
+            .recv_timeout(timeout)
+            .map(|packet| self.received(packet))
+    }
+
+    /// Number of packets delivered by the listener and not yet received.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        self.rx_listener_handle.handle()
+    }
+
+    /// Waits for the listener thread to exit.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PollSchedule;
+    use std::time::{Duration, Instant};
+
+    fn request(code: u8) -> flem::Packet<16> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(code);
+        packet.pack();
+        packet
+    }
+
+    #[test]
+    fn test_polls_keep_their_grid_and_responses_are_tagged() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut schedule = PollSchedule::new();
+        schedule.add("fast", ms(10), &request(0x10));
+        schedule.add("slow", ms(100), &request(0x11));
+
+        assert_eq!(schedule.due(start).len(), 2);
+        assert!(schedule.due(start + ms(9)).is_empty());
+        // A late poll doesn't shift the grid
+        assert_eq!(schedule.due(start + ms(13))[0].get_request(), 0x10);
+        assert!(schedule.due(start + ms(19)).is_empty());
+        assert_eq!(schedule.due(start + ms(20)).len(), 1);
+        // Stalled for 3 periods: one poll now, two skipped
+        assert_eq!(schedule.due(start + ms(55)).len(), 1);
+        assert!(schedule.due(start + ms(59)).is_empty());
+
+        let slow = schedule.tag(request(0x11), start + ms(60));
+        assert_eq!(slow.group.as_deref(), Some("slow"));
+        assert_eq!(slow.round_trip, Some(ms(60)));
+
+        let fast = schedule.tag(request(0x10), start + ms(57));
+        assert_eq!(fast.group.as_deref(), Some("fast"));
+        assert_eq!(fast.round_trip, Some(ms(2)));
+        assert_eq!(fast.missed_polls, 2);
+
+        // Already answered, or never polled
+        assert_eq!(schedule.tag(request(0x10), start + ms(58)).group, None);
+        assert_eq!(
+            schedule.tag(request(flem::Request::EVENT), start).group,
+            None
+        );
+    }
+}
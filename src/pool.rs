@@ -0,0 +1,432 @@
+use crate::{
+    scope::{self, LinkScope, ScopeHandle},
+    FlemRx, FlemSerial,
+};
+use serialport::SerialPortType;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How often the idle worker checks whether the pool is being dropped.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    /// The serial number was never added to the pool.
+    UnknownDevice,
+    /// The device is known but not currently connected.
+    NotReady,
+    /// The device is already checked out.
+    InUse,
+}
+
+/// A connected, listening link handed out by [FlemPool::acquire].
+pub struct PooledLink<const T: usize> {
+    serial_number: String,
+    port_name: String,
+    id: Option<flem::DataId>,
+    serial: FlemSerial<T>,
+    rx: FlemRx<T>,
+}
+
+impl<const T: usize> PooledLink<T> {
+    pub fn serial_number(&self) -> &str {
+        &self.serial_number
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Device ID read while the link was being warmed up, if the device
+    /// answered the ID request.
+    pub fn id(&self) -> Option<&flem::DataId> {
+        self.id.as_ref()
+    }
+
+    pub fn serial(&mut self) -> &mut FlemSerial<T> {
+        &mut self.serial
+    }
+
+    pub fn rx(&self) -> &FlemRx<T> {
+        &self.rx
+    }
+
+    fn close(mut self) {
+        self.serial.unlisten();
+        let _ = self.rx.join();
+    }
+}
+
+enum Slot<const T: usize> {
+    Disconnected,
+    Ready(Box<PooledLink<T>>),
+    CheckedOut,
+}
+
+struct PoolInner<const T: usize> {
+    slots: HashMap<String, Slot<T>>,
+}
+
+/// Keeps a set of devices, identified by USB serial number, connected and
+/// listening so a link can be handed out without paying the connect cost.
+///
+/// A background thread reconnects devices that are returned with
+/// [FlemPool::release] or whose listener stops.
+pub struct FlemPool<const T: usize> {
+    inner: Arc<Mutex<PoolInner<T>>>,
+    /// Outside `inner` so a closing scope can stop the worker without
+    /// waiting for the lock.
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<const T: usize> FlemPool<T> {
+    /// Creates a pool for `serial_numbers`. Devices are connected at `baud`
+    /// and idle links are checked every `interval`. When the device answers
+    /// an ID request within `id_timeout` the ID is kept with the link.
+    pub fn new(
+        serial_numbers: &[&str],
+        baud: u32,
+        interval: Duration,
+        id_timeout: Duration,
+    ) -> Self {
+        Self::start(serial_numbers, interval, None, move |serial, scope| {
+            warm_up(serial, baud, id_timeout, scope)
+        })
+    }
+
+    /// Like [FlemPool::new], with the worker and the pooled links tracked by
+    /// `scope`.
+    pub fn new_in(
+        serial_numbers: &[&str],
+        baud: u32,
+        interval: Duration,
+        id_timeout: Duration,
+        scope: &LinkScope,
+    ) -> Self {
+        Self::start(
+            serial_numbers,
+            interval,
+            Some(scope.handle()),
+            move |serial, scope| warm_up(serial, baud, id_timeout, scope),
+        )
+    }
+
+    /// Runs the worker, which makes links with `connect`.
+    fn start<F>(
+        serial_numbers: &[&str],
+        interval: Duration,
+        scope: Option<ScopeHandle>,
+        connect: F,
+    ) -> Self
+    where
+        F: Fn(&str, Option<ScopeHandle>) -> Option<PooledLink<T>> + Send + 'static,
+    {
+        let inner = Arc::new(Mutex::new(PoolInner {
+            slots: serial_numbers
+                .iter()
+                .map(|serial| (serial.to_string(), Slot::Disconnected))
+                .collect(),
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let inner_clone = inner.clone();
+        let worker_running = running.clone();
+        let stop_running = running.clone();
+        let link_scope = scope.clone();
+        let worker = scope::spawn_scoped(
+            "pool",
+            None,
+            None,
+            scope.as_ref(),
+            move || stop_running.store(false, Ordering::Release),
+            move || loop {
+                let (dead_links, to_connect): (Vec<PooledLink<T>>, Vec<String>) = {
+                    let mut inner = inner_clone.lock().unwrap();
+                    if !worker_running.load(Ordering::Acquire) {
+                        break;
+                    }
+
+                    // Retire links whose listener has died
+                    let mut dead = Vec::new();
+                    for (serial, slot) in inner.slots.iter() {
+                        if let Slot::Ready(link) = slot {
+                            if link.rx.join_handle().is_finished() {
+                                dead.push(serial.clone());
+                            }
+                        }
+                    }
+                    let dead_links = dead
+                        .into_iter()
+                        .filter_map(
+                            |serial| match inner.slots.insert(serial, Slot::Disconnected) {
+                                Some(Slot::Ready(link)) => Some(*link),
+                                _ => None,
+                            },
+                        )
+                        .collect();
+
+                    let to_connect = inner
+                        .slots
+                        .iter()
+                        .filter(|(_, slot)| matches!(slot, Slot::Disconnected))
+                        .map(|(serial, _)| serial.clone())
+                        .collect();
+                    (dead_links, to_connect)
+                };
+
+                // Closed outside the lock, joining a stuck listener would
+                // block acquire and release
+                for link in dead_links {
+                    link.close();
+                }
+
+                // Connect outside the lock so acquire isn't blocked by slow ports
+                for serial in to_connect {
+                    if let Some(link) = connect(&serial, link_scope.clone()) {
+                        let mut inner = inner_clone.lock().unwrap();
+                        match inner.slots.get(&serial) {
+                            Some(Slot::Disconnected) => {
+                                inner.slots.insert(serial, Slot::Ready(Box::new(link)));
+                            }
+                            _ => link.close(),
+                        }
+                    }
+                }
+
+                // Sliced so dropping the pool doesn't wait out the interval
+                let wake_at = Instant::now() + interval;
+                while worker_running.load(Ordering::Acquire) {
+                    let remaining = wake_at.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    thread::sleep(remaining.min(STOP_POLL_INTERVAL));
+                }
+            },
+        );
+
+        Self {
+            inner,
+            running,
+            worker: Some(worker),
+        }
+    }
+
+    /// Takes the ready link for `serial_number` out of the pool.
+    pub fn acquire(&self, serial_number: &str) -> Result<PooledLink<T>, PoolError> {
+        let mut inner = self.inner.lock().unwrap();
+        let slot = inner
+            .slots
+            .get_mut(serial_number)
+            .ok_or(PoolError::UnknownDevice)?;
+
+        match std::mem::replace(slot, Slot::CheckedOut) {
+            Slot::Ready(link) => Ok(*link),
+            Slot::Disconnected => {
+                *slot = Slot::Disconnected;
+                Err(PoolError::NotReady)
+            }
+            Slot::CheckedOut => Err(PoolError::InUse),
+        }
+    }
+
+    /// Returns a link to the pool. The link is closed and a fresh connection
+    /// is made in the background.
+    pub fn release(&self, link: PooledLink<T>) {
+        let serial_number = link.serial_number.clone();
+        link.close();
+
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(slot) = inner.slots.get_mut(&serial_number) {
+            *slot = Slot::Disconnected;
+        }
+    }
+
+    /// Serial numbers of the links currently ready to be acquired.
+    pub fn ready(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .slots
+            .iter()
+            .filter(|(_, slot)| matches!(slot, Slot::Ready(_)))
+            .map(|(serial, _)| serial.clone())
+            .collect()
+    }
+}
+
+impl<const T: usize> Drop for FlemPool<T> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        let slots = std::mem::take(&mut self.inner.lock().unwrap().slots);
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+
+        for (_, slot) in slots {
+            if let Slot::Ready(link) = slot {
+                link.close();
+            }
+        }
+    }
+}
+
+/// Finds the port of the USB device with `serial_number`.
+fn find_port_by_serial(serial_number: &str) -> Option<String> {
+    serialport::available_ports()
+        .ok()?
+        .into_iter()
+        .find(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => usb.serial_number.as_deref() == Some(serial_number),
+            _ => false,
+        })
+        .map(|port| port.port_name)
+}
+
+/// Finds the device's port, connects and warms the link up.
+fn warm_up<const T: usize>(
+    serial_number: &str,
+    baud: u32,
+    id_timeout: Duration,
+    scope: Option<ScopeHandle>,
+) -> Option<PooledLink<T>> {
+    let port_name = find_port_by_serial(serial_number)?;
+
+    let mut serial = FlemSerial::<T>::new();
+    serial.set_scope_handle(scope);
+    serial.connect(&port_name, baud).ok()?;
+    warm_up_link(serial_number, port_name, serial, id_timeout)
+}
+
+/// Starts listening on a connected link and asks the device for its ID.
+fn warm_up_link<const T: usize>(
+    serial_number: &str,
+    port_name: String,
+    mut serial: FlemSerial<T>,
+    id_timeout: Duration,
+) -> Option<PooledLink<T>> {
+    let rx = serial.listen().ok()?;
+
+    let mut request = flem::Packet::<T>::new();
+    request.set_request(flem::Request::ID);
+    request.pack();
+
+    let mut id = None;
+    if serial.send(&request).is_ok() {
+        // One deadline, a device streaming events can't hold up the worker
+        let deadline = Instant::now() + id_timeout;
+        while let Ok(packet) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            if packet.get_request() == flem::Request::ID {
+                id = flem::DataId::from(packet.get_data()).ok();
+                break;
+            }
+        }
+    }
+
+    Some(PooledLink {
+        serial_number: serial_number.to_string(),
+        port_name,
+        id,
+        serial,
+        rx,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{warm_up_link, FlemPool, PoolError, PooledLink};
+    use crate::{test_support::DeadHandle, FlemSerial};
+    use std::{
+        io::Cursor,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// A pool over in-memory links that counts the links it made. The
+    /// device "absent" is never found.
+    fn test_pool(
+        serial_numbers: &[&str],
+        interval: Duration,
+        unplugged: bool,
+    ) -> (FlemPool<64>, Arc<AtomicUsize>) {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let counted = connects.clone();
+        let pool = FlemPool::start(serial_numbers, interval, None, move |serial, _| {
+            if serial == "absent" {
+                return None;
+            }
+            counted.fetch_add(1, Ordering::AcqRel);
+            let link = if unplugged {
+                FlemSerial::<64>::from_transport(DeadHandle)
+            } else {
+                FlemSerial::<64>::from_transport(Cursor::new(Vec::new()))
+            };
+            warm_up_link(serial, format!("mem-{}", serial), link, Duration::ZERO)
+        });
+        (pool, connects)
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_acquire_hands_out_each_ready_link_once() {
+        let (pool, _) = test_pool(&["a", "absent"], Duration::from_millis(10), false);
+        wait_until(|| pool.ready() == ["a"]);
+
+        assert_eq!(pool.acquire("b").err(), Some(PoolError::UnknownDevice));
+        assert_eq!(pool.acquire("absent").err(), Some(PoolError::NotReady));
+
+        let link: PooledLink<64> = pool.acquire("a").unwrap();
+        assert_eq!(link.serial_number(), "a");
+        assert_eq!(link.port_name(), "mem-a");
+        assert_eq!(pool.acquire("a").err(), Some(PoolError::InUse));
+        assert!(pool.ready().is_empty());
+    }
+
+    #[test]
+    fn test_released_links_are_reconnected() {
+        let (pool, connects) = test_pool(&["a"], Duration::from_millis(10), false);
+        wait_until(|| pool.ready() == ["a"]);
+
+        let link = pool.acquire("a").unwrap();
+        pool.release(link);
+        wait_until(|| pool.ready() == ["a"]);
+        assert_eq!(connects.load(Ordering::Acquire), 2);
+    }
+
+    #[test]
+    fn test_links_whose_listener_stopped_are_retired() {
+        let (_pool, connects) = test_pool(&["a"], Duration::from_millis(10), true);
+
+        wait_until(|| connects.load(Ordering::Acquire) >= 3);
+    }
+
+    #[test]
+    fn test_dropping_does_not_wait_out_the_interval() {
+        let (pool, _) = test_pool(&["a"], Duration::from_secs(60), false);
+        wait_until(|| pool.ready() == ["a"]);
+
+        let started = Instant::now();
+        drop(pool);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
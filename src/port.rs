@@ -0,0 +1,122 @@
+use crate::{
+    open_port,
+    options::ConnectOptions,
+    tcp::TcpPort,
+    throttle::{PacedPort, SharedPacer},
+    transport::SharedTransport,
+    uart_errors::ErrorCounters,
+    FlemSerialError, FlemSerialPort,
+};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+/// An opened port or byte stream, ready to hand to
+/// [crate::FlemSerial::from_open_port] or
+/// [crate::ascii::AsciiLink::from_open_port]. Keeps the underlying serial
+/// library out of the public API.
+pub struct OpenPort {
+    pub(crate) port: FlemSerialPort,
+    /// Paces the bottom of `port`, adopted by the link it is handed to.
+    pub(crate) pacer: SharedPacer,
+    /// The driver's line error counters, for serial ports that have them.
+    pub(crate) uart_counters: Option<ErrorCounters>,
+}
+
+impl OpenPort {
+    /// Adds the pacing layer to a port with nothing below it.
+    fn paced(port: FlemSerialPort) -> Self {
+        let pacer = SharedPacer::default();
+        Self {
+            port: Box::new(PacedPort::new(port, pacer.clone())),
+            pacer,
+            uart_counters: None,
+        }
+    }
+
+    /// Opens `port_name` with the FLEM line settings and `options`, for
+    /// example before the process drops the privileges needed to open it.
+    pub fn open(
+        port_name: &str,
+        baud: u32,
+        options: &ConnectOptions,
+    ) -> Result<Self, FlemSerialError> {
+        let pacer = SharedPacer::default();
+        open_port(port_name, baud, options, &pacer)
+            .map(|(port, uart_counters)| Self {
+                port,
+                pacer,
+                uart_counters,
+            })
+            .map_err(|error| FlemSerialError::ErrorConnectingToDevice(error.into()))
+    }
+
+    /// Wraps any byte stream, such as a socket or a test double.
+    ///
+    /// The listener holds the stream's lock while reading, so reads must
+    /// return regularly (a read timeout, `WouldBlock`, or data) or sends
+    /// will stall. Serial line settings are not applicable and report an
+    /// error.
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Read + Write + Send + 'static,
+    {
+        Self::paced(Box::new(SharedTransport::new(stream)))
+    }
+
+    /// Connects to a serial-to-Ethernet converter at `address`, such as
+    /// "192.168.1.50:4001". The remote side closing the connection is
+    /// reported like an unplugged port.
+    pub fn connect_tcp(address: &str, timeout: Duration) -> Result<Self, FlemSerialError> {
+        TcpPort::connect(address, timeout)
+            .map(|port| Self::paced(Box::new(port)))
+            .map_err(FlemSerialError::ErrorConnectingToDevice)
+    }
+
+    /// Wraps a port opened with the `serialport` crate directly. It should
+    /// have a short read timeout, [crate::FlemSerial::connect] uses 10 ms.
+    ///
+    /// This ties the caller to the `serialport` version used by this crate
+    /// and is not part of [crate::prelude].
+    pub fn from_serialport(port: Box<dyn serialport::SerialPort>) -> Self {
+        Self::paced(port)
+    }
+
+    /// Name of the port, the address for TCP and None for streams.
+    pub fn name(&self) -> Option<String> {
+        self.port.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OpenPort;
+    use crate::{options::ConnectOptions, FlemSerial};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_a_port_opened_ahead_of_time_becomes_the_link() {
+        use crate::virtual_port::VirtualDevice;
+        use std::time::Duration;
+
+        let device = VirtualDevice::<64>::echo().unwrap();
+        let port = OpenPort::open(device.port_name(), 115200, &ConnectOptions::default()).unwrap();
+        assert_eq!(port.name().as_deref(), Some(device.port_name()));
+
+        let mut serial = FlemSerial::<64>::from_open_port(port);
+        // Known so the link can be reopened after a drop
+        assert_eq!(
+            serial.port_settings,
+            Some((device.port_name().to_string(), 115200))
+        );
+
+        let rx = serial.listen().unwrap();
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(0x30);
+        packet.pack();
+        serial.send(&packet).unwrap();
+        let echoed = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(echoed.get_request(), 0x30);
+    }
+}
@@ -0,0 +1,48 @@
+pub use crate::{
+    degrade::DegradePolicy,
+    desync::DesyncPolicy,
+    error::WriteFailure,
+    events::{EventRateLimit, LinkEvent},
+    framing::Framing,
+    inventory::{AdapterInfo, PortInfo, UsbIds},
+    keepalive::KeepalivePolicy,
+    options::{ConnectOptions, LineSettings, ListenOptions, SoftFlowControl},
+    reconnect::ReconnectPolicy,
+    router::PacketRouter,
+    stats::{LinkStats, ModemLines, PayloadHistogram, PortBuffers},
+    tunables::Tunables,
+    FlemSerialError,
+};
+
+#[cfg(feature = "link")]
+pub use crate::{
+    client::RequestClient,
+    hotplug::{PortEvent, PortWatcher},
+    manager::{DevicePacket, FlemDeviceManager},
+    port::OpenPort,
+    quickstart::AutoConnectError,
+    scope::LinkScope,
+    FlemRx, FlemSerial, TxPriority,
+};
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_links_are_built_from_prelude_types_alone() {
+        assert!(matches!(
+            OpenPort::open("/dev/flem-no-such-port", 115200, &ConnectOptions::default()),
+            Err(FlemSerialError::ErrorConnectingToDevice(_))
+        ));
+
+        let port = OpenPort::from_stream(Cursor::new(Vec::new()));
+        assert_eq!(port.name(), None);
+        let mut serial = FlemSerial::<64>::from_open_port(port);
+        let rx: FlemRx<64> = serial.listen().unwrap();
+        let stats: LinkStats = rx.stats();
+        assert_eq!(stats.rx_packets, 0);
+        assert_eq!(serial.port_buffers(), None);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::FlemSerial;
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+/// Settings swept by [qualify_link].
+#[derive(Debug, Clone)]
+pub struct QualifyOptions {
+    pub bauds: Vec<u32>,
+    /// Payload sizes to try at each baud, sizes above `T` are skipped.
+    pub payload_sizes: Vec<usize>,
+    /// Request code the device answers by echoing the payload back.
+    pub echo_request: u8,
+    pub packets_per_setting: u32,
+    pub timeout: Duration,
+    /// Highest fraction of failed echoes a setting may have and still be
+    /// considered reliable.
+    pub max_error_rate: f64,
+}
+
+impl Default for QualifyOptions {
+    fn default() -> Self {
+        Self {
+            bauds: vec![9600, 57600, 115200, 230400, 460800, 921600],
+            payload_sizes: vec![],
+            echo_request: 2,
+            packets_per_setting: 100,
+            timeout: Duration::from_millis(200),
+            max_error_rate: 0.0,
+        }
+    }
+}
+
+/// Outcome of one baud / payload size combination.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingResult {
+    pub baud: u32,
+    pub payload_size: usize,
+    /// False if the port could not be opened at this baud.
+    pub connected: bool,
+    pub sent: u32,
+    /// Echoes whose payload matched what was sent.
+    pub echoed: u32,
+    pub resync_errors: u64,
+    pub checksum_errors: u64,
+    /// Average time from send to matching echo.
+    pub mean_round_trip: Option<Duration>,
+}
+
+impl SettingResult {
+    pub fn error_rate(&self) -> f64 {
+        if self.sent == 0 {
+            1.0
+        } else {
+            (self.sent - self.echoed) as f64 / self.sent as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualifyReport {
+    pub port_name: String,
+    pub results: Vec<SettingResult>,
+    /// Fastest baud at which every payload size was reliable, and the
+    /// largest reliable payload size at that baud.
+    pub recommended: Option<(u32, usize)>,
+}
+
+impl QualifyReport {
+    /// The results as CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "port,baud,payload_size,connected,sent,echoed,error_rate,resync_errors,checksum_errors,mean_round_trip_us\n",
+        );
+        for result in self.results.iter() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{:.6},{},{},{}",
+                self.port_name,
+                result.baud,
+                result.payload_size,
+                result.connected,
+                result.sent,
+                result.echoed,
+                result.error_rate(),
+                result.resync_errors,
+                result.checksum_errors,
+                result
+                    .mean_round_trip
+                    .map(|rtt| rtt.as_micros().to_string())
+                    .unwrap_or_default(),
+            );
+        }
+        csv
+    }
+}
+
+/// Sweeps baud rates and payload sizes against an echo-capable device on
+/// `port_name`, measuring the error rate of each combination and
+/// recommending the fastest reliable one.
+///
+/// The port is reopened for each baud, so the device must follow the host's
+/// baud (e.g. USB CDC) or be switched between runs.
+pub fn qualify_link<const T: usize>(port_name: &String, options: &QualifyOptions) -> QualifyReport {
+    let payload_sizes: Vec<usize> = if options.payload_sizes.is_empty() {
+        vec![T]
+    } else {
+        options
+            .payload_sizes
+            .iter()
+            .copied()
+            .filter(|size| *size <= T)
+            .collect()
+    };
+
+    let mut results = Vec::new();
+    for baud in options.bauds.iter() {
+        for payload_size in payload_sizes.iter() {
+            results.push(qualify_setting::<T>(
+                port_name,
+                *baud,
+                *payload_size,
+                options,
+            ));
+        }
+    }
+
+    let recommended = recommend(&results, &options.bauds, options.max_error_rate);
+
+    QualifyReport {
+        port_name: port_name.clone(),
+        results,
+        recommended,
+    }
+}
+
+/// Fastest baud at which every result is reliable, with the largest
+/// payload size tried at it.
+fn recommend(
+    results: &[SettingResult],
+    bauds: &[u32],
+    max_error_rate: f64,
+) -> Option<(u32, usize)> {
+    let mut recommended = None;
+    for baud in bauds.iter() {
+        let at_baud: Vec<&SettingResult> = results.iter().filter(|r| r.baud == *baud).collect();
+        let all_reliable = !at_baud.is_empty()
+            && at_baud
+                .iter()
+                .all(|r| r.connected && r.error_rate() <= max_error_rate);
+        let fastest = recommended.map(|(b, _)| *baud > b).unwrap_or(true);
+        if all_reliable && fastest {
+            let largest = at_baud.iter().map(|r| r.payload_size).max().unwrap_or(0);
+            recommended = Some((*baud, largest));
+        }
+    }
+    recommended
+}
+
+fn qualify_setting<const T: usize>(
+    port_name: &String,
+    baud: u32,
+    payload_size: usize,
+    options: &QualifyOptions,
+) -> SettingResult {
+    let mut result = SettingResult {
+        baud,
+        payload_size,
+        connected: false,
+        sent: 0,
+        echoed: 0,
+        resync_errors: 0,
+        checksum_errors: 0,
+        mean_round_trip: None,
+    };
+
+    let mut serial = FlemSerial::<T>::new();
+    if serial.connect(port_name, baud).is_err() {
+        return result;
+    }
+    result.connected = true;
+
+    measure(&mut serial, payload_size, options, &mut result);
+    result
+}
+
+/// Sends the echo requests of one setting over the connected `serial` and
+/// fills in the counts of `result`.
+fn measure<const T: usize>(
+    serial: &mut FlemSerial<T>,
+    payload_size: usize,
+    options: &QualifyOptions,
+    result: &mut SettingResult,
+) {
+    let rx = match serial.listen() {
+        Ok(rx) => rx,
+        Err(_) => return,
+    };
+    let mut total_round_trip = Duration::ZERO;
+
+    for i in 0..options.packets_per_setting {
+        let payload: Vec<u8> = (0..payload_size)
+            .map(|j| (i as usize).wrapping_add(j) as u8)
+            .collect();
+
+        let mut packet = flem::Packet::<T>::new();
+        packet.set_request(options.echo_request);
+        if packet.add_data(&payload).is_err() {
+            break;
+        }
+        packet.pack();
+
+        let sent_at = Instant::now();
+        if serial.send(&packet).is_err() {
+            continue;
+        }
+        result.sent += 1;
+
+        // Echoes of earlier packets that arrived after their timeout are
+        // skipped, or one slow echo would fail two packets
+        let deadline = sent_at + options.timeout;
+        while let Ok(echo) = rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            if echo.get_request() == options.echo_request && echo.get_data() == payload.as_slice() {
+                result.echoed += 1;
+                total_round_trip += sent_at.elapsed();
+                break;
+            }
+        }
+    }
+
+    let stats = rx.stats();
+    result.resync_errors = stats.resync_errors;
+    result.checksum_errors = stats.checksum_errors;
+    if result.echoed > 0 {
+        result.mean_round_trip = Some(total_round_trip / result.echoed);
+    }
+
+    serial.unlisten();
+    let _ = rx.join();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{measure, recommend, QualifyOptions, SettingResult};
+    use crate::FlemSerial;
+    use std::{
+        collections::VecDeque,
+        io::{self, Read, Write},
+        time::Duration,
+    };
+
+    fn result(baud: u32, payload_size: usize, echoed: u32) -> SettingResult {
+        SettingResult {
+            baud,
+            payload_size,
+            connected: true,
+            sent: 10,
+            echoed,
+            resync_errors: 0,
+            checksum_errors: 0,
+            mean_round_trip: None,
+        }
+    }
+
+    #[test]
+    fn test_recommends_the_fastest_baud_reliable_at_every_size() {
+        let bauds = [9600, 115200, 921600];
+        let results = [
+            result(9600, 8, 10),
+            result(9600, 64, 10),
+            result(115200, 8, 10),
+            result(115200, 64, 10),
+            // One failed echo at the largest size rules out the baud
+            result(921600, 8, 10),
+            result(921600, 64, 9),
+        ];
+        assert_eq!(recommend(&results, &bauds, 0.0), Some((115200, 64)));
+        assert_eq!(recommend(&results, &bauds, 0.1), Some((921600, 64)));
+
+        let unconnected = SettingResult {
+            connected: false,
+            ..result(9600, 8, 10)
+        };
+        assert_eq!(recommend(&[unconnected], &[9600], 1.0), None);
+        assert_eq!(recommend(&results, &[], 0.0), None);
+    }
+
+    /// Echoes every packet, but the first one only after the second
+    /// arrived.
+    struct SlowFirstEcho {
+        incoming: flem::Packet<64>,
+        held: Option<Vec<u8>>,
+        received: u32,
+        outgoing: VecDeque<u8>,
+    }
+
+    impl Read for SlowFirstEcho {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.outgoing.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.outgoing.len());
+            for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for SlowFirstEcho {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                if let flem::Status::PacketReceived = self.incoming.add_byte(*byte) {
+                    let echo = self.incoming.bytes().to_vec();
+                    self.incoming.reset_lazy();
+                    self.received += 1;
+                    if self.received == 1 {
+                        self.held = Some(echo);
+                        continue;
+                    }
+                    if let Some(held) = self.held.take() {
+                        self.outgoing.extend(held);
+                    }
+                    self.outgoing.extend(echo);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_a_late_echo_fails_only_its_own_packet() {
+        let mut serial = FlemSerial::<64>::from_transport(SlowFirstEcho {
+            incoming: flem::Packet::new(),
+            held: None,
+            received: 0,
+            outgoing: VecDeque::new(),
+        });
+        let options = QualifyOptions {
+            packets_per_setting: 3,
+            timeout: Duration::from_millis(50),
+            ..QualifyOptions::default()
+        };
+        let mut result = result(115200, 4, 0);
+        result.sent = 0;
+
+        measure(&mut serial, 4, &options, &mut result);
+        assert_eq!((result.sent, result.echoed), (3, 2));
+        assert!(result.mean_round_trip.is_some());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{options::ConnectOptions, FlemRx, FlemSerial, FlemSerialError};
+use std::{error::Error, fmt, time::Duration};
+
+/// Baud rates [FlemSerial::auto_connect] tries on each port, most common
+/// first.
+pub const AUTO_CONNECT_BAUD_RATES: [u32; 5] = [115200, 921600, 1000000, 460800, 9600];
+
+/// How long [FlemSerial::auto_connect] waits for an ID response at each
+/// baud rate.
+pub const AUTO_CONNECT_ID_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A port that answered a FLEM ID request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundDevice {
+    pub port: String,
+    pub baud: u32,
+}
+
+/// Why [FlemSerial::auto_connect] failed.
+#[derive(Debug)]
+pub enum AutoConnectError {
+    /// The ports could not be listed.
+    ListPorts(FlemSerialError),
+    /// None of the `probed` ports answered an ID request at any of
+    /// [AUTO_CONNECT_BAUD_RATES].
+    NoDevice { probed: Vec<String> },
+    /// More than one port answered. Connect to one of them by name.
+    MultipleDevices(Vec<FoundDevice>),
+    /// The device was found but listening to it failed.
+    Listen(FlemSerialError),
+}
+
+impl fmt::Display for AutoConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoConnectError::ListPorts(error) => write!(f, "couldn't list ports: {}", error),
+            AutoConnectError::NoDevice { probed } if probed.is_empty() => {
+                write!(f, "no serial ports found")
+            }
+            AutoConnectError::NoDevice { probed } => {
+                write!(f, "no FLEM device answered on {}", probed.join(", "))
+            }
+            AutoConnectError::MultipleDevices(found) => {
+                let found: Vec<String> = found
+                    .iter()
+                    .map(|device| format!("{} at {} baud", device.port, device.baud))
+                    .collect();
+                write!(f, "several FLEM devices found: {}", found.join(", "))
+            }
+            AutoConnectError::Listen(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for AutoConnectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AutoConnectError::ListPorts(error) | AutoConnectError::Listen(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<const T: usize> FlemSerial<T> {
+    /// Finds the one attached FLEM device, connects and starts listening.
+    /// Every port is sent an ID request at each of
+    /// [AUTO_CONNECT_BAUD_RATES] until it answers, so this takes a while
+    /// when ports are silent. Meant for examples and scripts, applications
+    /// should connect to a known port.
+    pub fn auto_connect() -> Result<(FlemSerial<T>, FlemRx<T>), AutoConnectError> {
+        let ports = Self::new()
+            .list_ports()
+            .map_err(AutoConnectError::ListPorts)?;
+        let probed: Vec<String> = ports.into_iter().map(|port| port.name).collect();
+
+        // Probed links are kept open, reopening some boards resets them
+        let mut found: Vec<(FoundDevice, FlemSerial<T>)> =
+            probed.iter().filter_map(Self::probe_bauds).collect();
+
+        match found.len() {
+            0 => Err(AutoConnectError::NoDevice { probed }),
+            1 => {
+                let (_, mut serial) = found.remove(0);
+                let rx = serial.listen().map_err(AutoConnectError::Listen)?;
+                Ok((serial, rx))
+            }
+            _ => Err(AutoConnectError::MultipleDevices(
+                found.into_iter().map(|(device, _)| device).collect(),
+            )),
+        }
+    }
+
+    /// Connects to `port` at the first baud rate the device answers an ID
+    /// request at.
+    fn probe_bauds(port: &String) -> Option<(FoundDevice, FlemSerial<T>)> {
+        let options = ConnectOptions {
+            verify_device: true,
+            verify_timeout: AUTO_CONNECT_ID_TIMEOUT,
+            ..ConnectOptions::default()
+        };
+
+        AUTO_CONNECT_BAUD_RATES.into_iter().find_map(|baud| {
+            let mut serial = Self::new();
+            serial.connect_with_options(port, baud, &options).ok()?;
+            let device = FoundDevice {
+                port: port.clone(),
+                baud,
+            };
+            Some((device, serial))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutoConnectError, FoundDevice};
+
+    #[test]
+    fn test_errors_name_the_ports() {
+        let none = AutoConnectError::NoDevice {
+            probed: vec!["COM3".to_string(), "COM4".to_string()],
+        };
+        assert_eq!(none.to_string(), "no FLEM device answered on COM3, COM4");
+
+        let several = AutoConnectError::MultipleDevices(vec![
+            FoundDevice {
+                port: "/dev/ttyACM0".to_string(),
+                baud: 115200,
+            },
+            FoundDevice {
+                port: "/dev/ttyUSB0".to_string(),
+                baud: 9600,
+            },
+        ]);
+        assert_eq!(
+            several.to_string(),
+            "several FLEM devices found: /dev/ttyACM0 at 115200 baud, /dev/ttyUSB0 at 9600 baud"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{
+    inventory::DeviceIdentity,
+    reconnect::{self, ReconnectBackoff, ReconnectPolicy},
+    FlemSerial, FlemSerialPort,
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// How long [FlemSerial::reboot_device] waits for the port to disappear.
+/// Devices behind a separate USB to UART adapter never drop, in which case
+/// the wait simply runs out and reconnecting starts anyway.
+pub const REBOOT_DROP_TIMEOUT: Duration = Duration::from_secs(2);
+
+const DROP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How to make the device reboot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RebootStrategy {
+    /// Send an empty request with this code, which the firmware answers by
+    /// rebooting.
+    Request(u8),
+    /// Send an empty request with this code, which the firmware answers by
+    /// jumping to its bootloader.
+    Bootloader(u8),
+    /// Drop DTR for this long, for boards with an auto-reset circuit.
+    PulseDtr(Duration),
+    /// Drop RTS for this long, for boards wired to reset on RTS.
+    PulseRts(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebootError {
+    NotConnected,
+    /// The reboot request or line toggle could not be sent.
+    SendFailed,
+    /// The device did not answer an ID request before the reconnect policy
+    /// gave up.
+    DidNotReturn,
+}
+
+fn pulse<F>(port: &Arc<Mutex<FlemSerialPort>>, width: Duration, mut set: F) -> Option<()>
+where
+    F: FnMut(&mut FlemSerialPort, bool) -> serialport::Result<()>,
+{
+    let mut port = port.lock().ok()?;
+    set(&mut port, false).ok()?;
+    thread::sleep(width);
+    set(&mut port, true).ok()
+}
+
+fn is_listed(port_name: &str) -> bool {
+    serialport::available_ports()
+        .map(|ports| ports.iter().any(|port| port.port_name == port_name))
+        .unwrap_or(false)
+}
+
+impl<const T: usize> FlemSerial<T> {
+    /// Reboots the device with `strategy`, waits for the port to drop and
+    /// reconnects following `policy`, which should set `max_attempts`.
+    /// Returns the identity reported by the device once it is back.
+    ///
+    /// Stops listening first, call `listen` again afterwards.
+    pub fn reboot_device(
+        &mut self,
+        strategy: RebootStrategy,
+        policy: &ReconnectPolicy,
+    ) -> Result<flem::DataId, RebootError> {
+        let (port_name, baud) = self
+            .port_settings
+            .clone()
+            .ok_or(RebootError::NotConnected)?;
+        let tx_port = self.tx_port.clone().ok_or(RebootError::NotConnected)?;
+
+        self.unlisten();
+
+        match strategy {
+            RebootStrategy::Request(request) | RebootStrategy::Bootloader(request) => {
+                let mut packet = flem::Packet::<T>::new();
+                packet.set_request(request);
+                packet.pack();
+                self.send(&packet).map_err(|_| RebootError::SendFailed)?;
+            }
+            RebootStrategy::PulseDtr(width) => pulse(&tx_port, width, |port, level| {
+                port.write_data_terminal_ready(level)
+            })
+            .ok_or(RebootError::SendFailed)?,
+            RebootStrategy::PulseRts(width) => pulse(&tx_port, width, |port, level| {
+                port.write_request_to_send(level)
+            })
+            .ok_or(RebootError::SendFailed)?,
+        }
+
+        // Let go of the handle so the OS can re-enumerate the device. The
+        // error counters share its descriptor, and with it the port's lock,
+        // which would fail every reopen of an adapter that never drops
+        self.tx_port = None;
+        self.uart_counters = None;
+        drop(tx_port);
+
+        let drop_deadline = self.clock.now() + REBOOT_DROP_TIMEOUT;
+        while self.clock.now() < drop_deadline && is_listed(&port_name) {
+            self.clock.sleep(DROP_POLL_INTERVAL);
+        }
+
+        let mut backoff = ReconnectBackoff::new(*policy);
+        backoff.on_disconnect(self.clock.now());
+        while let Some(next_attempt) = backoff.next_attempt() {
+            let now = self.clock.now();
+            if next_attempt > now {
+                self.clock.sleep(next_attempt - now);
+            }
+
+            if let Some((mut port, uart_counters)) =
+                reconnect::reopen(&port_name, baud, &self.connect_options, &self.pacer)
+            {
+                if let Some(id) = Self::probe_id(&mut port, self.connect_options.verify_timeout) {
+                    backoff.on_success();
+                    self.session.set_identity(DeviceIdentity::from_data_id(&id));
+                    self.tx_port = Some(Arc::new(Mutex::new(port)));
+                    self.uart_counters = uart_counters;
+                    self.connected_at = Some(self.clock.now());
+                    self.warmup.on_connect(self.clock.now());
+                    return Ok(id);
+                }
+            }
+
+            backoff.on_failure(self.clock.now());
+        }
+
+        Err(RebootError::DidNotReturn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RebootError, RebootStrategy};
+    use crate::{
+        clock::MockClock, reconnect::ReconnectPolicy, test_support::SharedBuffer, FlemSerial,
+    };
+    use std::time::Duration;
+
+    fn policy() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2,
+            max_attempts: Some(2),
+        }
+    }
+
+    #[test]
+    fn test_a_link_without_a_port_to_reopen_is_not_connected() {
+        let mut serial = FlemSerial::<64>::new();
+        assert_eq!(
+            serial
+                .reboot_device(RebootStrategy::Request(0x50), &policy())
+                .err(),
+            Some(RebootError::NotConnected)
+        );
+
+        // A transport has nothing to reconnect to after the reboot
+        let mut serial = FlemSerial::<64>::from_transport(SharedBuffer::default());
+        assert_eq!(
+            serial
+                .reboot_device(RebootStrategy::Request(0x50), &policy())
+                .err(),
+            Some(RebootError::NotConnected)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_a_device_that_never_answers_its_id_did_not_return() {
+        use crate::{options::ConnectOptions, virtual_port::VirtualDevice};
+        use std::sync::{Arc, Mutex};
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let device = {
+            let received = received.clone();
+            VirtualDevice::<64>::spawn(move |packet| {
+                received.lock().unwrap().push(packet.get_request());
+                Vec::new()
+            })
+            .unwrap()
+        };
+
+        let mut serial = FlemSerial::<64>::new();
+        let options = ConnectOptions {
+            verify_timeout: Duration::from_millis(50),
+            ..ConnectOptions::default()
+        };
+        serial
+            .connect_with_options(&device.port_name().to_string(), 115200, &options)
+            .unwrap();
+        let clock = Arc::new(MockClock::new());
+        serial.set_clock(clock.clone());
+
+        assert_eq!(
+            serial
+                .reboot_device(RebootStrategy::Bootloader(0x51), &policy())
+                .err(),
+            Some(RebootError::DidNotReturn)
+        );
+
+        // The reboot request, then one ID request per attempt, backing off
+        // between them on the link's clock
+        assert_eq!(
+            *received.lock().unwrap(),
+            [0x51, flem::Request::ID, flem::Request::ID]
+        );
+        assert!(clock.elapsed() >= Duration::from_millis(300));
+        assert!(serial.send(&flem::Packet::<64>::new()).is_err());
+    }
+}
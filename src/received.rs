@@ -0,0 +1,158 @@
+use crate::clock::Clock;
+use crate::events::LinkEvent;
+use crate::listener::{ListenerShared, ListenerThread};
+use crate::stats::{LinkRates, LinkStats};
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// A packet along with when the listener parsed its final byte, so time
+/// spent waiting in the queue doesn't skew latency measurements.
+#[derive(Clone)]
+pub struct ReceivedPacket<const T: usize> {
+    pub packet: flem::Packet<T>,
+    /// Monotonic time, from the link's [Clock], for latency math.
+    pub received_at: Instant,
+    /// Wall-clock time, to line packets up with other systems.
+    pub received_wall: SystemTime,
+}
+
+impl<const T: usize> ReceivedPacket<T> {
+    /// Stamps `packet` as parsed now.
+    pub(crate) fn stamp(packet: flem::Packet<T>, clock: &dyn Clock) -> Self {
+        Self {
+            packet,
+            received_at: clock.now(),
+            received_wall: SystemTime::now(),
+        }
+    }
+
+    /// How long the packet waited between being parsed and `now`.
+    pub fn waited(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.received_at)
+    }
+}
+
+/// Stamps packets on the listener thread.
+pub(crate) struct TimestampedDelivery<const T: usize> {
+    sender: Sender<ReceivedPacket<T>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<const T: usize> TimestampedDelivery<T> {
+    pub(crate) fn new(sender: Sender<ReceivedPacket<T>>, clock: Arc<dyn Clock>) -> Self {
+        Self { sender, clock }
+    }
+
+    pub(crate) fn push(&self, packet: flem::Packet<T>) -> Result<(), ()> {
+        let received = ReceivedPacket::stamp(packet, self.clock.as_ref());
+        self.sender.send(received).map_err(|_| ())
+    }
+}
+
+/// Receive handle returned by [crate::FlemSerial::listen_timestamped].
+/// Dropping it stops its listener.
+pub struct FlemTimestampedRx<const T: usize> {
+    pub(crate) rx_listener_handle: ListenerThread,
+    pub(crate) rx_packet_queue: Receiver<ReceivedPacket<T>>,
+    pub(crate) events: Receiver<LinkEvent>,
+    pub(crate) shared: ListenerShared,
+}
+
+impl<const T: usize> FlemTimestampedRx<T> {
+    /// Raw access to the queue. Packets taken directly from the queue are
+    /// not counted by [FlemTimestampedRx::queue_depth].
+    pub fn queue(&self) -> &Receiver<ReceivedPacket<T>> {
+        &self.rx_packet_queue
+    }
+
+    /// Link state changes reported by the listener.
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    fn received(&self, packet: ReceivedPacket<T>) -> ReceivedPacket<T> {
+        self.shared.queue_depth.fetch_sub(1, Ordering::AcqRel);
+        packet
+    }
+
+    /// Blocks until a packet is received.
+    pub fn recv(&self) -> Result<ReceivedPacket<T>, RecvError> {
+        self.rx_packet_queue
+            .recv()
+            .map(|packet| self.received(packet))
+    }
+
+    /// Returns a packet if one is waiting.
+    pub fn try_recv(&self) -> Result<ReceivedPacket<T>, TryRecvError> {
+        self.rx_packet_queue
+            .try_recv()
+            .map(|packet| self.received(packet))
+    }
+
+    /// Blocks until a packet is received or `timeout` elapses.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ReceivedPacket<T>, RecvTimeoutError> {
+        self.rx_packet_queue
+            .recv_timeout(timeout)
+            .map(|packet| self.received(packet))
+    }
+
+    /// Number of packets delivered by the listener and not yet received.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.shared.rates()
+    }
+
+    pub fn join_handle(&self) -> &JoinHandle<()> {
+        self.rx_listener_handle.handle()
+    }
+
+    /// Waits for the listener thread to exit.
+    pub fn join(self) -> thread::Result<()> {
+        self.rx_listener_handle.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        clock::{Clock, MockClock},
+        FlemSerial,
+    };
+    use std::{io::Cursor, sync::Arc, time::Duration};
+
+    #[test]
+    fn test_packets_are_stamped_when_parsed() {
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.pack();
+
+        let clock = Arc::new(MockClock::new());
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(event.bytes().to_vec()));
+        serial.set_clock(clock.clone());
+        let parsed_at = clock.now();
+        let rx = serial.listen_timestamped().unwrap();
+
+        let received = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        clock.advance(Duration::from_millis(40));
+        assert_eq!(received.received_at, parsed_at);
+        assert_eq!(received.waited(clock.now()), Duration::from_millis(40));
+
+        serial.unlisten();
+    }
+}
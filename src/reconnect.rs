@@ -0,0 +1,183 @@
+#[cfg(feature = "link")]
+use crate::{
+    options::ConnectOptions, throttle::SharedPacer, uart_errors::ErrorCounters, FlemSerialPort,
+};
+#[cfg(feature = "link")]
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Consecutive hard read errors after which the port handle is considered
+/// dead. Read timeouts don't count.
+#[cfg(feature = "link")]
+pub(crate) const DEAD_HANDLE_ERRORS: u32 = 50;
+
+/// True for read errors that just mean no data arrived in time.
+#[cfg(feature = "link")]
+pub(crate) fn is_idle_error(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
+
+/// Reopens `port_name` if the OS still lists it or, for ports it doesn't
+/// list, the path still exists. RFC 2217 servers are always tried.
+#[cfg(feature = "link")]
+pub(crate) fn reopen(
+    port_name: &str,
+    baud: u32,
+    options: &ConnectOptions,
+    pacer: &SharedPacer,
+) -> Option<(FlemSerialPort, Option<ErrorCounters>)> {
+    let listed = crate::rfc2217::address(port_name).is_some()
+        || crate::unlisted_port_exists(port_name)
+        || serialport::available_ports()
+            .ok()?
+            .iter()
+            .any(|port| port.port_name == port_name);
+    if !listed {
+        return None;
+    }
+
+    crate::open_port(port_name, baud, options, pacer).ok()
+}
+
+/// When to retry opening a link after it drops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Wait before the first attempt.
+    pub initial_delay: Duration,
+    /// Upper bound on the wait between attempts.
+    pub max_delay: Duration,
+    /// Factor the wait grows by after each failed attempt.
+    pub multiplier: u32,
+    /// Attempts before giving up, None retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+/// Exponential backoff state for one link.
+#[derive(Debug, Clone)]
+pub struct ReconnectBackoff {
+    policy: ReconnectPolicy,
+    attempts: u32,
+    delay: Duration,
+    next_attempt: Option<Instant>,
+}
+
+impl ReconnectBackoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            policy,
+            attempts: 0,
+            delay: policy.initial_delay,
+            next_attempt: None,
+        }
+    }
+
+    /// The link dropped at `now`, schedules the first attempt.
+    pub fn on_disconnect(&mut self, now: Instant) {
+        self.attempts = 0;
+        self.delay = self.policy.initial_delay;
+        self.next_attempt = Some(now + self.delay);
+    }
+
+    /// When the next attempt is due, None if connected or given up.
+    pub fn next_attempt(&self) -> Option<Instant> {
+        self.next_attempt
+    }
+
+    /// Records a failed attempt at `now` and schedules the next one.
+    /// Returns false once `max_attempts` is used up.
+    pub fn on_failure(&mut self, now: Instant) -> bool {
+        self.attempts += 1;
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempts >= max_attempts {
+                self.next_attempt = None;
+                return false;
+            }
+        }
+
+        self.delay = self
+            .delay
+            .saturating_mul(self.policy.multiplier.max(1))
+            .min(self.policy.max_delay);
+        self.next_attempt = Some(now + self.delay);
+        true
+    }
+
+    /// The link is back, stops scheduling attempts.
+    pub fn on_success(&mut self) {
+        self.attempts = 0;
+        self.delay = self.policy.initial_delay;
+        self.next_attempt = None;
+    }
+
+    /// Failed attempts since the link dropped.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// True if attempts ran out before the link came back.
+    pub fn exhausted(&self) -> bool {
+        self.next_attempt.is_none()
+            && self
+                .policy
+                .max_attempts
+                .map(|max| self.attempts >= max)
+                .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReconnectBackoff, ReconnectPolicy};
+    use crate::clock::{Clock, MockClock};
+    use std::time::Duration;
+
+    #[test]
+    fn test_backoff_grows_to_the_cap_and_gives_up() {
+        let clock = MockClock::new();
+        let mut backoff = ReconnectBackoff::new(ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            multiplier: 2,
+            max_attempts: Some(4),
+        });
+        assert_eq!(backoff.next_attempt(), None);
+
+        backoff.on_disconnect(clock.now());
+        let mut waits = Vec::new();
+        while let Some(next_attempt) = backoff.next_attempt() {
+            waits.push(next_attempt - clock.now());
+            clock.sleep(next_attempt - clock.now());
+            backoff.on_failure(clock.now());
+        }
+        assert_eq!(
+            waits,
+            [100, 200, 300, 300].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(backoff.attempts(), 4);
+        assert!(backoff.exhausted());
+
+        // A new drop starts over from the initial delay
+        backoff.on_disconnect(clock.now());
+        assert_eq!(
+            backoff.next_attempt(),
+            Some(clock.now() + Duration::from_millis(100))
+        );
+        backoff.on_success();
+        assert_eq!(backoff.next_attempt(), None);
+        assert!(!backoff.exhausted());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::tx_queue::TxWriter;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Decides which packets may be sent again after a reconnect. Packets that
+/// were lost with the link may have reached the device before it dropped,
+/// so only commands that are safe to repeat should be replayed.
+pub trait ReplayGuard<const T: usize>: Send {
+    /// True if sending `packet` twice does no harm.
+    fn is_idempotent(&self, packet: &flem::Packet<T>) -> bool;
+}
+
+impl<const T: usize, F> ReplayGuard<T> for F
+where
+    F: Fn(&flem::Packet<T>) -> bool + Send,
+{
+    fn is_idempotent(&self, packet: &flem::Packet<T>) -> bool {
+        self(packet)
+    }
+}
+
+/// Packets from [crate::FlemSerial::send_queued] whose write failed, held
+/// until the listener reopens the port.
+pub(crate) struct ReplayBuffer<const T: usize> {
+    max_packets: usize,
+    guard: Box<dyn ReplayGuard<T>>,
+    held: VecDeque<(TxWriter<T>, flem::Packet<T>)>,
+}
+
+/// A replay buffer shared between a link, its TX thread and its listener.
+pub(crate) type SharedReplay<const T: usize> = Arc<Mutex<ReplayBuffer<T>>>;
+
+impl<const T: usize> ReplayBuffer<T> {
+    pub(crate) fn new<G: ReplayGuard<T> + 'static>(max_packets: usize, guard: G) -> Self {
+        Self {
+            max_packets,
+            guard: Box::new(guard),
+            held: VecDeque::new(),
+        }
+    }
+
+    /// Keeps a packet that failed to write. Returns it back if the guard
+    /// rejects it or the buffer is full.
+    pub(crate) fn hold(
+        &mut self,
+        writer: TxWriter<T>,
+        packet: flem::Packet<T>,
+    ) -> Result<(), flem::Packet<T>> {
+        if self.held.len() >= self.max_packets || !self.guard.is_idempotent(&packet) {
+            return Err(packet);
+        }
+        self.held.push_back((writer, packet));
+        Ok(())
+    }
+
+    /// Number of packets waiting to be replayed.
+    pub(crate) fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Takes the held packets, oldest first.
+    pub(crate) fn take(&mut self) -> Vec<(TxWriter<T>, flem::Packet<T>)> {
+        self.held.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FlemSerial;
+    use std::io;
+
+    /// A port whose handle has died.
+    struct DeadPort;
+
+    impl io::Read for DeadPort {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for DeadPort {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(request: u8) -> flem::Packet<64> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(request);
+        packet.pack();
+        packet
+    }
+
+    #[test]
+    fn test_failed_queued_sends_are_held_for_replay() {
+        let mut serial = FlemSerial::<64>::from_transport(DeadPort);
+        serial.set_replay_on_reconnect(2, |packet: &flem::Packet<64>| packet.get_request() != 0x20);
+
+        serial.send_queued(&packet(0x10)).unwrap();
+        serial.flush_tx().unwrap();
+        assert_eq!(serial.tx_held(), 1);
+
+        // Not idempotent, reported instead of held
+        serial.send_queued(&packet(0x20)).unwrap();
+        assert!(serial.flush_tx().is_err());
+
+        serial.send_queued(&packet(0x11)).unwrap();
+        serial.send_queued(&packet(0x12)).unwrap();
+        // Only room for two
+        assert!(serial.flush_tx().is_err());
+        assert_eq!(serial.tx_held(), 2);
+
+        serial.clear_replay_on_reconnect();
+        assert_eq!(serial.tx_held(), 0);
+    }
+}
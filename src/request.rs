@@ -0,0 +1,110 @@
+use crate::compat::FirmwareVersion;
+#[cfg(all(feature = "link", feature = "async"))]
+use crate::mailbox::Mailbox;
+#[cfg(feature = "link")]
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, SyncSender},
+};
+
+/// Why [crate::FlemSerial::request] or
+/// [crate::FlemSerial::request_async] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestError {
+    /// The link is not listening, so no response could be received.
+    NotListening,
+    /// Another request with the same request code is still waiting. Only
+    /// returned by `request`.
+    AlreadyPending(u8),
+    /// The packet was vetoed or writing it failed.
+    SendFailed,
+    /// No response arrived in time.
+    Timeout,
+    /// The connected firmware doesn't support the request code.
+    UnsupportedByFirmware(FirmwareVersion),
+}
+
+/// Requests waiting for their response, keyed by request code. The
+/// listener hands matching responses to the waiter instead of the packet
+/// queue.
+#[cfg(feature = "link")]
+pub(crate) struct PendingRequests<const T: usize> {
+    waiting: HashMap<u8, SyncSender<flem::Packet<T>>>,
+    #[cfg(feature = "async")]
+    pub(crate) mailbox: Mailbox<T>,
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> Default for PendingRequests<T> {
+    fn default() -> Self {
+        Self {
+            waiting: HashMap::new(),
+            #[cfg(feature = "async")]
+            mailbox: Mailbox::default(),
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> PendingRequests<T> {
+    pub(crate) fn register(
+        &mut self,
+        request: u8,
+    ) -> Result<Receiver<flem::Packet<T>>, RequestError> {
+        if self.waiting.contains_key(&request) {
+            return Err(RequestError::AlreadyPending(request));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.waiting.insert(request, sender);
+        Ok(receiver)
+    }
+
+    pub(crate) fn cancel(&mut self, request: u8) {
+        self.waiting.remove(&request);
+    }
+
+    /// Hands `packet` to the request waiting for it. Returns false if
+    /// nothing is waiting and the packet should be delivered as usual.
+    pub(crate) fn route(&mut self, packet: &flem::Packet<T>) -> bool {
+        if let Some(waiter) = self.waiting.remove(&packet.get_request()) {
+            return waiter.try_send(packet.clone()).is_ok();
+        }
+
+        #[cfg(feature = "async")]
+        if self.mailbox.route(packet) {
+            return true;
+        }
+
+        false
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{PendingRequests, RequestError};
+
+    fn packet(request: u8) -> flem::Packet<8> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(request);
+        packet
+    }
+
+    #[test]
+    fn test_responses_go_to_the_waiting_request() {
+        let mut pending = PendingRequests::<8>::default();
+        let waiter = pending.register(7).unwrap();
+        assert_eq!(
+            pending.register(7).err(),
+            Some(RequestError::AlreadyPending(7))
+        );
+
+        // Unrelated packets are delivered as usual
+        assert!(!pending.route(&packet(flem::Request::EVENT)));
+        assert!(pending.route(&packet(7)));
+        assert_eq!(waiter.try_recv().unwrap().get_request(), 7);
+
+        // Only one response per request
+        assert!(!pending.route(&packet(7)));
+    }
+}
@@ -0,0 +1,285 @@
+use std::time::Duration;
+#[cfg(feature = "link")]
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    thread,
+    time::Instant,
+};
+
+/// Retry policy for transient write errors on the transmit path. Errors
+/// that mean the port is gone are never retried, see
+/// [crate::error::WriteFailure].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxRetry {
+    /// Retries allowed per packet, zero disables retrying.
+    pub max_retries: u32,
+    /// Wait before the first retry.
+    pub delay: Duration,
+    /// Factor the wait grows by with every further retry, 1 keeps it
+    /// constant.
+    pub backoff: u32,
+    /// Longest wait between retries, however large the backoff grows.
+    pub max_delay: Duration,
+}
+
+impl Default for TxRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            delay: Duration::from_millis(2),
+            backoff: 1,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl TxRetry {
+    /// Retries `max_retries` times, waiting `delay` at first and twice as
+    /// long each time after, up to `max_delay`.
+    pub fn exponential(max_retries: u32, delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            delay,
+            backoff: 2,
+            max_delay,
+        }
+    }
+
+    /// Wait before retry number `retry`, counting from zero.
+    pub fn delay_before(&self, retry: u32) -> Duration {
+        self.delay
+            .saturating_mul(self.backoff.max(1).saturating_pow(retry))
+            .min(self.max_delay)
+    }
+}
+
+/// Errors that usually clear up on their own, such as a full OS buffer, an
+/// interrupted syscall or a momentary USB stall.
+#[cfg(feature = "link")]
+pub(crate) fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(
+        kind,
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::TimedOut
+    )
+}
+
+/// Writes all of `bytes`, retrying transient errors according to `policy`.
+/// Bytes already written are never repeated.
+#[cfg(feature = "link")]
+pub(crate) fn write_with_retry<W: Write + ?Sized>(
+    port: &mut W,
+    bytes: &[u8],
+    policy: &TxRetry,
+) -> io::Result<()> {
+    let mut written = 0;
+    let mut retries = 0;
+
+    while written < bytes.len() {
+        match port.write(&bytes[written..]) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(count) => written += count,
+            Err(error) if is_transient(error.kind()) && retries < policy.max_retries => {
+                thread::sleep(policy.delay_before(retries));
+                retries += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    port.flush()
+}
+
+/// How to handle a `flem::Response::BUSY` reply to a request code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusyRetry {
+    /// Wait between the busy reply and the retry.
+    pub delay: Duration,
+    /// Number of retries before the busy reply is delivered to the consumer.
+    pub max_retries: u32,
+}
+
+#[cfg(feature = "link")]
+struct Outstanding<const T: usize> {
+    packet: flem::Packet<T>,
+    retries: u32,
+    due: Option<Instant>,
+}
+
+/// Requests sent with an opted-in request code, waiting for a response.
+#[cfg(feature = "link")]
+pub(crate) struct BusyRetryState<const T: usize> {
+    policies: HashMap<u8, BusyRetry>,
+    outstanding: HashMap<u8, Outstanding<T>>,
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> Default for BusyRetryState<T> {
+    fn default() -> Self {
+        Self {
+            policies: HashMap::new(),
+            outstanding: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> BusyRetryState<T> {
+    pub(crate) fn set_policy(&mut self, request: u8, policy: BusyRetry) {
+        self.policies.insert(request, policy);
+    }
+
+    pub(crate) fn clear_policy(&mut self, request: u8) {
+        self.policies.remove(&request);
+        self.outstanding.remove(&request);
+    }
+
+    /// Remembers a request just sent so it can be retried.
+    pub(crate) fn on_send(&mut self, packet: &flem::Packet<T>) {
+        let request = packet.get_request();
+        if self.policies.contains_key(&request) {
+            self.outstanding.insert(
+                request,
+                Outstanding {
+                    packet: packet.clone(),
+                    retries: 0,
+                    due: None,
+                },
+            );
+        }
+    }
+
+    /// Returns true if the response was a busy reply that will be retried
+    /// and should not be delivered.
+    pub(crate) fn on_response(&mut self, packet: &flem::Packet<T>, now: Instant) -> bool {
+        let request = packet.get_request();
+        let policy = match self.policies.get(&request) {
+            Some(policy) => *policy,
+            None => return false,
+        };
+
+        if packet.get_response() != flem::Response::BUSY {
+            self.outstanding.remove(&request);
+            return false;
+        }
+
+        match self.outstanding.get_mut(&request) {
+            Some(outstanding) if outstanding.retries < policy.max_retries => {
+                outstanding.retries += 1;
+                outstanding.due = Some(now + policy.delay);
+                true
+            }
+            _ => {
+                self.outstanding.remove(&request);
+                false
+            }
+        }
+    }
+
+    /// Takes the requests whose retry delay has elapsed.
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<flem::Packet<T>> {
+        let mut packets = Vec::new();
+        for outstanding in self.outstanding.values_mut() {
+            if matches!(outstanding.due, Some(due) if due <= now) {
+                outstanding.due = None;
+                packets.push(outstanding.packet.clone());
+            }
+        }
+        packets
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{write_with_retry, BusyRetry, BusyRetryState, TxRetry};
+    use std::{
+        io::{self, Write},
+        time::{Duration, Instant},
+    };
+
+    /// Accepts two bytes per write and fails every other call.
+    struct FlakyPort {
+        written: Vec<u8>,
+        fail_next: bool,
+        kind: io::ErrorKind,
+    }
+
+    impl Write for FlakyPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.fail_next = !self.fail_next;
+            if !self.fail_next {
+                return Err(io::Error::from(self.kind));
+            }
+            let count = buf.len().min(2);
+            self.written.extend_from_slice(&buf[..count]);
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_with_retry() {
+        let policy = TxRetry {
+            max_retries: 2,
+            delay: Duration::ZERO,
+            ..TxRetry::default()
+        };
+
+        let mut port = FlakyPort {
+            written: Vec::new(),
+            fail_next: false,
+            kind: io::ErrorKind::WouldBlock,
+        };
+        write_with_retry(&mut port, &[1, 2, 3, 4, 5], &policy).unwrap();
+        assert_eq!(port.written, [1, 2, 3, 4, 5]);
+
+        port.written.clear();
+        port.fail_next = false;
+        assert!(write_with_retry(&mut port, &[1, 2, 3, 4, 5, 6, 7], &policy).is_err());
+
+        port.kind = io::ErrorKind::BrokenPipe;
+        port.fail_next = false;
+        assert!(write_with_retry(&mut port, &[1, 2, 3], &policy).is_err());
+    }
+
+    #[test]
+    fn test_retry_delays_back_off_up_to_the_limit() {
+        let ms = Duration::from_millis;
+        let policy = TxRetry::exponential(5, ms(10), ms(50));
+        let delays: Vec<Duration> = (0..5).map(|retry| policy.delay_before(retry)).collect();
+        assert_eq!(delays, [ms(10), ms(20), ms(40), ms(50), ms(50)]);
+
+        assert_eq!(TxRetry::default().delay_before(2), ms(2));
+    }
+
+    #[test]
+    fn test_busy_retry_limit() {
+        let mut state = BusyRetryState::<8>::default();
+        state.set_policy(
+            7,
+            BusyRetry {
+                delay: Duration::from_millis(5),
+                max_retries: 1,
+            },
+        );
+
+        let mut request = flem::Packet::<8>::new();
+        request.set_request(7);
+        state.on_send(&request);
+
+        let mut busy = request.clone();
+        busy.set_response(flem::Response::BUSY);
+
+        let now = Instant::now();
+        assert!(state.on_response(&busy, now));
+        assert!(state.due(now).is_empty());
+        assert_eq!(state.due(now + Duration::from_millis(5)).len(), 1);
+        assert!(state.due(now + Duration::from_millis(10)).is_empty());
+
+        // Retry limit reached, the busy reply is delivered
+        assert!(!state.on_response(&busy, now));
+    }
+}
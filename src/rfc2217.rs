@@ -0,0 +1,771 @@
+use crate::{
+    tcp::{connect_stream, TCP_CONNECT_TIMEOUT},
+    transport::not_serial,
+};
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Prefix of port names that are RFC 2217 serial servers rather than local
+/// ports, as in "rfc2217://192.168.1.50:2217". Such names can be passed to
+/// [crate::FlemSerial::connect] and friends.
+pub const RFC2217_SCHEME: &str = "rfc2217://";
+
+/// How long connecting waits for the server to accept the COM port option
+/// and confirm the baud rate.
+pub const RFC2217_NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const BINARY: u8 = 0;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const COM_PORT_OPTION: u8 = 44;
+
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+const NOTIFY_MODEMSTATE: u8 = 7;
+const SET_MODEMSTATE_MASK: u8 = 11;
+const PURGE_DATA: u8 = 12;
+/// Added to a command's code in the server's answer.
+const SERVER_OFFSET: u8 = 100;
+
+const MODEM_CTS: u8 = 0x10;
+const MODEM_DSR: u8 = 0x20;
+const MODEM_RI: u8 = 0x40;
+const MODEM_CD: u8 = 0x80;
+
+/// The server address in `port_name`, if it names an RFC 2217 server.
+pub(crate) fn address(port_name: &str) -> Option<&str> {
+    port_name.strip_prefix(RFC2217_SCHEME)
+}
+
+/// Telnet commands mixed into the received bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Control {
+    Will(u8),
+    Wont(u8),
+    Do(u8),
+    Dont(u8),
+    /// Subnegotiation payload, option first, with IAC escapes removed.
+    Sub(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+enum DecodeState {
+    #[default]
+    Data,
+    Iac,
+    Option(u8),
+    Sub,
+    SubIac,
+}
+
+/// Splits received bytes into serial data and telnet commands. Commands may
+/// be split across reads.
+#[derive(Debug, Default)]
+struct TelnetDecoder {
+    state: DecodeState,
+    sub: Vec<u8>,
+}
+
+impl TelnetDecoder {
+    fn decode(&mut self, input: &[u8], data: &mut VecDeque<u8>, controls: &mut Vec<Control>) {
+        for &byte in input {
+            self.state = match (std::mem::take(&mut self.state), byte) {
+                (DecodeState::Data, IAC) => DecodeState::Iac,
+                (DecodeState::Data, _) | (DecodeState::Iac, IAC) => {
+                    data.push_back(byte);
+                    DecodeState::Data
+                }
+                (DecodeState::Iac, WILL | WONT | DO | DONT) => DecodeState::Option(byte),
+                (DecodeState::Iac, SB) => {
+                    self.sub.clear();
+                    DecodeState::Sub
+                }
+                // NOP, go ahead and the like carry nothing for us
+                (DecodeState::Iac, _) => DecodeState::Data,
+                (DecodeState::Option(command), option) => {
+                    controls.push(match command {
+                        WILL => Control::Will(option),
+                        WONT => Control::Wont(option),
+                        DO => Control::Do(option),
+                        _ => Control::Dont(option),
+                    });
+                    DecodeState::Data
+                }
+                (DecodeState::Sub, IAC) => DecodeState::SubIac,
+                (DecodeState::Sub, _) | (DecodeState::SubIac, IAC) => {
+                    self.sub.push(byte);
+                    DecodeState::Sub
+                }
+                (DecodeState::SubIac, SE) => {
+                    controls.push(Control::Sub(std::mem::take(&mut self.sub)));
+                    DecodeState::Data
+                }
+                // Malformed, drop the subnegotiation
+                (DecodeState::SubIac, _) => DecodeState::Data,
+            };
+        }
+    }
+}
+
+/// Doubles IAC bytes so the server doesn't take them for commands.
+fn escape(bytes: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        escaped.push(byte);
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+    }
+    escaped
+}
+
+fn com_port_command(code: u8, value: &[u8]) -> Vec<u8> {
+    let mut command = vec![IAC, SB, COM_PORT_OPTION, code];
+    command.extend(escape(value));
+    command.extend([IAC, SE]);
+    command
+}
+
+/// Line settings requested from, and confirmed by, the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RemoteLine {
+    pub(crate) baud: u32,
+    pub(crate) data_bits: DataBits,
+    pub(crate) parity: Parity,
+    pub(crate) stop_bits: StopBits,
+    pub(crate) flow_control: FlowControl,
+}
+
+impl RemoteLine {
+    fn commands(&self) -> Vec<u8> {
+        let mut commands = com_port_command(SET_BAUDRATE, &self.baud.to_be_bytes());
+        commands.extend(com_port_command(
+            SET_DATASIZE,
+            &[data_bits_value(self.data_bits)],
+        ));
+        commands.extend(com_port_command(SET_PARITY, &[parity_value(self.parity)]));
+        commands.extend(com_port_command(
+            SET_STOPSIZE,
+            &[stop_bits_value(self.stop_bits)],
+        ));
+        commands.extend(com_port_command(
+            SET_CONTROL,
+            &[flow_control_value(self.flow_control)],
+        ));
+        commands
+    }
+}
+
+fn data_bits_value(data_bits: DataBits) -> u8 {
+    match data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    }
+}
+
+fn parity_value(parity: Parity) -> u8 {
+    match parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    }
+}
+
+fn stop_bits_value(stop_bits: StopBits) -> u8 {
+    match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    }
+}
+
+fn flow_control_value(flow_control: FlowControl) -> u8 {
+    match flow_control {
+        FlowControl::None => 1,
+        FlowControl::Software => 2,
+        FlowControl::Hardware => 3,
+    }
+}
+
+/// Protocol state shared by a port and its clones.
+#[derive(Debug)]
+struct State {
+    decoder: TelnetDecoder,
+    /// Serial data received but not yet read.
+    pending: VecDeque<u8>,
+    /// Options we said we will do.
+    offered: HashSet<u8>,
+    /// Options we asked the server to do.
+    requested: HashSet<u8>,
+    /// Whether the server agreed to the COM port option, None until it
+    /// answers.
+    com_port: Option<bool>,
+    line: RemoteLine,
+    baud_confirmed: bool,
+    modem: u8,
+}
+
+impl State {
+    /// Applies a command from the server, returning the reply to send.
+    fn handle(&mut self, control: Control) -> Vec<u8> {
+        match control {
+            Control::Do(option @ (BINARY | SUPPRESS_GO_AHEAD | COM_PORT_OPTION)) => {
+                if option == COM_PORT_OPTION {
+                    self.com_port = Some(true);
+                }
+                match self.offered.insert(option) {
+                    true => vec![IAC, WILL, option],
+                    false => Vec::new(),
+                }
+            }
+            Control::Do(option) => vec![IAC, WONT, option],
+            Control::Dont(option) => {
+                if option == COM_PORT_OPTION {
+                    self.com_port = Some(false);
+                }
+                self.offered.remove(&option);
+                Vec::new()
+            }
+            Control::Will(option @ (BINARY | SUPPRESS_GO_AHEAD)) => {
+                match self.requested.insert(option) {
+                    true => vec![IAC, DO, option],
+                    false => Vec::new(),
+                }
+            }
+            Control::Will(option) => vec![IAC, DONT, option],
+            Control::Wont(option) => {
+                self.requested.remove(&option);
+                Vec::new()
+            }
+            Control::Sub(payload) => {
+                if let [COM_PORT_OPTION, code, value @ ..] = payload.as_slice() {
+                    self.on_com_port(code.wrapping_sub(SERVER_OFFSET), value);
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    /// Records the server's answer to a COM port command.
+    fn on_com_port(&mut self, code: u8, value: &[u8]) {
+        match (code, value) {
+            (SET_BAUDRATE, &[a, b, c, d]) => {
+                self.line.baud = u32::from_be_bytes([a, b, c, d]);
+                self.baud_confirmed = true;
+            }
+            (SET_DATASIZE, &[bits]) => {
+                self.line.data_bits = match bits {
+                    5 => DataBits::Five,
+                    6 => DataBits::Six,
+                    7 => DataBits::Seven,
+                    8 => DataBits::Eight,
+                    _ => return,
+                }
+            }
+            (SET_PARITY, &[parity]) => {
+                self.line.parity = match parity {
+                    1 => Parity::None,
+                    2 => Parity::Odd,
+                    3 => Parity::Even,
+                    _ => return,
+                }
+            }
+            (SET_STOPSIZE, &[stop_bits]) => {
+                self.line.stop_bits = match stop_bits {
+                    1 => StopBits::One,
+                    2 => StopBits::Two,
+                    _ => return,
+                }
+            }
+            // SET-CONTROL also answers modem line and break changes
+            (SET_CONTROL, &[control]) => {
+                self.line.flow_control = match control {
+                    1 => FlowControl::None,
+                    2 => FlowControl::Software,
+                    3 => FlowControl::Hardware,
+                    _ => return,
+                }
+            }
+            (NOTIFY_MODEMSTATE, &[modem]) => self.modem = modem,
+            _ => {}
+        }
+    }
+}
+
+struct Shared {
+    /// Held for every write so commands and data don't interleave.
+    writer: Mutex<TcpStream>,
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn send(&self, bytes: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(bytes)?;
+        writer.flush()
+    }
+
+    /// Decodes bytes from the server, answering its commands.
+    fn receive(&self, raw: &[u8]) -> io::Result<()> {
+        let mut controls = Vec::new();
+        let mut replies = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            let state = &mut *state;
+            state.decoder.decode(raw, &mut state.pending, &mut controls);
+            for control in controls {
+                replies.extend(state.handle(control));
+            }
+        }
+        match replies.is_empty() {
+            true => Ok(()),
+            false => self.send(&replies),
+        }
+    }
+}
+
+/// A serial port on an RFC 2217 (telnet COM port control) server, such as
+/// a terminal server in a lab rack. Line settings and modem control lines
+/// are forwarded to the server. The modem status lines report the server's
+/// latest notification.
+pub(crate) struct Rfc2217Port {
+    reader: TcpStream,
+    address: String,
+    shared: Arc<Shared>,
+}
+
+impl Rfc2217Port {
+    /// Connects to the server at `address`, negotiates the COM port option
+    /// and applies `line`. Fails if the server refuses the option or doesn't
+    /// confirm the baud rate within `negotiation_timeout`.
+    pub(crate) fn connect(
+        address: &str,
+        line: RemoteLine,
+        read_timeout: Duration,
+        negotiation_timeout: Duration,
+    ) -> io::Result<Self> {
+        let stream = connect_stream(address, TCP_CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+
+        let mut port = Self {
+            reader: stream.try_clone()?,
+            address: address.to_string(),
+            shared: Arc::new(Shared {
+                writer: Mutex::new(stream),
+                state: Mutex::new(State {
+                    decoder: TelnetDecoder::default(),
+                    pending: VecDeque::new(),
+                    offered: HashSet::from([BINARY, SUPPRESS_GO_AHEAD, COM_PORT_OPTION]),
+                    requested: HashSet::from([BINARY, SUPPRESS_GO_AHEAD]),
+                    com_port: None,
+                    line,
+                    baud_confirmed: false,
+                    modem: 0,
+                }),
+            }),
+        };
+
+        let mut negotiation = vec![
+            IAC,
+            WILL,
+            BINARY,
+            IAC,
+            DO,
+            BINARY,
+            IAC,
+            WILL,
+            SUPPRESS_GO_AHEAD,
+            IAC,
+            DO,
+            SUPPRESS_GO_AHEAD,
+            IAC,
+            WILL,
+            COM_PORT_OPTION,
+        ];
+        negotiation.extend(line.commands());
+        negotiation.extend(com_port_command(
+            SET_MODEMSTATE_MASK,
+            &[MODEM_CTS | MODEM_DSR | MODEM_RI | MODEM_CD],
+        ));
+        port.shared.send(&negotiation)?;
+
+        let deadline = Instant::now() + negotiation_timeout;
+        loop {
+            {
+                let state = port.shared.state.lock().unwrap();
+                if state.com_port == Some(false) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "server refused RFC 2217 COM port control",
+                    ));
+                }
+                if state.com_port == Some(true) && state.baud_confirmed {
+                    if state.line.baud != line.baud {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "server set {} baud instead of {}",
+                                state.line.baud, line.baud
+                            ),
+                        ));
+                    }
+                    break;
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no RFC 2217 answer from the server",
+                ));
+            }
+            match port.read_raw() {
+                Ok(()) => {}
+                Err(error) if crate::reconnect::is_idle_error(error.kind()) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        port.set_timeout(read_timeout)?;
+        Ok(port)
+    }
+
+    /// Reads once from the socket and decodes what arrived.
+    fn read_raw(&mut self) -> io::Result<()> {
+        let mut raw = [0u8; 1024];
+        match self.reader.read(&mut raw)? {
+            // The listener takes 0 bytes for a quiet line, report the close
+            0 => Err(io::ErrorKind::ConnectionAborted.into()),
+            count => self.shared.receive(&raw[..count]),
+        }
+    }
+
+    fn command(&self, code: u8, value: &[u8]) -> serialport::Result<()> {
+        self.shared
+            .send(&com_port_command(code, value))
+            .map_err(Into::into)
+    }
+
+    fn update_line(&self, update: impl FnOnce(&mut RemoteLine)) {
+        update(&mut self.shared.state.lock().unwrap().line);
+    }
+
+    fn modem_line(&self, mask: u8) -> serialport::Result<bool> {
+        Ok(self.shared.state.lock().unwrap().modem & mask != 0)
+    }
+}
+
+impl Read for Rfc2217Port {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if !state.pending.is_empty() {
+                    let count = buf.len().min(state.pending.len());
+                    for (slot, byte) in buf.iter_mut().zip(state.pending.drain(..count)) {
+                        *slot = byte;
+                    }
+                    return Ok(count);
+                }
+            }
+            // Only telnet commands arrived, wait for data or the timeout
+            self.read_raw()?;
+        }
+    }
+}
+
+impl Write for Rfc2217Port {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.shared.send(&escape(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.shared.writer.lock().unwrap().flush()
+    }
+}
+
+impl SerialPort for Rfc2217Port {
+    fn name(&self) -> Option<String> {
+        Some(format!("{}{}", RFC2217_SCHEME, self.address))
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.shared.state.lock().unwrap().line.baud)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(self.shared.state.lock().unwrap().line.data_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(self.shared.state.lock().unwrap().line.flow_control)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(self.shared.state.lock().unwrap().line.parity)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(self.shared.state.lock().unwrap().line.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.reader
+            .read_timeout()
+            .ok()
+            .flatten()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.update_line(|line| line.baud = baud_rate);
+        self.command(SET_BAUDRATE, &baud_rate.to_be_bytes())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.update_line(|line| line.data_bits = data_bits);
+        self.command(SET_DATASIZE, &[data_bits_value(data_bits)])
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.update_line(|line| line.flow_control = flow_control);
+        self.command(SET_CONTROL, &[flow_control_value(flow_control)])
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.update_line(|line| line.parity = parity);
+        self.command(SET_PARITY, &[parity_value(parity)])
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.update_line(|line| line.stop_bits = stop_bits);
+        self.command(SET_STOPSIZE, &[stop_bits_value(stop_bits)])
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.reader
+            .set_read_timeout(Some(timeout).filter(|timeout| !timeout.is_zero()))
+            .map_err(Into::into)
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.command(SET_CONTROL, &[if level { 11 } else { 12 }])
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.command(SET_CONTROL, &[if level { 8 } else { 9 }])
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_CTS)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_DSR)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_RI)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.modem_line(MODEM_CD)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        if matches!(buffer_to_clear, ClearBuffer::Input | ClearBuffer::All) {
+            self.shared.state.lock().unwrap().pending.clear();
+        }
+        let purge = match buffer_to_clear {
+            ClearBuffer::Input => 1,
+            ClearBuffer::Output => 2,
+            ClearBuffer::All => 3,
+        };
+        self.command(PURGE_DATA, &[purge])
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(Rfc2217Port {
+            reader: self.reader.try_clone()?,
+            address: self.address.clone(),
+            shared: self.shared.clone(),
+        }))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.command(SET_CONTROL, &[5])
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.command(SET_CONTROL, &[6])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Control, TelnetDecoder, COM_PORT_OPTION, DO, IAC, SB, SE};
+    use crate::{ConnectOptions, FlemSerial};
+    use std::{
+        collections::VecDeque,
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_decoder_separates_data_from_commands() {
+        let mut decoder = TelnetDecoder::default();
+        let mut data = VecDeque::new();
+        let mut controls = Vec::new();
+
+        // A command and a subnegotiation split across reads, with escaped
+        // IAC bytes in the data and in the subnegotiation
+        decoder.decode(&[1, IAC, IAC, IAC], &mut data, &mut controls);
+        decoder.decode(
+            &[DO, COM_PORT_OPTION, 2, IAC, SB, COM_PORT_OPTION],
+            &mut data,
+            &mut controls,
+        );
+        decoder.decode(&[107, IAC, IAC, IAC, SE, 3], &mut data, &mut controls);
+
+        assert_eq!(data, [1, IAC, 2, 3]);
+        assert_eq!(
+            controls,
+            [
+                Control::Do(COM_PORT_OPTION),
+                Control::Sub(vec![COM_PORT_OPTION, 107, IAC])
+            ]
+        );
+    }
+
+    /// Reads from `socket` until `pattern` has been seen.
+    fn read_until(socket: &mut impl Read, received: &mut Vec<u8>, pattern: &[u8]) {
+        let mut buffer = [0u8; 256];
+        while !received
+            .windows(pattern.len())
+            .any(|window| window == pattern)
+        {
+            let count = socket.read(&mut buffer).unwrap();
+            assert!(count > 0, "client closed the connection");
+            received.extend_from_slice(&buffer[..count]);
+        }
+    }
+
+    #[test]
+    fn test_connect_negotiates_and_escapes_data() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("rfc2217://{}", server.local_addr().unwrap());
+        let baud = 115200u32.to_be_bytes();
+
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.add_data(&[IAC, 1]).unwrap();
+        event.pack();
+        let event_bytes = super::escape(event.bytes());
+
+        let mut reply = flem::Packet::<64>::new();
+        reply.set_request(0x20);
+        reply.add_data(&[IAC]).unwrap();
+        reply.pack();
+        let expected = super::escape(reply.bytes());
+
+        let terminal_server = thread::spawn(move || {
+            let (mut socket, _) = server.accept().unwrap();
+            let mut received = Vec::new();
+            let mut set_baud = vec![IAC, SB, COM_PORT_OPTION, 1];
+            set_baud.extend(baud);
+            read_until(&mut socket, &mut received, &set_baud);
+
+            let mut answer = vec![IAC, DO, COM_PORT_OPTION, IAC, SB, COM_PORT_OPTION, 101];
+            answer.extend(baud);
+            answer.extend([IAC, SE]);
+            answer.extend(&event_bytes);
+            socket.write_all(&answer).unwrap();
+
+            received.clear();
+            read_until(&mut socket, &mut received, &expected);
+        });
+
+        let mut serial = FlemSerial::<64>::new();
+        serial.connect(&address, 115200).unwrap();
+        let rx = serial.listen().unwrap();
+        let received = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received.get_data(), [IAC, 1]);
+
+        serial.send(&reply).unwrap();
+        terminal_server.join().unwrap();
+
+        serial.unlisten();
+    }
+
+    #[test]
+    fn test_connect_does_not_probe_the_server() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("rfc2217://{}", server.local_addr().unwrap());
+
+        let baud = 115200u32.to_be_bytes();
+        let mut set_baud = vec![IAC, SB, COM_PORT_OPTION, 1];
+        set_baud.extend(baud);
+
+        let terminal_server = thread::spawn(move || {
+            let (mut socket, _) = server.accept().unwrap();
+            let mut received = Vec::new();
+            read_until(&mut socket, &mut received, &set_baud);
+            let mut answer = vec![IAC, DO, COM_PORT_OPTION, IAC, SB, COM_PORT_OPTION, 101];
+            answer.extend(baud);
+            answer.extend([IAC, SE]);
+            socket.write_all(&answer).unwrap();
+
+            let _ = socket.read_to_end(&mut received);
+            received
+        });
+
+        let options = ConnectOptions {
+            probe_adapter: true,
+            ..ConnectOptions::default()
+        };
+        let mut serial = FlemSerial::<64>::new();
+        serial
+            .connect_with_options(&address, 115200, &options)
+            .unwrap();
+        assert!(serial.adapter_info().unwrap().baud_rates.is_empty());
+        drop(serial);
+
+        let received = terminal_server.join().unwrap();
+        let set_baud = [IAC, SB, COM_PORT_OPTION, 1];
+        let baud_commands = received
+            .windows(set_baud.len())
+            .filter(|window| *window == set_baud)
+            .count();
+        assert_eq!(baud_commands, 1);
+    }
+}
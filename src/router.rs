@@ -0,0 +1,114 @@
+#[cfg(feature = "link")]
+use crate::{handler::FlemHandlerRx, FlemSerial, FlemSerialError};
+use std::collections::HashMap;
+
+type Route<const T: usize> = Box<dyn FnMut(flem::Packet<T>) + Send>;
+
+/// Hands each received packet to the handler registered for its request
+/// code, replacing a `match` on [flem::Packet::get_request] in every
+/// consumer.
+///
+/// Packets with no handler go to the fallback set with
+/// [PacketRouter::on_unknown], or are dropped if there is none.
+pub struct PacketRouter<const T: usize> {
+    routes: HashMap<u8, Route<T>>,
+    unknown: Option<Route<T>>,
+}
+
+impl<const T: usize> Default for PacketRouter<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const T: usize> PacketRouter<T> {
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::new(),
+            unknown: None,
+        }
+    }
+
+    /// Calls `handler` for packets with `request`, replacing the handler
+    /// registered for it before, if any.
+    pub fn on<H>(&mut self, request: u8, handler: H)
+    where
+        H: FnMut(flem::Packet<T>) + Send + 'static,
+    {
+        self.routes.insert(request, Box::new(handler));
+    }
+
+    /// Calls `handler` for packets with no handler of their own.
+    pub fn on_unknown<H>(&mut self, handler: H)
+    where
+        H: FnMut(flem::Packet<T>) + Send + 'static,
+    {
+        self.unknown = Some(Box::new(handler));
+    }
+
+    /// Hands `packet` to its handler. Returns false if it was dropped
+    /// because no handler, fallback included, took it.
+    pub fn dispatch(&mut self, packet: flem::Packet<T>) -> bool {
+        let route = match self.routes.get_mut(&packet.get_request()) {
+            Some(route) => Some(route),
+            None => self.unknown.as_mut(),
+        };
+        match route {
+            Some(route) => {
+                route(packet);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl<const T: usize> FlemSerial<T> {
+    /// Like [FlemSerial::listen_with_handler], with `router` dispatching
+    /// each packet. Its handlers run on the listener thread, with the same
+    /// rules as a handler passed to `listen_with_handler`.
+    pub fn listen_routed(
+        &mut self,
+        mut router: PacketRouter<T>,
+    ) -> Result<FlemHandlerRx<T>, FlemSerialError> {
+        self.listen_with_handler(move |packet| {
+            router.dispatch(packet);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketRouter;
+    use std::sync::{Arc, Mutex};
+
+    fn packet(request: u8) -> flem::Packet<16> {
+        let mut packet = flem::Packet::new();
+        packet.set_request(request);
+        packet.pack();
+        packet
+    }
+
+    #[test]
+    fn test_packets_go_to_their_route_or_the_fallback() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut router = PacketRouter::<16>::new();
+
+        let events = seen.clone();
+        router.on(flem::Request::EVENT, move |_| {
+            events.lock().unwrap().push("event")
+        });
+        assert!(!router.dispatch(packet(0x42)));
+
+        let unknown = seen.clone();
+        router.on_unknown(move |packet| {
+            assert_eq!(packet.get_request(), 0x42);
+            unknown.lock().unwrap().push("unknown");
+        });
+
+        assert!(router.dispatch(packet(flem::Request::EVENT)));
+        assert!(router.dispatch(packet(0x42)));
+        assert_eq!(*seen.lock().unwrap(), ["event", "unknown"]);
+    }
+}
@@ -0,0 +1,249 @@
+#[cfg(feature = "link")]
+use crate::FlemRx;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How long [LinkScheduler::recv_timeout] sleeps between checks of idle
+/// links.
+#[cfg(feature = "link")]
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Time packets of one link spent waiting in the scheduler.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub packets: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn mean(&self) -> Duration {
+        if self.packets == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.packets as u32
+        }
+    }
+
+    fn record(&mut self, waited: Duration) {
+        self.packets += 1;
+        self.total += waited;
+        self.max = self.max.max(waited);
+    }
+}
+
+struct Lane<K, P> {
+    key: K,
+    weight: u32,
+    deficit: u32,
+    queue: VecDeque<(Instant, P)>,
+    latency: LatencyStats,
+}
+
+/// Deficit round robin over per-link queues. Each round a link may hand
+/// out up to `weight` items, so a link flooding its queue delays the others
+/// by at most its weight per round.
+pub struct WeightedQueue<K, P> {
+    lanes: Vec<Lane<K, P>>,
+    current: usize,
+}
+
+impl<K, P> Default for WeightedQueue<K, P> {
+    fn default() -> Self {
+        Self {
+            lanes: Vec::new(),
+            current: 0,
+        }
+    }
+}
+
+impl<K: PartialEq, P> WeightedQueue<K, P> {
+    /// Adds a link, or changes its weight. Weights below 1 count as 1.
+    pub fn set_weight(&mut self, key: K, weight: u32) {
+        let weight = weight.max(1);
+        match self.lanes.iter_mut().find(|lane| lane.key == key) {
+            Some(lane) => lane.weight = weight,
+            None => self.lanes.push(Lane {
+                key,
+                weight,
+                deficit: 0,
+                queue: VecDeque::new(),
+                latency: LatencyStats::default(),
+            }),
+        }
+    }
+
+    /// Queues `item` for `key`, which arrived at `now`. Unknown keys are
+    /// added with weight 1.
+    pub fn push(&mut self, key: K, item: P, now: Instant) {
+        let index = match self.lanes.iter().position(|lane| lane.key == key) {
+            Some(index) => index,
+            None => {
+                self.set_weight(key, 1);
+                self.lanes.len() - 1
+            }
+        };
+        self.lanes[index].queue.push_back((now, item));
+    }
+
+    /// Hands out the next item by weighted fair order, recording how long it
+    /// waited.
+    pub fn pop(&mut self, now: Instant) -> Option<(&K, P)> {
+        if self.lanes.iter().all(|lane| lane.queue.is_empty()) {
+            return None;
+        }
+
+        let index = loop {
+            let index = self.current % self.lanes.len();
+            let lane = &mut self.lanes[index];
+
+            if lane.queue.is_empty() {
+                lane.deficit = 0;
+                self.current = index + 1;
+                continue;
+            }
+
+            if lane.deficit == 0 {
+                lane.deficit = lane.weight;
+            }
+            lane.deficit -= 1;
+            if lane.queue.len() == 1 {
+                lane.deficit = 0;
+            }
+            if lane.deficit == 0 {
+                self.current = index + 1;
+            }
+            break index;
+        };
+
+        let lane = &mut self.lanes[index];
+        let (queued_at, item) = lane.queue.pop_front()?;
+        lane.latency
+            .record(now.saturating_duration_since(queued_at));
+        Some((&lane.key, item))
+    }
+
+    pub fn latency(&self, key: &K) -> Option<LatencyStats> {
+        self.lanes
+            .iter()
+            .find(|lane| lane.key == *key)
+            .map(|lane| lane.latency)
+    }
+
+    /// Items waiting for `key`.
+    pub fn queued(&self, key: &K) -> usize {
+        self.lanes
+            .iter()
+            .find(|lane| lane.key == *key)
+            .map(|lane| lane.queue.len())
+            .unwrap_or(0)
+    }
+}
+
+/// Serves several listening links from one consumer thread with weighted
+/// fair scheduling, so a high rate telemetry link can't starve a low rate
+/// control link. Per-link latency shows how long packets waited here.
+#[cfg(feature = "link")]
+pub struct LinkScheduler<K, const T: usize> {
+    links: Vec<(K, FlemRx<T>)>,
+    queue: WeightedQueue<K, flem::Packet<T>>,
+}
+
+#[cfg(feature = "link")]
+impl<K: Clone + PartialEq, const T: usize> Default for LinkScheduler<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "link")]
+impl<K: Clone + PartialEq, const T: usize> LinkScheduler<K, T> {
+    pub fn new() -> Self {
+        Self {
+            links: Vec::new(),
+            queue: WeightedQueue::default(),
+        }
+    }
+
+    /// Adds a link that may take up to `weight` turns per round.
+    pub fn add_link(&mut self, key: K, rx: FlemRx<T>, weight: u32) {
+        self.queue.set_weight(key.clone(), weight);
+        self.links.push((key, rx));
+    }
+
+    pub fn set_weight(&mut self, key: K, weight: u32) {
+        self.queue.set_weight(key, weight);
+    }
+
+    fn collect(&mut self, now: Instant) {
+        for (key, rx) in self.links.iter() {
+            while let Ok(packet) = rx.try_recv() {
+                self.queue.push(key.clone(), packet, now);
+            }
+        }
+    }
+
+    /// Returns the next packet in fair order, waiting up to `timeout` if
+    /// none is ready.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<(K, flem::Packet<T>)> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            self.collect(now);
+            if let Some((key, packet)) = self.queue.pop(now) {
+                return Some((key.clone(), packet));
+            }
+            if now >= deadline {
+                return None;
+            }
+            std::thread::sleep(IDLE_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    pub fn latency(&self, key: &K) -> Option<LatencyStats> {
+        self.queue.latency(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeightedQueue;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_busy_link_does_not_starve_quiet_link() {
+        let start = Instant::now();
+        let mut queue = WeightedQueue::default();
+        queue.set_weight("telemetry", 3);
+        queue.set_weight("control", 1);
+
+        for i in 0..100 {
+            queue.push("telemetry", i, start);
+        }
+        queue.push("control", 1000, start);
+
+        let order: Vec<&str> = (0..8)
+            .map(|_| *queue.pop(start + Duration::from_millis(1)).unwrap().0)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "telemetry",
+                "telemetry",
+                "telemetry",
+                "control",
+                "telemetry",
+                "telemetry",
+                "telemetry",
+                "telemetry"
+            ]
+        );
+
+        let control = queue.latency(&"control").unwrap();
+        assert_eq!(control.packets, 1);
+        assert_eq!(control.max, Duration::from_millis(1));
+        assert_eq!(queue.queued(&"telemetry"), 93);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::hooks::{self, AbortHook};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How long dropping a [LinkScope] waits for its threads.
+pub const DEFAULT_SCOPE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How a thread tracked by a [LinkScope] ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThreadOutcome {
+    /// The thread returned.
+    Exited,
+    /// The thread panicked with this message.
+    Panicked(String),
+    /// The thread didn't stop within the timeout and was left detached.
+    StillRunning,
+}
+
+/// One thread of a closed [LinkScope].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadReport {
+    /// Which thread it was, e.g. "listener", "tx" or "manager".
+    pub thread: &'static str,
+    /// Port name of the link the thread served, if known.
+    pub link: Option<String>,
+    pub outcome: ThreadOutcome,
+}
+
+type Stop = Box<dyn Fn() + Send>;
+
+struct Tracked {
+    id: u64,
+    thread: &'static str,
+    link: Option<String>,
+    /// Asks the thread to finish. Dropped once it has.
+    stop: Option<Stop>,
+    outcome: Option<ThreadOutcome>,
+}
+
+#[derive(Default)]
+struct ScopeState {
+    tracked: Vec<Tracked>,
+    next_id: u64,
+    closed: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct ScopeShared {
+    state: Mutex<ScopeState>,
+    exited: Condvar,
+}
+
+/// What links hold of the [LinkScope] they were attached to.
+pub(crate) type ScopeHandle = Arc<ScopeShared>;
+
+impl ScopeShared {
+    /// Records how a thread ended. Threads that return before the scope
+    /// closes are forgotten, so a long lived scope doesn't grow with every
+    /// listener it has seen. Panics are kept for the report.
+    fn finish(&self, id: u64, outcome: ThreadOutcome) {
+        let mut state = self.state.lock().unwrap();
+        let closed = state.closed;
+        if let Some(index) = state.tracked.iter().position(|tracked| tracked.id == id) {
+            if !closed && outcome == ThreadOutcome::Exited {
+                state.tracked.remove(index);
+            } else {
+                let tracked = &mut state.tracked[index];
+                tracked.stop = None;
+                tracked.outcome = Some(outcome);
+            }
+        }
+        self.exited.notify_all();
+    }
+}
+
+/// Like [hooks::spawn_supervised], but the thread is tracked by `scope`, if
+/// any. `stop` asks the thread to finish when the scope closes. Threads
+/// spawned after the scope closed are asked to stop straight away.
+pub(crate) fn spawn_scoped<R, F, S>(
+    thread: &'static str,
+    link: Option<String>,
+    link_hook: Option<AbortHook>,
+    scope: Option<&ScopeHandle>,
+    stop: S,
+    f: F,
+) -> JoinHandle<R>
+where
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+    S: Fn() + Send + 'static,
+{
+    let scope = match scope {
+        Some(scope) => scope.clone(),
+        None => return hooks::spawn_supervised(thread, link, link_hook, f),
+    };
+
+    let id = {
+        let mut state = scope.state.lock().unwrap();
+        if state.closed {
+            stop();
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        state.tracked.push(Tracked {
+            id,
+            thread,
+            link: link.clone(),
+            stop: Some(Box::new(stop)),
+            outcome: None,
+        });
+        id
+    };
+
+    hooks::spawn_supervised(thread, link, link_hook, move || {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => {
+                scope.finish(id, ThreadOutcome::Exited);
+                result
+            }
+            Err(payload) => {
+                let message = hooks::panic_message(payload.as_ref());
+                scope.finish(id, ThreadOutcome::Panicked(message));
+                panic::resume_unwind(payload)
+            }
+        }
+    })
+}
+
+/// Keeps track of every thread spawned for the links attached to it, see
+/// [crate::FlemSerial::set_scope], so none outlive the scope unnoticed.
+///
+/// Closing the scope asks each thread to stop, waits for them and reports
+/// how each one ended. Threads can't be killed, so one that doesn't stop in
+/// time is reported as [ThreadOutcome::StillRunning] and left detached.
+/// Dropping the scope closes it with [DEFAULT_SCOPE_TIMEOUT].
+pub struct LinkScope {
+    shared: ScopeHandle,
+}
+
+impl Default for LinkScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LinkScope {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(ScopeShared::default()),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> ScopeHandle {
+        self.shared.clone()
+    }
+
+    /// Number of tracked threads that haven't exited yet.
+    pub fn running(&self) -> usize {
+        let state = self.shared.state.lock().unwrap();
+        state
+            .tracked
+            .iter()
+            .filter(|tracked| tracked.outcome.is_none())
+            .count()
+    }
+
+    /// Asks every thread still running to stop and waits up to `timeout`
+    /// for all of them. Reports each thread in the order it was spawned,
+    /// leaving out those that had already returned before the close.
+    pub fn close(self, timeout: Duration) -> Vec<ThreadReport> {
+        self.close_within(timeout)
+    }
+
+    fn close_within(&self, timeout: Duration) -> Vec<ThreadReport> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        for stop in state
+            .tracked
+            .iter()
+            .filter_map(|tracked| tracked.stop.as_ref())
+        {
+            stop();
+        }
+
+        while state
+            .tracked
+            .iter()
+            .any(|tracked| tracked.outcome.is_none())
+        {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self
+                .shared
+                .exited
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+
+        state
+            .tracked
+            .iter()
+            .map(|tracked| ThreadReport {
+                thread: tracked.thread,
+                link: tracked.link.clone(),
+                outcome: tracked
+                    .outcome
+                    .clone()
+                    .unwrap_or(ThreadOutcome::StillRunning),
+            })
+            .collect()
+    }
+}
+
+impl Drop for LinkScope {
+    fn drop(&mut self) {
+        if !self.shared.state.lock().unwrap().closed {
+            self.close_within(DEFAULT_SCOPE_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spawn_scoped, LinkScope, ThreadOutcome};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_close_reports_how_each_thread_ended() {
+        let scope = LinkScope::new();
+        let handle = scope.handle();
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = stopped.clone();
+        spawn_scoped(
+            "listener",
+            Some("COM1".into()),
+            None,
+            Some(&handle),
+            move || stop.store(true, Ordering::Release),
+            move || {
+                while !stopped.load(Ordering::Acquire) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            },
+        );
+        spawn_scoped("tx", None, None, Some(&handle), || {}, || panic!("boom"));
+        spawn_scoped(
+            "stuck",
+            None,
+            None,
+            Some(&handle),
+            || {},
+            || thread::sleep(Duration::from_millis(500)),
+        );
+
+        let reports = scope.close(Duration::from_millis(100));
+        let outcomes: Vec<_> = reports
+            .iter()
+            .map(|report| (report.thread, report.outcome.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("listener", ThreadOutcome::Exited),
+                ("tx", ThreadOutcome::Panicked("boom".into())),
+                ("stuck", ThreadOutcome::StillRunning),
+            ]
+        );
+        assert_eq!(reports[0].link.as_deref(), Some("COM1"));
+    }
+
+    #[test]
+    fn test_threads_that_returned_before_close_are_forgotten() {
+        let scope = LinkScope::new();
+        let handle = scope.handle();
+
+        spawn_scoped("listener", None, None, Some(&handle), || {}, || {})
+            .join()
+            .unwrap();
+        assert_eq!(scope.running(), 0);
+        assert!(scope.close(Duration::from_millis(100)).is_empty());
+    }
+
+    #[cfg(feature = "serial")]
+    #[test]
+    fn test_link_threads_are_stopped_by_the_scope() {
+        let scope = LinkScope::new();
+        let mut serial = crate::FlemSerial::<64>::from_transport(std::io::Cursor::new(Vec::new()));
+        serial.set_scope(&scope);
+        let _rx = serial.listen().unwrap();
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.pack();
+        serial.send_queued(&packet).unwrap();
+        assert_eq!(scope.running(), 2);
+
+        let reports = scope.close(Duration::from_secs(1));
+        assert!(reports
+            .iter()
+            .all(|report| report.outcome == ThreadOutcome::Exited));
+        assert_eq!(reports.len(), 2);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::inventory::{AdapterInfo, DeviceIdentity};
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+static SESSION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Identity of a logical connection. A session is created with its
+/// [crate::FlemSerial] and survives calls to `connect`, so the packet
+/// indices keep counting across transport reconnects.
+#[derive(Debug)]
+pub struct Session {
+    id: u64,
+    rx_index: AtomicU64,
+    tx_index: AtomicU64,
+    tx_bytes: AtomicU64,
+    /// Microseconds since the Unix epoch, 0 if nothing was received yet.
+    last_rx: AtomicU64,
+    identity: Mutex<Option<DeviceIdentity>>,
+    label: Mutex<Option<String>>,
+    adapter: Mutex<Option<AdapterInfo>>,
+}
+
+/// Session ID and per-direction packet index attached to a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionStamp {
+    pub session: u64,
+    pub index: u64,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        let counter = SESSION_COUNTER.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            id: nanos ^ ((process::id() as u64) << 32) ^ counter.rotate_left(48),
+            rx_index: AtomicU64::new(0),
+            tx_index: AtomicU64::new(0),
+            tx_bytes: AtomicU64::new(0),
+            last_rx: AtomicU64::new(0),
+            identity: Mutex::new(None),
+            label: Mutex::new(None),
+            adapter: Mutex::new(None),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Number of packets received over the lifetime of the session.
+    pub fn rx_packets(&self) -> u64 {
+        self.rx_index.load(Ordering::Relaxed)
+    }
+
+    /// Number of packets sent over the lifetime of the session.
+    pub fn tx_packets(&self) -> u64 {
+        self.tx_index.load(Ordering::Relaxed)
+    }
+
+    /// Bytes sent over the lifetime of the session.
+    pub fn tx_bytes(&self) -> u64 {
+        self.tx_bytes.load(Ordering::Relaxed)
+    }
+
+    /// When the last packet was received.
+    pub fn last_rx(&self) -> Option<SystemTime> {
+        match self.last_rx.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(UNIX_EPOCH + Duration::from_micros(micros)),
+        }
+    }
+
+    /// Identity from the last ID response seen during the session.
+    pub fn identity(&self) -> Option<DeviceIdentity> {
+        self.identity.lock().unwrap().clone()
+    }
+
+    /// Human readable name of the link, see [crate::FlemSerial::set_label].
+    pub fn label(&self) -> Option<String> {
+        self.label.lock().unwrap().clone()
+    }
+
+    #[cfg(feature = "link")]
+    pub(crate) fn set_label(&self, label: &str) {
+        *self.label.lock().unwrap() = Some(label.to_string());
+    }
+
+    /// Adapter report from the last time the link was opened.
+    pub fn adapter(&self) -> Option<AdapterInfo> {
+        self.adapter.lock().unwrap().clone()
+    }
+
+    #[cfg(feature = "link")]
+    pub(crate) fn set_adapter(&self, adapter: AdapterInfo) {
+        *self.adapter.lock().unwrap() = Some(adapter);
+    }
+
+    #[cfg(feature = "link")]
+    pub(crate) fn set_identity(&self, identity: DeviceIdentity) {
+        *self.identity.lock().unwrap() = Some(identity);
+    }
+
+    #[cfg(feature = "link")]
+    pub(crate) fn next_rx(&self) -> SessionStamp {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        self.last_rx.store(now, Ordering::Relaxed);
+
+        SessionStamp {
+            session: self.id,
+            index: self.rx_index.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Counts a sent packet of `bytes` bytes on the wire.
+    #[cfg(feature = "link")]
+    pub(crate) fn next_tx(&self, bytes: usize) -> SessionStamp {
+        self.tx_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        SessionStamp {
+            session: self.id,
+            index: self.tx_index.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Session;
+
+    #[test]
+    fn test_sessions_get_distinct_ids() {
+        let ids: std::collections::HashSet<u64> = (0..100).map(|_| Session::new().id()).collect();
+        assert_eq!(ids.len(), 100);
+    }
+
+    #[cfg(all(unix, feature = "link"))]
+    #[test]
+    fn test_session_and_packet_indices_survive_a_reconnect() {
+        use crate::{
+            capture::{Direction, MultiLinkCapture},
+            capture_file::{read_capture, CaptureFormat},
+            test_support::SharedBuffer,
+            virtual_port::VirtualDevice,
+            FlemSerial,
+        };
+        use std::time::Duration;
+
+        let buffer = SharedBuffer::default();
+        let mut serial = FlemSerial::<64>::new();
+        serial.set_capture("arm", MultiLinkCapture::from_writer(buffer.clone()));
+        let session = serial.session().id();
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(0x30);
+        packet.pack();
+
+        // The same logical device seen through two transports in turn
+        let first = VirtualDevice::<64>::echo().unwrap();
+        let second = VirtualDevice::<64>::echo().unwrap();
+        for device in [&first, &second] {
+            serial
+                .connect(&device.port_name().to_string(), 115200)
+                .unwrap();
+            let rx = serial.listen().unwrap();
+            serial.send(&packet).unwrap();
+            rx.recv_timeout(Duration::from_secs(1)).unwrap();
+            serial.unlisten();
+        }
+
+        let stats = serial.stats();
+        assert_eq!(serial.session().id(), session);
+        assert_eq!(stats.session_id, session);
+        assert_eq!((stats.session_rx_packets, stats.session_tx_packets), (2, 2));
+
+        let records = read_capture(&buffer.contents()[..], CaptureFormat::Text).unwrap();
+        let mut stamps: Vec<_> = records
+            .iter()
+            .map(|record| {
+                let stamp = record.stamp.unwrap();
+                assert_eq!(stamp.session, session);
+                (record.direction, stamp.index)
+            })
+            .collect();
+        stamps.sort_by_key(|(direction, index)| (*direction == Direction::Rx, *index));
+        assert_eq!(
+            stamps,
+            [
+                (Direction::Tx, 0),
+                (Direction::Tx, 1),
+                (Direction::Rx, 0),
+                (Direction::Rx, 1)
+            ]
+        );
+    }
+}
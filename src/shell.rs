@@ -0,0 +1,177 @@
+use crate::{
+    client::{CallError, RequestClient},
+    matching::ResponseMatcher,
+    FlemRx, FlemSerial,
+};
+use std::{collections::VecDeque, time::Duration};
+
+/// Commands kept by [CommandShell::history] unless changed.
+pub const DEFAULT_HISTORY_LENGTH: usize = 100;
+
+/// Request codes of a firmware's text console: commands are sent with
+/// `command` and the device answers with a packet carrying `reply`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShellCodes {
+    pub command: u8,
+    pub reply: u8,
+}
+
+struct ReplyCode(u8);
+
+impl<const T: usize> ResponseMatcher<T> for ReplyCode {
+    fn matches(&self, _request: &flem::Packet<T>, response: &flem::Packet<T>) -> bool {
+        response.get_request() == self.0
+    }
+}
+
+/// A synchronous text console over a listening link. Each command is sent
+/// as the UTF-8 payload of one packet and the reply is one packet of UTF-8
+/// text, with trailing NULs and line endings removed.
+pub struct CommandShell<'a, const T: usize> {
+    client: RequestClient<'a, T>,
+    codes: ShellCodes,
+    history: VecDeque<String>,
+    history_length: usize,
+}
+
+impl<'a, const T: usize> CommandShell<'a, T> {
+    pub fn new(
+        serial: &'a mut FlemSerial<T>,
+        rx: &'a FlemRx<T>,
+        codes: ShellCodes,
+        timeout: Duration,
+    ) -> Self {
+        let mut client = RequestClient::new(serial, rx, timeout);
+        client.set_matcher(ReplyCode(codes.reply));
+
+        Self {
+            client,
+            codes,
+            history: VecDeque::new(),
+            history_length: DEFAULT_HISTORY_LENGTH,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.client.set_timeout(timeout);
+    }
+
+    /// Caps how many commands [CommandShell::history] keeps, dropping the
+    /// oldest first.
+    pub fn set_history_length(&mut self, history_length: usize) {
+        self.history_length = history_length;
+        while self.history.len() > history_length {
+            self.history.pop_front();
+        }
+    }
+
+    /// Sends `command` and waits for the reply. Commands that don't fit in a
+    /// packet fail with [CallError::SendFailed], replies that aren't UTF-8
+    /// with [CallError::DecodeFailed].
+    pub fn execute(&mut self, command: &str) -> Result<String, CallError> {
+        let command = command.trim();
+        if self.history_length > 0 && self.history.back().map(String::as_str) != Some(command) {
+            if self.history.len() == self.history_length {
+                self.history.pop_front();
+            }
+            self.history.push_back(command.to_string());
+        }
+
+        let reply: String = self.client.call(self.codes.command, command.as_bytes())?;
+        Ok(reply.trim_end_matches(['\0', '\r', '\n']).to_string())
+    }
+
+    /// Previously executed commands, oldest first. Repeats of the previous
+    /// command are only kept once.
+    pub fn history(&self) -> impl Iterator<Item = &str> {
+        self.history.iter().map(String::as_str)
+    }
+
+    /// Packets that arrived while waiting for replies but were not replies.
+    pub fn take_unsolicited(&mut self) -> Vec<flem::Packet<T>> {
+        self.client.take_unsolicited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandShell, ShellCodes};
+    use crate::FlemSerial;
+    use std::{
+        collections::VecDeque,
+        io::{self, Read, Write},
+        time::Duration,
+    };
+
+    const CODES: ShellCodes = ShellCodes {
+        command: 0x40,
+        reply: 0x41,
+    };
+
+    /// Answers every command packet with "ok <command>\r\n".
+    struct ConsoleDevice {
+        incoming: flem::Packet<64>,
+        outgoing: VecDeque<u8>,
+    }
+
+    impl ConsoleDevice {
+        fn new() -> Self {
+            Self {
+                incoming: flem::Packet::new(),
+                outgoing: VecDeque::new(),
+            }
+        }
+    }
+
+    impl Read for ConsoleDevice {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.outgoing.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.outgoing.len());
+            for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for ConsoleDevice {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                if let flem::Status::PacketReceived = self.incoming.add_byte(*byte) {
+                    let command = String::from_utf8(self.incoming.get_data().to_vec()).unwrap();
+                    let mut reply = flem::Packet::<64>::new();
+                    reply.set_request(CODES.reply);
+                    reply
+                        .add_data(format!("ok {}\r\n", command).as_bytes())
+                        .unwrap();
+                    reply.pack();
+                    self.outgoing.extend(reply.bytes());
+                    self.incoming.reset_lazy();
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_commands_get_text_replies_and_history() {
+        let mut serial = FlemSerial::<64>::from_transport(ConsoleDevice::new());
+        let rx = serial.listen().unwrap();
+
+        {
+            let mut shell = CommandShell::new(&mut serial, &rx, CODES, Duration::from_secs(1));
+            assert_eq!(shell.execute("status").unwrap(), "ok status");
+            assert_eq!(shell.execute("status").unwrap(), "ok status");
+            assert_eq!(shell.execute(" led on\n").unwrap(), "ok led on");
+            assert_eq!(shell.history().collect::<Vec<_>>(), ["status", "led on"]);
+        }
+
+        serial.unlisten();
+    }
+}
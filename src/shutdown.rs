@@ -0,0 +1,144 @@
+use crate::{hooks::AbortReason, stats::LinkStats, FlemSerial, FlemSerialError};
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The final state of a link stopped by [FlemSerial::shutdown].
+#[derive(Debug)]
+pub struct ShutdownReport {
+    /// Counters of the latest listener once it stopped. Read errors, resync
+    /// errors, validation failures and queue drops are only counted while
+    /// the link runs, this is where they add up.
+    pub stats: LinkStats,
+    /// Why the latest listener stopped before it was asked to, if it did.
+    pub abort: Option<AbortReason>,
+    /// First error writing packets from [FlemSerial::send_queued] that
+    /// [FlemSerial::flush_tx] hadn't reported yet.
+    pub tx_error: Option<FlemSerialError>,
+}
+
+/// Why [FlemSerial::shutdown] didn't finish within its timeout.
+#[derive(Debug)]
+pub enum ShutdownError {
+    /// `unwritten` queued packets were still waiting to be written. The
+    /// TX thread keeps writing them in the background.
+    TxTimedOut { unwritten: usize },
+    /// The listener was told to stop but was still running.
+    ListenerTimedOut,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownError::TxTimedOut { unwritten } => {
+                write!(f, "{} queued packets were not written in time", unwritten)
+            }
+            ShutdownError::ListenerTimedOut => write!(f, "listener did not stop in time"),
+        }
+    }
+}
+
+impl Error for ShutdownError {}
+
+impl<const T: usize> FlemSerial<T> {
+    /// Writes the packets still queued by [FlemSerial::send_queued], stops
+    /// every listener and waits for the latest one to exit, all within
+    /// `timeout`. Returns what the link recorded along the way, including
+    /// errors that were only counted or reported to the abort hook.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<ShutdownReport, ShutdownError> {
+        let deadline = Instant::now() + timeout;
+
+        let tx_error = match self.tx_queue.as_ref() {
+            Some(queue) => match queue.flush_timeout(timeout) {
+                Ok(result) => result.err(),
+                Err(unwritten) => return Err(ShutdownError::TxTimedOut { unwritten }),
+            },
+            None => None,
+        };
+
+        self.unlisten();
+        if let Some(exit) = self.listener_exit.take() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !exit.wait(remaining) {
+                return Err(ShutdownError::ListenerTimedOut);
+            }
+        }
+
+        let abort = self
+            .listener_shared
+            .as_ref()
+            .and_then(|shared| shared.abort.lock().unwrap().clone());
+        Ok(ShutdownReport {
+            stats: self.stats(),
+            abort,
+            tx_error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShutdownError;
+    use crate::FlemSerial;
+    use std::{
+        io::{self, Cursor},
+        time::Duration,
+    };
+
+    #[test]
+    fn test_shutdown_reports_what_the_listener_counted() {
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.pack();
+        let mut bytes = vec![0x00];
+        bytes.extend_from_slice(event.bytes());
+
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(bytes));
+        let rx = serial.listen().unwrap();
+        rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        serial.send_queued(&event).unwrap();
+
+        let report = serial.shutdown(Duration::from_secs(1)).unwrap();
+        assert_eq!(report.stats.rx_packets, 1);
+        assert_eq!(report.stats.resync_errors, 1);
+        assert_eq!(report.abort, None);
+        assert!(report.tx_error.is_none());
+        // The listener has exited
+        assert!(rx.recv().is_err());
+    }
+
+    /// Never finishes a write.
+    struct Stalled;
+
+    impl io::Read for Stalled {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for Stalled {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            std::thread::sleep(Duration::from_millis(200));
+            Ok(0)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_shutdown_gives_up_on_stalled_writes() {
+        let mut serial = FlemSerial::<64>::from_transport(Stalled);
+        let mut packet = flem::Packet::<64>::new();
+        packet.pack();
+        serial.send_queued(&packet).unwrap();
+
+        assert!(matches!(
+            serial.shutdown(Duration::from_millis(20)),
+            Err(ShutdownError::TxTimedOut { unwritten: 1 })
+        ));
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{uart_errors::UartErrorCounts, warmup::WarmupStats};
+use std::{ops::Range, time::Duration};
+#[cfg(feature = "link")]
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+/// Number of buckets in the received payload size histogram.
+pub const PAYLOAD_HISTOGRAM_BUCKETS: usize = 16;
+
+/// Smoothing window of [LinkRates] unless changed with
+/// [crate::FlemSerial::set_rate_window].
+pub const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Counts are folded into the averages at most this often, so bursts of
+/// packets read together don't register as extreme rates.
+#[cfg(feature = "link")]
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Counters updated by the listener thread.
+#[cfg(feature = "link")]
+pub(crate) struct LinkCounters {
+    pub(crate) rx_bytes: AtomicU64,
+    pub(crate) rx_packets: AtomicU64,
+    pub(crate) read_errors: AtomicU64,
+    pub(crate) queue_drops: AtomicU64,
+    pub(crate) resync_errors: AtomicU64,
+    pub(crate) suppressed_resync_errors: AtomicU64,
+    pub(crate) checksum_errors: AtomicU64,
+    pub(crate) suppressed_checksum_errors: AtomicU64,
+    pub(crate) dropped_while_degraded: AtomicU64,
+    pub(crate) validation_failures: AtomicU64,
+    payload_max: usize,
+    payload_bucket_width: usize,
+    payload_buckets: Vec<AtomicU64>,
+    payload_full: AtomicU64,
+}
+
+#[cfg(feature = "link")]
+impl LinkCounters {
+    /// Creates counters for a link whose packets carry at most
+    /// `payload_max` bytes of data.
+    pub(crate) fn new(payload_max: usize) -> Self {
+        Self {
+            rx_bytes: AtomicU64::new(0),
+            rx_packets: AtomicU64::new(0),
+            read_errors: AtomicU64::new(0),
+            queue_drops: AtomicU64::new(0),
+            resync_errors: AtomicU64::new(0),
+            suppressed_resync_errors: AtomicU64::new(0),
+            checksum_errors: AtomicU64::new(0),
+            suppressed_checksum_errors: AtomicU64::new(0),
+            dropped_while_degraded: AtomicU64::new(0),
+            validation_failures: AtomicU64::new(0),
+            payload_max,
+            payload_bucket_width: (payload_max + 1).div_ceil(PAYLOAD_HISTOGRAM_BUCKETS),
+            payload_buckets: (0..PAYLOAD_HISTOGRAM_BUCKETS)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            payload_full: AtomicU64::new(0),
+        }
+    }
+
+    /// Records the payload length of a received packet.
+    pub(crate) fn record_payload(&self, length: usize) {
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+        let bucket = (length / self.payload_bucket_width).min(PAYLOAD_HISTOGRAM_BUCKETS - 1);
+        self.payload_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        if length >= self.payload_max {
+            self.payload_full.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> LinkStats {
+        LinkStats {
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            queue_drops: self.queue_drops.load(Ordering::Relaxed),
+            resync_errors: self.resync_errors.load(Ordering::Relaxed),
+            suppressed_resync_errors: self.suppressed_resync_errors.load(Ordering::Relaxed),
+            checksum_errors: self.checksum_errors.load(Ordering::Relaxed),
+            suppressed_checksum_errors: self.suppressed_checksum_errors.load(Ordering::Relaxed),
+            dropped_while_degraded: self.dropped_while_degraded.load(Ordering::Relaxed),
+            validation_failures: self.validation_failures.load(Ordering::Relaxed),
+            label: None,
+            session_id: 0,
+            session_rx_packets: 0,
+            session_tx_packets: 0,
+            session_tx_bytes: 0,
+            warmup: WarmupStats::default(),
+            uart_errors: None,
+            payload_sizes: PayloadHistogram {
+                bucket_width: self.payload_bucket_width,
+                counts: self
+                    .payload_buckets
+                    .iter()
+                    .map(|count| count.load(Ordering::Relaxed))
+                    .collect(),
+                full: self.payload_full.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+/// A point in time copy of the link counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Bytes read from the port.
+    pub rx_bytes: u64,
+    /// Packets parsed with a valid header and checksum.
+    pub rx_packets: u64,
+    /// Failed reads, not counting timeouts on a quiet line.
+    pub read_errors: u64,
+    /// Valid packets lost because the receive handle was dropped.
+    pub queue_drops: u64,
+    /// Bytes discarded while searching for a packet header.
+    pub resync_errors: u64,
+    /// Bytes discarded while searching for a packet header during the
+    /// startup grace window, not counted in `resync_errors`.
+    pub suppressed_resync_errors: u64,
+    /// Packets with a valid header whose checksum did not match. Unlike
+    /// resync errors, which usually point at line noise or a wrong baud,
+    /// these usually point at the firmware building packets incorrectly.
+    pub checksum_errors: u64,
+    /// Checksum mismatches during the startup grace window, not counted in
+    /// `checksum_errors`.
+    pub suppressed_checksum_errors: u64,
+    /// Valid packets discarded while the link was degraded, see
+    /// [crate::degrade::DegradePolicy].
+    pub dropped_while_degraded: u64,
+    /// Packets rejected by the link's [crate::validation::RxValidator].
+    pub validation_failures: u64,
+    /// Label of the link, see [crate::FlemSerial::set_label].
+    pub label: Option<String>,
+    /// ID of the link's session, see [crate::session::Session].
+    pub session_id: u64,
+    /// Packets received over the whole session, across reconnects.
+    pub session_rx_packets: u64,
+    /// Packets sent over the whole session, across reconnects.
+    pub session_tx_packets: u64,
+    /// Bytes sent over the whole session, across reconnects.
+    pub session_tx_bytes: u64,
+    /// Startup timings since the last connect.
+    pub warmup: WarmupStats,
+    /// Parity, framing and overrun errors counted by the driver, None where
+    /// the platform or adapter doesn't report them. Updated once a second.
+    pub uart_errors: Option<UartErrorCounts>,
+    /// Sizes of the payloads received so far.
+    pub payload_sizes: PayloadHistogram,
+}
+
+impl LinkStats {
+    /// Adds the per-connection counters of an earlier connection, keeping
+    /// the label, session and warm-up fields of `self`.
+    #[cfg(feature = "link")]
+    pub(crate) fn add_counters(&mut self, earlier: &LinkStats) {
+        self.rx_bytes += earlier.rx_bytes;
+        self.rx_packets += earlier.rx_packets;
+        self.read_errors += earlier.read_errors;
+        self.queue_drops += earlier.queue_drops;
+        self.resync_errors += earlier.resync_errors;
+        self.suppressed_resync_errors += earlier.suppressed_resync_errors;
+        self.checksum_errors += earlier.checksum_errors;
+        self.suppressed_checksum_errors += earlier.suppressed_checksum_errors;
+        self.dropped_while_degraded += earlier.dropped_while_degraded;
+        self.validation_failures += earlier.validation_failures;
+
+        let payload_sizes = &mut self.payload_sizes;
+        if payload_sizes.counts.len() < earlier.payload_sizes.counts.len() {
+            payload_sizes.bucket_width = earlier.payload_sizes.bucket_width;
+            payload_sizes
+                .counts
+                .resize(earlier.payload_sizes.counts.len(), 0);
+        }
+        for (count, earlier) in payload_sizes
+            .counts
+            .iter_mut()
+            .zip(earlier.payload_sizes.counts.iter())
+        {
+            *count += earlier;
+        }
+        payload_sizes.full += earlier.payload_sizes.full;
+    }
+}
+
+/// Received packet and byte rates, smoothed with an exponential moving
+/// average so they can be shown on a dashboard as is.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkRates {
+    pub packets_per_second: f64,
+    /// All bytes read from the port, including ones that were not part of a
+    /// valid packet.
+    pub bytes_per_second: f64,
+    /// Time constant of the moving average.
+    pub window: Duration,
+}
+
+/// Exponential moving average of the packet and byte rates.
+#[cfg(feature = "link")]
+#[derive(Debug)]
+pub(crate) struct RateMeter {
+    window: Duration,
+    last_sample: Option<Instant>,
+    pending_packets: u64,
+    pending_bytes: u64,
+    packets_per_second: f64,
+    bytes_per_second: f64,
+}
+
+#[cfg(feature = "link")]
+impl RateMeter {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_sample: None,
+            pending_packets: 0,
+            pending_bytes: 0,
+            packets_per_second: 0.0,
+            bytes_per_second: 0.0,
+        }
+    }
+
+    fn sample(&mut self, now: Instant) {
+        let Some(last_sample) = self.last_sample else {
+            self.last_sample = Some(now);
+            return;
+        };
+        let elapsed = now.saturating_duration_since(last_sample);
+        if elapsed < RATE_SAMPLE_INTERVAL {
+            return;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        // Weight by elapsed time so irregular samples average correctly
+        let alpha = 1.0 - (-seconds / self.window.as_secs_f64().max(f64::EPSILON)).exp();
+        self.packets_per_second +=
+            alpha * (self.pending_packets as f64 / seconds - self.packets_per_second);
+        self.bytes_per_second +=
+            alpha * (self.pending_bytes as f64 / seconds - self.bytes_per_second);
+        self.pending_packets = 0;
+        self.pending_bytes = 0;
+        self.last_sample = Some(now);
+    }
+
+    pub(crate) fn record_bytes(&mut self, bytes: usize, now: Instant) {
+        self.sample(now);
+        self.pending_bytes += bytes as u64;
+    }
+
+    pub(crate) fn record_packet(&mut self, now: Instant) {
+        self.sample(now);
+        self.pending_packets += 1;
+    }
+
+    pub(crate) fn rates(&mut self, now: Instant) -> LinkRates {
+        self.sample(now);
+        LinkRates {
+            packets_per_second: self.packets_per_second,
+            bytes_per_second: self.bytes_per_second,
+            window: self.window,
+        }
+    }
+}
+
+/// Bytes waiting in the operating system's serial buffers.
+///
+/// A growing `bytes_to_read` means the host is not keeping up with the
+/// device, while an empty input buffer and no packets means the device is
+/// silent. A growing `bytes_to_write` means the device or adapter is not
+/// draining what the host sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PortBuffers {
+    pub bytes_to_read: u32,
+    pub bytes_to_write: u32,
+}
+
+/// Levels of the modem status lines driven by the device or adapter, see
+/// [crate::FlemSerial::read_modem_lines]. True means asserted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModemLines {
+    /// Clear to send.
+    pub cts: bool,
+    /// Data set ready.
+    pub dsr: bool,
+    /// Carrier detect.
+    pub cd: bool,
+    /// Ring indicator.
+    pub ri: bool,
+}
+
+/// Histogram of received payload sizes with [PAYLOAD_HISTOGRAM_BUCKETS]
+/// equally sized buckets spanning `0..=T`.
+///
+/// Mostly small payloads suggest `T` is oversized, while a large `full`
+/// count suggests the device is splitting data that does not fit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayloadHistogram {
+    pub bucket_width: usize,
+    pub counts: Vec<u64>,
+    /// Packets whose payload filled the whole packet.
+    pub full: u64,
+}
+
+impl PayloadHistogram {
+    /// Range of payload lengths counted by bucket `index`.
+    pub fn bucket_range(&self, index: usize) -> Range<usize> {
+        index * self.bucket_width..(index + 1) * self.bucket_width
+    }
+
+    /// Total number of packets recorded.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{LinkCounters, RateMeter, PAYLOAD_HISTOGRAM_BUCKETS};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_payload_histogram_buckets() {
+        let counters = LinkCounters::new(512);
+        counters.record_payload(0);
+        counters.record_payload(32);
+        counters.record_payload(511);
+        counters.record_payload(512);
+
+        let histogram = counters.snapshot().payload_sizes;
+
+        assert_eq!(histogram.counts.len(), PAYLOAD_HISTOGRAM_BUCKETS);
+        assert_eq!(histogram.bucket_width, 33);
+        assert_eq!(histogram.counts[0], 2);
+        assert_eq!(histogram.counts[PAYLOAD_HISTOGRAM_BUCKETS - 1], 2);
+        assert_eq!(histogram.full, 1);
+        assert_eq!(histogram.total(), 4);
+        assert_eq!(histogram.bucket_range(1), 33..66);
+    }
+
+    #[test]
+    fn test_rates_settle_and_decay() {
+        let start = Instant::now();
+        let mut meter = RateMeter::new(Duration::from_secs(1));
+
+        // 100 packets of 10 bytes a second for 10 s
+        for tick in 0..1000 {
+            let now = start + Duration::from_millis(tick * 10);
+            meter.record_bytes(10, now);
+            meter.record_packet(now);
+        }
+        let steady = meter.rates(start + Duration::from_secs(10));
+        assert!((steady.packets_per_second - 100.0).abs() < 1.0);
+        assert!((steady.bytes_per_second - 1000.0).abs() < 10.0);
+
+        // Silence for 5 time constants
+        let idle = meter.rates(start + Duration::from_secs(15));
+        assert!(idle.packets_per_second < 1.0);
+    }
+}
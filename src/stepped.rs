@@ -0,0 +1,217 @@
+use crate::{
+    events::LinkEvent,
+    listener::{Delivery, Listener},
+    stats::{LinkRates, LinkStats},
+};
+use std::sync::{
+    atomic::Ordering,
+    mpsc::{Receiver, TryRecvError},
+};
+
+/// The receive engine without its thread, see
+/// [crate::FlemSerial::listen_stepped].
+///
+/// Each call to `step` runs exactly the work one pass of the listener
+/// thread would: backpressure, due busy retries and batch flushing, then
+/// parsing of the given bytes. Combined with [crate::clock::MockClock] this
+/// gives fully deterministic interleavings of sends, receives and timeouts.
+pub struct SteppedRx<const T: usize> {
+    listener: Listener<T>,
+    delivery: Delivery<T>,
+    rx_packet_queue: Receiver<flem::Packet<T>>,
+    events: Receiver<LinkEvent>,
+}
+
+impl<const T: usize> SteppedRx<T> {
+    pub(crate) fn new(
+        listener: Listener<T>,
+        delivery: Delivery<T>,
+        rx_packet_queue: Receiver<flem::Packet<T>>,
+        events: Receiver<LinkEvent>,
+    ) -> Self {
+        Self {
+            listener,
+            delivery,
+            rx_packet_queue,
+            events,
+        }
+    }
+
+    /// Processes `bytes` as if they had just been read from the port.
+    pub fn step(&mut self, bytes: &[u8]) {
+        let _ = self.listener.poll(&mut self.delivery);
+        let _ = self.listener.process(bytes, &mut self.delivery);
+    }
+
+    /// Runs only the time based work, as a pass with nothing read would.
+    pub fn tick(&mut self) {
+        let _ = self.listener.poll(&mut self.delivery);
+    }
+
+    pub fn try_recv(&self) -> Result<flem::Packet<T>, TryRecvError> {
+        let packet = self.rx_packet_queue.try_recv()?;
+        self.listener
+            .shared
+            .queue_depth
+            .fetch_sub(1, Ordering::AcqRel);
+        Ok(packet)
+    }
+
+    /// Receives every packet delivered so far.
+    pub fn drain(&self) -> Vec<flem::Packet<T>> {
+        std::iter::from_fn(|| self.try_recv().ok()).collect()
+    }
+
+    pub fn events(&self) -> &Receiver<LinkEvent> {
+        &self.events
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.listener.shared.queue_depth.load(Ordering::Acquire)
+    }
+
+    /// Snapshot of the link counters.
+    pub fn stats(&self) -> LinkStats {
+        self.listener.shared.snapshot()
+    }
+
+    /// Smoothed receive rates, see [crate::FlemSerial::set_rate_window].
+    pub fn rates(&self) -> LinkRates {
+        self.listener.shared.rates()
+    }
+}
+
+/// There is no thread to stop, but the listener is marked stopped and
+/// exited like a threaded one, for anything waiting on it.
+impl<const T: usize> Drop for SteppedRx<T> {
+    fn drop(&mut self) {
+        self.listener.shared.stop.store(true, Ordering::Release);
+        self.listener.shared.exit.set();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FlemSerial;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_packets_are_only_processed_when_stepped() {
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        let mut rx = serial.listen_stepped().unwrap();
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        packet.pack();
+        let bytes = packet.bytes().to_vec();
+        let (first, rest) = bytes.split_at(bytes.len() / 2);
+
+        rx.step(first);
+        assert!(rx.drain().is_empty());
+
+        rx.step(rest);
+        let received = rx.drain();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].get_data(), [1, 2, 3]);
+        assert_eq!(rx.queue_depth(), 0);
+    }
+
+    #[test]
+    fn test_dropping_marks_the_listener_exited() {
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        let rx = serial.listen_stepped().unwrap();
+        let exit = rx.listener.shared.exit.clone();
+        assert!(!exit.wait(std::time::Duration::ZERO));
+
+        drop(rx);
+        assert!(exit.wait(std::time::Duration::ZERO));
+    }
+
+    #[test]
+    fn test_label_is_reported_in_stats() {
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        let rx = serial.listen_stepped().unwrap();
+        assert_eq!(rx.stats().label, None);
+
+        serial.set_label("left-arm-controller");
+        assert_eq!(serial.label().as_deref(), Some("left-arm-controller"));
+        assert_eq!(rx.stats().label.as_deref(), Some("left-arm-controller"));
+    }
+
+    #[test]
+    fn test_link_counters_in_stats() {
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        let mut rx = serial.listen_stepped().unwrap();
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        packet.pack();
+        let bytes = packet.bytes().to_vec();
+
+        rx.step(&[0x00, 0x01]);
+        rx.step(&bytes);
+        serial.send(&packet).unwrap();
+
+        let stats = serial.stats();
+        assert_eq!(stats.rx_bytes, 2 + bytes.len() as u64);
+        assert_eq!(stats.rx_packets, 1);
+        assert_eq!(stats.resync_errors, 2);
+        assert_eq!(stats.session_tx_bytes, bytes.len() as u64);
+        assert_eq!(stats, rx.stats());
+    }
+
+    #[test]
+    fn test_grace_window_keeps_resync_and_checksum_errors_apart() {
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        packet.pack();
+        let mut corrupt = packet.bytes().to_vec();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        serial.set_startup_grace(std::time::Duration::from_secs(60), false);
+        let mut rx = serial.listen_stepped().unwrap();
+        rx.step(&[0x00, 0x01]);
+        rx.step(&corrupt);
+        let stats = rx.stats();
+        assert_eq!(stats.suppressed_resync_errors, 2);
+        assert_eq!(stats.suppressed_checksum_errors, 1);
+        assert_eq!((stats.resync_errors, stats.checksum_errors), (0, 0));
+
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        let mut rx = serial.listen_stepped().unwrap();
+        rx.step(&[0x00, 0x01]);
+        rx.step(&corrupt);
+        let stats = rx.stats();
+        assert_eq!((stats.resync_errors, stats.checksum_errors), (2, 1));
+        assert_eq!(stats.suppressed_resync_errors, 0);
+        assert_eq!(stats.suppressed_checksum_errors, 0);
+    }
+
+    #[test]
+    fn test_boot_banner_is_captured_only_within_the_grace_window() {
+        let clock = std::sync::Arc::new(crate::clock::MockClock::new());
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        serial.set_clock(clock.clone());
+        serial.set_startup_grace(std::time::Duration::from_secs(1), true);
+        let mut rx = serial.listen_stepped().unwrap();
+
+        rx.step(b"BOOT v1.2\r\n");
+        let stats = rx.stats();
+        assert_eq!(stats.suppressed_resync_errors, 11);
+        assert_eq!(stats.resync_errors, 0);
+
+        clock.advance(std::time::Duration::from_secs(2));
+        rx.step(b"ok");
+        let stats = rx.stats();
+        assert_eq!(stats.suppressed_resync_errors, 11);
+        assert_eq!(stats.resync_errors, 2);
+
+        let banner = rx.listener.shared.banner.lock().unwrap().clone();
+        assert_eq!(banner, b"BOOT v1.2\r\n");
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{
+    events::LinkEvent,
+    hooks::{AbortHook, AbortReport},
+    options::ConnectOptions,
+    reconnect::{ReconnectBackoff, ReconnectPolicy},
+    stats::LinkStats,
+    FlemSerial, FlemSerialError,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How often the supervisor checks for shutdown while waiting.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Connects the supervised link, again after every disconnect.
+type Connect<const T: usize> =
+    Box<dyn FnMut(&mut FlemSerial<T>) -> Result<(), FlemSerialError> + Send>;
+
+/// Everything that happens to a supervised link, in place of errors.
+#[derive(Debug, Clone)]
+pub enum SupervisorEvent {
+    Connected,
+    /// Connecting failed, the next attempt follows the reconnect policy.
+    ConnectFailed {
+        attempt: u32,
+    },
+    /// The listener stopped, for example because the device was unplugged.
+    Disconnected,
+    Link(LinkEvent),
+    /// An internal thread died, see [crate::hooks].
+    Aborted(AbortReport),
+    /// `run` returned after a shutdown request.
+    Stopped,
+}
+
+/// Requests a supervised `run` loop to exit. Can be sent to other threads,
+/// for example a Windows service control handler.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    shutdown: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+}
+
+/// Keeps one link up for the lifetime of a service or daemon.
+///
+/// Reconnects forever following the policy's backoff (`max_attempts` is
+/// ignored), reports failures as [SupervisorEvent]s instead of returning or
+/// panicking, and keeps statistics across reconnects.
+pub struct Supervisor<const T: usize> {
+    serial: FlemSerial<T>,
+    connect: Connect<T>,
+    policy: ReconnectPolicy,
+    shutdown: ShutdownHandle,
+    events: Sender<SupervisorEvent>,
+    retired_stats: Option<LinkStats>,
+    current_stats: Option<LinkStats>,
+}
+
+impl<const T: usize> Supervisor<T> {
+    /// Supervises `serial`, which should be configured but not connected.
+    pub fn new(
+        serial: FlemSerial<T>,
+        port_name: &str,
+        baud: u32,
+        options: ConnectOptions,
+        policy: ReconnectPolicy,
+    ) -> (Self, Receiver<SupervisorEvent>) {
+        let port_name = port_name.to_string();
+        Self::with_connect(
+            serial,
+            Box::new(move |serial| serial.connect_with_options(&port_name, baud, &options)),
+            policy,
+        )
+    }
+
+    fn with_connect(
+        mut serial: FlemSerial<T>,
+        connect: Connect<T>,
+        policy: ReconnectPolicy,
+    ) -> (Self, Receiver<SupervisorEvent>) {
+        let (events, rx) = mpsc::channel();
+
+        let abort_events = Mutex::new(events.clone());
+        let hook: AbortHook = Arc::new(move |report: &AbortReport| {
+            if let Ok(events) = abort_events.lock() {
+                let _ = events.send(SupervisorEvent::Aborted(report.clone()));
+            }
+        });
+        serial.set_abort_hook(hook);
+
+        let supervisor = Self {
+            serial,
+            connect,
+            policy: ReconnectPolicy {
+                max_attempts: None,
+                ..policy
+            },
+            shutdown: ShutdownHandle {
+                shutdown: Arc::new(AtomicBool::new(false)),
+            },
+            events,
+            retired_stats: None,
+            current_stats: None,
+        };
+
+        (supervisor, rx)
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Statistics over every connection made so far, as of the last packet
+    /// handled.
+    pub fn stats(&self) -> LinkStats {
+        let mut stats = self.current_stats.clone().unwrap_or_default();
+        if let Some(retired) = self.retired_stats.as_ref() {
+            stats.add_counters(retired);
+        }
+        stats
+    }
+
+    fn emit(&self, event: SupervisorEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Sleeps until `until`, returning early on shutdown.
+    fn wait_until(&self, until: Instant) {
+        while !self.shutdown.is_shutdown() {
+            let now = Instant::now();
+            if now >= until {
+                break;
+            }
+            thread::sleep((until - now).min(SHUTDOWN_POLL_INTERVAL));
+        }
+    }
+
+    /// Connects, listens and hands every received packet to `on_packet`
+    /// along with the link, for replies. Only returns after
+    /// [ShutdownHandle::shutdown].
+    pub fn run<F>(&mut self, mut on_packet: F)
+    where
+        F: FnMut(&mut FlemSerial<T>, flem::Packet<T>),
+    {
+        let mut backoff = ReconnectBackoff::new(self.policy);
+        let mut attempt = 0;
+
+        while !self.shutdown.is_shutdown() {
+            let connected = (self.connect)(&mut self.serial)
+                .ok()
+                .and_then(|_| self.serial.listen().ok());
+
+            let rx = match connected {
+                Some(rx) => rx,
+                None => {
+                    attempt += 1;
+                    self.emit(SupervisorEvent::ConnectFailed { attempt });
+                    if backoff.next_attempt().is_none() {
+                        backoff.on_disconnect(Instant::now());
+                    } else {
+                        backoff.on_failure(Instant::now());
+                    }
+                    if let Some(next_attempt) = backoff.next_attempt() {
+                        self.wait_until(next_attempt);
+                    }
+                    continue;
+                }
+            };
+
+            attempt = 0;
+            backoff.on_success();
+            self.emit(SupervisorEvent::Connected);
+
+            while !self.shutdown.is_shutdown() {
+                for event in rx.events().try_iter() {
+                    self.emit(SupervisorEvent::Link(event));
+                }
+
+                match rx.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                    Ok(packet) => on_packet(&mut self.serial, packet),
+                    Err(RecvTimeoutError::Timeout) if !rx.join_handle().is_finished() => {}
+                    Err(_) => break,
+                }
+                self.current_stats = Some(rx.stats());
+            }
+
+            self.serial.unlisten();
+            let mut stats = rx.stats();
+            if let Some(retired) = self.retired_stats.as_ref() {
+                stats.add_counters(retired);
+            }
+            self.retired_stats = Some(stats);
+            self.current_stats = None;
+            // A panicked listener was already reported through the abort hook
+            let _ = rx.join();
+
+            if !self.shutdown.is_shutdown() {
+                self.emit(SupervisorEvent::Disconnected);
+                backoff.on_disconnect(Instant::now());
+                if let Some(next_attempt) = backoff.next_attempt() {
+                    self.wait_until(next_attempt);
+                }
+            }
+        }
+
+        self.emit(SupervisorEvent::Stopped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Supervisor, SupervisorEvent};
+    use crate::{port::OpenPort, reconnect::ReconnectPolicy, FlemSerial, FlemSerialError};
+    use std::{
+        collections::VecDeque,
+        io::{self, Read, Write},
+        time::Duration,
+    };
+
+    /// Delivers its bytes, then fails every read like an unplugged port.
+    struct Connection(VecDeque<u8>);
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            let count = buf.len().min(self.0.len());
+            for (slot, byte) in buf.iter_mut().zip(self.0.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reconnects_and_keeps_stats_across_connections() {
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.pack();
+        // A garbage byte ahead of the event, counted as a resync error
+        let mut bytes = vec![0x00];
+        bytes.extend_from_slice(event.bytes());
+
+        let mut connects = 0;
+        let connect = Box::new(move |serial: &mut FlemSerial<64>| {
+            connects += 1;
+            // The second attempt fails, the third connects again
+            if connects == 2 {
+                return Err(FlemSerialError::NoDeviceFoundByThatName);
+            }
+            serial.attach(OpenPort::from_stream(Connection(
+                bytes.iter().copied().collect(),
+            )));
+            Ok(())
+        });
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            ..ReconnectPolicy::default()
+        };
+        let (mut supervisor, events) =
+            Supervisor::with_connect(FlemSerial::<64>::new(), connect, policy);
+
+        let shutdown = supervisor.shutdown_handle();
+        let mut packets = 0;
+        supervisor.run(|_, _| {
+            packets += 1;
+            if packets == 2 {
+                shutdown.shutdown();
+            }
+        });
+
+        let sequence: Vec<String> = events
+            .try_iter()
+            .filter(|event| {
+                !matches!(
+                    event,
+                    SupervisorEvent::Link(_) | SupervisorEvent::Aborted(_)
+                )
+            })
+            .map(|event| format!("{:?}", event))
+            .collect();
+        assert_eq!(
+            sequence,
+            [
+                "Connected",
+                "Disconnected",
+                "ConnectFailed { attempt: 1 }",
+                "Connected",
+                "Stopped"
+            ]
+        );
+
+        let stats = supervisor.stats();
+        assert_eq!(stats.rx_packets, 2);
+        assert_eq!(stats.resync_errors, 2);
+    }
+}
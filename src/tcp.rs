@@ -0,0 +1,224 @@
+use crate::transport::not_serial;
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+/// How long [crate::FlemSerial::from_tcp] waits for each resolved address
+/// to accept the connection.
+pub const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A TCP connection to a serial-to-Ethernet converter, presented as a
+/// [SerialPort]. Unlike [crate::transport::SharedTransport], clones are
+/// independent socket handles, so sends never wait on the listener's read.
+pub(crate) struct TcpPort {
+    stream: TcpStream,
+    address: String,
+}
+
+/// Connects to the first address `address` resolves to that accepts within
+/// `timeout`.
+pub(crate) fn connect_stream(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "address did not resolve");
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => {
+                // Packets are small, don't wait to coalesce them
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+impl TcpPort {
+    /// Connects to the first address `address` resolves to that accepts
+    /// within `timeout`.
+    pub(crate) fn connect(address: &str, timeout: Duration) -> io::Result<Self> {
+        let stream = connect_stream(address, timeout)?;
+        stream.set_read_timeout(Some(Duration::from_millis(10)))?;
+        Ok(Self {
+            stream,
+            address: address.to_string(),
+        })
+    }
+}
+
+impl Read for TcpPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf)? {
+            // The listener takes 0 bytes for a quiet line, report the close
+            0 if !buf.is_empty() => Err(io::ErrorKind::ConnectionAborted.into()),
+            count => Ok(count),
+        }
+    }
+}
+
+impl Write for TcpPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl SerialPort for TcpPort {
+    fn name(&self) -> Option<String> {
+        Some(self.address.clone())
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        not_serial()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        not_serial()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        not_serial()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        not_serial()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.stream
+            .read_timeout()
+            .ok()
+            .flatten()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.stream
+            .set_read_timeout(Some(timeout).filter(|timeout| !timeout.is_zero()))
+            .map_err(Into::into)
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn clear(&self, _buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(TcpPort {
+            stream: self.stream.try_clone()?,
+            address: self.address.clone(),
+        }))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        not_serial()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FlemSerial;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_listen_and_send_over_tcp() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.add_data(&[1, 2, 3]).unwrap();
+        event.pack();
+        let event_bytes = event.bytes().to_vec();
+
+        let converter = thread::spawn(move || {
+            let (mut socket, _) = server.accept().unwrap();
+            socket.write_all(&event_bytes).unwrap();
+            let mut sent = [0u8; 8];
+            socket.read_exact(&mut sent).unwrap();
+            sent
+        });
+
+        let mut serial = FlemSerial::<64>::from_tcp(&address).unwrap();
+        let rx = serial.listen().unwrap();
+        let received = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received.get_data(), [1, 2, 3]);
+
+        let mut id = flem::Packet::<64>::new();
+        id.set_request(flem::Request::ID);
+        id.pack();
+        serial.send(&id).unwrap();
+        assert_eq!(converter.join().unwrap(), id.bytes());
+
+        serial.unlisten();
+    }
+}
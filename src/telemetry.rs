@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+/// Type of a field inside a telemetry or log entry. All multi-byte values
+/// are little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    U8,
+    U16,
+    U32,
+    I16,
+    I32,
+    F32,
+}
+
+impl FieldKind {
+    pub fn size(&self) -> usize {
+        match self {
+            FieldKind::U8 => 1,
+            FieldKind::U16 | FieldKind::I16 => 2,
+            FieldKind::U32 | FieldKind::I32 | FieldKind::F32 => 4,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Value {
+        match self {
+            FieldKind::U8 => Value::Unsigned(bytes[0] as u64),
+            FieldKind::U16 => Value::Unsigned(u16::from_le_bytes([bytes[0], bytes[1]]) as u64),
+            FieldKind::U32 => {
+                Value::Unsigned(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64)
+            }
+            FieldKind::I16 => Value::Signed(i16::from_le_bytes([bytes[0], bytes[1]]) as i64),
+            FieldKind::I32 => {
+                Value::Signed(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64)
+            }
+            FieldKind::F32 => {
+                Value::Float(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+}
+
+/// Layout of one kind of entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub name: String,
+    pub fields: Vec<(String, FieldKind)>,
+}
+
+impl Schema {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Appends a field, builder style.
+    pub fn field(mut self, name: &str, kind: FieldKind) -> Self {
+        self.fields.push((name.to_string(), kind));
+        self
+    }
+
+    /// Size of an entry body in bytes, not counting the type tag.
+    pub fn size(&self) -> usize {
+        self.fields.iter().map(|(_, kind)| kind.size()).sum()
+    }
+}
+
+/// A decoded entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub schema: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// No schema is registered for the entry's type tag.
+    UnknownEntryType { tag: u8, offset: usize },
+    /// The buffer ended in the middle of an entry.
+    Truncated { offset: usize },
+}
+
+/// Maps an entry type tag to the schema used to decode it.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<u8, Schema>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, tag: u8, schema: Schema) -> Option<Schema> {
+        self.schemas.insert(tag, schema)
+    }
+
+    pub fn get(&self, tag: u8) -> Option<&Schema> {
+        self.schemas.get(&tag)
+    }
+
+    /// Decodes one entry laid out as a type tag followed by the schema's
+    /// fields.
+    pub fn decode_entry(&self, bytes: &[u8]) -> Result<(Record, usize), DecodeError> {
+        self.decode_at(bytes, 0)
+    }
+
+    /// Decodes back to back entries until `bytes` is used up, passing each
+    /// record to `sink`. Stops at the first entry that can't be decoded.
+    pub fn decode_all<F: FnMut(Record)>(
+        &self,
+        bytes: &[u8],
+        mut sink: F,
+    ) -> Result<(), DecodeError> {
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (record, used) = self.decode_at(bytes, offset)?;
+            sink(record);
+            offset += used;
+        }
+        Ok(())
+    }
+
+    fn decode_at(&self, bytes: &[u8], offset: usize) -> Result<(Record, usize), DecodeError> {
+        let tag = bytes[offset..]
+            .first()
+            .copied()
+            .ok_or(DecodeError::Truncated { offset })?;
+        let schema = self
+            .schemas
+            .get(&tag)
+            .ok_or(DecodeError::UnknownEntryType { tag, offset })?;
+
+        let body = &bytes[offset + 1..];
+        if body.len() < schema.size() {
+            return Err(DecodeError::Truncated { offset });
+        }
+
+        let mut position = 0;
+        let mut fields = Vec::with_capacity(schema.fields.len());
+        for (name, kind) in schema.fields.iter() {
+            fields.push((name.clone(), kind.decode(&body[position..])));
+            position += kind.size();
+        }
+
+        Ok((
+            Record {
+                schema: schema.name.clone(),
+                fields,
+            },
+            1 + schema.size(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeError, FieldKind, Schema, SchemaRegistry, Value};
+
+    #[test]
+    fn test_decode_entries() {
+        let mut registry = SchemaRegistry::new();
+        registry.register(
+            1,
+            Schema::new("temperature")
+                .field("sensor", FieldKind::U8)
+                .field("celsius", FieldKind::I16),
+        );
+
+        let mut records = Vec::new();
+        registry
+            .decode_all(&[1, 2, 0xfe, 0xff, 1, 3, 10, 0], |record| {
+                records.push(record)
+            })
+            .unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].schema, "temperature");
+        assert_eq!(records[0].fields[1].1, Value::Signed(-2));
+        assert_eq!(records[1].fields[0].1, Value::Unsigned(3));
+
+        assert_eq!(
+            registry.decode_all(&[2], |_| {}),
+            Err(DecodeError::UnknownEntryType { tag: 2, offset: 0 })
+        );
+        assert_eq!(
+            registry.decode_all(&[1, 2], |_| {}),
+            Err(DecodeError::Truncated { offset: 0 })
+        );
+    }
+}
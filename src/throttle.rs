@@ -0,0 +1,405 @@
+#[cfg(feature = "link")]
+use crate::clock::{Clock, SystemClock};
+#[cfg(feature = "link")]
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::time::Duration;
+#[cfg(feature = "link")]
+use std::{
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Bytes written at once by [TxThrottle::bytes_per_second].
+pub const DEFAULT_BURST: usize = 16;
+
+/// Paces transmission for targets with small UART FIFOs that drop bytes
+/// when the host sends faster than they can drain them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxThrottle {
+    /// Average transmit rate, or None for no limit.
+    pub bytes_per_second: Option<u32>,
+    /// Most bytes written at once while `bytes_per_second` is set, usually
+    /// the target's FIFO depth.
+    pub burst: usize,
+    /// Minimum time between the end of one packet and the start of the
+    /// next.
+    pub packet_gap: Duration,
+}
+
+impl TxThrottle {
+    /// Limits the average rate to `rate` bytes per second, written
+    /// [DEFAULT_BURST] bytes at a time.
+    pub fn bytes_per_second(rate: u32) -> Self {
+        Self {
+            bytes_per_second: Some(rate),
+            burst: DEFAULT_BURST,
+            packet_gap: Duration::ZERO,
+        }
+    }
+
+    /// Leaves at least `gap` between packets.
+    pub fn packet_gap(gap: Duration) -> Self {
+        Self {
+            bytes_per_second: None,
+            burst: DEFAULT_BURST,
+            packet_gap: gap,
+        }
+    }
+
+    /// Time the rate limit allows for `bytes`.
+    #[cfg(feature = "link")]
+    fn duration_of(&self, bytes: usize) -> Duration {
+        match self.bytes_per_second {
+            Some(rate) => Duration::from_secs_f64(bytes as f64 / f64::from(rate.max(1))),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// Enforces a [TxThrottle] on the bytes that reach the wire, see
+/// [PacedPort].
+#[cfg(feature = "link")]
+pub(crate) struct Pacer {
+    throttle: Option<TxThrottle>,
+    /// Earliest time the next write may start.
+    ready_at: Option<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+#[cfg(feature = "link")]
+impl Default for Pacer {
+    fn default() -> Self {
+        Self {
+            throttle: None,
+            ready_at: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// Shared by every clone of a link's port, so writes from `send`, the TX
+/// queue and the listener are paced together.
+#[cfg(feature = "link")]
+pub(crate) type SharedPacer = Arc<Mutex<Pacer>>;
+
+#[cfg(feature = "link")]
+impl Pacer {
+    pub(crate) fn set_throttle(&mut self, throttle: Option<TxThrottle>) {
+        self.throttle = throttle;
+        self.ready_at = None;
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    fn wait(&self) {
+        if let Some(ready_at) = self.ready_at {
+            let wait = ready_at.saturating_duration_since(self.clock.now());
+            if !wait.is_zero() {
+                self.clock.sleep(wait);
+            }
+        }
+    }
+
+    /// Passes `bytes` to `write`, in bursts spaced out by the throttle.
+    /// Returns how many bytes were written like [io::Write::write]: an
+    /// error only if nothing was, otherwise the bytes written before it.
+    /// The packet gap follows once all of `bytes` are written.
+    pub(crate) fn write<F>(&mut self, bytes: &[u8], mut write: F) -> io::Result<usize>
+    where
+        F: FnMut(&[u8]) -> io::Result<usize>,
+    {
+        let Some(throttle) = self.throttle else {
+            return write(bytes);
+        };
+
+        let burst = match throttle.bytes_per_second {
+            Some(_) => throttle.burst.max(1),
+            None => bytes.len().max(1),
+        };
+        let mut written = 0;
+        while written < bytes.len() {
+            self.wait();
+            let end = (written + burst).min(bytes.len());
+            match write(&bytes[written..end]) {
+                Ok(0) => break,
+                Ok(count) => {
+                    written += count;
+                    self.ready_at = Some(self.clock.now() + throttle.duration_of(count));
+                }
+                Err(error) if written == 0 => return Err(error),
+                Err(_) => break,
+            }
+        }
+
+        // The gap starts once the rate limit considers the packet sent
+        if written == bytes.len() {
+            if let Some(ready_at) = self.ready_at.as_mut() {
+                *ready_at += throttle.packet_gap;
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// The bottom layer of every port the crate opens, below framing and
+/// XON/XOFF stuffing, so the throttle paces the bytes that actually go on
+/// the wire. Every write above it is one packet, or one frame of it, so
+/// the packet gap is left between writes.
+#[cfg(feature = "link")]
+pub(crate) struct PacedPort {
+    inner: Box<dyn SerialPort>,
+    pacer: SharedPacer,
+}
+
+#[cfg(feature = "link")]
+impl PacedPort {
+    pub(crate) fn new(inner: Box<dyn SerialPort>, pacer: SharedPacer) -> Self {
+        Self { inner, pacer }
+    }
+}
+
+#[cfg(feature = "link")]
+impl Read for PacedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+#[cfg(feature = "link")]
+impl Write for PacedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        self.pacer.lock().unwrap().write(buf, |bytes| {
+            inner.write_all(bytes)?;
+            // Bytes handed to the OS are on their way, make the rate limit
+            // count from when they leave
+            inner.flush()?;
+            Ok(bytes.len())
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "link")]
+impl SerialPort for PacedPort {
+    fn name(&self) -> Option<String> {
+        self.inner.name()
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        self.inner.baud_rate()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        self.inner.data_bits()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        self.inner.flow_control()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        self.inner.parity()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        self.inner.stop_bits()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.inner.timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.inner.set_baud_rate(baud_rate)
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.inner.set_data_bits(data_bits)
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.inner.set_flow_control(flow_control)
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.inner.set_parity(parity)
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.inner.set_stop_bits(stop_bits)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_request_to_send(level)
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_data_terminal_ready(level)
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.inner.read_clear_to_send()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.inner.read_data_set_ready()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.inner.read_ring_indicator()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.inner.read_carrier_detect()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_read()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_write()
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        self.inner.clear(buffer_to_clear)
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(PacedPort::new(
+            self.inner.try_clone()?,
+            self.pacer.clone(),
+        )))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.inner.set_break()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.inner.clear_break()
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{Pacer, TxThrottle};
+    use crate::clock::MockClock;
+    use std::{sync::Arc, time::Duration};
+
+    fn pacer(clock: &Arc<MockClock>, throttle: Option<TxThrottle>) -> Pacer {
+        let mut pacer = Pacer::default();
+        pacer.set_clock(clock.clone());
+        pacer.set_throttle(throttle);
+        pacer
+    }
+
+    #[test]
+    fn test_writes_are_paced_by_rate_and_gap() {
+        let clock = Arc::new(MockClock::new());
+        let mut pacer = pacer(
+            &clock,
+            Some(TxThrottle {
+                bytes_per_second: Some(1000),
+                burst: 4,
+                packet_gap: Duration::from_millis(20),
+            }),
+        );
+
+        let mut writes = Vec::new();
+        let mut write = |bytes: &[u8]| {
+            writes.push((clock.elapsed(), bytes.len()));
+            Ok(bytes.len())
+        };
+        assert_eq!(pacer.write(&[0; 10], &mut write).unwrap(), 10);
+        assert_eq!(pacer.write(&[0; 2], &mut write).unwrap(), 2);
+
+        let ms = Duration::from_millis;
+        assert_eq!(writes, [(ms(0), 4), (ms(4), 4), (ms(8), 2), (ms(30), 2)]);
+    }
+
+    #[test]
+    fn test_without_a_throttle_bytes_are_written_at_once() {
+        let clock = Arc::new(MockClock::new());
+        let mut pacer = pacer(&clock, None);
+        let mut writes = Vec::new();
+        pacer
+            .write(&[0; 100], |bytes| {
+                writes.push(bytes.len());
+                Ok(bytes.len())
+            })
+            .unwrap();
+        pacer.write(&[0; 100], |bytes| Ok(bytes.len())).unwrap();
+
+        assert_eq!(writes, [100]);
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_framed_packets_are_paced_as_one_frame() {
+        use super::PacedPort;
+        use crate::{
+            framing::{FramedPort, Framing},
+            port::OpenPort,
+        };
+        use std::{
+            io::{self, Write},
+            sync::Mutex,
+        };
+
+        #[derive(Clone, Default)]
+        struct Wire(Arc<Mutex<Vec<Vec<u8>>>>);
+
+        impl io::Read for Wire {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Ok(0)
+            }
+        }
+
+        impl io::Write for Wire {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().push(buf.to_vec());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let clock = Arc::new(MockClock::new());
+        let pacer = Arc::new(Mutex::new(pacer(
+            &clock,
+            Some(TxThrottle {
+                bytes_per_second: Some(1000),
+                burst: 4,
+                packet_gap: Duration::ZERO,
+            }),
+        )));
+        let wire = Wire::default();
+        let paced = PacedPort::new(OpenPort::from_stream(wire.clone()).port, pacer);
+        let mut framed = FramedPort::new(Box::new(paced), Framing::Cobs);
+
+        let packet = [1, 0, 2, 3, 4, 5, 6];
+        framed.write_all(&packet).unwrap();
+
+        // One COBS frame, split into bursts only below the framing
+        let bursts = wire.0.lock().unwrap().clone();
+        assert!(bursts.iter().all(|burst| burst.len() <= 4));
+        let frame: Vec<u8> = bursts.concat();
+        assert_eq!(frame.iter().filter(|byte| **byte == 0).count(), 1);
+        assert_eq!(*frame.last().unwrap(), 0);
+        assert!(clock.elapsed() > Duration::ZERO);
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Which clocks are read when timestamping packets.
+///
+/// Monotonic time is right for latency math since it never jumps, while
+/// wall-clock time is needed to line captures up with other systems.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampMode {
+    Monotonic,
+    WallClock,
+    #[default]
+    Both,
+}
+
+/// A moment read from the clocks selected by a [TimestampMode].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub monotonic: Option<Instant>,
+    pub wall: Option<SystemTime>,
+}
+
+impl Timestamp {
+    pub fn now(mode: TimestampMode) -> Self {
+        let monotonic = match mode {
+            TimestampMode::Monotonic | TimestampMode::Both => Some(Instant::now()),
+            TimestampMode::WallClock => None,
+        };
+        let wall = match mode {
+            TimestampMode::WallClock | TimestampMode::Both => Some(SystemTime::now()),
+            TimestampMode::Monotonic => None,
+        };
+
+        Self { monotonic, wall }
+    }
+
+    /// Wall-clock time as microseconds since the Unix epoch.
+    pub fn wall_micros(&self) -> Option<u128> {
+        self.wall
+            .and_then(|wall| wall.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_micros())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Timestamp, TimestampMode};
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_only_the_selected_clocks_are_read() {
+        let monotonic = Timestamp::now(TimestampMode::Monotonic);
+        assert!(monotonic.monotonic.is_some());
+        assert_eq!((monotonic.wall, monotonic.wall_micros()), (None, None));
+
+        let wall = Timestamp::now(TimestampMode::WallClock);
+        assert!(wall.monotonic.is_none());
+        let micros = wall.wall_micros().unwrap();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_micros();
+        assert!(micros <= now && now - micros < 1_000_000);
+
+        let both = Timestamp::now(TimestampMode::default());
+        assert!(both.monotonic.is_some() && both.wall.is_some());
+    }
+}
@@ -0,0 +1,177 @@
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use std::{
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Adapts any `Read + Write` byte stream to the [SerialPort] interface the
+/// FLEM engine runs on. Clones share the same stream.
+pub(crate) struct SharedTransport<S> {
+    stream: Arc<Mutex<S>>,
+    timeout: Duration,
+}
+
+impl<S> SharedTransport<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: Arc::new(Mutex::new(stream)),
+            timeout: Duration::from_millis(10),
+        }
+    }
+}
+
+pub(crate) fn not_serial<T>() -> serialport::Result<T> {
+    Err(serialport::Error::new(
+        serialport::ErrorKind::Unknown,
+        "not supported by this transport",
+    ))
+}
+
+fn poisoned() -> io::Error {
+    io::Error::other("transport lock poisoned")
+}
+
+impl<S: Read> Read for SharedTransport<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.lock().map_err(|_| poisoned())?.read(buf)
+    }
+}
+
+impl<S: Write> Write for SharedTransport<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.lock().map_err(|_| poisoned())?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.lock().map_err(|_| poisoned())?.flush()
+    }
+}
+
+impl<S: Read + Write + Send + 'static> SerialPort for SharedTransport<S> {
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        not_serial()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        not_serial()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        not_serial()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        not_serial()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        not_serial()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        not_serial()
+    }
+
+    fn clear(&self, _buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(SharedTransport {
+            stream: self.stream.clone(),
+            timeout: self.timeout,
+        }))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        not_serial()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        not_serial()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedTransport;
+    use serialport::SerialPort;
+    use std::io::{Cursor, Read, Write};
+
+    #[test]
+    fn test_clones_share_stream() {
+        let transport = SharedTransport::new(Cursor::new(Vec::new()));
+        let mut writer = transport.try_clone().unwrap();
+        writer.write_all(&[1, 2, 3]).unwrap();
+
+        let mut reader = transport.try_clone().unwrap();
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).unwrap();
+
+        // Both clones share one cursor, which already sits at the end
+        assert!(buffer.is_empty());
+        assert_eq!(transport.stream.lock().unwrap().get_ref(), &[1, 2, 3]);
+    }
+}
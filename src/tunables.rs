@@ -0,0 +1,122 @@
+use crate::events::EventRateLimit;
+#[cfg(feature = "link")]
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+
+/// Default for [Tunables::idle_poll_interval].
+pub const DEFAULT_IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Settings a running listener picks up without being restarted, see
+/// [crate::FlemSerial::reconfigure].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tunables {
+    /// Longest the listener sleeps between reads of an idle port, see
+    /// [crate::options::ListenOptions], and how long it sleeps between
+    /// reads while the link is degraded.
+    pub idle_poll_interval: Duration,
+    pub event_rate_limit: EventRateLimit,
+    /// High and low watermarks of the receive queue, see
+    /// [crate::backpressure::Backpressure]. None keeps the current ones,
+    /// ignored if backpressure is not enabled.
+    pub queue_watermarks: Option<(usize, usize)>,
+    /// Interval between keepalives, see
+    /// [crate::keepalive::KeepalivePolicy]. None keeps the current one,
+    /// ignored if keepalives are not enabled.
+    pub keepalive_interval: Option<Duration>,
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            idle_poll_interval: DEFAULT_IDLE_POLL_INTERVAL,
+            event_rate_limit: EventRateLimit::default(),
+            queue_watermarks: None,
+            keepalive_interval: None,
+        }
+    }
+}
+
+/// The latest [Tunables] and how often they were replaced, shared between a
+/// link and its worker threads.
+#[cfg(feature = "link")]
+#[derive(Debug, Default)]
+pub(crate) struct TunablesCell {
+    generation: AtomicU64,
+    tunables: Mutex<Tunables>,
+}
+
+#[cfg(feature = "link")]
+impl TunablesCell {
+    pub(crate) fn get(&self) -> Tunables {
+        *self.tunables.lock().unwrap()
+    }
+
+    /// Replaces all tunables at once.
+    pub(crate) fn publish(&self, tunables: Tunables) {
+        let mut current = self.tunables.lock().unwrap();
+        *current = tunables;
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    pub(crate) fn watch(self: &Arc<Self>) -> TunablesWatch {
+        let tunables = self.tunables.lock().unwrap();
+        TunablesWatch {
+            seen: self.generation.load(Ordering::Acquire),
+            current: *tunables,
+            cell: self.clone(),
+        }
+    }
+}
+
+/// A worker thread's view of a [TunablesCell].
+#[cfg(feature = "link")]
+pub(crate) struct TunablesWatch {
+    cell: Arc<TunablesCell>,
+    seen: u64,
+    current: Tunables,
+}
+
+#[cfg(feature = "link")]
+impl TunablesWatch {
+    pub(crate) fn current(&self) -> &Tunables {
+        &self.current
+    }
+
+    /// Returns the tunables if they were replaced since the last call. Costs
+    /// a single atomic load while nothing changed.
+    pub(crate) fn changed(&mut self) -> Option<Tunables> {
+        let generation = self.cell.generation.load(Ordering::Acquire);
+        if generation == self.seen {
+            return None;
+        }
+        self.seen = generation;
+        self.current = self.cell.get();
+        Some(self.current)
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{Tunables, TunablesCell};
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn test_watch_sees_each_replacement_once() {
+        let cell = Arc::new(TunablesCell::default());
+        let mut watch = cell.watch();
+        assert_eq!(watch.changed(), None);
+
+        let tunables = Tunables {
+            idle_poll_interval: Duration::from_millis(2),
+            queue_watermarks: Some((64, 16)),
+            ..Tunables::default()
+        };
+        cell.publish(tunables);
+        assert_eq!(watch.changed(), Some(tunables));
+        assert_eq!(watch.changed(), None);
+        assert_eq!(watch.current().idle_poll_interval, Duration::from_millis(2));
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{
+    capture::Direction,
+    clock::Clock,
+    error::FlemSerialError,
+    hooks::AbortHook,
+    keepalive::{KeepaliveLedger, KeepalivePolicy},
+    replay::SharedReplay,
+    retry::{self, BusyRetryState, TxRetry},
+    scope::{self, ScopeHandle},
+    session::Session,
+    warmup::WarmupTracker,
+    FlemCapture, FlemSerialTx,
+};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// Writes a packet to the port and does the bookkeeping that follows a
+/// successful write. Cheap to build, so each queued packet carries the
+/// settings that were current when it was queued.
+pub(crate) struct TxWriter<const T: usize> {
+    pub(crate) port: FlemSerialTx,
+    pub(crate) retry: TxRetry,
+    pub(crate) busy_retry: Arc<Mutex<BusyRetryState<T>>>,
+    pub(crate) warmup: Arc<WarmupTracker>,
+    pub(crate) session: Arc<Session>,
+    pub(crate) capture: FlemCapture,
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Where a queued packet goes if writing it fails, see
+    /// [crate::FlemSerial::set_replay_on_reconnect].
+    pub(crate) replay: Option<SharedReplay<T>>,
+    /// Where requests with the keepalive's code are noted so the listener
+    /// can tell their responses from the keepalive's.
+    pub(crate) keepalive: Option<(KeepalivePolicy, Arc<KeepaliveLedger>)>,
+}
+
+impl<const T: usize> TxWriter<T> {
+    pub(crate) fn write(&self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        if let Some((policy, ledger)) = self.keepalive.as_ref() {
+            // Noted first, the response may be read before the write returns
+            if packet.get_request() == policy.request {
+                ledger.on_send(self.clock.now(), policy.interval);
+            }
+        }
+        self.resend(packet)?;
+        self.busy_retry.lock().unwrap().on_send(packet);
+        if packet.get_request() == flem::Request::ID {
+            self.warmup.on_id_sent(self.clock.now());
+        }
+        Ok(())
+    }
+
+    /// Writes and records a packet that was sent before, such as a busy
+    /// retry, leaving its retry count alone.
+    pub(crate) fn resend(&self, packet: &flem::Packet<T>) -> Result<(), FlemSerialError> {
+        let mut port = self
+            .port
+            .as_ref()
+            .ok_or(FlemSerialError::NotConnected)?
+            .lock()
+            .map_err(|_| FlemSerialError::WriteFailed(io::Error::other("port lock poisoned")))?;
+        retry::write_with_retry(port.as_mut(), packet.bytes(), &self.retry)
+            .map_err(FlemSerialError::WriteFailed)?;
+        drop(port);
+
+        self.record(packet);
+        Ok(())
+    }
+
+    /// Stamps the packet and adds it to the capture, if any.
+    pub(crate) fn record(&self, packet: &flem::Packet<T>) {
+        let stamp = self.session.next_tx(packet.bytes().len());
+        if let Some((device, capture)) = self.capture.as_ref() {
+            let _ = capture.record(device, Some(stamp), Direction::Tx, packet);
+        }
+    }
+}
+
+/// Order in which [crate::FlemSerial::send_queued_with_priority] packets
+/// are written. Packets of the same priority are written in the order they
+/// were queued.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TxPriority {
+    /// Bulk traffic, such as firmware images.
+    Low,
+    #[default]
+    Normal,
+    /// Control packets, such as an abort, written before anything else
+    /// waiting.
+    High,
+}
+
+impl TxPriority {
+    /// Position in [TxState::waiting].
+    fn index(self) -> usize {
+        match self {
+            TxPriority::High => 0,
+            TxPriority::Normal => 1,
+            TxPriority::Low => 2,
+        }
+    }
+}
+
+type Queued<const T: usize> = (TxWriter<T>, flem::Packet<T>);
+
+struct TxState<const T: usize> {
+    /// Waiting packets, highest priority first.
+    waiting: [VecDeque<Queued<T>>; 3],
+    /// Packets waiting or being written.
+    queued: usize,
+    error: Option<FlemSerialError>,
+    /// No more packets are accepted, the thread exits once the waiting
+    /// ones are written.
+    closed: bool,
+}
+
+impl<const T: usize> TxState<T> {
+    fn pop(&mut self) -> Option<Queued<T>> {
+        self.waiting.iter_mut().find_map(VecDeque::pop_front)
+    }
+}
+
+type Shared<const T: usize> = Arc<(Mutex<TxState<T>>, Condvar)>;
+
+fn close<const T: usize>(shared: &Shared<T>) {
+    let (state, changed) = &**shared;
+    state.lock().unwrap().closed = true;
+    changed.notify_all();
+}
+
+/// Closes the queue when the writer thread exits, even by panicking, so
+/// nothing waits for packets that will never be written.
+struct ExitGuard<const T: usize>(Shared<T>);
+
+impl<const T: usize> Drop for ExitGuard<T> {
+    fn drop(&mut self) {
+        let (state, changed) = &*self.0;
+        let mut state = state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.closed = true;
+        state.waiting.iter_mut().for_each(VecDeque::clear);
+        state.queued = 0;
+        changed.notify_all();
+    }
+}
+
+/// Packets waiting for the writer thread started by
+/// [crate::FlemSerial::send_queued]. The thread exits once the queue is
+/// dropped, or closed by its [crate::scope::LinkScope], and everything
+/// queued before has been written.
+pub(crate) struct TxQueue<const T: usize> {
+    shared: Shared<T>,
+}
+
+impl<const T: usize> TxQueue<T> {
+    pub(crate) fn spawn(
+        link: Option<String>,
+        abort_hook: Option<AbortHook>,
+        scope: Option<&ScopeHandle>,
+    ) -> Self {
+        let shared: Shared<T> = Arc::new((
+            Mutex::new(TxState {
+                waiting: Default::default(),
+                queued: 0,
+                error: None,
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let closing = shared.clone();
+        let stop = move || close(&closing);
+        let thread_shared = shared.clone();
+        scope::spawn_scoped("tx", link, abort_hook, scope, stop, move || {
+            let _guard = ExitGuard(thread_shared.clone());
+            let (state, changed) = &*thread_shared;
+            loop {
+                let next = {
+                    let mut state = changed
+                        .wait_while(state.lock().unwrap(), |state| {
+                            !state.closed && state.waiting.iter().all(VecDeque::is_empty)
+                        })
+                        .unwrap();
+                    state.pop()
+                };
+                let Some((writer, packet)) = next else {
+                    break;
+                };
+
+                let result = match (writer.write(&packet), writer.replay.clone()) {
+                    (Err(error), Some(replay)) => replay
+                        .lock()
+                        .unwrap()
+                        .hold(writer, packet)
+                        .map_err(|_| error),
+                    (result, _) => result,
+                };
+
+                let mut state = state.lock().unwrap();
+                state.queued -= 1;
+                if let Err(error) = result {
+                    state.error.get_or_insert(error);
+                }
+                if state.queued == 0 {
+                    changed.notify_all();
+                }
+            }
+        });
+
+        Self { shared }
+    }
+
+    pub(crate) fn push(
+        &self,
+        writer: TxWriter<T>,
+        packet: flem::Packet<T>,
+        priority: TxPriority,
+    ) -> Result<(), FlemSerialError> {
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().unwrap();
+        if state.closed {
+            return Err(FlemSerialError::WriteFailed(io::Error::other(
+                "TX thread exited",
+            )));
+        }
+        state.waiting[priority.index()].push_back((writer, packet));
+        state.queued += 1;
+        changed.notify_all();
+        Ok(())
+    }
+
+    /// Number of packets queued and not yet written.
+    pub(crate) fn len(&self) -> usize {
+        self.shared.0.lock().unwrap().queued
+    }
+
+    /// Waits until every queued packet has been written, then returns the
+    /// first write error since the last flush, if any.
+    pub(crate) fn flush(&self) -> Result<(), FlemSerialError> {
+        let (state, changed) = &*self.shared;
+        let mut state = changed
+            .wait_while(state.lock().unwrap(), |state| state.queued > 0)
+            .unwrap();
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `flush`, but gives up after `timeout`, returning the number of
+    /// packets still queued.
+    pub(crate) fn flush_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Result<(), FlemSerialError>, usize> {
+        let (state, changed) = &*self.shared;
+        let (mut state, _) = changed
+            .wait_timeout_while(state.lock().unwrap(), timeout, |state| state.queued > 0)
+            .unwrap();
+        if state.queued > 0 {
+            return Err(state.queued);
+        }
+        Ok(match state.error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        })
+    }
+}
+
+impl<const T: usize> Drop for TxQueue<T> {
+    fn drop(&mut self) {
+        close(&self.shared);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FlemSerial, TxPriority};
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Accepts writes slowly, like a full OS buffer.
+    struct SlowSink(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Read for SlowSink {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl io::Write for SlowSink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(50));
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_queued_sends_return_before_the_write() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut serial = FlemSerial::<64>::from_transport(SlowSink(written.clone()));
+
+        let mut expected = Vec::new();
+        let started = Instant::now();
+        for data in 0..3u8 {
+            let mut packet = flem::Packet::<64>::new();
+            packet.set_request(flem::Request::EVENT);
+            packet.add_data(&[data]).unwrap();
+            packet.pack();
+            expected.extend_from_slice(packet.bytes());
+            serial.send_queued(&packet).unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_millis(50));
+        assert!(serial.tx_queued() > 0);
+
+        serial.flush_tx().unwrap();
+        assert_eq!(serial.tx_queued(), 0);
+        assert_eq!(*written.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_high_priority_packets_overtake_bulk_traffic() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut serial = FlemSerial::<64>::from_transport(SlowSink(written.clone()));
+
+        let packet = |request: u8| {
+            let mut packet = flem::Packet::<64>::new();
+            packet.set_request(request);
+            packet.pack();
+            packet
+        };
+        for _ in 0..4 {
+            serial
+                .send_queued_with_priority(&packet(0x10), TxPriority::Low)
+                .unwrap();
+        }
+        serial.send_queued(&packet(0x20)).unwrap();
+        serial
+            .send_queued_with_priority(&packet(0x30), TxPriority::High)
+            .unwrap();
+        serial.flush_tx().unwrap();
+
+        let packet_len = packet(0).bytes().len();
+        let requests: Vec<u8> = written
+            .lock()
+            .unwrap()
+            .chunks(packet_len)
+            .map(|bytes| {
+                let mut packet = flem::Packet::<64>::new();
+                bytes.iter().for_each(|byte| {
+                    packet.add_byte(*byte);
+                });
+                packet.get_request()
+            })
+            .collect();
+        // Only the low priority packet already being written, if any, goes
+        // first
+        let position = |request| requests.iter().position(|r| *r == request).unwrap();
+        assert!(position(0x30) <= 1);
+        assert_eq!(position(0x20), position(0x30) + 1);
+        assert_eq!(requests[3..], [0x10, 0x10, 0x10]);
+    }
+}
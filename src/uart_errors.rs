@@ -0,0 +1,221 @@
+#[cfg(feature = "link")]
+use std::time::{Duration, Instant};
+
+/// How often the listener reads the driver's error counters.
+#[cfg(feature = "link")]
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Line errors counted by the UART driver since listening started. These
+/// point at electrical problems (noise, wrong baud or parity, a host that
+/// can't keep up) rather than protocol problems.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UartErrorCounts {
+    pub parity: u64,
+    pub framing: u64,
+    /// Bytes lost because the UART's hardware FIFO overflowed.
+    pub overrun: u64,
+    /// Bytes lost because the driver's receive buffer overflowed.
+    pub buffer_overrun: u64,
+}
+
+#[cfg(feature = "link")]
+impl UartErrorCounts {
+    fn since(&self, baseline: &UartErrorCounts) -> UartErrorCounts {
+        UartErrorCounts {
+            parity: self.parity.saturating_sub(baseline.parity),
+            framing: self.framing.saturating_sub(baseline.framing),
+            overrun: self.overrun.saturating_sub(baseline.overrun),
+            buffer_overrun: self.buffer_overrun.saturating_sub(baseline.buffer_overrun),
+        }
+    }
+
+    fn plus(&self, other: &UartErrorCounts) -> UartErrorCounts {
+        UartErrorCounts {
+            parity: self.parity + other.parity,
+            framing: self.framing + other.framing,
+            overrun: self.overrun + other.overrun,
+            buffer_overrun: self.buffer_overrun + other.buffer_overrun,
+        }
+    }
+}
+
+#[cfg(all(feature = "link", target_os = "linux"))]
+mod platform {
+    use super::UartErrorCounts;
+    use std::os::{
+        fd::{AsRawFd, BorrowedFd, OwnedFd},
+        raw::c_int,
+    };
+
+    /// `struct serial_icounter_struct` from linux/serial.h, which libc
+    /// doesn't declare.
+    #[repr(C)]
+    #[derive(Default)]
+    struct SerialIcounter {
+        cts: c_int,
+        dsr: c_int,
+        rng: c_int,
+        dcd: c_int,
+        rx: c_int,
+        tx: c_int,
+        frame: c_int,
+        overrun: c_int,
+        parity: c_int,
+        brk: c_int,
+        buf_overrun: c_int,
+        reserved: [c_int; 9],
+    }
+
+    /// A duplicate of an open port's descriptor. It shares the port's open
+    /// file description, so reading the counters never opens the device a
+    /// second time.
+    pub(crate) struct ErrorCounters(OwnedFd);
+
+    impl ErrorCounters {
+        pub(crate) fn of(port: &impl AsRawFd) -> Option<Self> {
+            // SAFETY: `port` owns the descriptor and keeps it open while
+            // borrowed here, and the borrow ends once it is duplicated.
+            let fd = unsafe { BorrowedFd::borrow_raw(port.as_raw_fd()) };
+            fd.try_clone_to_owned().ok().map(Self)
+        }
+
+        pub(crate) fn try_clone(&self) -> Option<Self> {
+            self.0.try_clone().ok().map(Self)
+        }
+
+        pub(super) fn read(&self) -> Option<UartErrorCounts> {
+            let mut counters = SerialIcounter::default();
+            // SAFETY: the descriptor is owned and open, and TIOCGICOUNT
+            // writes one serial_icounter_struct, which `counters` matches.
+            // USB CDC and many USB adapters don't implement it and fail.
+            let result = unsafe {
+                libc::ioctl(
+                    self.0.as_raw_fd(),
+                    libc::TIOCGICOUNT,
+                    &mut counters as *mut SerialIcounter,
+                )
+            };
+            if result != 0 {
+                return None;
+            }
+
+            Some(UartErrorCounts {
+                parity: counters.parity as u64,
+                framing: counters.frame as u64,
+                overrun: counters.overrun as u64,
+                buffer_overrun: counters.buf_overrun as u64,
+            })
+        }
+    }
+}
+
+#[cfg(all(feature = "link", not(target_os = "linux")))]
+mod platform {
+    use super::UartErrorCounts;
+
+    pub(crate) struct ErrorCounters;
+
+    impl ErrorCounters {
+        pub(crate) fn of<P>(_port: &P) -> Option<Self> {
+            None
+        }
+
+        pub(crate) fn try_clone(&self) -> Option<Self> {
+            None
+        }
+
+        pub(super) fn read(&self) -> Option<UartErrorCounts> {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+pub(crate) use platform::ErrorCounters;
+
+/// Periodically reads the driver's error counters for one port. Only
+/// created where the platform and driver expose them.
+#[cfg(feature = "link")]
+pub(crate) struct UartErrorPoller {
+    source: ErrorCounters,
+    baseline: UartErrorCounts,
+    /// Counts from before the port was last reopened.
+    carried: UartErrorCounts,
+    last_poll: Instant,
+}
+
+#[cfg(feature = "link")]
+impl UartErrorPoller {
+    /// Starts counting from the current values, adding them to `carried`.
+    pub(crate) fn open(
+        source: ErrorCounters,
+        carried: UartErrorCounts,
+        now: Instant,
+    ) -> Option<Self> {
+        let baseline = source.read()?;
+        Some(Self {
+            source,
+            baseline,
+            carried,
+            last_poll: now,
+        })
+    }
+
+    /// Counts since the poller was opened, if a poll was due and succeeded.
+    pub(crate) fn poll(&mut self, now: Instant) -> Option<UartErrorCounts> {
+        if now.saturating_duration_since(self.last_poll) < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = now;
+        Some(
+            self.source
+                .read()?
+                .since(&self.baseline)
+                .plus(&self.carried),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::{ErrorCounters, UartErrorCounts, UartErrorPoller};
+    use std::time::Instant;
+
+    #[test]
+    fn test_counts_are_relative_to_baseline() {
+        let baseline = UartErrorCounts {
+            parity: 2,
+            framing: 5,
+            overrun: 0,
+            buffer_overrun: 1,
+        };
+        let now = UartErrorCounts {
+            parity: 3,
+            framing: 5,
+            overrun: 7,
+            buffer_overrun: 1,
+        };
+        assert_eq!(
+            now.since(&baseline),
+            UartErrorCounts {
+                parity: 1,
+                framing: 0,
+                overrun: 7,
+                buffer_overrun: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_a_file_that_is_not_a_tty_has_no_counters() {
+        let file = std::fs::File::open("Cargo.toml").unwrap();
+        // Linux duplicates the descriptor, elsewhere there is nothing to read
+        if let Some(counters) = ErrorCounters::of(&file) {
+            assert!(counters.try_clone().is_some());
+            assert!(
+                UartErrorPoller::open(counters, UartErrorCounts::default(), Instant::now())
+                    .is_none()
+            );
+        }
+    }
+}
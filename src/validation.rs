@@ -0,0 +1,70 @@
+#[cfg(feature = "link")]
+use std::sync::{Arc, Mutex};
+
+/// Checks every received packet on the listener thread before it is
+/// delivered, for application invariants such as a CRC or signature in the
+/// payload. Rejected packets are counted in
+/// [crate::stats::LinkStats::validation_failures] and reported as
+/// [crate::events::LinkEvent::ValidationFailed] instead of being delivered.
+pub trait RxValidator<const T: usize>: Send {
+    /// Returns the reason the packet is invalid, if it is.
+    fn validate(&mut self, packet: &flem::Packet<T>) -> Result<(), String>;
+}
+
+impl<const T: usize, F> RxValidator<T> for F
+where
+    F: FnMut(&flem::Packet<T>) -> Result<(), String> + Send,
+{
+    fn validate(&mut self, packet: &flem::Packet<T>) -> Result<(), String> {
+        self(packet)
+    }
+}
+
+/// A validator shared between a link and its listener threads.
+#[cfg(feature = "link")]
+pub(crate) type SharedValidator<const T: usize> = Arc<Mutex<Box<dyn RxValidator<T>>>>;
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use crate::{events::LinkEvent, FlemSerial};
+    use std::io::Cursor;
+
+    fn packet(data: &[u8]) -> Vec<u8> {
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(flem::Request::EVENT);
+        packet.add_data(data).unwrap();
+        packet.pack();
+        packet.bytes().to_vec()
+    }
+
+    #[test]
+    fn test_invalid_packets_are_counted_not_delivered() {
+        let mut serial = FlemSerial::<64>::from_transport(Cursor::new(Vec::new()));
+        // The last payload byte must be the sum of the others
+        serial.set_rx_validator(|packet: &flem::Packet<64>| {
+            let (check, data) = packet.get_data().split_last().ok_or("empty")?;
+            let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            if sum == *check {
+                Ok(())
+            } else {
+                Err(format!("sum {} != {}", sum, check))
+            }
+        });
+        let mut rx = serial.listen_stepped().unwrap();
+
+        rx.step(&packet(&[1, 2, 3]));
+        rx.step(&packet(&[1, 2, 4]));
+
+        let received = rx.drain();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].get_data(), [1, 2, 3]);
+        assert_eq!(rx.stats().validation_failures, 1);
+        assert_eq!(
+            rx.events().try_recv().unwrap(),
+            LinkEvent::ValidationFailed {
+                request: flem::Request::EVENT,
+                reason: "sum 3 != 4".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Simulated devices on pseudo-terminals, so the whole `connect`, `listen`
+//! and `send` path can be tested on machines with no serial hardware.
+//!
+//! The host end of the pseudo-terminal is a real tty and is opened by name
+//! like any port, although the OS doesn't list it.
+
+use crate::scope::{self, LinkScope, ScopeHandle};
+use std::{
+    ffi::CStr,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How long the device waits before reading again when nothing arrived.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How long writes wait for a host that isn't reading.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `ptsname` returns a static buffer.
+static PTSNAME: Mutex<()> = Mutex::new(());
+
+/// Opens a pseudo-terminal, returning the non-blocking device end, the host
+/// end and the host end's path.
+fn open_pty() -> io::Result<(File, File, String)> {
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+        .open("/dev/ptmx")?;
+    let fd = device.as_raw_fd();
+
+    // SAFETY: `fd` is the open pseudo-terminal master owned by `device`.
+    if unsafe { libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 } {
+        return Err(io::Error::last_os_error());
+    }
+    let port_name = {
+        let _guard = PTSNAME.lock().unwrap();
+        // SAFETY: `fd` is an unlocked master. The returned buffer is
+        // static, so it is copied out while PTSNAME is held.
+        unsafe {
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            CStr::from_ptr(name).to_string_lossy().into_owned()
+        }
+    };
+
+    // Kept open so the device end doesn't fail with EIO while the host
+    // hasn't opened the port, and raw so nothing is echoed or line buffered
+    // before the host configures it
+    let host = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(&port_name)?;
+    let mut termios = MaybeUninit::<libc::termios>::uninit();
+    // SAFETY: `host` is an open tty, tcgetattr fills `termios` when it
+    // succeeds and it is only read after that.
+    unsafe {
+        if libc::tcgetattr(host.as_raw_fd(), termios.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut termios = termios.assume_init();
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(host.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((device, host, port_name))
+}
+
+fn write_all(device: &Mutex<File>, mut bytes: &[u8]) -> io::Result<()> {
+    let deadline = Instant::now() + WRITE_TIMEOUT;
+    while !bytes.is_empty() {
+        match device.lock().unwrap().write(bytes) {
+            Ok(written) => bytes = &bytes[written..],
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// A simulated device on a pseudo-terminal. Connect to
+/// [VirtualDevice::port_name] as to a serial port; every packet the host
+/// sends is passed to the device's handler and the packets it returns are
+/// sent back.
+///
+/// Line settings such as the baud rate are accepted and have no effect.
+/// The device stops when dropped.
+pub struct VirtualDevice<const T: usize> {
+    port_name: String,
+    device: Arc<Mutex<File>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    _host: File,
+}
+
+impl<const T: usize> VirtualDevice<T> {
+    /// Creates a pseudo-terminal and answers the packets received on it
+    /// with `handler`.
+    pub fn spawn<H>(handler: H) -> io::Result<Self>
+    where
+        H: FnMut(&flem::Packet<T>) -> Vec<flem::Packet<T>> + Send + 'static,
+    {
+        Self::spawn_with(None, handler)
+    }
+
+    /// Like [VirtualDevice::spawn], with the device thread tracked by
+    /// `scope`.
+    pub fn spawn_in<H>(scope: &LinkScope, handler: H) -> io::Result<Self>
+    where
+        H: FnMut(&flem::Packet<T>) -> Vec<flem::Packet<T>> + Send + 'static,
+    {
+        Self::spawn_with(Some(&scope.handle()), handler)
+    }
+
+    fn spawn_with<H>(scope: Option<&ScopeHandle>, mut handler: H) -> io::Result<Self>
+    where
+        H: FnMut(&flem::Packet<T>) -> Vec<flem::Packet<T>> + Send + 'static,
+    {
+        let (device, host, port_name) = open_pty()?;
+        let device = Arc::new(Mutex::new(device));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = {
+            let device = device.clone();
+            let running = running.clone();
+            let stop = running.clone();
+            let stop = move || stop.store(false, Ordering::Release);
+            scope::spawn_scoped("virtual device", None, None, scope, stop, move || {
+                let mut incoming = flem::Packet::<T>::new();
+                let mut buffer = [0u8; 256];
+                while running.load(Ordering::Acquire) {
+                    let read = device.lock().unwrap().read(&mut buffer);
+                    let count = match read {
+                        Ok(count) if count > 0 => count,
+                        // Nothing written yet, or the host closed the port
+                        _ => {
+                            thread::sleep(POLL_INTERVAL);
+                            continue;
+                        }
+                    };
+
+                    for byte in &buffer[..count] {
+                        match incoming.add_byte(*byte) {
+                            flem::Status::PacketReceived => {
+                                for reply in handler(&incoming) {
+                                    let _ = write_all(&device, reply.bytes());
+                                }
+                                incoming.reset_lazy();
+                            }
+                            flem::Status::PacketBuilding => {}
+                            _ => incoming.reset_lazy(),
+                        }
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            port_name,
+            device,
+            running,
+            thread: Some(thread),
+            _host: host,
+        })
+    }
+
+    /// A device that answers every packet with a copy of it.
+    pub fn echo() -> io::Result<Self> {
+        Self::spawn(|packet| vec![packet.clone()])
+    }
+
+    /// Path of the host end, such as "/dev/pts/3", to pass to
+    /// [crate::FlemSerial::connect].
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Sends a packet the host didn't ask for, such as an event.
+    pub fn emit(&self, packet: &flem::Packet<T>) -> io::Result<()> {
+        write_all(&self.device, packet.bytes())
+    }
+}
+
+impl<const T: usize> Drop for VirtualDevice<T> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VirtualDevice;
+    use crate::FlemSerial;
+    use std::time::Duration;
+
+    #[test]
+    fn test_connect_listen_and_send_through_a_pseudo_terminal() {
+        let device = VirtualDevice::<64>::echo().unwrap();
+        let mut serial = FlemSerial::<64>::new();
+        serial
+            .connect(&device.port_name().to_string(), 115200)
+            .unwrap();
+        let rx = serial.listen().unwrap();
+
+        let mut packet = flem::Packet::<64>::new();
+        packet.set_request(0x30);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        packet.pack();
+        serial.send(&packet).unwrap();
+        let echoed = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(echoed.bytes(), packet.bytes());
+
+        let mut event = flem::Packet::<64>::new();
+        event.set_request(flem::Request::EVENT);
+        event.pack();
+        device.emit(&event).unwrap();
+        let received = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(received.get_request(), flem::Request::EVENT);
+
+        serial.unlisten();
+    }
+}
@@ -0,0 +1,153 @@
+use crate::clock::{Clock, MockClock};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// A state machine driven by deadlines, such as reconnect backoff or busy
+/// retries, that can be stepped by a [VirtualTimeRunner].
+pub trait TimedStateMachine {
+    /// The next moment the machine needs to run, None if it is idle.
+    fn next_deadline(&self) -> Option<Instant>;
+
+    /// Called with the clock set to a deadline returned by `next_deadline`.
+    fn on_deadline(&mut self, now: Instant);
+}
+
+/// Summary of a [VirtualTimeRunner::run_for] call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Number of deadlines fired.
+    pub steps: usize,
+    /// Virtual time that passed.
+    pub elapsed: Duration,
+}
+
+/// Runs timed state machines against a [MockClock], jumping straight from
+/// one deadline to the next so that minutes of timeouts and backoff finish
+/// in microseconds of real time.
+pub struct VirtualTimeRunner {
+    clock: Arc<MockClock>,
+}
+
+impl Default for VirtualTimeRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualTimeRunner {
+    pub fn new() -> Self {
+        Self {
+            clock: Arc::new(MockClock::new()),
+        }
+    }
+
+    /// The runner's clock, to be shared with the code under test.
+    pub fn clock(&self) -> Arc<MockClock> {
+        self.clock.clone()
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Fires deadlines in time order until every machine is idle or `limit`
+    /// of virtual time has passed. The clock ends at the last deadline fired,
+    /// or at `limit` if a deadline remains beyond it.
+    pub fn run_for(
+        &self,
+        machines: &mut [&mut dyn TimedStateMachine],
+        limit: Duration,
+    ) -> RunSummary {
+        let start = self.clock.now();
+        let end = start + limit;
+        let mut steps = 0;
+
+        loop {
+            let next = machines
+                .iter()
+                .enumerate()
+                .filter_map(|(index, machine)| machine.next_deadline().map(|at| (at, index)))
+                .min();
+
+            match next {
+                Some((at, index)) if at <= end => {
+                    let now = self.clock.now();
+                    if at > now {
+                        self.clock.advance(at - now);
+                    }
+                    machines[index].on_deadline(self.clock.now());
+                    steps += 1;
+                }
+                Some(_) => {
+                    let now = self.clock.now();
+                    self.clock.advance(end - now);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        RunSummary {
+            steps,
+            elapsed: self.clock.now() - start,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimedStateMachine, VirtualTimeRunner};
+    use crate::reconnect::{ReconnectBackoff, ReconnectPolicy};
+    use std::time::{Duration, Instant};
+
+    /// A device that stays unplugged until `back_at`.
+    struct Replug {
+        backoff: ReconnectBackoff,
+        back_at: Instant,
+        reconnected_at: Option<Instant>,
+    }
+
+    impl TimedStateMachine for Replug {
+        fn next_deadline(&self) -> Option<Instant> {
+            self.backoff.next_attempt()
+        }
+
+        fn on_deadline(&mut self, now: Instant) {
+            if now >= self.back_at {
+                self.backoff.on_success();
+                self.reconnected_at = Some(now);
+            } else {
+                self.backoff.on_failure(now);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ten_minute_reconnect_backoff() {
+        let runner = VirtualTimeRunner::new();
+        let start = runner.now();
+
+        let mut replug = Replug {
+            backoff: ReconnectBackoff::new(ReconnectPolicy {
+                initial_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(60),
+                multiplier: 2,
+                max_attempts: None,
+            }),
+            back_at: start + Duration::from_secs(600),
+            reconnected_at: None,
+        };
+        replug.backoff.on_disconnect(start);
+
+        let summary = runner.run_for(&mut [&mut replug], Duration::from_secs(3600));
+
+        // Attempts at 1, 3, 7, 15, 31 and 63 s, then every 60 s until 603 s
+        let reconnected_at = replug.reconnected_at.unwrap();
+        assert_eq!(reconnected_at - start, Duration::from_secs(603));
+        assert_eq!(summary.steps, 15);
+        assert_eq!(summary.elapsed, Duration::from_secs(603));
+        assert!(replug.backoff.next_attempt().is_none());
+    }
+}
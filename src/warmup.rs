@@ -0,0 +1,92 @@
+use std::time::Duration;
+#[cfg(feature = "link")]
+use std::{sync::Mutex, time::Instant};
+
+/// How quickly a link became usable after connecting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WarmupStats {
+    /// Time from connecting to the first valid packet received.
+    pub first_packet: Option<Duration>,
+    /// Time from sending the first ID request to receiving its response.
+    pub id_round_trip: Option<Duration>,
+}
+
+#[cfg(feature = "link")]
+#[derive(Default)]
+struct WarmupState {
+    connected_at: Option<Instant>,
+    id_sent_at: Option<Instant>,
+    stats: WarmupStats,
+}
+
+/// Records the warm-up timings of a link. Only the first packet and the
+/// first ID exchange after each connect are measured.
+#[cfg(feature = "link")]
+#[derive(Default)]
+pub(crate) struct WarmupTracker {
+    state: Mutex<WarmupState>,
+}
+
+#[cfg(feature = "link")]
+impl WarmupTracker {
+    /// Starts a new measurement, discarding the previous one.
+    pub(crate) fn on_connect(&self, now: Instant) {
+        *self.state.lock().unwrap() = WarmupState {
+            connected_at: Some(now),
+            ..Default::default()
+        };
+    }
+
+    pub(crate) fn on_id_sent(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if state.id_sent_at.is_none() && state.stats.id_round_trip.is_none() {
+            state.id_sent_at = Some(now);
+        }
+    }
+
+    /// Called for every valid packet received.
+    pub(crate) fn on_packet(&self, is_id_response: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if state.stats.first_packet.is_none() {
+            if let Some(connected_at) = state.connected_at {
+                state.stats.first_packet = Some(now.saturating_duration_since(connected_at));
+            }
+        }
+        if is_id_response {
+            if let Some(sent_at) = state.id_sent_at.take() {
+                state.stats.id_round_trip = Some(now.saturating_duration_since(sent_at));
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> WarmupStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+#[cfg(all(test, feature = "link"))]
+mod tests {
+    use super::WarmupTracker;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_only_first_packet_and_id_exchange_are_measured() {
+        let start = Instant::now();
+        let tracker = WarmupTracker::default();
+        tracker.on_connect(start);
+
+        tracker.on_packet(false, start + Duration::from_millis(40));
+        tracker.on_id_sent(start + Duration::from_millis(50));
+        tracker.on_packet(false, start + Duration::from_millis(55));
+        tracker.on_packet(true, start + Duration::from_millis(58));
+        tracker.on_id_sent(start + Duration::from_millis(100));
+        tracker.on_packet(true, start + Duration::from_millis(200));
+
+        let stats = tracker.snapshot();
+        assert_eq!(stats.first_packet, Some(Duration::from_millis(40)));
+        assert_eq!(stats.id_round_trip, Some(Duration::from_millis(8)));
+
+        tracker.on_connect(start + Duration::from_secs(1));
+        assert_eq!(tracker.snapshot(), Default::default());
+    }
+}
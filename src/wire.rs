@@ -0,0 +1,96 @@
+//! Packets in a form that can cross process boundaries. With the `serde`
+//! feature [WirePacket] and [crate::events::LinkEvent] implement
+//! `Serialize` and `Deserialize`, so traffic can be forwarded as JSON over
+//! IPC or a WebSocket and rebuilt on the other side.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The fields of a packet that survive the trip. The checksum is
+/// recomputed by [WirePacket::to_packet].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WirePacket {
+    pub request: u8,
+    pub response: u8,
+    pub data: Vec<u8>,
+}
+
+impl WirePacket {
+    pub fn from_packet<const T: usize>(packet: &flem::Packet<T>) -> Self {
+        Self {
+            request: packet.get_request(),
+            response: packet.get_response(),
+            data: packet.get_data().to_vec(),
+        }
+    }
+
+    /// Rebuilds the packet. None if the data doesn't fit in a packet of
+    /// size `T`.
+    pub fn to_packet<const T: usize>(&self) -> Option<flem::Packet<T>> {
+        let mut packet = flem::Packet::<T>::new();
+        packet.set_request(self.request);
+        packet.set_response(self.response);
+        packet.add_data(&self.data).ok()?;
+        packet.pack();
+        Some(packet)
+    }
+}
+
+impl<const T: usize> From<&flem::Packet<T>> for WirePacket {
+    fn from(packet: &flem::Packet<T>) -> Self {
+        Self::from_packet(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WirePacket;
+
+    #[test]
+    fn test_packets_are_rebuilt_with_their_checksum() {
+        let mut packet = flem::Packet::<16>::new();
+        packet.set_request(0x21);
+        packet.set_response(flem::Response::ERROR);
+        packet.add_data(&[1, 2, 3]).unwrap();
+        packet.pack();
+
+        let wire = WirePacket::from(&packet);
+        assert_eq!(wire.data, [1, 2, 3]);
+        let rebuilt = wire.to_packet::<16>().unwrap();
+        assert_eq!(rebuilt.bytes(), packet.bytes());
+
+        // Doesn't fit in a smaller packet
+        let large = WirePacket {
+            data: vec![0; 17],
+            ..wire
+        };
+        assert!(large.to_packet::<16>().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        use crate::events::LinkEvent;
+        use std::io;
+
+        let wire = WirePacket {
+            request: 1,
+            response: 0,
+            data: vec![0xff],
+        };
+        let json = serde_json::to_string(&wire).unwrap();
+        assert_eq!(json, r#"{"request":1,"response":0,"data":[255]}"#);
+        assert_eq!(serde_json::from_str::<WirePacket>(&json).unwrap(), wire);
+
+        let event = LinkEvent::Repeated {
+            event: Box::new(LinkEvent::ReadError {
+                kind: io::ErrorKind::TimedOut,
+                message: "timed out".into(),
+            }),
+            count: 3,
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(serde_json::from_str::<LinkEvent>(&json).unwrap(), event);
+    }
+}
@@ -0,0 +1,234 @@
+#[cfg(feature = "link")]
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+#[cfg(feature = "link")]
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+pub const XON: u8 = 0x11;
+pub const XOFF: u8 = 0x13;
+/// Precedes an escaped byte, which is sent XORed with [ESCAPE_XOR].
+pub const ESCAPE: u8 = 0x7D;
+pub const ESCAPE_XOR: u8 = 0x20;
+
+fn needs_escape(byte: u8) -> bool {
+    matches!(byte, XON | XOFF | ESCAPE)
+}
+
+/// Escapes XON, XOFF and [ESCAPE] so the driver never sees them in data.
+pub fn stuff(bytes: &[u8]) -> Vec<u8> {
+    let mut stuffed = Vec::with_capacity(bytes.len() + bytes.len() / 8);
+    for byte in bytes.iter() {
+        if needs_escape(*byte) {
+            stuffed.push(ESCAPE);
+            stuffed.push(byte ^ ESCAPE_XOR);
+        } else {
+            stuffed.push(*byte);
+        }
+    }
+    stuffed
+}
+
+/// Undoes [stuff] on a stream that may split escape sequences across reads.
+#[derive(Debug, Default)]
+pub struct Unstuffer {
+    escaped: bool,
+}
+
+impl Unstuffer {
+    /// Unstuffs `bytes` in place and returns the new length.
+    pub fn unstuff(&mut self, bytes: &mut [u8]) -> usize {
+        let mut length = 0;
+        for i in 0..bytes.len() {
+            let byte = bytes[i];
+            if self.escaped {
+                bytes[length] = byte ^ ESCAPE_XOR;
+                length += 1;
+                self.escaped = false;
+            } else if byte == ESCAPE {
+                self.escaped = true;
+            } else {
+                bytes[length] = byte;
+                length += 1;
+            }
+        }
+        length
+    }
+}
+
+/// A port with XON/XOFF enabled that byte-stuffs everything written and
+/// unstuffs everything read, so binary FLEM packets pass through the
+/// driver intact. The device must apply the same escaping.
+#[cfg(feature = "link")]
+pub(crate) struct StuffedPort {
+    inner: Box<dyn SerialPort>,
+    unstuffer: Unstuffer,
+}
+
+#[cfg(feature = "link")]
+impl StuffedPort {
+    pub fn new(inner: Box<dyn SerialPort>) -> Self {
+        Self {
+            inner,
+            unstuffer: Unstuffer::default(),
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl Read for StuffedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let bytes_read = self.inner.read(buf)?;
+            if bytes_read == 0 {
+                return Ok(0);
+            }
+            // A lone escape byte yields nothing, read again
+            let length = self.unstuffer.unstuff(&mut buf[..bytes_read]);
+            if length > 0 {
+                return Ok(length);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "link")]
+impl Write for StuffedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write_all(&stuff(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "link")]
+impl SerialPort for StuffedPort {
+    fn name(&self) -> Option<String> {
+        self.inner.name()
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        self.inner.baud_rate()
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        self.inner.data_bits()
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        self.inner.flow_control()
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        self.inner.parity()
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        self.inner.stop_bits()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.inner.timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.inner.set_baud_rate(baud_rate)
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.inner.set_data_bits(data_bits)
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.inner.set_flow_control(flow_control)
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.inner.set_parity(parity)
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.inner.set_stop_bits(stop_bits)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_request_to_send(level)
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+        self.inner.write_data_terminal_ready(level)
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        self.inner.read_clear_to_send()
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        self.inner.read_data_set_ready()
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        self.inner.read_ring_indicator()
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        self.inner.read_carrier_detect()
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_read()
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        self.inner.bytes_to_write()
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        self.inner.clear(buffer_to_clear)
+    }
+
+    // The clone starts with its own unstuffing state, so only one clone
+    // should read.
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(StuffedPort::new(self.inner.try_clone()?)))
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        self.inner.set_break()
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        self.inner.clear_break()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stuff, Unstuffer, ESCAPE, XOFF, XON};
+
+    #[test]
+    fn test_stuffing_round_trips_across_split_reads() {
+        let data = [0x00, XON, 0x42, XOFF, ESCAPE, 0xFF];
+        let stuffed = stuff(&data);
+        assert!(!stuffed.contains(&XON) && !stuffed.contains(&XOFF));
+
+        let mut unstuffer = Unstuffer::default();
+        let mut received = Vec::new();
+        // Split right after an escape byte
+        let split = stuffed.iter().position(|byte| *byte == ESCAPE).unwrap() + 1;
+        for chunk in [&stuffed[..split], &stuffed[split..]] {
+            let mut buffer = chunk.to_vec();
+            let length = unstuffer.unstuff(&mut buffer);
+            received.extend_from_slice(&buffer[..length]);
+        }
+        assert_eq!(received, data);
+    }
+}